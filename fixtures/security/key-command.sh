@@ -0,0 +1,7 @@
+#!/bin/sh
+# Answers the seed 1234 for security level 01 with the key EDCB, and anything else with nothing.
+if [ "$1" = "01" ] && [ "$2" = "1234" ]; then
+    echo "EDCB"
+else
+    exit 1
+fi
@@ -1,40 +1,366 @@
 // TODO: this could maybe live in `can`, as like `can::identifier::obd` or something, since they are
-// standardized so it's not like we'd be including totally random stuff.  might also want dedicated
-// types that wrap the IDs so we can have a more type-driven approach to target/source addresses
-// when using physical addressing, since right now it's ugly/gross to do the back and forth.
+// standardized so it's not like we'd be including totally random stuff.
 
-use can::identifier::{obd::{DiagnosticBroadcastAddress, DiagnosticResponseFilter, DiagnosticResponseAddress}, StandardId, ExtendedId};
+use core::fmt;
+
+use can::identifier::{ExtendedId, Id, StandardId};
 use clap::ArgEnum;
 use socketcan::CANFilter;
 
+use crate::protocol::can::frame::CANAnyFrame;
+
+/// Functional request identifier for 11-bit addressing.
+const STANDARD_FUNCTIONAL_REQUEST_ID: u32 = 0x7DF;
+
+/// First physical request identifier for 11-bit addressing.  ECUs respond on their request
+/// identifier plus `STANDARD_RESPONSE_OFFSET`.
+const STANDARD_PHYSICAL_REQUEST_BASE: u32 = 0x7E0;
+
+/// Offset between 11-bit physical request and response identifiers.
+const STANDARD_RESPONSE_OFFSET: u32 = 0x08;
+
+/// Number of ECUs addressable with 11-bit addressing.
+const STANDARD_ECU_COUNT: u32 = 8;
+
+/// Base of 29-bit normal fixed identifiers for physical addressing: priority 6, PF 0xDA.
+const NORMAL_FIXED_PHYSICAL_BASE: u32 = 0x18DA0000;
+
+/// Base of 29-bit normal fixed identifiers for functional addressing: priority 6, PF 0xDB.
+const NORMAL_FIXED_FUNCTIONAL_BASE: u32 = 0x18DB0000;
+
+/// Mask covering the priority and PDU format of a 29-bit normal fixed identifier.
+const NORMAL_FIXED_FORMAT_MASK: u32 = 0x1FFF0000;
+
+/// How the width of the CAN identifiers used for diagnostics is chosen.
 #[derive(ArgEnum, Clone, Copy, Debug)]
+pub enum AddressingMode {
+    /// Detected per ISO 15765-4, by trying 11-bit identifiers and then 29-bit identifiers.
+    Auto,
+    /// 11-bit identifiers, with physical requests on 0x7E0-0x7E7 and responses on 0x7E8-0x7EF.
+    Standard,
+    /// 29-bit normal fixed identifiers, `0x18DA<TA><SA>` for physical and `0x18DB<TA><SA>` for
+    /// functional requests.
+    Extended,
+}
+
+/// Width of the CAN identifiers used for diagnostics.
+#[derive(Clone, Copy, Debug)]
 pub enum Addressing {
     Standard,
     Extended,
 }
 
+impl fmt::Display for Addressing {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Standard => write!(f, "11-bit identifiers"),
+            Self::Extended => write!(f, "29-bit identifiers"),
+        }
+    }
+}
+
 impl Addressing {
-    pub fn obd_broadcast_address(&self) -> DiagnosticBroadcastAddress {
+    /// Gets the width of an identifier that was given explicitly, which is extended whenever it
+    /// doesn't fit within 11 bits, as with `id_from_raw`.
+    pub fn of(id: Id) -> Self {
+        if id.as_raw() > 0x7FF {
+            Self::Extended
+        } else {
+            Self::Standard
+        }
+    }
+
+    /// Gets the identifier functional requests are sent to.
+    ///
+    /// `functional_address` and `tester_address` are only used for 29-bit addressing.
+    pub fn functional_request_address(
+        &self,
+        functional_address: u8,
+        tester_address: u8,
+    ) -> RequestAddress {
         match self {
-            Self::Standard => DiagnosticBroadcastAddress::standard(),
-            Self::Extended => DiagnosticBroadcastAddress::extended(),
+            Self::Standard => RequestAddress(standard_id(STANDARD_FUNCTIONAL_REQUEST_ID)),
+            Self::Extended => RequestAddress(normal_fixed_id(
+                NORMAL_FIXED_FUNCTIONAL_BASE,
+                functional_address,
+                tester_address,
+            )),
         }
     }
 
-    pub fn obd_response_address_filter(&self) -> CANFilter {
+    /// Creates a filter that only accepts responses addressed to the tester.
+    pub fn response_filter(&self, tester_address: u8) -> IdFilter {
+        let (id, mask) = match self {
+            Self::Standard => (
+                STANDARD_PHYSICAL_REQUEST_BASE + STANDARD_RESPONSE_OFFSET,
+                0x7FF & !(STANDARD_ECU_COUNT - 1),
+            ),
+            Self::Extended => (
+                NORMAL_FIXED_PHYSICAL_BASE | ((tester_address as u32) << 8),
+                0x1FFFFF00,
+            ),
+        };
+
+        IdFilter { id, mask }
+    }
+
+    /// Gets the response address for the given identifier, if it's a response addressed to the
+    /// tester.
+    pub fn response_address(&self, id: Id, tester_address: u8) -> Option<ResponseAddress> {
+        let raw = id.as_raw();
+        let is_response = match self {
+            Self::Standard => {
+                let base = STANDARD_PHYSICAL_REQUEST_BASE + STANDARD_RESPONSE_OFFSET;
+                (base..base + STANDARD_ECU_COUNT).contains(&raw)
+            }
+            Self::Extended => {
+                raw & NORMAL_FIXED_FORMAT_MASK == NORMAL_FIXED_PHYSICAL_BASE
+                    && (raw >> 8) as u8 == tester_address
+            }
+        };
+
+        if is_response {
+            Some(ResponseAddress(id))
+        } else {
+            None
+        }
+    }
+
+    /// Gets the request and response addresses of the ECU with the given target address.
+    ///
+    /// Only 29-bit normal fixed addressing carries target addresses in the identifier, so this is
+    /// `None` for 11-bit addressing.
+    pub fn physical_addresses(
+        &self,
+        target_address: u8,
+        tester_address: u8,
+    ) -> Option<(RequestAddress, ResponseAddress)> {
         match self {
-            Self::Standard => DiagnosticResponseFilter::standard().into(),
-            Self::Extended => DiagnosticResponseFilter::extended().into(),
+            Self::Standard => None,
+            Self::Extended => {
+                let request_address = RequestAddress(normal_fixed_id(
+                    NORMAL_FIXED_PHYSICAL_BASE,
+                    target_address,
+                    tester_address,
+                ));
+                let response_address = request_address.response_address()?;
+                Some((request_address, response_address))
+            }
         }
     }
+}
+
+/// Identifier that requests are sent to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct RequestAddress(Id);
+
+impl RequestAddress {
+    pub fn new(id: impl Into<Id>) -> Self {
+        Self(id.into())
+    }
+
+    pub fn id(&self) -> Id {
+        self.0
+    }
+
+    /// Gets the identifier that the ECU at this address responds on.
+    ///
+    /// Only possible for identifiers following the OBD or normal fixed conventions, since
+    /// anything else is vendor-specific.
+    pub fn response_address(&self) -> Option<ResponseAddress> {
+        let raw = self.0.as_raw();
+        let id = if is_standard_physical_id(self.0, STANDARD_PHYSICAL_REQUEST_BASE) {
+            standard_id(raw + STANDARD_RESPONSE_OFFSET)
+        } else {
+            swap_normal_fixed_addresses(self.0)?
+        };
+
+        Some(ResponseAddress(id))
+    }
+}
+
+impl fmt::Display for RequestAddress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// Identifier that responses are received from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct ResponseAddress(Id);
+
+impl ResponseAddress {
+    pub fn new(id: impl Into<Id>) -> Self {
+        Self(id.into())
+    }
+
+    pub fn id(&self) -> Id {
+        self.0
+    }
+
+    /// Gets the identifier that the ECU responding on this address expects requests on.
+    ///
+    /// Only possible for identifiers following the OBD or normal fixed conventions, since
+    /// anything else is vendor-specific.
+    pub fn request_address(&self) -> Option<RequestAddress> {
+        let raw = self.0.as_raw();
+        let base = STANDARD_PHYSICAL_REQUEST_BASE + STANDARD_RESPONSE_OFFSET;
+        let id = if is_standard_physical_id(self.0, base) {
+            standard_id(raw - STANDARD_RESPONSE_OFFSET)
+        } else {
+            swap_normal_fixed_addresses(self.0)?
+        };
+
+        Some(RequestAddress(id))
+    }
+
+    /// Whether this is addressed to a tester other than the one with the given address.
+    ///
+    /// Only normal fixed identifiers carry the tester's address, so anything else could be for us.
+    pub fn is_for_other_tester(&self, tester_address: u8) -> bool {
+        let raw = self.0.as_raw();
+        raw & NORMAL_FIXED_FORMAT_MASK == NORMAL_FIXED_PHYSICAL_BASE
+            && (raw >> 8) as u8 != tester_address
+    }
+}
+
+impl fmt::Display for ResponseAddress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+fn standard_id(raw: u32) -> Id {
+    StandardId::new(raw as u16)
+        .expect("should never fail to construct standard identifier")
+        .into()
+}
+
+fn normal_fixed_id(base: u32, target_address: u8, source_address: u8) -> Id {
+    ExtendedId::new(base | ((target_address as u32) << 8) | source_address as u32)
+        .expect("should never fail to construct normal fixed identifier")
+        .into()
+}
+
+fn is_standard_physical_id(id: Id, base: u32) -> bool {
+    (base..base + STANDARD_ECU_COUNT).contains(&id.as_raw())
+}
+
+/// Swaps the target and source addresses of a 29-bit normal fixed physical identifier, turning a
+/// request identifier into its response identifier, and vice versa.
+fn swap_normal_fixed_addresses(id: Id) -> Option<Id> {
+    let raw = id.as_raw();
+    if raw & NORMAL_FIXED_FORMAT_MASK != NORMAL_FIXED_PHYSICAL_BASE {
+        return None;
+    }
+
+    let target_address = (raw >> 8) as u8;
+    let source_address = raw as u8;
+    Some(normal_fixed_id(
+        raw & NORMAL_FIXED_FORMAT_MASK,
+        source_address,
+        target_address,
+    ))
+}
 
-    pub fn get_diagnostic_response_id(&self, id: u32) -> Option<DiagnosticResponseAddress> {
+/// ISO 15765-2 addressing format, which determines whether an address byte precedes the protocol
+/// control information in every frame.
+///
+/// This is independent of `Addressing`, which only determines the width of the CAN identifiers.
+#[derive(ArgEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AddressingFormat {
+    /// Addressing is carried entirely by the CAN identifier.
+    Normal,
+    /// The first byte of every frame is the target address: the ECU for requests, and the tester
+    /// for responses.
+    Extended,
+    /// The first byte of every frame is an address extension, which is the same in both
+    /// directions.
+    Mixed,
+}
+
+/// Address bytes carried at the start of every frame under extended or mixed addressing.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PayloadAddress {
+    /// Address byte that transmitted frames are prefixed with.
+    pub tx: u8,
+    /// Address byte that received frames are prefixed with.
+    pub rx: u8,
+}
+
+impl AddressingFormat {
+    /// Gets the address bytes carried in each frame, if any.
+    ///
+    /// Returns `None` for normal addressing, or if no target address was given.
+    pub fn payload_address(
+        &self,
+        target_address: Option<u8>,
+        tester_address: u8,
+    ) -> Option<PayloadAddress> {
+        let target_address = target_address?;
         match self {
-            Self::Standard => id.try_into().ok()
-                .and_then(StandardId::new)
-                .and_then(|id| DiagnosticResponseAddress::from_id(id.into())),
-            Self::Extended => ExtendedId::new(id)
-                .and_then(|id| DiagnosticResponseAddress::from_id(id.into())),
+            Self::Normal => None,
+            Self::Extended => Some(PayloadAddress {
+                tx: target_address,
+                rx: tester_address,
+            }),
+            Self::Mixed => Some(PayloadAddress {
+                tx: target_address,
+                rx: target_address,
+            }),
         }
     }
 }
+
+/// Filter on the identifiers of received frames, accepting those which match `id` in every bit set
+/// in `mask`.
+#[derive(Clone, Copy, Debug)]
+pub struct IdFilter {
+    id: u32,
+    mask: u32,
+}
+
+impl IdFilter {
+    pub fn matches(&self, id: u32) -> bool {
+        id & self.mask == self.id & self.mask
+    }
+
+    /// Gets the equivalent filter for a SocketCAN raw socket, which applies it in the kernel.
+    pub fn to_can_filter(&self) -> CANFilter {
+        CANFilter::new(self.id, self.mask).expect("should never fail to construct CAN filter")
+    }
+}
+
+/// Creates a filter that only accepts frames with the given identifier.
+pub fn exact_id_filter(id: Id) -> IdFilter {
+    let raw = id.as_raw();
+    let mask = if raw <= 0x7FF { 0x7FF } else { 0x1FFFFFFF };
+
+    IdFilter { id: raw, mask }
+}
+
+/// Creates an identifier from its raw value.
+///
+/// Values that fit within 11 bits are treated as standard identifiers, and everything else is
+/// treated as an extended identifier.
+pub fn id_from_raw(raw: u32) -> Option<Id> {
+    if raw <= 0x7FF {
+        StandardId::new(raw as u16).map(Into::into)
+    } else {
+        ExtendedId::new(raw).map(Into::into)
+    }
+}
+
+/// Gets the identifier of the given frame.
+pub fn frame_id(frame: &CANAnyFrame) -> Option<Id> {
+    if frame.is_extended() {
+        ExtendedId::new(frame.id()).map(Into::into)
+    } else {
+        frame
+            .id()
+            .try_into()
+            .ok()
+            .and_then(StandardId::new)
+            .map(Into::into)
+    }
+}
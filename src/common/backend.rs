@@ -0,0 +1,271 @@
+use std::{collections::HashMap, fmt, path::PathBuf, str::FromStr};
+
+#[cfg(test)]
+use crate::protocol::can::mock::MockBus;
+use crate::protocol::{can::slcan::is_supported_bitrate, doip::DOIP_PORT};
+
+/// Baud rate ELM327 adapters talk at out of the box.
+const DEFAULT_ELM327_BAUD_RATE: u32 = 38400;
+
+/// Baud rate for slcan adapters.  Most are USB devices that ignore it entirely, and the rest tend to
+/// default to this.
+const DEFAULT_SLCAN_BAUD_RATE: u32 = 115200;
+
+/// Bit rate of the bus, for adapters that need to be told.  OBD-II vehicles use 500 kbit/s.
+const DEFAULT_BITRATE: u32 = 500000;
+
+/// Port socketcand listens on out of the box.
+const DEFAULT_SOCKETCAND_PORT: u16 = 29536;
+
+/// Logical address we use as a DoIP tester, the first of those set aside for external test
+/// equipment.
+const DEFAULT_DOIP_SOURCE_ADDRESS: u16 = 0x0E00;
+
+/// Functional address that ISO 13400-2 sets aside for legislated OBD requests.
+const DEFAULT_DOIP_FUNCTIONAL_ADDRESS: u16 = 0xE000;
+
+/// What we reach the bus through.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Backend {
+    /// The SocketCAN interface given by `--socket-name`.
+    SocketCAN,
+    /// An ELM327-compatible adapter on a serial device, which only carries diagnostic requests.
+    Elm327 { device: PathBuf, baud_rate: u32 },
+    /// An slcan (Lawicel) adapter on a serial device, which carries raw frames.
+    Slcan {
+        device: PathBuf,
+        baud_rate: u32,
+        bitrate: u32,
+        /// Whether the adapter should timestamp received frames.
+        timestamps: bool,
+    },
+    /// A CAN interface on another machine, shared over TCP by a socketcand daemon.
+    Socketcand {
+        host: String,
+        port: u16,
+        interface: String,
+    },
+    /// ECUs behind a DoIP (ISO 13400) gateway, which only carries diagnostic requests.
+    DoIP {
+        /// Host of the gateway, or `None` to find it with vehicle identification.
+        host: Option<String>,
+        port: u16,
+        /// Our logical address as a tester.
+        source_address: u16,
+        activation_type: u8,
+        /// Logical address that functional requests are sent to.
+        functional_address: u16,
+    },
+    /// An in-memory bus, for tests.
+    #[cfg(test)]
+    Mock(MockBus),
+}
+
+impl Backend {
+    /// Whether raw CAN frames can be sent and received through this backend, rather than only
+    /// diagnostic requests and their responses.
+    pub fn has_raw_access(&self) -> bool {
+        !matches!(self, Backend::Elm327 { .. } | Backend::DoIP { .. })
+    }
+}
+
+impl fmt::Display for Backend {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Backend::SocketCAN => write!(f, "SocketCAN"),
+            Backend::Elm327 { device, baud_rate } => {
+                write!(f, "ELM327 at {} ({} baud)", device.display(), baud_rate)
+            }
+            Backend::Slcan {
+                device, bitrate, ..
+            } => write!(f, "slcan at {} ({} bit/s)", device.display(), bitrate),
+            Backend::Socketcand {
+                host,
+                port,
+                interface,
+            } => write!(f, "socketcand at {}:{} ({})", host, port, interface),
+            Backend::DoIP {
+                host: Some(host),
+                port,
+                ..
+            } => write!(f, "DoIP gateway at {}:{}", host, port),
+            Backend::DoIP { host: None, .. } => write!(f, "DoIP gateway"),
+            #[cfg(test)]
+            Backend::Mock(_) => write!(f, "mock bus"),
+        }
+    }
+}
+
+/// Parses a backend from its URL, such as `elm327:///dev/ttyUSB0?baud=115200`, or just the name of
+/// the backend if it doesn't need one.
+pub fn parse_backend(s: &str) -> Result<Backend, String> {
+    let (scheme, rest) = match s.split_once("://") {
+        Some((scheme, rest)) => (scheme, rest),
+        None => (s, ""),
+    };
+    let (location, query) = match rest.split_once('?') {
+        Some((location, query)) => (location, query),
+        None => (rest, ""),
+    };
+    let mut options = parse_options(query)?;
+
+    let backend = match scheme {
+        "socketcan" => Backend::SocketCAN,
+        "elm327" => Backend::Elm327 {
+            device: serial_device(scheme, location)?,
+            baud_rate: take_option(&mut options, "baud", DEFAULT_ELM327_BAUD_RATE)?,
+        },
+        "slcan" => {
+            let bitrate = take_option(&mut options, "bitrate", DEFAULT_BITRATE)?;
+            if !is_supported_bitrate(bitrate) {
+                return Err(format!("slcan adapters don't support {} bit/s", bitrate));
+            }
+
+            Backend::Slcan {
+                device: serial_device(scheme, location)?,
+                baud_rate: take_option(&mut options, "baud", DEFAULT_SLCAN_BAUD_RATE)?,
+                bitrate,
+                timestamps: take_option(&mut options, "timestamps", false)?,
+            }
+        }
+        "socketcand" => socketcand_location(location)?,
+        "doip" => doip_location(location, &mut options)?,
+        _ => return Err(format!("unknown backend '{}'", scheme)),
+    };
+
+    match options.keys().next() {
+        Some(option) => Err(format!(
+            "unknown option '{}' for backend '{}'",
+            option, scheme
+        )),
+        None => Ok(backend),
+    }
+}
+
+fn parse_options(query: &str) -> Result<HashMap<&str, &str>, String> {
+    query
+        .split('&')
+        .filter(|option| !option.is_empty())
+        .map(|option| {
+            option
+                .split_once('=')
+                .ok_or_else(|| format!("option '{}' has no value", option))
+        })
+        .collect()
+}
+
+fn take_option<T: FromStr>(
+    options: &mut HashMap<&str, &str>,
+    name: &str,
+    default: T,
+) -> Result<T, String> {
+    match options.remove(name) {
+        Some(value) => value
+            .parse()
+            .map_err(|_| format!("'{}' is not a valid value for option '{}'", value, name)),
+        None => Ok(default),
+    }
+}
+
+fn take_hex_option(
+    options: &mut HashMap<&str, &str>,
+    name: &str,
+    default: u16,
+) -> Result<u16, String> {
+    match options.remove(name) {
+        Some(value) => u16::from_str_radix(value.trim_start_matches("0x"), 16).map_err(|_| {
+            format!(
+                "'{}' is not a valid hexadecimal value for option '{}'",
+                value, name
+            )
+        }),
+        None => Ok(default),
+    }
+}
+
+fn serial_device(scheme: &str, location: &str) -> Result<PathBuf, String> {
+    if location.is_empty() {
+        return Err(format!(
+            "the {} backend needs a serial device, such as {}:///dev/ttyUSB0",
+            scheme, scheme
+        ));
+    }
+
+    Ok(PathBuf::from(location))
+}
+
+fn socketcand_location(location: &str) -> Result<Backend, String> {
+    let (address, interface) = match location.split_once('/') {
+        Some((address, interface)) if !address.is_empty() && !interface.is_empty() => {
+            (address, interface)
+        }
+        _ => {
+            return Err(
+                "the socketcand backend needs a host and an interface, such as socketcand://lab-rig:29536/can0"
+                    .to_string(),
+            )
+        }
+    };
+
+    let (host, port) = split_host_port(address, DEFAULT_SOCKETCAND_PORT)?;
+
+    Ok(Backend::Socketcand {
+        host: host.to_string(),
+        port,
+        interface: interface.to_string(),
+    })
+}
+
+fn doip_location(location: &str, options: &mut HashMap<&str, &str>) -> Result<Backend, String> {
+    // Without a host, the gateway is found with vehicle identification, on the default port.
+    let (host, port) = match location.trim_end_matches('/') {
+        "" => (None, DOIP_PORT),
+        address => {
+            let (host, port) = split_host_port(address, DOIP_PORT)?;
+            (Some(host.to_string()), port)
+        }
+    };
+    let activation_type = take_hex_option(options, "activation", 0x00)?;
+
+    Ok(Backend::DoIP {
+        host,
+        port,
+        source_address: take_hex_option(options, "source", DEFAULT_DOIP_SOURCE_ADDRESS)?,
+        activation_type: u8::try_from(activation_type)
+            .map_err(|_| format!("activation type 0x{:X} is out of range", activation_type))?,
+        functional_address: take_hex_option(
+            options,
+            "functional",
+            DEFAULT_DOIP_FUNCTIONAL_ADDRESS,
+        )?,
+    })
+}
+
+/// Splits an address into its host and its port, if it has one.
+///
+/// Hosts given as IPv6 addresses are bracketed, so that their colons aren't mistaken for the
+/// port's.
+fn split_host_port(address: &str, default_port: u16) -> Result<(&str, u16), String> {
+    let (host, port) = match address.strip_prefix('[') {
+        Some(bracketed) => match bracketed.split_once(']') {
+            Some((host, "")) => (host, None),
+            Some((host, port)) => match port.strip_prefix(':') {
+                Some(port) => (host, Some(port)),
+                None => return Err(format!("'{}' is not a valid address", address)),
+            },
+            None => return Err(format!("'{}' is missing a closing bracket", address)),
+        },
+        None => match address.split_once(':') {
+            Some((host, port)) => (host, Some(port)),
+            None => (address, None),
+        },
+    };
+    let port = match port {
+        Some(port) => port
+            .parse()
+            .map_err(|_| format!("'{}' is not a valid port", port))?,
+        None => default_port,
+    };
+
+    Ok((host, port))
+}
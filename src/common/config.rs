@@ -1,9 +1,30 @@
-use std::time::Duration;
+use std::{path::PathBuf, time::Duration};
 
+use can::identifier::Id;
 use clap::{ArgEnum, Args, Parser, Subcommand};
 use tracing::Level;
 
-use super::addressing::Addressing;
+use crate::protocol::{
+    can::{frame::is_fd_data_length, isotp::ISOTPBackend},
+    j1939::diagnostics::SpnConversion,
+    uds::{
+        fuzz::Mutation,
+        services::{
+            AuthenticationMode, IdentifierRange, IdentifierSource, MemorySource,
+            PeriodicFrameFormat, TransmissionRate,
+        },
+    },
+};
+
+use super::{
+    addressing::{
+        id_from_raw, Addressing, AddressingFormat, AddressingMode, PayloadAddress, RequestAddress,
+        ResponseAddress,
+    },
+    backend::{parse_backend, Backend},
+    parse::{parse_can_id, parse_hex_u16, parse_hex_u32, parse_hex_u8, HexBytes},
+    targets::{load_named_target, Target, TargetError},
+};
 
 #[derive(Parser)]
 #[clap(name = "hypercan")]
@@ -20,6 +41,9 @@ pub struct AppConfig {
     #[clap(flatten)]
     can_parameters: CANParameters,
 
+    #[clap(flatten)]
+    target_parameters: TargetParameters,
+
     #[clap(subcommand)]
     operation: Command,
 }
@@ -29,6 +53,21 @@ impl AppConfig {
         self.can_parameters.clone()
     }
 
+    /// Resolves the ECU to talk to, if one was specified.
+    ///
+    /// Takes the CAN parameters to resolve against, since addressing may have been detected since
+    /// they were parsed.
+    pub fn target(&self, can_parameters: &CANParameters) -> Result<Option<Target>, TargetError> {
+        self.target_parameters.resolve(can_parameters)
+    }
+
+    /// Whether the ECU to talk to was given by its identifiers, directly or by name, rather than
+    /// by an address they'd be derived from.
+    pub fn has_explicit_identifiers(&self) -> bool {
+        let target = &self.target_parameters;
+        target.request_id.is_some() || target.response_id.is_some() || target.target.is_some()
+    }
+
     pub fn command(&self) -> Command {
         self.operation.clone()
     }
@@ -44,23 +83,254 @@ impl AppConfig {
 
 #[derive(Args, Clone)]
 pub struct CANParameters {
-    #[clap(long)]
+    /// SocketCAN interface to use, when using the SocketCAN backend.
+    #[clap(long, default_value = "can0")]
     pub socket_name: String,
 
+    /// What to reach the bus through: `socketcan`, an ELM327-compatible adapter on a serial device,
+    /// such as `elm327:///dev/ttyUSB0?baud=38400`, an slcan adapter, such as
+    /// `slcan:///dev/ttyACM0?bitrate=500000&timestamps=true`, or an interface shared by a
+    /// socketcand daemon, such as `socketcand://lab-rig:29536/can0`, or a DoIP gateway, such as
+    /// `doip://192.168.0.10?source=0E00`, where leaving out the host finds the gateway with vehicle
+    /// identification.
+    #[clap(long, parse(try_from_str = parse_backend), default_value = "socketcan")]
+    pub backend: Backend,
+
     #[clap(long, parse(try_from_str = duration_str::parse), default_value = "2s")]
     pub read_timeout: Duration,
 
     #[clap(long, parse(try_from_str = duration_str::parse), default_value = "2s")]
     pub write_timeout: Duration,
 
+    /// Enables CAN FD, allowing FD frames to be sent and received.
+    #[clap(long)]
+    pub can_fd: bool,
+
+    /// Sends FD frames with bit rate switching, so their data is sent at the faster data bit rate.
+    #[clap(long, requires = "can-fd")]
+    pub can_fd_bit_rate_switch: bool,
+
+    /// Sends FD frames with the error state indicator set, marking us as error passive.
+    #[clap(long, requires = "can-fd")]
+    pub can_fd_error_state_indicator: bool,
+
     #[clap(long)]
     pub disable_isotp_frame_padding: bool,
 
     #[clap(long, default_value_t = 0xCC)]
     pub tx_frame_padding: u8,
 
-    #[clap(long, short, arg_enum, default_value_t = Addressing::Standard)]
-    pub addressing: Addressing,
+    /// Width of the CAN identifiers used for diagnostics, or `auto` to detect it whenever
+    /// identifiers have to be derived from it, rather than given with `--request-id`,
+    /// `--response-id`, or `--target`.
+    #[clap(long = "addressing", short, arg_enum, default_value_t = AddressingMode::Standard)]
+    pub addressing_mode: AddressingMode,
+
+    /// ISO-TP addressing format: whether frames carry an address byte ahead of their payload.
+    #[clap(long, arg_enum, default_value_t = AddressingFormat::Normal)]
+    pub addressing_format: AddressingFormat,
+
+    /// Address of the ECU, in hexadecimal.  Under 29-bit addressing, it determines the request and
+    /// response identifiers when they aren't given explicitly.  Under extended addressing, it's
+    /// carried in every request frame, and under mixed addressing, it's the address extension.
+    #[clap(
+        long,
+        parse(try_from_str = parse_hex_u8),
+        required_if_eq_any = &[("addressing-format", "extended"), ("addressing-format", "mixed")]
+    )]
+    pub target_address: Option<u8>,
+
+    /// How long to collect responses to functional requests for.
+    #[clap(long, parse(try_from_str = duration_str::parse), default_value = "1s")]
+    pub functional_timeout: Duration,
+
+    /// How long to wait for the real response once an ECU says it's still working on it. (P2*)
+    #[clap(long, parse(try_from_str = duration_str::parse), default_value = "5s")]
+    pub response_pending_timeout: Duration,
+
+    /// Address of the tester, in hexadecimal.  Under 29-bit addressing, it's the source address of
+    /// requests, and under extended addressing, it's carried in every response frame.
+    #[clap(long, parse(try_from_str = parse_hex_u8), default_value = "F1")]
+    pub tester_address: u8,
+
+    /// Target address of functional requests under 29-bit addressing, in hexadecimal.
+    #[clap(long, parse(try_from_str = parse_hex_u8), default_value = "33")]
+    pub functional_address: u8,
+
+    /// ISO-TP implementation to use.  The userspace implementation does not require the kernel's
+    /// `can-isotp` module.
+    #[clap(long, arg_enum, default_value_t = ISOTPBackend::Kernel)]
+    pub isotp_backend: ISOTPBackend,
+
+    /// Number of consecutive frames ECUs may send before waiting for flow control.  Zero means
+    /// there is no limit.
+    #[clap(long, default_value_t = 0)]
+    pub isotp_block_size: u8,
+
+    /// Minimum time ECUs must leave between consecutive frames. (STmin)
+    #[clap(long, parse(try_from_str = duration_str::parse), default_value = "0ms")]
+    pub isotp_separation_time: Duration,
+
+    /// Maximum number of wait frames to send in a row while receiving. (N_WFTmax)  Passed on to
+    /// the kernel's implementation, whether local or behind socketcand; ours never sends any.
+    #[clap(long, default_value_t = 0)]
+    pub isotp_max_wait_frames: u8,
+
+    /// Number of wait frames to accept in a row while sending, before giving up on the transfer.
+    /// Only our implementation enforces this, as the kernel's waits for as long as it's told to.
+    #[clap(long, default_value_t = 16)]
+    pub isotp_wait_frame_limit: u8,
+
+    /// Byte that received frames must be padded with, in hexadecimal.  Frames that aren't padded
+    /// out to a full frame with it are rejected.
+    #[clap(long, parse(try_from_str = parse_hex_u8))]
+    pub isotp_rx_padding: Option<u8>,
+
+    /// Time each transmitted frame takes on the bus, which is left between consecutive frames on
+    /// top of any separation time. (N_As)  Defaults to 50us, as the kernel's implementation does.
+    /// Not supported by socketcand.
+    #[clap(long, parse(try_from_str = duration_str::parse))]
+    pub isotp_frame_transmit_time: Option<Duration>,
+
+    /// Only listen, never sending flow control frames.
+    #[clap(long)]
+    pub isotp_listen_only: bool,
+
+    /// Extended address byte to prefix frames with, in hexadecimal.
+    #[clap(long, parse(try_from_str = parse_hex_u8))]
+    pub isotp_ext_address: Option<u8>,
+
+    /// Extended address byte that received frames are prefixed with, in hexadecimal, if it differs
+    /// from `--isotp-ext-address`.
+    #[clap(long, parse(try_from_str = parse_hex_u8), requires = "isotp-ext-address")]
+    pub isotp_rx_ext_address: Option<u8>,
+
+    /// Length of transmitted frames. (TX_DL)  Anything over 8 requires CAN FD, and must be one of
+    /// the FD frame lengths: 12, 16, 20, 24, 32, 48, or 64.
+    #[clap(long, default_value_t = 8, parse(try_from_str = parse_tx_data_length))]
+    pub isotp_tx_data_length: u8,
+}
+
+impl CANParameters {
+    /// Gets the width of the CAN identifiers used for diagnostics.
+    ///
+    /// When it's to be detected automatically, it must have been detected before this is called.
+    pub fn addressing(&self) -> Addressing {
+        match self.addressing_mode {
+            AddressingMode::Standard => Addressing::Standard,
+            AddressingMode::Extended => Addressing::Extended,
+            AddressingMode::Auto => panic!("addressing should be detected before it's used"),
+        }
+    }
+
+    /// Sets the width of the CAN identifiers used for diagnostics, once it's been detected.
+    pub fn set_addressing(&mut self, addressing: Addressing) {
+        self.addressing_mode = match addressing {
+            Addressing::Standard => AddressingMode::Standard,
+            Addressing::Extended => AddressingMode::Extended,
+        };
+    }
+
+    /// Gets the address bytes carried in each ISO-TP frame, if any.
+    pub fn payload_address(&self) -> Option<PayloadAddress> {
+        self.addressing_format
+            .payload_address(self.target_address, self.tester_address)
+    }
+
+    /// Gets the byte to pad transmitted frames with, if padding is enabled.
+    pub fn tx_padding(&self) -> Option<u8> {
+        if self.disable_isotp_frame_padding {
+            None
+        } else {
+            Some(self.tx_frame_padding)
+        }
+    }
+}
+
+/// Physical addressing for a single UDS-capable ECU.
+///
+/// ECUs can be given by their request and response identifiers directly, or by name from a
+/// targets file.  Either identifier can be left out if it can be derived from the other, or both
+/// can be left out if they can be derived from the target address.
+#[derive(Args, Clone, Debug)]
+pub struct TargetParameters {
+    /// CAN identifier that requests are sent to, in hexadecimal, or the ECU's logical address when
+    /// using the DoIP backend.
+    #[clap(long, parse(try_from_str = parse_can_id))]
+    pub request_id: Option<Id>,
+
+    /// CAN identifier that responses are received from, in hexadecimal.
+    #[clap(long, parse(try_from_str = parse_can_id))]
+    pub response_id: Option<Id>,
+
+    /// Name of a target, from the targets file, to use instead of `--request-id`/`--response-id`.
+    #[clap(long, conflicts_with_all = &["request-id", "response-id"])]
+    pub target: Option<String>,
+
+    /// File that named targets are loaded from.
+    #[clap(long, default_value = "targets.toml")]
+    pub targets_file: PathBuf,
+}
+
+impl TargetParameters {
+    /// Resolves the request and response addresses of the ECU.
+    ///
+    /// Returns `None` if no ECU was specified at all, such that operations which can work without
+    /// one, such as querying OBD-II PIDs, fall back to discovering ECUs on their own.
+    pub fn resolve(&self, can_parameters: &CANParameters) -> Result<Option<Target>, TargetError> {
+        let (request_id, response_id) = match &self.target {
+            Some(name) => load_named_target(&self.targets_file, name)?,
+            None => (self.request_id, self.response_id),
+        };
+        if request_id.is_none() && response_id.is_none() && can_parameters.target_address.is_none()
+        {
+            return Ok(None);
+        }
+
+        // ECUs behind a DoIP gateway have a single logical address, so there's nothing to derive.
+        if let Backend::DoIP { .. } = can_parameters.backend {
+            let id = request_id.or(response_id).or_else(|| {
+                can_parameters
+                    .target_address
+                    .and_then(|address| id_from_raw(address.into()))
+            });
+            return Ok(id.map(Target::logical));
+        }
+
+        let request_address = request_id.map(RequestAddress::new);
+        let response_address = response_id.map(ResponseAddress::new);
+
+        let addresses = match (request_address, response_address) {
+            (Some(request_address), Some(response_address)) => {
+                Some((request_address, response_address))
+            }
+            (Some(request_address), None) => request_address
+                .response_address()
+                .map(|response_address| (request_address, response_address)),
+            (None, Some(response_address)) => response_address
+                .request_address()
+                .map(|request_address| (request_address, response_address)),
+            (None, None) => can_parameters.target_address.and_then(|target_address| {
+                can_parameters
+                    .addressing()
+                    .physical_addresses(target_address, can_parameters.tester_address)
+            }),
+        };
+
+        match addresses {
+            Some((request_address, response_address)) => Ok(Some(Target {
+                request_address,
+                response_address,
+            })),
+            None => Err(TargetError::Unresolved {
+                field_name: if request_id.is_none() {
+                    "request_id"
+                } else {
+                    "response_id"
+                },
+            }),
+        }
+    }
 }
 
 #[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ArgEnum)]
@@ -79,4 +349,430 @@ pub enum Command {
     /// Scans for available OBD-II PIDs.
     #[clap(name = "query-available-pids")]
     QueryAvailablePIDs,
+
+    /// Logs periodic data identifiers, optionally defining a dynamic data identifier to log.
+    #[clap(name = "log-periodic")]
+    LogPeriodic(LogPeriodicArgs),
+
+    /// Clears one, or all, dynamically-defined data identifiers.
+    #[clap(name = "clear-dynamic-identifier")]
+    ClearDynamicIdentifier(ClearDynamicIdentifierArgs),
+
+    /// Transfers files to and from an ECU, or lists its directories.
+    #[clap(name = "file-transfer")]
+    FileTransfer(FileTransferArgs),
+
+    /// Authenticates with an ECU using certificate-based authentication.
+    #[clap(name = "authenticate")]
+    Authenticate(AuthenticateArgs),
+
+    /// Discovers diagnostic-capable ECUs via functional, and optionally physical, UDS requests.
+    #[clap(name = "discover")]
+    Discover(DiscoverArgs),
+
+    /// Scans for supported data identifiers via ReadDataByIdentifier.
+    #[clap(name = "scan-dids")]
+    ScanDIDs(ScanDIDsArgs),
+
+    /// Maps which services an ECU supports in each diagnostic session.
+    #[clap(name = "scan-services")]
+    ScanServices(ScanServicesArgs),
+
+    /// Fuzzes an ECU with mutated UDS requests, watching for it to reset or stop responding.
+    #[clap(name = "fuzz")]
+    Fuzz(FuzzArgs),
+
+    /// Talks to an ECU that speaks KWP2000 (ISO 14230-3) rather than UDS.
+    #[clap(name = "kwp")]
+    Kwp(KwpArgs),
+
+    /// Requests a parameter group from one, or every, node on a J1939 bus.
+    #[clap(name = "j1939-request")]
+    J1939Request(J1939RequestArgs),
+
+    /// Reads active and previously active DTCs from nodes on a J1939 bus, optionally clearing them.
+    #[clap(name = "j1939-dtcs")]
+    J1939Dtcs(J1939DtcsArgs),
+
+    /// Simulates one or more ECUs, answering OBD-II and UDS requests as described by a profile.
+    #[clap(name = "simulate")]
+    Simulate(SimulateArgs),
+}
+
+#[derive(Args, Clone, Debug)]
+pub struct LogPeriodicArgs {
+    /// CAN identifier that periodic responses are received from, in hexadecimal.
+    #[clap(long, parse(try_from_str = parse_can_id))]
+    pub periodic_response_id: Id,
+
+    /// How periodic responses are framed.
+    #[clap(long, arg_enum, default_value_t = PeriodicFrameFormat::Unsegmented)]
+    pub frame_format: PeriodicFrameFormat,
+
+    /// Rate at which the ECU should transmit periodic responses.
+    #[clap(long, arg_enum, default_value_t = TransmissionRate::Fast)]
+    pub rate: TransmissionRate,
+
+    /// Periodic data identifier to log, as the low byte of the full `0xF2XX` identifier.
+    #[clap(long = "periodic-identifier", parse(try_from_str = parse_hex_u8))]
+    pub periodic_identifiers: Vec<u8>,
+
+    /// Dynamic data identifier to define, in hexadecimal, which is then logged alongside any
+    /// other periodic data identifiers.  Must be within `0xF200` to `0xF2FF`.
+    #[clap(long, parse(try_from_str = parse_hex_u16))]
+    pub define_identifier: Option<u16>,
+
+    /// Source for the dynamic data identifier, as `<did>:<position>:<size>`.
+    #[clap(long = "source-identifier", conflicts_with = "source-memory")]
+    pub source_identifiers: Vec<IdentifierSource>,
+
+    /// Source for the dynamic data identifier, as `<address>:<size>`.
+    #[clap(long = "source-memory")]
+    pub source_memory: Vec<MemorySource>,
+
+    /// How long to log for.  Logs until interrupted if not specified.
+    #[clap(long, parse(try_from_str = duration_str::parse))]
+    pub duration: Option<Duration>,
+}
+
+#[derive(Args, Clone, Debug)]
+pub struct ClearDynamicIdentifierArgs {
+    /// Dynamic data identifier to clear, in hexadecimal.  Clears all of them if not specified.
+    #[clap(long, parse(try_from_str = parse_hex_u16))]
+    pub identifier: Option<u16>,
+}
+
+#[derive(Args, Clone, Debug)]
+pub struct FileTransferArgs {
+    /// Vendor-specific compression method.  Data is sent and saved as-is, so it must already be
+    /// compressed accordingly.
+    #[clap(long, default_value_t = 0, parse(try_from_str = parse_nibble))]
+    pub compression_method: u8,
+
+    /// Vendor-specific encryption method.  Data is sent and saved as-is, so it must already be
+    /// encrypted accordingly.
+    #[clap(long, default_value_t = 0, parse(try_from_str = parse_nibble))]
+    pub encryption_method: u8,
+
+    #[clap(subcommand)]
+    pub action: FileTransferAction,
+}
+
+#[derive(Clone, Debug, Subcommand)]
+pub enum FileTransferAction {
+    /// Adds a new file to the ECU.
+    Add {
+        /// Local file to upload.
+        local_path: PathBuf,
+        /// Path of the file on the ECU.
+        remote_path: String,
+        /// Size of the file once decompressed.  Defaults to the size of the local file.
+        #[clap(long)]
+        uncompressed_size: Option<u64>,
+    },
+
+    /// Replaces a file on the ECU.
+    Replace {
+        /// Local file to upload.
+        local_path: PathBuf,
+        /// Path of the file on the ECU.
+        remote_path: String,
+        /// Size of the file once decompressed.  Defaults to the size of the local file.
+        #[clap(long)]
+        uncompressed_size: Option<u64>,
+    },
+
+    /// Deletes a file from the ECU.
+    Delete {
+        /// Path of the file on the ECU.
+        remote_path: String,
+    },
+
+    /// Reads a file from the ECU.
+    Read {
+        /// Path of the file on the ECU.
+        remote_path: String,
+        /// Local file to save to.
+        local_path: PathBuf,
+    },
+
+    /// Lists a directory on the ECU.
+    #[clap(name = "read-dir")]
+    ReadDir {
+        /// Path of the directory on the ECU.
+        remote_path: String,
+    },
+}
+
+#[derive(Args, Clone, Debug)]
+pub struct AuthenticateArgs {
+    #[clap(subcommand)]
+    pub action: AuthenticateAction,
+}
+
+#[derive(Clone, Debug, Subcommand)]
+pub enum AuthenticateAction {
+    /// Queries which authentication scheme the ECU supports.
+    Configuration,
+
+    /// Ends any authenticated state on the ECU.
+    Deauthenticate,
+
+    /// Exchanges certificates with the ECU and proves ownership of ours.
+    Certificate {
+        /// Whether the ECU also has to prove its identity to us.
+        #[clap(long, arg_enum, default_value_t = AuthenticationMode::Unidirectional)]
+        mode: AuthenticationMode,
+
+        /// Our certificate, in PEM or DER form.
+        #[clap(long)]
+        certificate: PathBuf,
+
+        /// Private key for our certificate, in PEM or DER form.  Must be a P-256 key in PKCS#8
+        /// form.
+        #[clap(long)]
+        private_key: PathBuf,
+    },
+}
+
+#[derive(Args, Clone, Debug)]
+pub struct DiscoverArgs {
+    /// How long to wait for responses after each functional request.
+    #[clap(long, parse(try_from_str = duration_str::parse), default_value = "1s")]
+    pub listen_timeout: Duration,
+
+    /// Range of identifiers to send physical requests to, as `<start>-<end>` in hexadecimal.
+    ///
+    /// For example, `0x700-0x7FF` for 11-bit identifiers, or `0x18DA00F1-0x18DAFFF1` (with a step
+    /// of `0x100`) for 29-bit normal fixed addressing.
+    #[clap(long)]
+    pub physical_range: Option<IdentifierRange>,
+
+    /// Step between identifiers in the physical range, in hexadecimal.
+    #[clap(long, parse(try_from_str = parse_hex_u32), default_value = "1")]
+    pub physical_step: u32,
+
+    /// How long to wait for a response after each physical request.
+    #[clap(long, parse(try_from_str = duration_str::parse), default_value = "50ms")]
+    pub probe_timeout: Duration,
+}
+
+#[derive(Args, Clone, Debug)]
+pub struct ScanDIDsArgs {
+    /// First data identifier to scan, in hexadecimal.
+    #[clap(long, parse(try_from_str = parse_hex_u16), default_value = "0000")]
+    pub start: u16,
+
+    /// Last data identifier to scan, in hexadecimal.
+    #[clap(long, parse(try_from_str = parse_hex_u16), default_value = "FFFF")]
+    pub end: u16,
+
+    /// Delay between requests.
+    #[clap(long, parse(try_from_str = duration_str::parse), default_value = "10ms")]
+    pub delay: Duration,
+
+    /// File to write results to.  If the file already exists, the scan resumes after the last
+    /// data identifier in it, as long as it holds a scan of the same range.
+    #[clap(long)]
+    pub output: PathBuf,
+}
+
+#[derive(Args, Clone, Debug)]
+pub struct ScanServicesArgs {
+    /// Diagnostic session to scan, in hexadecimal.  Sessions are switched to in the order given,
+    /// and any the ECU doesn't accept are skipped.
+    #[clap(
+        long = "session",
+        parse(try_from_str = parse_hex_u8),
+        default_values = &["01", "03", "02"]
+    )]
+    pub sessions: Vec<u8>,
+
+    /// Also probe how each security level answers a request for its seed in each session, telling
+    /// levels that are locked apart from those that aren't available in the session.
+    #[clap(long)]
+    pub security_levels: bool,
+
+    /// Program that computes the key for a security level's seed, letting levels be unlocked so
+    /// that services and sub-functions can be probed again behind them.  It's run with the level
+    /// and the seed as hexadecimal arguments, and should print the key in hexadecimal.  Levels
+    /// offering a seed are reported as locked without it.
+    #[clap(long, requires = "security-levels")]
+    pub key_command: Option<PathBuf>,
+}
+
+#[derive(Args, Clone, Debug)]
+pub struct FuzzArgs {
+    /// Seed for generating cases.  A random seed is used, and logged, if not specified.
+    #[clap(long)]
+    pub seed: Option<u64>,
+
+    /// Index of the first case to run.
+    #[clap(long, default_value_t = 0)]
+    pub start_case: u64,
+
+    /// Number of cases to run.
+    #[clap(long, default_value_t = 1000)]
+    pub cases: u64,
+
+    /// Mutation to generate cases with.  All mutations are used if not specified.
+    #[clap(
+        long = "mutation",
+        arg_enum,
+        default_values = &["random-length", "invalid-sub-function", "oversized"]
+    )]
+    pub mutations: Vec<Mutation>,
+
+    /// Diagnostic session to fuzz in, in hexadecimal.  Falling back to the default session is
+    /// treated as a reset.
+    #[clap(long, parse(try_from_str = parse_hex_u8))]
+    pub session: Option<u8>,
+
+    /// Delay between cases.
+    #[clap(long, parse(try_from_str = duration_str::parse), default_value = "10ms")]
+    pub delay: Duration,
+
+    /// How long to wait for the ECU to start responding again before giving up.
+    #[clap(long, parse(try_from_str = duration_str::parse), default_value = "10s")]
+    pub recovery_timeout: Duration,
+
+    /// Number of cases preceding a failure to record.
+    #[clap(long, default_value_t = 16)]
+    pub history: usize,
+
+    /// File to record failures to.
+    #[clap(long, default_value = "fuzz-failures.log")]
+    pub failure_log: PathBuf,
+}
+
+#[derive(Args, Clone, Debug)]
+pub struct KwpArgs {
+    /// Diagnostic session to start before anything else, in hexadecimal.  Stays in whichever
+    /// session the ECU is already in if not specified.
+    #[clap(long, parse(try_from_str = parse_hex_u8))]
+    pub session: Option<u8>,
+
+    #[clap(subcommand)]
+    pub action: KwpAction,
+}
+
+#[derive(Clone, Debug, Subcommand)]
+pub enum KwpAction {
+    /// Reads the records behind one or more local identifiers. (Service 0x21)
+    #[clap(name = "read-local-identifier")]
+    ReadLocalIdentifier {
+        /// Local identifier to read, in hexadecimal.
+        #[clap(required = true, parse(try_from_str = parse_hex_u8))]
+        identifiers: Vec<u8>,
+    },
+
+    /// Reads diagnostic trouble codes, along with their statuses. (Service 0x18)
+    #[clap(name = "read-dtcs")]
+    ReadDtcs {
+        /// Which DTCs to report, in hexadecimal: `00` for those currently identified, or `02` for
+        /// every identified DTC, including any only stored in memory.
+        #[clap(long, parse(try_from_str = parse_hex_u8), default_value = "02")]
+        status: u8,
+
+        /// Group of DTCs to report, in hexadecimal.  `FF00` covers all of them.
+        #[clap(long, parse(try_from_str = parse_hex_u16), default_value = "FF00")]
+        group: u16,
+    },
+
+    /// Requests the seed for a security level, and sends the key for it if one is given.
+    /// (Service 0x27)
+    #[clap(name = "security-access")]
+    SecurityAccess {
+        /// Security level, as the odd "request seed" access mode, in hexadecimal.
+        #[clap(long, parse(try_from_str = parse_hex_u8), default_value = "01")]
+        level: u8,
+
+        /// Key to send for the seed, in hexadecimal.
+        #[clap(long)]
+        key: Option<HexBytes>,
+    },
+}
+
+/// How we present ourselves on a J1939 bus.
+#[derive(Args, Clone, Debug)]
+pub struct J1939NodeArgs {
+    /// Address to claim on the J1939 bus, in hexadecimal.  An arbitrary address from `80` to `F7`
+    /// is claimed instead if a node with a higher-priority NAME already has it.
+    #[clap(long, parse(try_from_str = parse_hex_u8), default_value = "F9")]
+    pub claim_address: u8,
+
+    /// Identity number in our NAME, which has to differ from that of any other service tool on the
+    /// bus.
+    #[clap(long, default_value_t = 0)]
+    pub identity_number: u32,
+}
+
+#[derive(Args, Clone, Debug)]
+pub struct J1939RequestArgs {
+    #[clap(flatten)]
+    pub node: J1939NodeArgs,
+
+    /// Parameter group number to request, in decimal.
+    pub pgn: u32,
+
+    /// Address to request the parameter group from, in hexadecimal.  Requested from every node if
+    /// not specified.
+    #[clap(long, parse(try_from_str = parse_hex_u8), default_value = "FF")]
+    pub destination: u8,
+
+    /// How long to wait for responses.
+    #[clap(long, parse(try_from_str = duration_str::parse), default_value = "1s")]
+    pub listen_timeout: Duration,
+}
+
+#[derive(Args, Clone, Debug)]
+pub struct J1939DtcsArgs {
+    #[clap(flatten)]
+    pub node: J1939NodeArgs,
+
+    /// Address of the node to read DTCs from, in hexadecimal.  DTCs are read from every node if not
+    /// specified.
+    #[clap(long, parse(try_from_str = parse_hex_u8))]
+    pub source: Option<u8>,
+
+    /// How long to listen for DM1 broadcasts, and responses to requests.  Nodes broadcast DM1
+    /// once a second.
+    #[clap(long, parse(try_from_str = duration_str::parse), default_value = "2s")]
+    pub listen_timeout: Duration,
+
+    /// How to decode SPNs of DTCs that use one of the older conversion methods, which they don't
+    /// say which of.
+    #[clap(long, arg_enum, default_value_t = SpnConversion::Version1)]
+    pub spn_conversion: SpnConversion,
+
+    /// Clears active DTCs once they've been read. (DM11)
+    #[clap(long)]
+    pub clear_active: bool,
+
+    /// Clears previously active DTCs once they've been read. (DM3)
+    #[clap(long)]
+    pub clear_previously_active: bool,
+}
+
+#[derive(Args, Clone, Debug)]
+pub struct SimulateArgs {
+    /// Profile describing the ECUs to simulate.
+    #[clap(long)]
+    pub profile: PathBuf,
+}
+
+fn parse_tx_data_length(s: &str) -> Result<u8, String> {
+    match s.parse::<u8>() {
+        Ok(value) if is_fd_data_length(value as usize) => Ok(value),
+        Ok(value) => Err(format!("{} is not a valid frame length", value)),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+fn parse_nibble(s: &str) -> Result<u8, String> {
+    match s.parse::<u8>() {
+        Ok(value) if value <= 0x0F => Ok(value),
+        Ok(value) => Err(format!("{} does not fit within four bits", value)),
+        Err(e) => Err(e.to_string()),
+    }
 }
@@ -1,3 +1,6 @@
 pub mod addressing;
+pub mod backend;
 pub mod config;
 pub mod error;
+pub mod parse;
+pub mod targets;
@@ -0,0 +1,59 @@
+use std::{num::ParseIntError, str::FromStr};
+
+use can::identifier::Id;
+
+use super::addressing::id_from_raw;
+
+fn strip_hex_prefix(s: &str) -> &str {
+    s.strip_prefix("0x")
+        .or_else(|| s.strip_prefix("0X"))
+        .unwrap_or(s)
+}
+
+pub fn parse_hex_u8(s: &str) -> Result<u8, ParseIntError> {
+    u8::from_str_radix(strip_hex_prefix(s), 16)
+}
+
+pub fn parse_hex_u16(s: &str) -> Result<u16, ParseIntError> {
+    u16::from_str_radix(strip_hex_prefix(s), 16)
+}
+
+pub fn parse_hex_u32(s: &str) -> Result<u32, ParseIntError> {
+    u32::from_str_radix(strip_hex_prefix(s), 16)
+}
+
+/// Parses a CAN identifier from a hexadecimal string.
+pub fn parse_can_id(s: &str) -> Result<Id, String> {
+    let raw = parse_hex_u32(s).map_err(|e| e.to_string())?;
+    id_from_raw(raw).ok_or_else(|| format!("0x{:X} is not a valid CAN identifier", raw))
+}
+
+/// Parses bytes from a hexadecimal string, ignoring any whitespace between them.
+pub fn parse_hex_bytes(s: &str) -> Result<Vec<u8>, String> {
+    let digits = s.chars().filter(|c| !c.is_whitespace()).collect::<String>();
+    let digits = strip_hex_prefix(&digits);
+    if digits.is_empty() || digits.len() % 2 != 0 || !digits.chars().all(|c| c.is_ascii_hexdigit())
+    {
+        return Err(format!(
+            "'{}' is not a whole number of hexadecimal bytes",
+            s
+        ));
+    }
+
+    (0..digits.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&digits[i..i + 2], 16).map_err(|e| e.to_string()))
+        .collect()
+}
+
+/// Bytes given as a single hexadecimal argument, such as a security key.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct HexBytes(pub Vec<u8>);
+
+impl FromStr for HexBytes {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        parse_hex_bytes(s).map(Self)
+    }
+}
@@ -0,0 +1,118 @@
+//! Named targets, for ECUs whose request and response identifiers don't follow any convention.
+//!
+//! Targets are defined in a TOML file, keyed by name:
+//!
+//! ```toml
+//! [targets.gateway]
+//! request_id = "0x6F1"
+//! response_id = "0x6F9"
+//! ```
+//!
+//! As with the command line options, either identifier can be left out if it can be derived from
+//! the other.
+
+use std::{
+    collections::HashMap,
+    io,
+    path::{Path, PathBuf},
+};
+
+use can::identifier::Id;
+use serde::Deserialize;
+use thiserror::Error;
+
+use super::{
+    addressing::{RequestAddress, ResponseAddress},
+    parse::parse_can_id,
+};
+
+#[derive(Debug, Error)]
+pub enum TargetError {
+    #[error("failed to read targets file '{}': {source}", .path.display())]
+    Io { path: PathBuf, source: io::Error },
+    #[error("failed to parse targets file '{}': {source}", .path.display())]
+    Parse {
+        path: PathBuf,
+        source: toml::de::Error,
+    },
+    #[error("no target named '{0}' in targets file")]
+    UnknownTarget(String),
+    #[error("invalid {field_name} for target '{target}': {reason}")]
+    InvalidIdentifier {
+        target: String,
+        field_name: &'static str,
+        reason: String,
+    },
+    #[error("could not derive {field_name} for target, and none was given")]
+    Unresolved { field_name: &'static str },
+}
+
+/// An ECU, as the identifiers that requests are sent to and responses are received from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Target {
+    pub request_address: RequestAddress,
+    pub response_address: ResponseAddress,
+}
+
+impl Target {
+    /// Creates the target for an ECU behind a DoIP gateway, which has a single logical address
+    /// that requests are sent to and responses come from.
+    pub fn logical(id: Id) -> Self {
+        Self {
+            request_address: RequestAddress::new(id),
+            response_address: ResponseAddress::new(id),
+        }
+    }
+
+    /// Gets the logical address of the ECU, as it's known behind a DoIP gateway.
+    ///
+    /// Returns `None` if the request identifier doesn't fit in a logical address.
+    pub fn logical_address(&self) -> Option<u16> {
+        u16::try_from(self.request_address.id().as_raw()).ok()
+    }
+}
+
+#[derive(Deserialize)]
+struct TargetsFile {
+    #[serde(default)]
+    targets: HashMap<String, NamedTarget>,
+}
+
+#[derive(Deserialize)]
+struct NamedTarget {
+    request_id: Option<String>,
+    response_id: Option<String>,
+}
+
+/// Loads the request and response identifiers of the named target from the given targets file.
+pub fn load_named_target(path: &Path, name: &str) -> Result<(Option<Id>, Option<Id>), TargetError> {
+    let contents = std::fs::read_to_string(path).map_err(|source| TargetError::Io {
+        path: path.to_path_buf(),
+        source,
+    })?;
+    let mut file: TargetsFile = toml::from_str(&contents).map_err(|source| TargetError::Parse {
+        path: path.to_path_buf(),
+        source,
+    })?;
+
+    let target = file
+        .targets
+        .remove(name)
+        .ok_or_else(|| TargetError::UnknownTarget(name.to_string()))?;
+
+    let parse = |field_name, value: Option<String>| {
+        value
+            .map(|value| parse_can_id(&value))
+            .transpose()
+            .map_err(|reason| TargetError::InvalidIdentifier {
+                target: name.to_string(),
+                field_name,
+                reason,
+            })
+    };
+
+    Ok((
+        parse("request_id", target.request_id)?,
+        parse("response_id", target.response_id)?,
+    ))
+}
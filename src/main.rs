@@ -7,6 +7,7 @@ use tracing::Level;
 mod common;
 mod operations;
 mod protocol;
+mod simulator;
 
 #[tokio::main]
 async fn main() {
@@ -0,0 +1,62 @@
+use async_trait::async_trait;
+use tracing::{error, info};
+
+use super::Operation;
+use crate::{
+    common::{
+        config::{AuthenticateAction, AuthenticateArgs, CANParameters},
+        targets::Target,
+    },
+    protocol::uds::{
+        client::UdsClient,
+        services::{AuthenticationService, Credentials},
+    },
+};
+
+pub struct Authenticate {
+    args: AuthenticateArgs,
+    target: Target,
+}
+
+impl Authenticate {
+    pub fn new(args: AuthenticateArgs, target: Target) -> Self {
+        Self { args, target }
+    }
+}
+
+#[async_trait]
+impl Operation for Authenticate {
+    async fn run(self, can_parameters: CANParameters) {
+        let mut client = match UdsClient::connect(can_parameters, self.target).await {
+            Ok(client) => client,
+            Err(e) => return error!("Failed to connect to ECU: {}", e),
+        };
+        let mut service = AuthenticationService::new(&mut client);
+
+        match &self.args.action {
+            AuthenticateAction::Configuration => match service.configuration().await {
+                Ok(parameter) => info!("Authentication configuration: {}", parameter),
+                Err(e) => error!("Failed to query authentication configuration: {}", e),
+            },
+            AuthenticateAction::Deauthenticate => match service.deauthenticate().await {
+                Ok(parameter) => info!("Deauthenticated: {}", parameter),
+                Err(e) => error!("Failed to deauthenticate: {}", e),
+            },
+            AuthenticateAction::Certificate {
+                mode,
+                certificate,
+                private_key,
+            } => {
+                let credentials = match Credentials::load(certificate, private_key) {
+                    Ok(credentials) => credentials,
+                    Err(e) => return error!("Failed to load credentials: {}", e),
+                };
+
+                match service.authenticate(*mode, &credentials).await {
+                    Ok(parameter) => info!("Authenticated ({:?}): {}", mode, parameter),
+                    Err(e) => error!("Failed to authenticate: {}", e),
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,43 @@
+use async_trait::async_trait;
+use tracing::{error, info};
+
+use super::Operation;
+use crate::{
+    common::{
+        config::{CANParameters, ClearDynamicIdentifierArgs},
+        targets::Target,
+    },
+    protocol::uds::{client::UdsClient, services::DynamicDataService},
+};
+
+pub struct ClearDynamicIdentifier {
+    args: ClearDynamicIdentifierArgs,
+    target: Target,
+}
+
+impl ClearDynamicIdentifier {
+    pub fn new(args: ClearDynamicIdentifierArgs, target: Target) -> Self {
+        Self { args, target }
+    }
+}
+
+#[async_trait]
+impl Operation for ClearDynamicIdentifier {
+    async fn run(self, can_parameters: CANParameters) {
+        let mut client = match UdsClient::connect(can_parameters, self.target).await {
+            Ok(client) => client,
+            Err(e) => return error!("Failed to connect to ECU: {}", e),
+        };
+
+        match DynamicDataService::new(&mut client)
+            .clear(self.args.identifier)
+            .await
+        {
+            Ok(()) => match self.args.identifier {
+                Some(identifier) => info!("Cleared dynamic data identifier 0x{:04X}.", identifier),
+                None => info!("Cleared all dynamic data identifiers."),
+            },
+            Err(e) => error!("Failed to clear dynamic data identifier(s): {}", e),
+        }
+    }
+}
@@ -0,0 +1,69 @@
+use async_trait::async_trait;
+use tracing::{error, info};
+
+use super::Operation;
+use crate::{
+    common::config::{CANParameters, DiscoverArgs},
+    protocol::uds::services::DiscoveryService,
+};
+
+pub struct Discover {
+    args: DiscoverArgs,
+}
+
+impl Discover {
+    pub fn new(args: DiscoverArgs) -> Self {
+        Self { args }
+    }
+}
+
+#[async_trait]
+impl Operation for Discover {
+    async fn run(self, can_parameters: CANParameters) {
+        if self.args.physical_step == 0 {
+            return error!("Physical step must be greater than zero.");
+        }
+
+        let physical_range = self
+            .args
+            .physical_range
+            .map(|range| (range, self.args.physical_step));
+
+        let mut discovery_service = DiscoveryService::new(can_parameters);
+        match discovery_service
+            .discover(
+                self.args.listen_timeout,
+                physical_range,
+                self.args.probe_timeout,
+            )
+            .await
+        {
+            Ok(ecus) => {
+                if ecus.is_empty() {
+                    info!("No ECUs responded.")
+                } else {
+                    info!("Discovered {} ECU(s):", ecus.len());
+                    for ecu in ecus {
+                        let request_id = ecu
+                            .request_id
+                            .map(|id| id.to_string())
+                            .unwrap_or_else(|| "unknown".to_string());
+                        let strategies = ecu
+                            .strategies
+                            .iter()
+                            .map(|strategy| strategy.to_string())
+                            .collect::<Vec<_>>();
+
+                        info!(
+                            "  request ID {} / response ID {} (via {})",
+                            request_id,
+                            ecu.response_id,
+                            strategies.join(", ")
+                        );
+                    }
+                }
+            }
+            Err(e) => error!("Error occurred while discovering ECUs: {}", e),
+        }
+    }
+}
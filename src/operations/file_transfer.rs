@@ -0,0 +1,149 @@
+use std::path::Path;
+
+use async_trait::async_trait;
+use tracing::{error, info};
+
+use super::Operation;
+use crate::{
+    common::{
+        config::{CANParameters, FileTransferAction, FileTransferArgs},
+        targets::Target,
+    },
+    protocol::uds::{
+        client::UdsClient,
+        services::{DataFormat, FileTransferService},
+    },
+};
+
+pub struct FileTransfer {
+    args: FileTransferArgs,
+    target: Target,
+}
+
+impl FileTransfer {
+    pub fn new(args: FileTransferArgs, target: Target) -> Self {
+        Self { args, target }
+    }
+
+    fn data_format(&self) -> DataFormat {
+        DataFormat {
+            compression_method: self.args.compression_method,
+            encryption_method: self.args.encryption_method,
+        }
+    }
+
+    async fn upload(
+        &self,
+        service: &mut FileTransferService<'_>,
+        local_path: &Path,
+        remote_path: &str,
+        uncompressed_size: Option<u64>,
+        replace: bool,
+    ) {
+        let data = match tokio::fs::read(local_path).await {
+            Ok(data) => data,
+            Err(e) => return error!("Failed to read '{}': {}", local_path.display(), e),
+        };
+        let uncompressed_size = uncompressed_size.unwrap_or(data.len() as u64);
+
+        let result = if replace {
+            service
+                .replace_file(remote_path, &data, self.data_format(), uncompressed_size)
+                .await
+        } else {
+            service
+                .add_file(remote_path, &data, self.data_format(), uncompressed_size)
+                .await
+        };
+
+        match result {
+            Ok(()) => info!(
+                "Uploaded '{}' to '{}' ({} bytes).",
+                local_path.display(),
+                remote_path,
+                data.len()
+            ),
+            Err(e) => error!("Failed to upload '{}': {}", local_path.display(), e),
+        }
+    }
+}
+
+#[async_trait]
+impl Operation for FileTransfer {
+    async fn run(self, can_parameters: CANParameters) {
+        let mut client = match UdsClient::connect(can_parameters, self.target).await {
+            Ok(client) => client,
+            Err(e) => return error!("Failed to connect to ECU: {}", e),
+        };
+        let mut service = FileTransferService::new(&mut client);
+
+        match &self.args.action {
+            FileTransferAction::Add {
+                local_path,
+                remote_path,
+                uncompressed_size,
+            } => {
+                self.upload(
+                    &mut service,
+                    local_path,
+                    remote_path,
+                    *uncompressed_size,
+                    false,
+                )
+                .await
+            }
+            FileTransferAction::Replace {
+                local_path,
+                remote_path,
+                uncompressed_size,
+            } => {
+                self.upload(
+                    &mut service,
+                    local_path,
+                    remote_path,
+                    *uncompressed_size,
+                    true,
+                )
+                .await
+            }
+            FileTransferAction::Delete { remote_path } => {
+                match service.delete_file(remote_path).await {
+                    Ok(()) => info!("Deleted '{}'.", remote_path),
+                    Err(e) => error!("Failed to delete '{}': {}", remote_path, e),
+                }
+            }
+            FileTransferAction::Read {
+                remote_path,
+                local_path,
+            } => match service.read_file(remote_path, self.data_format()).await {
+                Ok(response) => {
+                    if let Err(e) = tokio::fs::write(local_path, &response.data).await {
+                        return error!("Failed to write '{}': {}", local_path.display(), e);
+                    }
+
+                    info!(
+                        "Downloaded '{}' to '{}' ({} bytes, {} bytes uncompressed, compression method {}, encryption method {}).",
+                        remote_path,
+                        local_path.display(),
+                        response.data.len(),
+                        response.uncompressed_size,
+                        response.data_format.compression_method,
+                        response.data_format.encryption_method,
+                    );
+                }
+                Err(e) => error!("Failed to read '{}': {}", remote_path, e),
+            },
+            FileTransferAction::ReadDir { remote_path } => {
+                match service.read_dir(remote_path).await {
+                    Ok(listing) => {
+                        info!("Directory listing for '{}':", remote_path);
+                        for line in String::from_utf8_lossy(&listing).lines() {
+                            info!("  {}", line);
+                        }
+                    }
+                    Err(e) => error!("Failed to read directory '{}': {}", remote_path, e),
+                }
+            }
+        }
+    }
+}
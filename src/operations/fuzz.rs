@@ -0,0 +1,278 @@
+use std::{collections::VecDeque, time::Duration};
+
+use async_trait::async_trait;
+use tokio::{
+    fs::OpenOptions,
+    io::AsyncWriteExt,
+    time::{sleep, Instant},
+};
+use tracing::{debug, error, info, warn};
+
+use super::Operation;
+use crate::{
+    common::{
+        config::{CANParameters, FuzzArgs},
+        targets::Target,
+    },
+    protocol::uds::{
+        client::UdsClient,
+        error::UdsError,
+        fuzz::{FuzzCase, FuzzCaseGenerator},
+        services::{ReadDataService, SessionControlService, TesterPresentService},
+    },
+};
+
+/// Data identifier holding the active diagnostic session.
+const ACTIVE_DIAGNOSTIC_SESSION_IDENTIFIER: u16 = 0xF186;
+
+/// How often to probe the ECU while waiting for it to recover.
+const RECOVERY_PROBE_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Ways in which the ECU can fail after a fuzz case.
+enum Failure {
+    /// The ECU stopped responding, but came back, or fell back to a different session.
+    Reset(String),
+    /// The ECU stopped responding and never came back.
+    Unresponsive,
+}
+
+pub struct Fuzz {
+    args: FuzzArgs,
+    target: Target,
+}
+
+impl Fuzz {
+    pub fn new(args: FuzzArgs, target: Target) -> Self {
+        Self { args, target }
+    }
+
+    async fn enter_session(&self, client: &mut UdsClient) -> Result<(), UdsError> {
+        if let Some(session) = self.args.session {
+            SessionControlService::new(client).change(session).await?;
+        }
+        Ok(())
+    }
+
+    /// Checks that the ECU is still alive, and still in the session we expect it to be in.
+    async fn check(&self, client: &mut UdsClient) -> Option<Failure> {
+        // A late response to the fuzz case itself can show up in place of the response to our
+        // probe, so we give it one more chance before assuming the worst.
+        let mut alive = false;
+        for _ in 0..2 {
+            match TesterPresentService::new(client).ping().await {
+                Ok(()) => {
+                    alive = true;
+                    break;
+                }
+                Err(e) if e.is_timeout() => break,
+                Err(e) => debug!("Unexpected response to probe: {}", e),
+            }
+        }
+
+        if !alive {
+            let started_at = Instant::now();
+            while started_at.elapsed() < self.args.recovery_timeout {
+                sleep(RECOVERY_PROBE_INTERVAL).await;
+                if TesterPresentService::new(client).ping().await.is_ok() {
+                    return Some(Failure::Reset(format!(
+                        "ECU stopped responding, recovered after {:?}",
+                        started_at.elapsed()
+                    )));
+                }
+            }
+
+            return Some(Failure::Unresponsive);
+        }
+
+        // ECUs fall back to the default session when they reset, so if we're supposed to be in a
+        // different session, make sure we still are.
+        if let Some(session) = self.args.session {
+            match ReadDataService::new(client)
+                .read(ACTIVE_DIAGNOSTIC_SESSION_IDENTIFIER)
+                .await
+            {
+                Ok(data) if data.first() != Some(&session) => {
+                    return Some(Failure::Reset(format!(
+                        "ECU fell back to session {:02X?}",
+                        data
+                    )))
+                }
+                Ok(_) => {}
+                Err(e) => debug!("Failed to read active diagnostic session: {}", e),
+            }
+        }
+
+        None
+    }
+
+    async fn record_failure(&self, seed: u64, history: &VecDeque<FuzzCase>, reason: &str) {
+        let first_case = history.front().map(|case| case.index).unwrap_or_default();
+        let mut entry = format!(
+            "# {} (seed {}, reproduce with {})\n",
+            reason,
+            seed,
+            reproduction_args(&self.args, seed, first_case, history.len())
+        );
+        for case in history {
+            entry.push_str(&format!(
+                "case {} {} {}\n",
+                case.index,
+                case.mutation,
+                case.payload
+                    .iter()
+                    .map(|b| format!("{:02X}", b))
+                    .collect::<String>()
+            ));
+        }
+
+        let log_path = &self.args.failure_log;
+        let result = async {
+            let mut file = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(log_path)
+                .await?;
+            file.write_all(entry.as_bytes()).await?;
+            file.flush().await
+        }
+        .await;
+
+        if let Err(e) = result {
+            error!("Failed to write '{}': {}", log_path.display(), e);
+        }
+    }
+}
+
+/// Arguments that regenerate the given cases, in the same session.
+fn reproduction_args(args: &FuzzArgs, seed: u64, first_case: u64, cases: usize) -> String {
+    let mut reproduction = format!(
+        "--seed {} --start-case {} --cases {}",
+        seed, first_case, cases
+    );
+    // Which mutation each case uses depends on which mutations it could choose from.
+    for mutation in &args.mutations {
+        reproduction.push_str(&format!(" --mutation {}", mutation));
+    }
+    if let Some(session) = args.session {
+        reproduction.push_str(&format!(" --session {:02X}", session));
+    }
+    reproduction
+}
+
+#[async_trait]
+impl Operation for Fuzz {
+    async fn run(self, can_parameters: CANParameters) {
+        if self.args.history == 0 {
+            return error!("History must hold at least one case.");
+        }
+
+        let mut client = match UdsClient::connect(can_parameters, self.target).await {
+            Ok(client) => client,
+            Err(e) => return error!("Failed to connect to ECU: {}", e),
+        };
+
+        if let Err(e) = self.enter_session(&mut client).await {
+            return error!("Failed to enter diagnostic session: {}", e);
+        }
+
+        let end_case = match self.args.start_case.checked_add(self.args.cases) {
+            Some(end_case) => end_case,
+            None => return error!("Cases must not run past case {}.", u64::MAX),
+        };
+
+        let seed = self.args.seed.unwrap_or_else(rand::random);
+        let generator = FuzzCaseGenerator::new(seed, self.args.mutations.clone());
+        info!(
+            "Fuzzing with seed {}, cases {} to {}...",
+            seed, self.args.start_case, end_case
+        );
+
+        let mut history = VecDeque::with_capacity(self.args.history);
+        let mut failures = 0;
+        for index in self.args.start_case..end_case {
+            let case = generator.generate(index);
+            debug!(
+                "Case {} ({}): {:02X?}",
+                case.index, case.mutation, &case.payload
+            );
+
+            // We don't care how the ECU responds to the case itself, only whether or not it
+            // survives it.
+            if let Err(e) = client.request(&case.payload).await {
+                debug!("Case {} response: {}", case.index, e);
+            }
+
+            if history.len() == self.args.history {
+                history.pop_front();
+            }
+            history.push_back(case);
+
+            match self.check(&mut client).await {
+                None => {}
+                Some(Failure::Reset(reason)) => {
+                    failures += 1;
+                    warn!("Failure after case {}: {}", index, reason);
+                    self.record_failure(seed, &history, &reason).await;
+                    history.clear();
+
+                    if let Err(e) = self.enter_session(&mut client).await {
+                        return error!("Failed to re-enter diagnostic session: {}", e);
+                    }
+                }
+                Some(Failure::Unresponsive) => {
+                    let reason = "ECU stopped responding and did not recover";
+                    error!("Failure after case {}: {}. Stopping.", index, reason);
+                    self.record_failure(seed, &history, reason).await;
+                    return;
+                }
+            }
+
+            sleep(self.args.delay).await;
+        }
+
+        info!(
+            "Fuzzing complete. {} failure(s) recorded to '{}'.",
+            failures,
+            self.args.failure_log.display()
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::Parser;
+
+    use super::*;
+
+    #[derive(Parser)]
+    struct FuzzCommand {
+        #[clap(flatten)]
+        args: FuzzArgs,
+    }
+
+    fn parse(args: &[&str]) -> FuzzArgs {
+        FuzzCommand::try_parse_from(["fuzz"].iter().chain(args))
+            .expect("arguments should be valid")
+            .args
+    }
+
+    #[test]
+    fn reproduction_args_regenerate_the_same_cases() {
+        let args = parse(&[
+            "--mutation",
+            "oversized",
+            "--mutation",
+            "random-length",
+            "--session",
+            "03",
+        ]);
+
+        let reproduction = reproduction_args(&args, 42, 100, 16);
+        let reproduced = parse(&reproduction.split_whitespace().collect::<Vec<_>>());
+        assert_eq!(reproduced.seed, Some(42));
+        assert_eq!(reproduced.start_case, 100);
+        assert_eq!(reproduced.cases, 16);
+        assert_eq!(reproduced.mutations, args.mutations);
+        assert_eq!(reproduced.session, Some(0x03));
+    }
+}
@@ -0,0 +1,149 @@
+use std::collections::BTreeSet;
+
+use async_trait::async_trait;
+use tokio::time::{timeout_at, Instant};
+use tracing::{error, info, warn};
+
+use super::Operation;
+use crate::{
+    common::config::{CANParameters, J1939DtcsArgs},
+    protocol::j1939::{
+        diagnostics::{DiagnosticMessage, DM11_PGN, DM1_PGN, DM2_PGN, DM3_PGN},
+        error::J1939Error,
+        name::Name,
+        socket::J1939Socket,
+        Acknowledgement, GLOBAL_ADDRESS,
+    },
+};
+
+pub struct J1939Dtcs {
+    args: J1939DtcsArgs,
+}
+
+impl J1939Dtcs {
+    pub fn new(args: J1939DtcsArgs) -> Self {
+        Self { args }
+    }
+
+    fn destination(&self) -> u8 {
+        self.args.source.unwrap_or(GLOBAL_ADDRESS)
+    }
+
+    /// Listens for DM1 broadcasts, and asks for DM2, logging each node's DTCs the first time they
+    /// arrive.
+    async fn read(&self, socket: &mut J1939Socket) -> Result<(), J1939Error> {
+        socket.request(DM2_PGN, self.destination()).await?;
+
+        let deadline = Instant::now() + self.args.listen_timeout;
+        let mut seen = BTreeSet::new();
+        loop {
+            let message = match timeout_at(deadline, socket.receive()).await {
+                Ok(message) => message?,
+                Err(_) => break,
+            };
+            if matches!(self.args.source, Some(source) if source != message.source_address) {
+                continue;
+            }
+
+            let kind = match message.pgn {
+                DM1_PGN => "Active",
+                DM2_PGN => "Previously active",
+                _ => {
+                    if let Some(acknowledgement) = Acknowledgement::of(&message, DM2_PGN) {
+                        warn!(
+                            "0x{:02X} answered the request for previously active DTCs: {}.",
+                            message.source_address, acknowledgement
+                        );
+                    }
+                    continue;
+                }
+            };
+            if !seen.insert((message.pgn, message.source_address)) {
+                continue;
+            }
+
+            match DiagnosticMessage::decode(&message.data, self.args.spn_conversion) {
+                Some(diagnostics) => {
+                    info!(
+                        "{} DTCs from 0x{:02X}: {} ({})",
+                        kind,
+                        message.source_address,
+                        diagnostics.dtcs.len(),
+                        diagnostics.lamps
+                    );
+                    for dtc in diagnostics.dtcs {
+                        info!("  {}", dtc);
+                    }
+                }
+                None => warn!(
+                    "Ignoring malformed diagnostic message from 0x{:02X}: {:02X?}",
+                    message.source_address, message.data
+                ),
+            }
+        }
+
+        if !seen.iter().any(|(pgn, _)| *pgn == DM1_PGN) {
+            info!("No DM1 broadcasts were seen.");
+        }
+        Ok(())
+    }
+
+    /// Requests that DTCs be cleared, logging every acknowledgement of it.
+    async fn clear(
+        &self,
+        socket: &mut J1939Socket,
+        pgn: u32,
+        kind: &str,
+    ) -> Result<(), J1939Error> {
+        socket.request(pgn, self.destination()).await?;
+
+        let deadline = Instant::now() + self.args.listen_timeout;
+        let mut acknowledged = false;
+        loop {
+            let message = match timeout_at(deadline, socket.receive()).await {
+                Ok(message) => message?,
+                Err(_) => break,
+            };
+
+            if let Some(acknowledgement) = Acknowledgement::of(&message, pgn) {
+                info!(
+                    "0x{:02X} answered the request to clear {} DTCs: {}.",
+                    message.source_address, kind, acknowledgement
+                );
+                acknowledged = true;
+            }
+        }
+
+        if !acknowledged {
+            info!("Nothing acknowledged the request to clear {} DTCs.", kind);
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Operation for J1939Dtcs {
+    async fn run(self, can_parameters: CANParameters) {
+        let name = Name::service_tool(self.args.node.identity_number);
+        let mut socket =
+            match J1939Socket::open(can_parameters, name, self.args.node.claim_address).await {
+                Ok(socket) => socket,
+                Err(e) => return error!("Failed to join the J1939 bus: {}", e),
+            };
+
+        if let Err(e) = self.read(&mut socket).await {
+            return error!("Failed to read DTCs: {}", e);
+        }
+
+        if self.args.clear_active {
+            if let Err(e) = self.clear(&mut socket, DM11_PGN, "active").await {
+                return error!("Failed to clear active DTCs: {}", e);
+            }
+        }
+        if self.args.clear_previously_active {
+            if let Err(e) = self.clear(&mut socket, DM3_PGN, "previously active").await {
+                error!("Failed to clear previously active DTCs: {}", e);
+            }
+        }
+    }
+}
@@ -0,0 +1,70 @@
+use async_trait::async_trait;
+use tokio::time::{timeout_at, Instant};
+use tracing::{error, info, warn};
+
+use super::Operation;
+use crate::{
+    common::config::{CANParameters, J1939RequestArgs},
+    protocol::j1939::{name::Name, socket::J1939Socket, Acknowledgement, GLOBAL_ADDRESS},
+};
+
+pub struct J1939Request {
+    args: J1939RequestArgs,
+}
+
+impl J1939Request {
+    pub fn new(args: J1939RequestArgs) -> Self {
+        Self { args }
+    }
+}
+
+#[async_trait]
+impl Operation for J1939Request {
+    async fn run(self, can_parameters: CANParameters) {
+        let name = Name::service_tool(self.args.node.identity_number);
+        let mut socket =
+            match J1939Socket::open(can_parameters, name, self.args.node.claim_address).await {
+                Ok(socket) => socket,
+                Err(e) => return error!("Failed to join the J1939 bus: {}", e),
+            };
+
+        let pgn = self.args.pgn;
+        if let Err(e) = socket.request(pgn, self.args.destination).await {
+            return error!("Failed to request PGN {}: {}", pgn, e);
+        }
+
+        let deadline = Instant::now() + self.args.listen_timeout;
+        let mut responders = 0;
+        loop {
+            let message = match timeout_at(deadline, socket.receive()).await {
+                Ok(Ok(message)) => message,
+                Ok(Err(e)) => return error!("Failed to receive responses: {}", e),
+                Err(_) => break,
+            };
+
+            if message.pgn == pgn {
+                info!(
+                    "PGN {} from 0x{:02X}: {:02X?}",
+                    pgn, message.source_address, message.data
+                );
+                responders += 1;
+            } else if let Some(acknowledgement) = Acknowledgement::of(&message, pgn) {
+                // Nodes only acknowledge requests sent to them in particular.
+                warn!(
+                    "0x{:02X} answered the request for PGN {}: {}.",
+                    message.source_address, pgn, acknowledgement
+                );
+                responders += 1;
+            }
+        }
+
+        if responders == 0 {
+            let destination = if self.args.destination == GLOBAL_ADDRESS {
+                "any node".to_string()
+            } else {
+                format!("0x{:02X}", self.args.destination)
+            };
+            info!("No response to PGN {} from {}.", pgn, destination);
+        }
+    }
+}
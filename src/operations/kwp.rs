@@ -0,0 +1,122 @@
+use async_trait::async_trait;
+use tracing::{error, info, warn};
+
+use super::Operation;
+use crate::{
+    common::{
+        config::{CANParameters, KwpAction, KwpArgs},
+        targets::Target,
+    },
+    protocol::kwp::{
+        client::KwpClient,
+        error::NegativeResponseCode,
+        services::{
+            ReadDtcsService, ReadLocalDataService, SecurityAccessService, SessionControlService,
+        },
+    },
+};
+
+pub struct Kwp {
+    args: KwpArgs,
+    target: Target,
+}
+
+impl Kwp {
+    pub fn new(args: KwpArgs, target: Target) -> Self {
+        Self { args, target }
+    }
+}
+
+#[async_trait]
+impl Operation for Kwp {
+    async fn run(self, can_parameters: CANParameters) {
+        let mut client = match KwpClient::connect(can_parameters, self.target).await {
+            Ok(client) => client,
+            Err(e) => return error!("Failed to connect to ECU: {}", e),
+        };
+
+        if let Some(session) = self.args.session {
+            match SessionControlService::new(&mut client).start(session).await {
+                Ok(()) => info!("Started diagnostic session 0x{:02X}.", session),
+                Err(e) => {
+                    return error!(
+                        "Failed to start diagnostic session 0x{:02X}: {}",
+                        session, e
+                    )
+                }
+            }
+        }
+
+        match &self.args.action {
+            KwpAction::ReadLocalIdentifier { identifiers } => {
+                let mut service = ReadLocalDataService::new(&mut client);
+                for identifier in identifiers {
+                    match service.read(*identifier).await {
+                        Ok(record) => {
+                            info!("Local identifier 0x{:02X}: {:02X?}", identifier, record)
+                        }
+                        Err(e) => {
+                            error!(
+                                "Failed to read local identifier 0x{:02X}: {}",
+                                identifier, e
+                            )
+                        }
+                    }
+                }
+            }
+            KwpAction::ReadDtcs { status, group } => {
+                match ReadDtcsService::new(&mut client)
+                    .read(*status, *group)
+                    .await
+                {
+                    Ok(dtcs) if dtcs.is_empty() => info!("No DTCs reported."),
+                    Ok(dtcs) => {
+                        for dtc in dtcs {
+                            info!(
+                                "{} (status 0x{:02X}, {}, warning lamp {})",
+                                dtc,
+                                dtc.status,
+                                if dtc.is_present() {
+                                    "present"
+                                } else {
+                                    "not present"
+                                },
+                                if dtc.is_warning_lamp_on() {
+                                    "on"
+                                } else {
+                                    "off"
+                                },
+                            );
+                        }
+                    }
+                    Err(e) => error!("Failed to read DTCs: {}", e),
+                }
+            }
+            KwpAction::SecurityAccess { level, key } => {
+                let mut service = SecurityAccessService::new(&mut client);
+                let seed = match service.request_seed(*level).await {
+                    Ok(seed) => seed,
+                    Err(e) => {
+                        if e.negative_response_code()
+                            == Some(NegativeResponseCode::RequiredTimeDelayNotExpired)
+                        {
+                            warn!("ECU is locked out after failed attempts; wait before retrying.");
+                        }
+                        return error!("Failed to request seed for level 0x{:02X}: {}", level, e);
+                    }
+                };
+                if seed.iter().all(|b| *b == 0) {
+                    return info!("Security level 0x{:02X} is already unlocked.", level);
+                }
+                info!("Seed for security level 0x{:02X}: {:02X?}", level, seed);
+
+                if let Some(key) = key {
+                    match service.send_key(*level, &key.0).await {
+                        Ok(()) => info!("Security level 0x{:02X} unlocked.", level),
+                        Err(e) => error!("Key for level 0x{:02X} was not accepted: {}", level, e),
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,156 @@
+use std::time::Instant;
+
+use async_trait::async_trait;
+use futures::StreamExt;
+use tokio::{pin, select, signal::ctrl_c, time::sleep};
+use tracing::{error, info, warn};
+
+use super::Operation;
+use crate::{
+    common::{
+        addressing::ResponseAddress,
+        config::{CANParameters, LogPeriodicArgs},
+        targets::Target,
+    },
+    protocol::{
+        can::error::SocketError,
+        uds::{
+            client::UdsClient,
+            error::UdsError,
+            services::{DynamicDataService, PeriodicDataService, PeriodicListener},
+        },
+    },
+};
+
+pub struct LogPeriodic {
+    args: LogPeriodicArgs,
+    target: Target,
+}
+
+impl LogPeriodic {
+    pub fn new(args: LogPeriodicArgs, target: Target) -> Self {
+        Self { args, target }
+    }
+
+    async fn define_identifier(
+        &self,
+        client: &mut UdsClient,
+        identifier: u16,
+    ) -> Result<(), UdsError> {
+        let mut dynamic_data_service = DynamicDataService::new(client);
+        if !self.args.source_memory.is_empty() {
+            dynamic_data_service
+                .define_by_memory_address(identifier, &self.args.source_memory)
+                .await
+        } else {
+            dynamic_data_service
+                .define_by_identifier(identifier, &self.args.source_identifiers)
+                .await
+        }
+    }
+}
+
+#[async_trait]
+impl Operation for LogPeriodic {
+    async fn run(self, can_parameters: CANParameters) {
+        let mut client = match UdsClient::connect(can_parameters.clone(), self.target).await {
+            Ok(client) => client,
+            Err(e) => return error!("Failed to connect to ECU: {}", e),
+        };
+
+        let mut periodic_identifiers = self.args.periodic_identifiers.clone();
+        if let Some(identifier) = self.args.define_identifier {
+            if identifier & 0xFF00 != 0xF200 {
+                return error!(
+                    "Dynamic data identifier 0x{:04X} is not a periodic data identifier.",
+                    identifier
+                );
+            }
+
+            if let Err(e) = self.define_identifier(&mut client, identifier).await {
+                return error!("Failed to define dynamic data identifier: {}", e);
+            }
+
+            info!("Defined dynamic data identifier 0x{:04X}.", identifier);
+            periodic_identifiers.push(identifier as u8);
+        }
+
+        if periodic_identifiers.is_empty() {
+            return error!("No periodic data identifiers to log.");
+        }
+
+        // Start listening before we ask for periodic transmission so that we don't miss anything.
+        let listener = match PeriodicListener::open(
+            can_parameters,
+            ResponseAddress::new(self.args.periodic_response_id),
+            self.args.frame_format,
+        ) {
+            Ok(listener) => listener,
+            Err(e) => return error!("Failed to open periodic response listener: {}", e),
+        };
+
+        if let Err(e) = PeriodicDataService::new(&mut client)
+            .start(self.args.rate, &periodic_identifiers)
+            .await
+        {
+            return error!("Failed to start periodic transmission: {}", e);
+        }
+
+        info!(
+            "Logging periodic data identifiers {:02X?} at {:?} rate...",
+            &periodic_identifiers[..],
+            self.args.rate
+        );
+
+        let started_at = Instant::now();
+        let records = listener.into_stream();
+        pin!(records);
+
+        let stop_logging = async {
+            match self.args.duration {
+                Some(duration) => sleep(duration).await,
+                None => {
+                    let _ = ctrl_c().await;
+                }
+            }
+        };
+        pin!(stop_logging);
+
+        loop {
+            select! {
+                _ = &mut stop_logging => break,
+                record = records.next() => match record {
+                    Some(Ok(record)) => info!(
+                        "[{:>10.3}s] 0x{:04X}: {:02X?}",
+                        record.received_at.duration_since(started_at).as_secs_f64(),
+                        record.identifier,
+                        &record.data[..]
+                    ),
+                    // Slower transmission rates can easily exceed the read timeout, so we don't
+                    // treat that as fatal.
+                    Some(Err(UdsError::Io(SocketError::Timeout(duration)))) => {
+                        warn!("No periodic responses received in the last {:?}.", duration)
+                    }
+                    Some(Err(e)) => warn!("Failed to decode periodic response: {}", e),
+                    None => break,
+                },
+            }
+        }
+
+        if let Err(e) = PeriodicDataService::new(&mut client)
+            .stop(&periodic_identifiers)
+            .await
+        {
+            error!("Failed to stop periodic transmission: {}", e);
+        }
+
+        if let Some(identifier) = self.args.define_identifier {
+            if let Err(e) = DynamicDataService::new(&mut client)
+                .clear(Some(identifier))
+                .await
+            {
+                error!("Failed to clear dynamic data identifier: {}", e);
+            }
+        }
+    }
+}
@@ -1,10 +1,37 @@
 use async_trait::async_trait;
+use tracing::{error, info};
 
-use crate::common::config::{AppConfig, CANParameters, Command};
+use crate::{
+    common::{
+        addressing::{Addressing, AddressingMode},
+        backend::Backend,
+        config::{AppConfig, CANParameters, Command},
+        targets::Target,
+    },
+    protocol::{obd::services::CurrentDataService, transport::open_transport},
+};
 
-use self::{query_available_pids::QueryAvailablePIDs, validate_socket::ValidateSocket};
+use self::{
+    authenticate::Authenticate, clear_dynamic_identifier::ClearDynamicIdentifier,
+    discover::Discover, file_transfer::FileTransfer, fuzz::Fuzz, j1939_dtcs::J1939Dtcs,
+    j1939_request::J1939Request, kwp::Kwp, log_periodic::LogPeriodic,
+    query_available_pids::QueryAvailablePIDs, scan_dids::ScanDIDs, scan_services::ScanServices,
+    simulate::Simulate, validate_socket::ValidateSocket,
+};
 
+mod authenticate;
+mod clear_dynamic_identifier;
+mod discover;
+mod file_transfer;
+mod fuzz;
+mod j1939_dtcs;
+mod j1939_request;
+mod kwp;
+mod log_periodic;
 mod query_available_pids;
+mod scan_dids;
+mod scan_services;
+mod simulate;
 mod validate_socket;
 
 #[async_trait]
@@ -13,14 +40,194 @@ pub trait Operation {
 }
 
 pub async fn run_operation(config: &AppConfig) {
+    let mut can_parameters = config.can_parameters();
+
+    if needs_raw_access(&config.command()) && !can_parameters.backend.has_raw_access() {
+        return error!(
+            "This operation needs raw CAN access, which the {} backend doesn't provide.",
+            can_parameters.backend
+        );
+    }
+
+    // Validating the socket doesn't talk to any ECUs, simulated ECUs are the ones being looked
+    // for, and J1939 nodes are found by the addresses they claim, so there's nothing to detect or
+    // target for any of them.
     match config.command() {
         Command::ValidateSocket => {
             let validate_socket = ValidateSocket::default();
-            validate_socket.run(config.can_parameters()).await
+            return validate_socket.run(can_parameters).await;
+        }
+        Command::Simulate(args) => {
+            let simulate = Simulate::new(args);
+            return simulate.run(can_parameters).await;
+        }
+        Command::J1939Request(args) => {
+            let j1939_request = J1939Request::new(args);
+            return j1939_request.run(can_parameters).await;
+        }
+        Command::J1939Dtcs(args) => {
+            let j1939_dtcs = J1939Dtcs::new(args);
+            return j1939_dtcs.run(can_parameters).await;
         }
+        _ => {}
+    }
+
+    if derives_identifiers(config, &can_parameters) && !detect_addressing(&mut can_parameters).await
+    {
+        return;
+    }
+
+    let target = match config.target(&can_parameters) {
+        Ok(target) => target,
+        Err(e) => return error!("Failed to resolve target: {}", e),
+    };
+
+    // Anything else sent, such as functional requests, uses the same width as the identifiers that
+    // were given.
+    if let (AddressingMode::Auto, Some(target)) = (can_parameters.addressing_mode, target) {
+        can_parameters.set_addressing(Addressing::of(target.request_address.id()));
+    }
+
+    match config.command() {
         Command::QueryAvailablePIDs => {
-            let query_available_pids = QueryAvailablePIDs::default();
-            query_available_pids.run(config.can_parameters()).await
+            let query_available_pids = QueryAvailablePIDs::new(target);
+            query_available_pids.run(can_parameters).await
+        }
+        Command::LogPeriodic(args) => {
+            if let Some(target) = require_target(target) {
+                let log_periodic = LogPeriodic::new(args, target);
+                log_periodic.run(can_parameters).await
+            }
+        }
+        Command::ClearDynamicIdentifier(args) => {
+            if let Some(target) = require_target(target) {
+                let clear_dynamic_identifier = ClearDynamicIdentifier::new(args, target);
+                clear_dynamic_identifier.run(can_parameters).await
+            }
+        }
+        Command::FileTransfer(args) => {
+            if let Some(target) = require_target(target) {
+                let file_transfer = FileTransfer::new(args, target);
+                file_transfer.run(can_parameters).await
+            }
+        }
+        Command::Authenticate(args) => {
+            if let Some(target) = require_target(target) {
+                let authenticate = Authenticate::new(args, target);
+                authenticate.run(can_parameters).await
+            }
+        }
+        Command::Discover(args) => {
+            let discover = Discover::new(args);
+            discover.run(can_parameters).await
+        }
+        Command::ScanDIDs(args) => {
+            if let Some(target) = require_target(target) {
+                let scan_dids = ScanDIDs::new(args, target);
+                scan_dids.run(can_parameters).await
+            }
+        }
+        Command::ScanServices(args) => {
+            if let Some(target) = require_target(target) {
+                let scan_services = ScanServices::new(args, target);
+                scan_services.run(can_parameters).await
+            }
         }
+        Command::Fuzz(args) => {
+            if let Some(target) = require_target(target) {
+                let fuzz = Fuzz::new(args, target);
+                fuzz.run(can_parameters).await
+            }
+        }
+        Command::Kwp(args) => {
+            if let Some(target) = require_target(target) {
+                let kwp = Kwp::new(args, target);
+                kwp.run(can_parameters).await
+            }
+        }
+        Command::ValidateSocket
+        | Command::Simulate(_)
+        | Command::J1939Request(_)
+        | Command::J1939Dtcs(_) => {
+            unreachable!("operations without targets should have started already")
+        }
+    }
+}
+
+/// Whether the given operation sends or receives raw CAN frames, rather than only diagnostic
+/// requests and their responses.
+fn needs_raw_access(command: &Command) -> bool {
+    matches!(
+        command,
+        Command::ValidateSocket
+            | Command::LogPeriodic(_)
+            | Command::Discover(_)
+            | Command::Simulate(_)
+            | Command::J1939Request(_)
+            | Command::J1939Dtcs(_)
+    )
+}
+
+/// Ensures that a target was specified, for operations that talk to a single ECU.
+fn require_target(target: Option<Target>) -> Option<Target> {
+    if target.is_none() {
+        error!(
+            "No target specified.  Use `--request-id`/`--response-id`, `--target`, or `--target-address`."
+        );
+    }
+    target
+}
+
+/// Whether the operation derives CAN identifiers from the addressing, so that it has to be
+/// detected first when it's to be detected automatically.
+///
+/// ECUs behind a DoIP gateway are reached by their logical addresses, and identifiers given
+/// explicitly are used as they are, but discovery always derives the identifiers it probes.
+fn derives_identifiers(config: &AppConfig, can_parameters: &CANParameters) -> bool {
+    match config.command() {
+        _ if matches!(can_parameters.backend, Backend::DoIP { .. }) => false,
+        Command::Discover(_) => true,
+        _ => !config.has_explicit_identifiers(),
+    }
+}
+
+/// Detects the width of the CAN identifiers used for diagnostics, if it's to be detected
+/// automatically, so that every operation can use it from then on.
+///
+/// Per the ISO 15765-4 initialization sequence, a functional request for the supported PIDs is
+/// broadcast with 11-bit identifiers, and then with 29-bit identifiers if nothing answered.
+async fn detect_addressing(can_parameters: &mut CANParameters) -> bool {
+    if !matches!(can_parameters.addressing_mode, AddressingMode::Auto) {
+        return true;
     }
+
+    info!("Detecting OBD-II protocol...");
+    for addressing in [Addressing::Standard, Addressing::Extended] {
+        let mut candidate_parameters = can_parameters.clone();
+        candidate_parameters.set_addressing(addressing);
+
+        let transport = match open_transport(candidate_parameters, None).await {
+            Ok(transport) => transport,
+            Err(e) => {
+                error!("Failed to detect OBD-II protocol: {}", e);
+                return false;
+            }
+        };
+        let mut current_data_service = CurrentDataService::new(transport);
+        match current_data_service.has_responders().await {
+            Ok(true) => {
+                info!("Detected ISO 15765-4 CAN with {}.", addressing);
+                can_parameters.set_addressing(addressing);
+                return true;
+            }
+            Ok(false) => {}
+            Err(e) => {
+                error!("Failed to detect OBD-II protocol: {}", e);
+                return false;
+            }
+        }
+    }
+
+    error!("No ECUs responded with either 11-bit or 29-bit identifiers.");
+    false
 }
@@ -2,16 +2,30 @@ use async_trait::async_trait;
 use tracing::{error, info};
 
 use super::Operation;
-use crate::{common::config::CANParameters, protocol::obd::services::CurrentDataService};
+use crate::{
+    common::{config::CANParameters, targets::Target},
+    protocol::{obd::services::CurrentDataService, transport::open_transport},
+};
 
-#[derive(Default)]
-pub struct QueryAvailablePIDs;
+pub struct QueryAvailablePIDs {
+    target: Option<Target>,
+}
+
+impl QueryAvailablePIDs {
+    pub fn new(target: Option<Target>) -> Self {
+        Self { target }
+    }
+}
 
 #[async_trait]
 impl Operation for QueryAvailablePIDs {
     async fn run(self, can_parameters: CANParameters) {
-        let mut current_data_service = CurrentDataService::new(can_parameters);
-        match current_data_service.query_available_pids().await {
+        let transport = match open_transport(can_parameters, None).await {
+            Ok(transport) => transport,
+            Err(e) => return error!("Failed to open transport: {}", e),
+        };
+        let mut current_data_service = CurrentDataService::new(transport);
+        match current_data_service.query_available_pids(self.target).await {
             Ok(pid_map) => {
                 if pid_map.is_empty() {
                     info!("No available PIDs found.")
@@ -0,0 +1,235 @@
+use std::path::Path;
+
+use async_trait::async_trait;
+use tokio::{
+    fs::{self, OpenOptions},
+    io::AsyncWriteExt,
+    time::sleep,
+};
+use tracing::{error, info};
+
+use super::Operation;
+use crate::{
+    common::{
+        config::{CANParameters, ScanDIDsArgs},
+        parse::parse_hex_u16,
+        targets::Target,
+    },
+    protocol::uds::{
+        client::UdsClient,
+        services::{DataIdentifierStatus, ReadDataService},
+    },
+};
+
+const RANGE_PREFIX: &str = "# range: ";
+const RESULTS_HEADER: &str = "# identifier,status,nrc,data\n";
+
+/// How often, in data identifiers, to log scan progress.
+const PROGRESS_INTERVAL: u32 = 0x100;
+
+pub struct ScanDIDs {
+    args: ScanDIDsArgs,
+    target: Target,
+}
+
+impl ScanDIDs {
+    pub fn new(args: ScanDIDsArgs, target: Target) -> Self {
+        Self { args, target }
+    }
+}
+
+#[async_trait]
+impl Operation for ScanDIDs {
+    async fn run(self, can_parameters: CANParameters) {
+        if self.args.start > self.args.end {
+            return error!("Start identifier must not be greater than end identifier.");
+        }
+
+        // The results file doubles as our checkpoint: every scanned identifier is written to it,
+        // so we can pick up right after the last one if the scan is interrupted.  Only scans of
+        // the same range are resumed, as otherwise the file wouldn't tell us what's left to scan.
+        let output = &self.args.output;
+        let range = (self.args.start, self.args.end);
+        let start = match read_checkpoint(output).await {
+            Ok(None) => self.args.start,
+            Ok(Some(checkpoint)) if checkpoint.range != Some(range) => {
+                return error!(
+                    "'{}' holds the results of a scan of a different range, so it can't be \
+                     resumed.  Scan the same range, or write to another file.",
+                    output.display()
+                )
+            }
+            Ok(Some(Checkpoint {
+                last_scanned: Some(last),
+                ..
+            })) if last >= self.args.end => {
+                return info!("Scan of '{}' is already complete.", output.display())
+            }
+            Ok(Some(Checkpoint {
+                last_scanned: Some(last),
+                ..
+            })) => {
+                let start = self.args.start.max(last + 1);
+                info!("Resuming scan from 0x{:04X}.", start);
+                start
+            }
+            Ok(Some(_)) => self.args.start,
+            Err(e) => return error!("Failed to read '{}': {}", output.display(), e),
+        };
+
+        let mut results = match OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(output)
+            .await
+        {
+            Ok(file) => file,
+            Err(e) => return error!("Failed to open '{}': {}", output.display(), e),
+        };
+        let is_empty = match results.metadata().await {
+            Ok(metadata) => metadata.len() == 0,
+            Err(e) => return error!("Failed to read '{}': {}", output.display(), e),
+        };
+        if is_empty {
+            let header = format!(
+                "{}0x{:04X}-0x{:04X}\n{}",
+                RANGE_PREFIX, self.args.start, self.args.end, RESULTS_HEADER
+            );
+            if let Err(e) = results.write_all(header.as_bytes()).await {
+                return error!("Failed to write '{}': {}", output.display(), e);
+            }
+        }
+
+        let mut client = match UdsClient::connect(can_parameters, self.target).await {
+            Ok(client) => client,
+            Err(e) => return error!("Failed to connect to ECU: {}", e),
+        };
+        let mut service = ReadDataService::new(&mut client);
+
+        info!(
+            "Scanning data identifiers 0x{:04X} to 0x{:04X}...",
+            start, self.args.end
+        );
+
+        let mut supported = 0;
+        for identifier in start..=self.args.end {
+            let status = match DataIdentifierStatus::classify(service.read(identifier).await) {
+                Ok(status) => status,
+                Err(e) => {
+                    return error!(
+                        "Error occurred while reading 0x{:04X}, stopping scan: {}",
+                        identifier, e
+                    )
+                }
+            };
+
+            if status.is_supported() {
+                supported += 1;
+                info!("0x{:04X}: {}", identifier, status);
+            }
+
+            let data = match &status {
+                DataIdentifierStatus::Positive(data) => hex_string(data),
+                _ => String::new(),
+            };
+            let nrc = match status.negative_response_code() {
+                Some(code) => format!("0x{:02X}", code.as_byte()),
+                None => String::new(),
+            };
+            let line = format!("0x{:04X},{},{},{}\n", identifier, status, nrc, data);
+            if let Err(e) = results.write_all(line.as_bytes()).await {
+                return error!("Failed to write '{}': {}", output.display(), e);
+            }
+
+            if (identifier as u32 + 1) % PROGRESS_INTERVAL == 0 {
+                info!("Scanned up to 0x{:04X}...", identifier);
+            }
+
+            sleep(self.args.delay).await;
+        }
+
+        if let Err(e) = results.flush().await {
+            return error!("Failed to write '{}': {}", output.display(), e);
+        }
+
+        info!(
+            "Scan complete.  Found {} supported data identifier(s) in this run.",
+            supported
+        );
+    }
+}
+
+/// What an existing results file says about the scan that wrote it.
+#[derive(Debug, PartialEq, Eq)]
+struct Checkpoint {
+    range: Option<(u16, u16)>,
+    last_scanned: Option<u16>,
+}
+
+/// Reads the checkpoint from a results file, if there's anything in it.
+async fn read_checkpoint(path: &Path) -> std::io::Result<Option<Checkpoint>> {
+    if !path.exists() {
+        return Ok(None);
+    }
+
+    let contents = fs::read_to_string(path).await?;
+    Ok(parse_checkpoint(&contents))
+}
+
+fn parse_checkpoint(contents: &str) -> Option<Checkpoint> {
+    if contents.trim().is_empty() {
+        return None;
+    }
+
+    let range = contents
+        .lines()
+        .find_map(|line| line.strip_prefix(RANGE_PREFIX))
+        .and_then(|range| range.split_once('-'))
+        .and_then(|(start, end)| Some((parse_hex_u16(start).ok()?, parse_hex_u16(end).ok()?)));
+    let last_scanned = contents
+        .lines()
+        .filter(|line| !line.starts_with('#'))
+        .filter_map(|line| line.split(',').next())
+        .filter_map(|identifier| parse_hex_u16(identifier).ok())
+        .max();
+
+    Some(Checkpoint {
+        range,
+        last_scanned,
+    })
+}
+
+fn hex_string(data: &[u8]) -> String {
+    data.iter().map(|b| format!("{:02X}", b)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_checkpoints() {
+        assert_eq!(parse_checkpoint(""), None);
+        assert_eq!(
+            parse_checkpoint(
+                "# range: 0xF100-0xF1FF\n\
+                 # identifier,status,nrc,data\n\
+                 0xF100,requestOutOfRange,0x31,\n\
+                 0xF101,positive,,1234\n"
+            ),
+            Some(Checkpoint {
+                range: Some((0xF100, 0xF1FF)),
+                last_scanned: Some(0xF101),
+            })
+        );
+
+        // Results written before the range was recorded can't be resumed.
+        assert_eq!(
+            parse_checkpoint("# identifier,status,data\n0x0000,noResponse,\n"),
+            Some(Checkpoint {
+                range: None,
+                last_scanned: Some(0x0000),
+            })
+        );
+    }
+}
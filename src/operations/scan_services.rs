@@ -0,0 +1,485 @@
+use core::fmt;
+use std::path::Path;
+
+use async_trait::async_trait;
+use tokio::process::Command;
+use tracing::{debug, error, info, warn};
+
+use super::Operation;
+use crate::{
+    common::{
+        config::{CANParameters, ScanServicesArgs},
+        parse::parse_hex_bytes,
+        targets::Target,
+    },
+    protocol::uds::{
+        client::UdsClient,
+        error::UdsError,
+        services::{
+            SecurityAccessService, ServiceProbe, ServiceStatus, SessionControlService,
+            DEFAULT_SESSION,
+        },
+    },
+};
+
+/// Highest "request seed" sub-function outside of the ranges reserved for ISO 26021 and
+/// vendor-specific use.
+const MAX_SECURITY_LEVEL: u8 = 0x41;
+
+/// Services whose sub-functions are probed along with security levels.  These are the only ones
+/// it's safe to send nothing but a sub-function to, since the rest either act on it alone, such as
+/// ECUReset or CommunicationControl, or don't have sub-functions at all.
+const SUB_FUNCTION_SERVICES: &[u8] = &[0x19, 0x31, 0x3E];
+
+/// Highest sub-function that's probed, since the ones above it only set the
+/// suppressPosRspMsgIndicationBit, which hides positive responses.
+const MAX_SUB_FUNCTION: u8 = 0x7F;
+
+/// Services available with some level of access, along with the sub-functions of
+/// [`SUB_FUNCTION_SERVICES`], if they were probed.
+struct Capabilities {
+    services: Vec<ServiceStatus>,
+    /// Sub-functions of each of [`SUB_FUNCTION_SERVICES`], in order.  Services that aren't
+    /// available have none.
+    sub_functions: Vec<Vec<ServiceStatus>>,
+}
+
+impl Capabilities {
+    fn service(&self, service_id: u8) -> ServiceStatus {
+        self.services[service_id as usize]
+    }
+
+    /// Status of a sub-function of the service at `idx` in [`SUB_FUNCTION_SERVICES`], which is
+    /// that of the service itself if it wasn't probed for sub-functions.
+    fn sub_function(&self, idx: usize, sub_function: u8) -> ServiceStatus {
+        self.sub_functions
+            .get(idx)
+            .and_then(|statuses| statuses.get(sub_function as usize))
+            .copied()
+            .unwrap_or_else(|| self.service(SUB_FUNCTION_SERVICES[idx]))
+    }
+}
+
+/// Why a security level that offered a seed wasn't probed with it unlocked.
+enum NotUnlocked {
+    AlreadyUnlocked,
+    NoKey,
+    Failed(String),
+}
+
+impl fmt::Display for NotUnlocked {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::AlreadyUnlocked => write!(f, "already unlocked, so it's the same as NONE"),
+            Self::NoKey => write!(
+                f,
+                "can't be unlocked without a key; pass --key-command to compute keys from seeds"
+            ),
+            Self::Failed(reason) => write!(f, "{}", reason),
+        }
+    }
+}
+
+/// Services that are available in a given session, along with how each security level answered a
+/// request for its seed, and what's available with each level that offered one unlocked, if they
+/// were probed.
+struct SessionCapabilities {
+    session: u8,
+    locked: Capabilities,
+    security_levels: Vec<ServiceStatus>,
+    unlocked: Vec<(u8, Result<Capabilities, NotUnlocked>)>,
+}
+
+pub struct ScanServices {
+    args: ScanServicesArgs,
+    target: Target,
+}
+
+impl ScanServices {
+    pub fn new(args: ScanServicesArgs, target: Target) -> Self {
+        Self { args, target }
+    }
+
+    async fn scan_session(
+        &self,
+        client: &mut UdsClient,
+        session: u8,
+    ) -> Result<SessionCapabilities, UdsError> {
+        let locked = self.probe_capabilities(client, session, None).await?;
+
+        let mut security_levels = Vec::new();
+        let mut offered = Vec::new();
+        if self.args.security_levels {
+            let mut security_access = SecurityAccessService::new(client);
+            for level in security_level_range() {
+                let status = ServiceStatus::classify_sub_function(
+                    security_access.request_seed(level).await,
+                )?;
+                debug!(
+                    "Session 0x{:02X}, security level 0x{:02X}: {}",
+                    session, level, status
+                );
+                if status == ServiceStatus::Positive {
+                    offered.push(level);
+                }
+                security_levels.push(status);
+            }
+        }
+
+        // Levels are relocked by leaving the session and coming back to it, so that each one is
+        // probed on its own.
+        let mut unlocked = Vec::new();
+        for level in offered {
+            if let Err(reason) = self.unlock(client, level).await {
+                unlocked.push((level, Err(reason)));
+                continue;
+            }
+
+            info!(
+                "Unlocked security level 0x{:02X} in session 0x{:02X}. Scanning services...",
+                level, session
+            );
+            let capabilities = self
+                .probe_capabilities(client, session, Some(level))
+                .await?;
+            unlocked.push((level, Ok(capabilities)));
+
+            let mut session_control = SessionControlService::new(client);
+            session_control.change(DEFAULT_SESSION).await?;
+            session_control.change(session).await?;
+        }
+
+        Ok(SessionCapabilities {
+            session,
+            locked,
+            security_levels,
+            unlocked,
+        })
+    }
+
+    /// Probes every service in the active session, along with the sub-functions of
+    /// [`SUB_FUNCTION_SERVICES`] when security levels are being probed too.
+    async fn probe_capabilities(
+        &self,
+        client: &mut UdsClient,
+        session: u8,
+        level: Option<u8>,
+    ) -> Result<Capabilities, UdsError> {
+        let access = match level {
+            Some(level) => format!("security level 0x{:02X}", level),
+            None => "locked".to_string(),
+        };
+
+        let mut probe = ServiceProbe::new(client);
+        let mut services = Vec::with_capacity(256);
+        for service_id in 0..=u8::MAX {
+            let status = probe.probe(service_id).await?;
+            debug!(
+                "Session 0x{:02X} ({}), service 0x{:02X}: {}",
+                session, access, service_id, status
+            );
+            services.push(status);
+        }
+
+        let mut sub_functions = Vec::new();
+        if self.args.security_levels {
+            for service_id in SUB_FUNCTION_SERVICES {
+                let mut statuses = Vec::new();
+                if services[*service_id as usize].is_available() {
+                    for sub_function in 0..=MAX_SUB_FUNCTION {
+                        let status = probe.probe_sub_function(*service_id, sub_function).await?;
+                        debug!(
+                            "Session 0x{:02X} ({}), service 0x{:02X}, sub-function 0x{:02X}: {}",
+                            session, access, service_id, sub_function, status
+                        );
+                        statuses.push(status);
+                    }
+                }
+                sub_functions.push(statuses);
+            }
+        }
+
+        Ok(Capabilities {
+            services,
+            sub_functions,
+        })
+    }
+
+    /// Tries to unlock the given security level with a key from the key command.
+    async fn unlock(&self, client: &mut UdsClient, level: u8) -> Result<(), NotUnlocked> {
+        let mut security_access = SecurityAccessService::new(client);
+        let seed = security_access
+            .request_seed(level)
+            .await
+            .map_err(|e| NotUnlocked::Failed(format!("failed to request seed: {}", e)))?;
+        if seed.iter().all(|b| *b == 0) {
+            return Err(NotUnlocked::AlreadyUnlocked);
+        }
+
+        let command = match &self.args.key_command {
+            Some(command) => command,
+            None => return Err(NotUnlocked::NoKey),
+        };
+        let key = compute_key(command, level, &seed)
+            .await
+            .map_err(NotUnlocked::Failed)?;
+        security_access
+            .send_key(level, &key)
+            .await
+            .map_err(|e| NotUnlocked::Failed(format!("key was not accepted: {}", e)))
+    }
+}
+
+#[async_trait]
+impl Operation for ScanServices {
+    async fn run(self, can_parameters: CANParameters) {
+        let mut client = match UdsClient::connect(can_parameters, self.target).await {
+            Ok(client) => client,
+            Err(e) => return error!("Failed to connect to ECU: {}", e),
+        };
+
+        let mut capabilities = Vec::new();
+        for session in &self.args.sessions {
+            match SessionControlService::new(&mut client)
+                .change(*session)
+                .await
+            {
+                Ok(timings) => info!(
+                    "Switched to session 0x{:02X} (P2 {:?}, P2* {:?}). Scanning services...",
+                    session, timings.p2, timings.p2_extended
+                ),
+                Err(e) => {
+                    warn!("ECU did not accept session 0x{:02X}: {}", session, e);
+                    continue;
+                }
+            }
+
+            match self.scan_session(&mut client, *session).await {
+                Ok(session_capabilities) => capabilities.push(session_capabilities),
+                Err(e) => return error!("Error occurred while scanning services: {}", e),
+            }
+        }
+
+        if let Err(e) = SessionControlService::new(&mut client)
+            .change(DEFAULT_SESSION)
+            .await
+        {
+            warn!("Failed to return to the default session: {}", e);
+        }
+
+        if capabilities.is_empty() {
+            return info!("ECU did not accept any of the requested sessions.");
+        }
+
+        // Only list services which are available in at least one session, since the rest of the
+        // matrix would just be noise.
+        let header = capabilities
+            .iter()
+            .map(|c| format!("  {:02X}", c.session))
+            .collect::<String>();
+        info!("Service/session matrix (OK = positive, XX = negative response code, S = not supported in session, - = not supported, ? = no response):");
+        info!(" SID |{}", header);
+        for service_id in 0..=u8::MAX {
+            if !capabilities
+                .iter()
+                .any(|c| c.locked.service(service_id).is_available())
+            {
+                continue;
+            }
+
+            let cells = capabilities
+                .iter()
+                .map(|c| format!("{:>4}", c.locked.service(service_id).label()))
+                .collect::<String>();
+            info!("  {:02X} |{}", service_id, cells);
+        }
+
+        // Levels that are locked in a session answer with securityAccessDenied, or some other
+        // code, rather than the 'S' of security access not being available in it at all.
+        if self.args.security_levels {
+            info!("Security level/session matrix (OK = seed offered, XX = negative response code, S = not available in session, - = not supported, ? = no response):");
+            info!(" LVL |{}", header);
+            for (idx, level) in security_level_range().enumerate() {
+                if !capabilities
+                    .iter()
+                    .any(|c| c.security_levels[idx].is_available())
+                {
+                    continue;
+                }
+
+                let cells = capabilities
+                    .iter()
+                    .map(|c| format!("{:>4}", c.security_levels[idx].label()))
+                    .collect::<String>();
+                info!("  {:02X} |{}", level, cells);
+            }
+
+            for session_capabilities in &capabilities {
+                report_unlocked(session_capabilities);
+            }
+        }
+    }
+}
+
+/// Reports what each security level that could be unlocked in a session makes available, next to
+/// what's available with none of them, along with why the rest weren't unlocked.
+fn report_unlocked(capabilities: &SessionCapabilities) {
+    let session = capabilities.session;
+    let mut columns = vec![&capabilities.locked];
+    let mut header = vec!["NONE".to_string()];
+    for (level, unlocked) in &capabilities.unlocked {
+        match unlocked {
+            Ok(unlocked) => {
+                columns.push(unlocked);
+                header.push(format!("  {:02X}", level));
+            }
+            Err(reason) => warn!(
+                "Security level 0x{:02X} in session 0x{:02X} was not probed unlocked: {}",
+                level, session, reason
+            ),
+        }
+    }
+    if columns.len() == 1 {
+        return;
+    }
+
+    info!(
+        "Service/security level matrix for session 0x{:02X} (NONE = no level unlocked, same labels as above):",
+        session
+    );
+    info!(" SID/SF |{}", header.concat());
+    let row = |statuses: Vec<ServiceStatus>| -> Option<String> {
+        if !statuses.iter().any(ServiceStatus::is_available) {
+            return None;
+        }
+        Some(
+            statuses
+                .iter()
+                .map(|status| format!("{:>4}", status.label()))
+                .collect(),
+        )
+    };
+    for service_id in 0..=u8::MAX {
+        let statuses = columns.iter().map(|c| c.service(service_id)).collect();
+        if let Some(cells) = row(statuses) {
+            info!("  {:02X}    |{}", service_id, cells);
+        }
+
+        let idx = match SUB_FUNCTION_SERVICES.iter().position(|s| *s == service_id) {
+            Some(idx) => idx,
+            None => continue,
+        };
+        for sub_function in 0..=MAX_SUB_FUNCTION {
+            let statuses = columns
+                .iter()
+                .map(|c| c.sub_function(idx, sub_function))
+                .collect();
+            if let Some(cells) = row(statuses) {
+                info!("  {:02X}/{:02X} |{}", service_id, sub_function, cells);
+            }
+        }
+    }
+}
+
+/// "Request seed" sub-functions of every security level that's probed.
+fn security_level_range() -> impl Iterator<Item = u8> {
+    (0x01..=MAX_SECURITY_LEVEL).step_by(2)
+}
+
+/// Runs the key command for a security level's seed, returning the key it prints.
+async fn compute_key(command: &Path, level: u8, seed: &[u8]) -> Result<Vec<u8>, String> {
+    let output = Command::new(command)
+        .arg(format!("{:02X}", level))
+        .arg(hex_string(seed))
+        .output()
+        .await
+        .map_err(|e| format!("failed to run {}: {}", command.display(), e))?;
+    if !output.status.success() {
+        return Err(format!(
+            "{} failed for seed {}: {}",
+            command.display(),
+            hex_string(seed),
+            output.status
+        ));
+    }
+
+    parse_hex_bytes(String::from_utf8_lossy(&output.stdout).trim())
+}
+
+fn hex_string(data: &[u8]) -> String {
+    data.iter().map(|b| format!("{:02X}", b)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use crate::protocol::{
+        transport::mock::{standard_target, FakeEcu},
+        uds::error::NegativeResponseCode,
+    };
+
+    use super::*;
+
+    #[tokio::test]
+    async fn tells_locked_security_levels_from_unavailable_ones() {
+        let mut client = FakeEcu::new(standard_target(0x7E0))
+            .respond(&[0x27, 0x01], &[&[0x67, 0x01, 0x12, 0x34]])
+            .reject(&[0x27, 0x03], NegativeResponseCode::SecurityAccessDenied)
+            .reject(
+                &[0x27, 0x05],
+                NegativeResponseCode::ServiceNotSupportedInActiveSession,
+            )
+            .reject(
+                &[0x27, 0x07],
+                NegativeResponseCode::SubFunctionNotSupportedInActiveSession,
+            )
+            .reject(&[0x27, 0x09], NegativeResponseCode::SubFunctionNotSupported)
+            .connect();
+        let mut security_access = SecurityAccessService::new(&mut client);
+
+        let mut labels = Vec::new();
+        for level in [0x01, 0x03, 0x05, 0x07, 0x09] {
+            let status =
+                ServiceStatus::classify_sub_function(security_access.request_seed(level).await);
+            labels.push(status.unwrap().label());
+        }
+        assert_eq!(labels, vec!["OK", "33", "S", "S", "-"]);
+    }
+
+    fn scan_services(key_command: Option<PathBuf>) -> ScanServices {
+        let args = ScanServicesArgs {
+            sessions: vec![DEFAULT_SESSION],
+            security_levels: true,
+            key_command,
+        };
+        ScanServices::new(args, standard_target(0x7E0))
+    }
+
+    #[tokio::test]
+    async fn unlocks_security_levels_with_keys_from_the_key_command() {
+        let key_command =
+            Path::new(env!("CARGO_MANIFEST_DIR")).join("fixtures/security/key-command.sh");
+        let mut client = FakeEcu::new(standard_target(0x7E0))
+            .respond(&[0x27, 0x01], &[&[0x67, 0x01, 0x12, 0x34]])
+            .respond(&[0x27, 0x02, 0xED, 0xCB], &[&[0x67, 0x02]])
+            .respond(&[0x27, 0x03], &[&[0x67, 0x03, 0x56, 0x78]])
+            .respond(&[0x27, 0x05], &[&[0x67, 0x05, 0x00, 0x00]])
+            .connect();
+
+        let scan = scan_services(Some(key_command));
+        assert!(scan.unlock(&mut client, 0x01).await.is_ok());
+        assert!(matches!(
+            scan.unlock(&mut client, 0x03).await,
+            Err(NotUnlocked::Failed(_))
+        ));
+        assert!(matches!(
+            scan.unlock(&mut client, 0x05).await,
+            Err(NotUnlocked::AlreadyUnlocked)
+        ));
+        assert!(matches!(
+            scan_services(None).unlock(&mut client, 0x01).await,
+            Err(NotUnlocked::NoKey)
+        ));
+    }
+}
@@ -0,0 +1,164 @@
+use async_trait::async_trait;
+use can::identifier::Id;
+use tokio::select;
+use tracing::{debug, error, info, warn};
+
+use super::Operation;
+use crate::{
+    common::config::{CANParameters, SimulateArgs},
+    protocol::can::{
+        error::{SocketBuildError, SocketError},
+        isotp::ISOTPSocket,
+    },
+    simulator::{load_profile, EcuProfile, SimulatedEcu},
+};
+
+pub struct Simulate {
+    args: SimulateArgs,
+}
+
+impl Simulate {
+    pub fn new(args: SimulateArgs) -> Self {
+        Self { args }
+    }
+}
+
+#[async_trait]
+impl Operation for Simulate {
+    async fn run(self, can_parameters: CANParameters) {
+        let profiles = match load_profile(&self.args.profile, &can_parameters) {
+            Ok(profiles) => profiles,
+            Err(e) => return error!("Failed to load profile: {}", e),
+        };
+
+        let count = profiles.len();
+        for profile in profiles {
+            let name = profile.name.clone();
+            if let Err(e) = spawn_ecu(&can_parameters, profile) {
+                return error!("Failed to open sockets for '{}': {}", name, e);
+            }
+        }
+
+        info!("Simulating {} ECU(s). Press Ctrl-C to stop.", count);
+        if let Err(e) = tokio::signal::ctrl_c().await {
+            error!("Failed to wait for Ctrl-C: {}", e);
+        }
+        info!("Stopping simulation.");
+    }
+}
+
+/// Opens the sockets for a simulated ECU, and starts answering requests on them.
+///
+/// Functional requests are received on a socket of their own, but are answered from the physical
+/// socket, same as physical requests: testers send flow control for multi-frame responses to the
+/// ECU's physical request identifier, whichever way the request was sent.
+fn spawn_ecu(can_parameters: &CANParameters, profile: EcuProfile) -> Result<(), SocketBuildError> {
+    let target = profile.target;
+    let functional_request_address = profile.functional_request_address;
+    let physical = open_socket(
+        can_parameters,
+        target.request_address.id(),
+        target.response_address.id(),
+    )?;
+    let functional = open_socket(
+        can_parameters,
+        functional_request_address.id(),
+        target.response_address.id(),
+    )?;
+
+    info!(
+        "Simulating '{}', receiving on {} and {}, responding on {}.",
+        profile.name, target.request_address, functional_request_address, target.response_address
+    );
+    tokio::spawn(serve(physical, functional, SimulatedEcu::new(profile)));
+    Ok(())
+}
+
+/// Opens an ISO-TP socket in the server role, receiving requests and sending responses.
+fn open_socket(
+    can_parameters: &CANParameters,
+    source_id: Id,
+    destination_id: Id,
+) -> Result<ISOTPSocket, SocketBuildError> {
+    let mut builder = ISOTPSocket::builder()
+        .can_parameters(can_parameters.clone())
+        .source_id(source_id)
+        .destination_id(destination_id);
+
+    // Address bytes are the other way around for the ECU, as it receives what the tester sends.
+    if let Some(address) = can_parameters.payload_address() {
+        builder = builder.ext_address(address.rx).rx_ext_address(address.tx);
+    }
+
+    builder.build()
+}
+
+/// Answers every request received on either socket until one of them fails.
+///
+/// Functional requests always fit in a single frame, so one arriving part way through a
+/// multi-frame physical request is the only way for the physical request to be dropped.
+async fn serve(mut physical: ISOTPSocket, mut functional: ISOTPSocket, mut ecu: SimulatedEcu) {
+    let name = ecu.profile().name.clone();
+    loop {
+        let (request, is_functional) = select! {
+            request = physical.read() => (request, false),
+            request = functional.read() => (request, true),
+        };
+
+        let request = match request {
+            Ok(request) => request,
+            Err(SocketError::Timeout(_)) => continue,
+            Err(SocketError::Io(e)) => return error!("Stopped simulating '{}': {}", name, e),
+            Err(e) => {
+                debug!("Ignoring malformed request: {}", e);
+                continue;
+            }
+        };
+
+        let responses = ecu.answer(&request, is_functional);
+        debug!("{}: {:02X?} -> {:02X?}", name, request, responses);
+
+        for response in responses {
+            if let Err(e) = physical.write(&response).await {
+                warn!("{}: failed to send response: {}", name, e);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{path::Path, time::Duration};
+
+    use crate::{
+        common::addressing::Addressing,
+        protocol::can::{isotp::FunctionalRequester, mock::MockBus},
+    };
+
+    use super::*;
+
+    #[tokio::test]
+    async fn answers_functional_requests_across_multiple_frames() {
+        let bus = MockBus::new();
+        let can_parameters = bus.can_parameters(&[]);
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("fixtures/simulator/engine.toml");
+        for profile in load_profile(&path, &can_parameters).unwrap() {
+            spawn_ecu(&can_parameters, profile).unwrap();
+        }
+
+        // The VIN takes three frames to send, so this only completes if the ECU gets our flow
+        // control on its physical request identifier.
+        let mut requester =
+            FunctionalRequester::open(&can_parameters, Addressing::Standard).unwrap();
+        let responses = requester
+            .request(&[0x09, 0x02], Duration::from_millis(500))
+            .await
+            .unwrap();
+        assert_eq!(responses.len(), 1);
+        assert_eq!(responses[0].response_address.id().as_raw(), 0x7E8);
+        assert_eq!(
+            responses[0].payload,
+            [&[0x49, 0x02, 0x01][..], b"1HGCM82633A004352"].concat()
+        );
+    }
+}
@@ -2,7 +2,10 @@ use async_trait::async_trait;
 use can::identifier::StandardId;
 use tracing::{error, info};
 
-use crate::{common::config::CANParameters, protocol::can::isotp::ISOTPSocket};
+use crate::{
+    common::{backend::Backend, config::CANParameters},
+    protocol::can::isotp::ISOTPSocket,
+};
 
 use super::Operation;
 
@@ -12,7 +15,10 @@ pub struct ValidateSocket;
 #[async_trait]
 impl Operation for ValidateSocket {
     async fn run(self, can_parameters: CANParameters) {
-        let socket_name = can_parameters.socket_name.clone();
+        let socket_name = match &can_parameters.backend {
+            Backend::SocketCAN => can_parameters.socket_name.clone(),
+            backend => backend.to_string(),
+        };
         let socket_builder = ISOTPSocket::builder()
             .can_parameters(can_parameters)
             .source_id(StandardId::ZERO)
@@ -8,8 +8,12 @@ use thiserror::Error;
 pub enum SocketBuildError {
     #[error("required field was not configured: {field_name}")]
     MissingRequiredField { field_name: &'static str },
+    #[error("option was out of range: {option_name}")]
+    InvalidOption { option_name: &'static str },
     #[error("the specified socket was not found")]
     SocketNotFound,
+    #[error("adapter failed to initialize: {source}")]
+    AdapterInitialization { source: SocketError },
     #[error("I/O error while building the socket: {source}")]
     Io {
         #[from]
@@ -41,4 +45,18 @@ pub enum SocketError {
     Io(#[from] io::Error),
     #[error("operation timed out after {0:?}")]
     Timeout(Duration),
+    #[error("payload of {0} bytes cannot be sent over ISO-TP")]
+    PayloadLength(usize),
+    #[error("consecutive frame out of sequence: expected {expected}, got {actual}")]
+    SequenceNumber { expected: u8, actual: u8 },
+    #[error("receiver reported a buffer overflow")]
+    Overflow,
+    #[error("receiver sent more than {0} consecutive wait frames")]
+    WaitLimitExceeded(u8),
+    #[error("receiver sent invalid flow status: 0x{0:X}")]
+    InvalidFlowStatus(u8),
+    #[error("received frame was not padded with 0x{0:02X}")]
+    InvalidPadding(u8),
+    #[error("cannot send while listening only")]
+    ListenOnly,
 }
@@ -0,0 +1,298 @@
+//! CAN FD frames, and frames that can be either classic or FD.
+//!
+//! `socketcan` only knows about classic frames, so we mirror the kernel's `canfd_frame` ourselves.
+
+use std::{io, mem::size_of, os::unix::prelude::RawFd};
+
+use socketcan::CANFrame;
+
+/// Size of a classic frame, as read from or written to a raw socket.
+const CAN_MTU: usize = size_of::<CANFrame>();
+
+/// Size of an FD frame, as read from or written to a raw socket.
+pub const CANFD_MTU: usize = size_of::<RawCANFDFrame>();
+
+/// Largest payload of a classic frame.
+const CAN_MAX_DATA_LENGTH: usize = 8;
+
+/// Largest payload of an FD frame.
+const CANFD_MAX_DATA_LENGTH: usize = 64;
+
+/// Payload lengths that an FD frame can have.  Anything else is padded out to the next of these.
+const CANFD_DATA_LENGTHS: [usize; 8] = [8, 12, 16, 20, 24, 32, 48, 64];
+
+const CAN_EFF_FLAG: u32 = 0x8000_0000;
+const CAN_SFF_MASK: u32 = 0x0000_07FF;
+const CAN_EFF_MASK: u32 = 0x1FFF_FFFF;
+
+const CANFD_BRS: u8 = 0x01;
+const CANFD_ESI: u8 = 0x02;
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct RawCANFDFrame {
+    can_id: u32,
+    len: u8,
+    flags: u8,
+    res0: u8,
+    res1: u8,
+    data: [u8; CANFD_MAX_DATA_LENGTH],
+}
+
+/// Whether or not an FD frame can carry exactly this many bytes, from a full classic frame's worth
+/// upwards.
+pub fn is_fd_data_length(length: usize) -> bool {
+    CANFD_DATA_LENGTHS.contains(&length)
+}
+
+/// Rounds a payload length up to the nearest length an FD frame can have.
+pub fn fd_data_length(length: usize) -> usize {
+    if length <= 8 {
+        length
+    } else {
+        CANFD_DATA_LENGTHS
+            .iter()
+            .copied()
+            .find(|valid| *valid >= length)
+            .unwrap_or(CANFD_MAX_DATA_LENGTH)
+    }
+}
+
+/// Creates a classic frame, with an extended identifier if `extended` is set, even one that would
+/// fit in 11 bits.
+///
+/// `CANFrame::new` decides by the identifier alone, which loses the format of frames received from
+/// adapters.  Returns `None` if the identifier doesn't fit the format, or the payload is longer than
+/// 8 bytes.
+pub fn classic_frame(id: u32, data: &[u8], extended: bool) -> Option<CANFrame> {
+    if !extended {
+        return match id {
+            0..=CAN_SFF_MASK => CANFrame::new(id, data, false, false).ok(),
+            _ => None,
+        };
+    }
+    if id > CAN_EFF_MASK || data.len() > CAN_MAX_DATA_LENGTH {
+        return None;
+    }
+
+    let mut raw = RawCANFDFrame {
+        can_id: id | CAN_EFF_FLAG,
+        len: data.len() as u8,
+        flags: 0,
+        res0: 0,
+        res1: 0,
+        data: [0; CANFD_MAX_DATA_LENGTH],
+    };
+    raw.data[..data.len()].copy_from_slice(data);
+
+    // SAFETY: `CANFrame` mirrors the kernel's `can_frame`, which is laid out the same as the start of
+    // a `canfd_frame`.
+    Some(unsafe { std::ptr::read(&raw as *const _ as *const CANFrame) })
+}
+
+/// A CAN FD frame.
+#[derive(Clone, Copy)]
+pub struct CANFDFrame {
+    inner: RawCANFDFrame,
+}
+
+impl CANFDFrame {
+    /// Creates an FD frame.
+    ///
+    /// As with `CANFrame`, identifiers that don't fit in 11 bits are sent as extended identifiers.
+    /// Returns `None` if the identifier doesn't fit in 29 bits, or the payload is longer than 64
+    /// bytes.  Payloads that aren't a valid FD length are padded out with zeroes.
+    pub fn new(
+        id: u32,
+        data: &[u8],
+        bit_rate_switch: bool,
+        error_state_indicator: bool,
+    ) -> Option<Self> {
+        if id > CAN_EFF_MASK || data.len() > CANFD_MAX_DATA_LENGTH {
+            return None;
+        }
+
+        let mut inner = RawCANFDFrame {
+            can_id: if id > CAN_SFF_MASK {
+                id | CAN_EFF_FLAG
+            } else {
+                id
+            },
+            len: fd_data_length(data.len()) as u8,
+            flags: 0,
+            res0: 0,
+            res1: 0,
+            data: [0; CANFD_MAX_DATA_LENGTH],
+        };
+        inner.data[..data.len()].copy_from_slice(data);
+        if bit_rate_switch {
+            inner.flags |= CANFD_BRS;
+        }
+        if error_state_indicator {
+            inner.flags |= CANFD_ESI;
+        }
+
+        Some(Self { inner })
+    }
+
+    pub fn id(&self) -> u32 {
+        self.inner.can_id & CAN_EFF_MASK
+    }
+
+    pub fn is_extended(&self) -> bool {
+        self.inner.can_id & CAN_EFF_FLAG != 0
+    }
+
+    pub fn data(&self) -> &[u8] {
+        &self.inner.data[..(self.inner.len as usize).min(CANFD_MAX_DATA_LENGTH)]
+    }
+}
+
+/// A classic or FD frame.
+#[derive(Clone, Copy)]
+pub enum CANAnyFrame {
+    Classic(CANFrame),
+    FD(CANFDFrame),
+}
+
+impl CANAnyFrame {
+    pub fn id(&self) -> u32 {
+        match self {
+            Self::Classic(frame) => frame.id(),
+            Self::FD(frame) => frame.id(),
+        }
+    }
+
+    pub fn is_extended(&self) -> bool {
+        match self {
+            Self::Classic(frame) => frame.is_extended(),
+            Self::FD(frame) => frame.is_extended(),
+        }
+    }
+
+    pub fn data(&self) -> &[u8] {
+        match self {
+            Self::Classic(frame) => frame.data(),
+            Self::FD(frame) => frame.data(),
+        }
+    }
+}
+
+impl From<CANFrame> for CANAnyFrame {
+    fn from(frame: CANFrame) -> Self {
+        Self::Classic(frame)
+    }
+}
+
+impl From<CANFDFrame> for CANAnyFrame {
+    fn from(frame: CANFDFrame) -> Self {
+        Self::FD(frame)
+    }
+}
+
+/// Allows FD frames to be read from, and written to, the given raw socket.
+pub fn enable_fd_frames(fd: RawFd) -> io::Result<()> {
+    let enable: libc::c_int = 1;
+    let rv = unsafe {
+        libc::setsockopt(
+            fd,
+            libc::SOL_CAN_RAW,
+            libc::CAN_RAW_FD_FRAMES,
+            &enable as *const _ as *const libc::c_void,
+            size_of::<libc::c_int>() as libc::socklen_t,
+        )
+    };
+    if rv != 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(())
+}
+
+/// Reads a classic or FD frame from a raw socket with FD frames enabled.
+pub fn read_any_frame(fd: RawFd) -> io::Result<CANAnyFrame> {
+    let mut raw = RawCANFDFrame {
+        can_id: 0,
+        len: 0,
+        flags: 0,
+        res0: 0,
+        res1: 0,
+        data: [0; CANFD_MAX_DATA_LENGTH],
+    };
+    let rv = unsafe { libc::read(fd, &mut raw as *mut _ as *mut libc::c_void, CANFD_MTU) };
+    if rv < 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    match rv as usize {
+        // SAFETY: `CANFrame` mirrors the kernel's `can_frame`, which is laid out the same as the
+        // start of a `canfd_frame`, and the kernel just wrote a full `can_frame` for us.
+        CAN_MTU => Ok(CANAnyFrame::Classic(unsafe {
+            std::ptr::read(&raw as *const _ as *const CANFrame)
+        })),
+        CANFD_MTU => Ok(CANAnyFrame::FD(CANFDFrame { inner: raw })),
+        n => Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "read {} bytes, which is neither a classic nor an FD frame",
+                n
+            ),
+        )),
+    }
+}
+
+/// Writes an FD frame to a raw socket with FD frames enabled.
+pub fn write_fd_frame(fd: RawFd, frame: &CANFDFrame) -> io::Result<()> {
+    let rv = unsafe {
+        libc::write(
+            fd,
+            &frame.inner as *const _ as *const libc::c_void,
+            CANFD_MTU,
+        )
+    };
+    if rv < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    if rv as usize != CANFD_MTU {
+        return Err(io::Error::new(
+            io::ErrorKind::WriteZero,
+            "failed to write full FD frame",
+        ));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fd_frames_are_padded_to_valid_lengths() {
+        let frame = CANFDFrame::new(0x18DA_F110, &[0x01; 10], true, false).unwrap();
+        assert!(frame.is_extended());
+        assert_eq!(frame.id(), 0x18DA_F110);
+        assert_eq!(frame.data(), [&[0x01; 10][..], &[0x00; 2]].concat());
+
+        assert_eq!(
+            CANFDFrame::new(0x7E0, &[0x01; 5], false, false)
+                .unwrap()
+                .data()
+                .len(),
+            5
+        );
+        assert!(CANFDFrame::new(0x7E0, &[0x01; 65], false, false).is_none());
+        assert!(CANFDFrame::new(0x2000_0000, &[], false, false).is_none());
+    }
+
+    #[test]
+    fn classic_frames_keep_their_format() {
+        let frame = classic_frame(0x7E0, &[0x02, 0x10, 0x03], true).unwrap();
+        assert!(frame.is_extended());
+        assert_eq!((frame.id(), frame.data()), (0x7E0, &[0x02, 0x10, 0x03][..]));
+
+        assert!(!classic_frame(0x7E0, &[], false).unwrap().is_extended());
+        assert!(classic_frame(0x800, &[], false).is_none());
+        assert!(classic_frame(0x7E0, &[0; 9], true).is_none());
+    }
+}
@@ -0,0 +1,447 @@
+//! ISO 15765-2 protocol data units, as carried in classic and FD CAN frames.
+
+use std::time::Duration;
+
+use socketcan::CANFrame;
+
+use crate::{
+    common::config::CANParameters,
+    protocol::can::frame::{fd_data_length, CANAnyFrame, CANFDFrame},
+};
+
+use super::strip_address;
+
+/// Length of a classic CAN frame, which frames are padded out to.
+pub const CAN_FRAME_LENGTH: usize = 8;
+
+/// Largest payload that can be described by a first frame's 12-bit length.
+const MAX_SHORT_PAYLOAD_LENGTH: usize = 0xFFF;
+
+/// Largest payload that can be described by a first frame at all, using the escape sequence.
+pub const MAX_PAYLOAD_LENGTH: usize = u32::MAX as usize;
+
+/// Byte that FD frames are padded out to a valid length with, when padding is otherwise disabled.
+const FD_PADDING: u8 = 0xCC;
+
+const SINGLE_FRAME: u8 = 0x00;
+const FIRST_FRAME: u8 = 0x10;
+const CONSECUTIVE_FRAME: u8 = 0x20;
+const FLOW_CONTROL: u8 = 0x30;
+
+/// How transmitted frames are sent on the bus.
+#[derive(Clone, Copy)]
+pub enum FrameFormat {
+    Classic,
+    FD {
+        bit_rate_switch: bool,
+        error_state_indicator: bool,
+    },
+}
+
+impl FrameFormat {
+    pub fn new(can_parameters: &CANParameters) -> Self {
+        if can_parameters.can_fd {
+            Self::FD {
+                bit_rate_switch: can_parameters.can_fd_bit_rate_switch,
+                error_state_indicator: can_parameters.can_fd_error_state_indicator,
+            }
+        } else {
+            Self::Classic
+        }
+    }
+
+    /// Wraps encoded frame data in a CAN frame of this format.
+    ///
+    /// Panics if the data doesn't fit, which can't happen for data from `Frame::encode` as long as
+    /// only FD frames are longer than a classic frame.
+    pub fn frame(&self, id: u32, data: &[u8]) -> CANAnyFrame {
+        match *self {
+            Self::Classic => CANFrame::new(id, data, false, false)
+                .expect("should never fail to construct ISO-TP frame")
+                .into(),
+            Self::FD {
+                bit_rate_switch,
+                error_state_indicator,
+            } => CANFDFrame::new(id, data, bit_rate_switch, error_state_indicator)
+                .expect("should never fail to construct ISO-TP frame")
+                .into(),
+        }
+    }
+}
+
+/// Flow status of a flow control frame.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FlowStatus {
+    ContinueToSend,
+    Wait,
+    Overflow,
+    Reserved(u8),
+}
+
+impl FlowStatus {
+    fn from_raw(raw: u8) -> Self {
+        match raw {
+            0x0 => Self::ContinueToSend,
+            0x1 => Self::Wait,
+            0x2 => Self::Overflow,
+            raw => Self::Reserved(raw),
+        }
+    }
+
+    fn as_raw(&self) -> u8 {
+        match self {
+            Self::ContinueToSend => 0x0,
+            Self::Wait => 0x1,
+            Self::Overflow => 0x2,
+            Self::Reserved(raw) => *raw,
+        }
+    }
+}
+
+/// A single ISO-TP frame.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Frame<'a> {
+    Single(&'a [u8]),
+    First {
+        length: usize,
+        data: &'a [u8],
+    },
+    Consecutive {
+        sequence_number: u8,
+        data: &'a [u8],
+    },
+    FlowControl {
+        status: FlowStatus,
+        block_size: u8,
+        separation_time: Duration,
+    },
+}
+
+impl<'a> Frame<'a> {
+    /// Parses a frame from the data of a CAN frame.
+    ///
+    /// When `address` is given, the frame must start with that address byte.  Frames which are
+    /// malformed, or addressed to someone else, are ignored by receivers per ISO 15765-2, so they're
+    /// parsed as `None` rather than as an error.
+    pub fn parse(data: &'a [u8], address: Option<u8>) -> Option<Self> {
+        let frame_length = data.len();
+        let data = strip_address(data, address)?;
+
+        let pci = *data.first()?;
+        match pci & 0xF0 {
+            SINGLE_FRAME => {
+                let offset = single_frame_header_length(frame_length);
+                let length = if offset == 1 {
+                    (pci & 0x0F) as usize
+                } else if pci & 0x0F == 0 {
+                    *data.get(1)? as usize
+                } else {
+                    return None;
+                };
+                if length == 0 || length > data.len() - offset {
+                    return None;
+                }
+
+                Some(Self::Single(&data[offset..offset + length]))
+            }
+            FIRST_FRAME => {
+                let (length, offset) = match ((pci as usize & 0x0F) << 8) | *data.get(1)? as usize {
+                    // Payloads too long for 12 bits have their length in the next four bytes.
+                    0 => {
+                        let length = u32::from_be_bytes(data.get(2..6)?.try_into().ok()?);
+                        if length as usize <= MAX_SHORT_PAYLOAD_LENGTH {
+                            return None;
+                        }
+                        (length as usize, 6)
+                    }
+                    length => (length, 2),
+                };
+                if length <= max_single_frame_length(address, frame_length)
+                    || frame_length < CAN_FRAME_LENGTH
+                {
+                    return None;
+                }
+
+                Some(Self::First {
+                    length,
+                    data: &data[offset..],
+                })
+            }
+            CONSECUTIVE_FRAME => Some(Self::Consecutive {
+                sequence_number: pci & 0x0F,
+                data: &data[1..],
+            }),
+            FLOW_CONTROL => {
+                if data.len() < 3 {
+                    return None;
+                }
+
+                Some(Self::FlowControl {
+                    status: FlowStatus::from_raw(pci & 0x0F),
+                    block_size: data[1],
+                    separation_time: separation_time_from_raw(data[2]),
+                })
+            }
+            _ => None,
+        }
+    }
+
+    /// Encodes this frame as the data of a CAN frame, prefixed with `address` if given, and padded
+    /// out to a full frame if `padding` is given.
+    ///
+    /// Frames longer than a classic frame are always padded out to the next valid FD frame length,
+    /// with `0xCC` if `padding` isn't given.
+    pub fn encode(&self, address: Option<u8>, padding: Option<u8>) -> Vec<u8> {
+        let mut encoded = Vec::with_capacity(CAN_FRAME_LENGTH);
+        encoded.extend(address);
+
+        match self {
+            Self::Single(data) => {
+                // Single frames only use the escape sequence when they don't fit in a classic frame.
+                if address_length(address) + 1 + data.len() <= CAN_FRAME_LENGTH {
+                    encoded.push(SINGLE_FRAME | data.len() as u8);
+                } else {
+                    encoded.push(SINGLE_FRAME);
+                    encoded.push(data.len() as u8);
+                }
+                encoded.extend_from_slice(data);
+            }
+            Self::First { length, data } => {
+                if *length > MAX_SHORT_PAYLOAD_LENGTH {
+                    encoded.push(FIRST_FRAME);
+                    encoded.push(0);
+                    encoded.extend_from_slice(&(*length as u32).to_be_bytes());
+                } else {
+                    encoded.push(FIRST_FRAME | (*length >> 8) as u8);
+                    encoded.push(*length as u8);
+                }
+                encoded.extend_from_slice(data);
+            }
+            Self::Consecutive {
+                sequence_number,
+                data,
+            } => {
+                encoded.push(CONSECUTIVE_FRAME | (sequence_number & 0x0F));
+                encoded.extend_from_slice(data);
+            }
+            Self::FlowControl {
+                status,
+                block_size,
+                separation_time,
+            } => {
+                encoded.push(FLOW_CONTROL | status.as_raw());
+                encoded.push(*block_size);
+                encoded.push(separation_time_to_raw(*separation_time));
+            }
+        }
+
+        let length = fd_data_length(encoded.len());
+        match padding {
+            Some(padding) => encoded.resize(length.max(CAN_FRAME_LENGTH), padding),
+            None => encoded.resize(length, FD_PADDING),
+        }
+        encoded
+    }
+}
+
+fn address_length(address: Option<u8>) -> usize {
+    if address.is_some() {
+        1
+    } else {
+        0
+    }
+}
+
+/// Length of the protocol control information of a single frame.
+///
+/// Single frames longer than a classic frame carry their length in a byte of its own.
+fn single_frame_header_length(frame_length: usize) -> usize {
+    if frame_length > CAN_FRAME_LENGTH {
+        2
+    } else {
+        1
+    }
+}
+
+/// Largest payload that fits in a single frame.
+pub fn max_single_frame_length(address: Option<u8>, frame_length: usize) -> usize {
+    frame_length - address_length(address) - single_frame_header_length(frame_length)
+}
+
+/// Number of payload bytes carried by the first frame of a payload of `length` bytes.
+pub fn first_frame_data_length(address: Option<u8>, frame_length: usize, length: usize) -> usize {
+    let pci_length = if length > MAX_SHORT_PAYLOAD_LENGTH {
+        6
+    } else {
+        2
+    };
+    frame_length - address_length(address) - pci_length
+}
+
+/// Number of payload bytes carried by a consecutive frame.
+pub fn consecutive_frame_data_length(address: Option<u8>, frame_length: usize) -> usize {
+    frame_length - address_length(address) - 1
+}
+
+/// Checks that a received frame is at least a full classic frame, and that everything after the
+/// first `used` bytes is the given padding.
+pub fn is_padded(data: &[u8], used: usize, padding: u8) -> bool {
+    data.len() >= CAN_FRAME_LENGTH && data[used.min(data.len())..].iter().all(|b| *b == padding)
+}
+
+/// Length of the protocol control information, and address, preceding the payload of a frame that
+/// was received in a CAN frame of `frame_length` bytes.
+pub fn header_length(frame: &Frame<'_>, address: Option<u8>, frame_length: usize) -> usize {
+    let pci_length = match frame {
+        Frame::Single(_) => single_frame_header_length(frame_length),
+        Frame::First { length, .. } if *length > MAX_SHORT_PAYLOAD_LENGTH => 6,
+        Frame::First { .. } => 2,
+        Frame::FlowControl { .. } => 3,
+        Frame::Consecutive { .. } => 1,
+    };
+    address_length(address) + pci_length
+}
+
+/// Decodes an STmin value.
+///
+/// Reserved values are treated as the longest valid separation time, as required by ISO 15765-2.
+pub fn separation_time_from_raw(raw: u8) -> Duration {
+    match raw {
+        0x00..=0x7F => Duration::from_millis(raw as u64),
+        0xF1..=0xF9 => Duration::from_micros((raw - 0xF0) as u64 * 100),
+        _ => Duration::from_millis(0x7F),
+    }
+}
+
+/// Encodes an STmin value, rounding up to the nearest representable value.
+pub fn separation_time_to_raw(separation_time: Duration) -> u8 {
+    let micros = separation_time.as_micros();
+    if micros == 0 {
+        0x00
+    } else if micros <= 900 {
+        0xF0 + ((micros + 99) / 100) as u8
+    } else {
+        ((micros + 999) / 1000).min(0x7F) as u8
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn classic_single_frame_round_trip() {
+        let encoded = Frame::Single(&[0x3E, 0x00]).encode(None, Some(0xAA));
+        assert_eq!(encoded, [0x02, 0x3E, 0x00, 0xAA, 0xAA, 0xAA, 0xAA, 0xAA]);
+        assert_eq!(
+            Frame::parse(&encoded, None),
+            Some(Frame::Single(&[0x3E, 0x00]))
+        );
+    }
+
+    #[test]
+    fn fd_single_frame_uses_escape_sequence() {
+        let payload = [0x22, 0xF1, 0x90, 0xF1, 0x8C, 0xF1, 0x87, 0xF1, 0x8A, 0xF1];
+        let encoded = Frame::Single(&payload).encode(None, None);
+        assert_eq!(encoded.len(), 12);
+        assert_eq!(&encoded[..2], &[0x00, 0x0A]);
+        assert_eq!(Frame::parse(&encoded, None), Some(Frame::Single(&payload)));
+    }
+
+    #[test]
+    fn first_frame_uses_escape_sequence_for_long_payloads() {
+        let frame = Frame::First {
+            length: 0x1000,
+            data: &[0x62, 0xF1],
+        };
+        let encoded = frame.encode(None, None);
+        assert_eq!(encoded, [0x10, 0x00, 0x00, 0x00, 0x10, 0x00, 0x62, 0xF1]);
+        assert_eq!(Frame::parse(&encoded, None), Some(frame));
+
+        // Lengths that would have fit in 12 bits must not use the escape sequence.
+        let encoded = [0x10, 0x00, 0x00, 0x00, 0x00, 0x20, 0x62, 0xF1];
+        assert_eq!(Frame::parse(&encoded, None), None);
+    }
+
+    #[test]
+    fn fd_first_frame_uses_escape_sequence_for_long_payloads() {
+        let data = [0x36; 58];
+        let frame = Frame::First {
+            length: 0x1_0000,
+            data: &data,
+        };
+        let encoded = frame.encode(None, None);
+        assert_eq!(encoded.len(), 64);
+        assert_eq!(&encoded[..6], &[0x10, 0x00, 0x00, 0x01, 0x00, 0x00]);
+        assert_eq!(Frame::parse(&encoded, None), Some(frame));
+        assert_eq!(first_frame_data_length(None, 64, 0x1_0000), 58);
+
+        // Anything that would have fit in an FD single frame isn't a first frame.
+        let mut encoded = vec![0x10, 0x3E];
+        encoded.resize(64, 0xCC);
+        assert_eq!(Frame::parse(&encoded, None), None);
+    }
+
+    #[test]
+    fn fd_single_frames_with_extended_addressing() {
+        let payload = [0x2E; 20];
+        let encoded = Frame::Single(&payload).encode(Some(0xF1), None);
+        assert_eq!(encoded.len(), 24);
+        assert_eq!(&encoded[..3], &[0xF1, 0x00, 0x14]);
+        assert!(encoded[23..].iter().all(|b| *b == FD_PADDING));
+        assert_eq!(
+            Frame::parse(&encoded, Some(0xF1)),
+            Some(Frame::Single(&payload))
+        );
+        assert_eq!(max_single_frame_length(Some(0xF1), 64), 61);
+
+        // Escaped single frames need the length byte, and classic ones can't use it.
+        let mut encoded = vec![0x05, 0x3E];
+        encoded.resize(12, 0xCC);
+        assert_eq!(Frame::parse(&encoded, None), None);
+    }
+
+    #[test]
+    fn malformed_frames_are_ignored() {
+        // Length of zero, length longer than the frame, and a reserved PCI type.
+        assert_eq!(Frame::parse(&[0x00, 0x3E, 0x00], None), None);
+        assert_eq!(Frame::parse(&[0x07, 0x3E, 0x00], None), None);
+        assert_eq!(Frame::parse(&[0x40, 0x3E, 0x00], None), None);
+        // First frames that would have fit in a single frame.
+        assert_eq!(Frame::parse(&[0x10, 0x07, 0, 0, 0, 0, 0, 0], None), None);
+    }
+
+    #[test]
+    fn extended_addressing() {
+        let encoded = Frame::Single(&[0x10, 0x03]).encode(Some(0xF1), None);
+        assert_eq!(encoded, [0xF1, 0x02, 0x10, 0x03]);
+        assert_eq!(
+            Frame::parse(&encoded, Some(0xF1)),
+            Some(Frame::Single(&[0x10, 0x03]))
+        );
+        assert_eq!(Frame::parse(&encoded, Some(0x10)), None);
+        assert_eq!(max_single_frame_length(Some(0xF1), CAN_FRAME_LENGTH), 6);
+    }
+
+    #[test]
+    fn flow_control_round_trip() {
+        let frame = Frame::FlowControl {
+            status: FlowStatus::Wait,
+            block_size: 8,
+            separation_time: Duration::from_micros(300),
+        };
+        let encoded = frame.encode(None, Some(0x00));
+        assert_eq!(encoded, [0x31, 0x08, 0xF3, 0x00, 0x00, 0x00, 0x00, 0x00]);
+        assert_eq!(Frame::parse(&encoded, None), Some(frame));
+    }
+
+    #[test]
+    fn separation_times() {
+        assert_eq!(separation_time_to_raw(Duration::ZERO), 0x00);
+        assert_eq!(separation_time_to_raw(Duration::from_micros(150)), 0xF2);
+        assert_eq!(separation_time_to_raw(Duration::from_micros(1500)), 0x02);
+        assert_eq!(separation_time_to_raw(Duration::from_secs(1)), 0x7F);
+        assert_eq!(separation_time_from_raw(0xF9), Duration::from_micros(900));
+        assert_eq!(separation_time_from_raw(0x80), Duration::from_millis(0x7F));
+    }
+}
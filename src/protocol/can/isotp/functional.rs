@@ -0,0 +1,288 @@
+use std::{collections::HashMap, time::Duration};
+
+use tokio::time::Instant;
+use tracing::{debug, trace};
+
+use crate::{
+    common::{
+        addressing::{frame_id, Addressing, PayloadAddress, RequestAddress, ResponseAddress},
+        config::CANParameters,
+    },
+    protocol::can::{
+        error::{SocketBuildError, SocketError},
+        raw::RawSocket,
+    },
+};
+
+use super::{
+    frame::{max_single_frame_length, FlowStatus, Frame, FrameFormat, CAN_FRAME_LENGTH},
+    reception::Reception,
+};
+
+/// Complete response of a single ECU to a functional request.
+pub struct FunctionalResponse {
+    pub response_address: ResponseAddress,
+    pub payload: Vec<u8>,
+}
+
+/// Sends functional requests, which every ECU on the bus may respond to, and collects each ECU's
+/// response.
+///
+/// Functional requests have to fit in a single frame, of up to TX_DL bytes, but responses don't, so
+/// segmented responses are reassembled per ECU, with flow control sent to each ECU on its physical
+/// request identifier.  Both are sent as FD frames when CAN FD is enabled.
+pub struct FunctionalRequester {
+    socket: RawSocket,
+    addressing: Addressing,
+    request_address: RequestAddress,
+    tester_address: u8,
+    payload_address: Option<PayloadAddress>,
+    tx_padding: Option<u8>,
+    frame_format: FrameFormat,
+    tx_data_length: usize,
+    block_size: u8,
+    separation_time: Duration,
+}
+
+impl FunctionalRequester {
+    pub fn open(
+        can_parameters: &CANParameters,
+        addressing: Addressing,
+    ) -> Result<Self, SocketBuildError> {
+        let tx_data_length = can_parameters.isotp_tx_data_length as usize;
+        if tx_data_length > CAN_FRAME_LENGTH && !can_parameters.can_fd {
+            return Err(SocketBuildError::InvalidOption {
+                option_name: "tx_data_length",
+            });
+        }
+
+        let tester_address = can_parameters.tester_address;
+        let socket = RawSocket::builder()
+            .can_parameters(can_parameters.clone())
+            .source_id_filter(addressing.response_filter(tester_address))
+            .build()?;
+
+        Ok(Self {
+            socket,
+            addressing,
+            request_address: addressing
+                .functional_request_address(can_parameters.functional_address, tester_address),
+            tester_address,
+            payload_address: can_parameters.payload_address(),
+            tx_padding: can_parameters.tx_padding(),
+            frame_format: FrameFormat::new(can_parameters),
+            tx_data_length,
+            block_size: can_parameters.isotp_block_size,
+            separation_time: can_parameters.isotp_separation_time,
+        })
+    }
+
+    /// Sends a functional request, and collects every complete response received before
+    /// `listen_timeout` elapses.
+    ///
+    /// Responses are returned in the order they completed in.  ECUs can respond more than once,
+    /// such as when they need more time, so there may be more than one response per ECU.  Responses
+    /// that are still being received when time runs out, or that are received out of sequence, are
+    /// dropped.
+    pub async fn request(
+        &mut self,
+        payload: &[u8],
+        listen_timeout: Duration,
+    ) -> Result<Vec<FunctionalResponse>, SocketError> {
+        let tx_address = self.payload_address.map(|address| address.tx);
+        let rx_address = self.payload_address.map(|address| address.rx);
+        if payload.is_empty()
+            || payload.len() > max_single_frame_length(tx_address, self.tx_data_length)
+        {
+            return Err(SocketError::PayloadLength(payload.len()));
+        }
+
+        let data = Frame::Single(payload).encode(tx_address, self.tx_padding);
+        let frame = self
+            .frame_format
+            .frame(self.request_address.id().as_raw(), &data);
+        self.socket.write(frame).await?;
+
+        let deadline = Instant::now() + listen_timeout;
+        let mut receptions: HashMap<ResponseAddress, Reception> = HashMap::new();
+        let mut responses = Vec::new();
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                break;
+            }
+
+            let frame = match self.socket.read_with_timeout(Some(remaining)).await {
+                Ok(frame) => frame,
+                Err(SocketError::Timeout(_)) => break,
+                Err(e) => return Err(e),
+            };
+
+            let response_address = match frame_id(&frame)
+                .and_then(|id| self.addressing.response_address(id, self.tester_address))
+            {
+                Some(address) => address,
+                None => continue,
+            };
+
+            match Frame::parse(frame.data(), rx_address) {
+                Some(Frame::Single(data)) => {
+                    receptions.remove(&response_address);
+                    responses.push(FunctionalResponse {
+                        response_address,
+                        payload: data.to_vec(),
+                    });
+                }
+                Some(Frame::First { length, data }) => {
+                    receptions.insert(response_address, Reception::new(length, data));
+                    self.send_flow_control(response_address).await?;
+                }
+                Some(Frame::Consecutive {
+                    sequence_number,
+                    data,
+                }) => {
+                    let reception = match receptions.get_mut(&response_address) {
+                        Some(reception) => reception,
+                        None => {
+                            trace!("ignoring consecutive frame outside of a reception");
+                            continue;
+                        }
+                    };
+
+                    if let Err(e) = reception.push(sequence_number, data) {
+                        debug!("Dropping response from {}: {}", response_address, e);
+                        receptions.remove(&response_address);
+                        continue;
+                    }
+
+                    if reception.is_complete() {
+                        let reception = receptions
+                            .remove(&response_address)
+                            .expect("reception should be present");
+                        responses.push(FunctionalResponse {
+                            response_address,
+                            payload: reception.into_payload(),
+                        });
+                    } else if reception.end_of_block(self.block_size) {
+                        self.send_flow_control(response_address).await?;
+                    }
+                }
+                _ => trace!("ignoring unexpected frame: {:02X?}", frame.data()),
+            }
+        }
+
+        for response_address in receptions.keys() {
+            debug!(
+                "Dropping incomplete response from {} at deadline.",
+                response_address
+            );
+        }
+
+        Ok(responses)
+    }
+
+    /// Lets the ECU responding on the given address send the rest of its response.
+    async fn send_flow_control(
+        &mut self,
+        response_address: ResponseAddress,
+    ) -> Result<(), SocketError> {
+        let request_address = match response_address.request_address() {
+            Some(address) => address,
+            None => {
+                debug!(
+                    "Can't send flow control to {}: unknown request address.",
+                    response_address
+                );
+                return Ok(());
+            }
+        };
+
+        let data = Frame::FlowControl {
+            status: FlowStatus::ContinueToSend,
+            block_size: self.block_size,
+            separation_time: self.separation_time,
+        }
+        .encode(
+            self.payload_address.map(|address| address.tx),
+            self.tx_padding,
+        );
+        let frame = self
+            .frame_format
+            .frame(request_address.id().as_raw(), &data);
+        self.socket.write(frame).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use socketcan::CANFrame;
+
+    use crate::{
+        common::addressing::Addressing,
+        protocol::can::{error::SocketError, frame::CANAnyFrame, mock::MockBus, raw::RawSocket},
+    };
+
+    use super::FunctionalRequester;
+
+    async fn send(ecu: &mut RawSocket, data: &[u8]) {
+        let frame = CANFrame::new(0x7E8, data, false, false).unwrap();
+        ecu.write(frame).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn sends_fd_requests_and_flow_control() {
+        let bus = MockBus::new();
+        let mut ecu = bus.raw_socket();
+        let can_parameters = bus.can_parameters(&[
+            "--can-fd",
+            "--can-fd-bit-rate-switch",
+            "--isotp-tx-data-length",
+            "16",
+        ]);
+        let mut requester =
+            FunctionalRequester::open(&can_parameters, Addressing::Standard).unwrap();
+
+        // Too long for a classic single frame, so this only fits with CAN FD.
+        let request = [0x22, 0xF1, 0x90, 0xF1, 0x8C, 0xF1, 0x87, 0xF1, 0x8A];
+        let response = (0..10).collect::<Vec<u8>>();
+        let ecu_side = async {
+            let frame = ecu.read().await.unwrap();
+            assert!(matches!(frame, CANAnyFrame::FD(_)));
+            assert_eq!(frame.id(), 0x7DF);
+            assert_eq!(frame.data().len(), 12);
+            assert_eq!(frame.data()[..2], [0x00, 0x09]);
+            assert_eq!(frame.data()[2..11], request);
+
+            send(&mut ecu, &[&[0x10, 10][..], &response[..6]].concat()).await;
+            let flow_control = ecu.read().await.unwrap();
+            assert!(matches!(flow_control, CANAnyFrame::FD(_)));
+            assert_eq!(flow_control.id(), 0x7E0);
+            assert_eq!(flow_control.data()[0], 0x30);
+            send(&mut ecu, &[&[0x21][..], &response[6..]].concat()).await;
+        };
+
+        let (responses, ()) = tokio::join!(
+            requester.request(&request, Duration::from_millis(200)),
+            ecu_side
+        );
+        let responses = responses.unwrap();
+        assert_eq!(responses.len(), 1);
+        assert_eq!(responses[0].response_address.id().as_raw(), 0x7E8);
+        assert_eq!(responses[0].payload, response);
+    }
+
+    #[tokio::test]
+    async fn rejects_requests_longer_than_a_single_frame() {
+        let bus = MockBus::new();
+        let mut requester =
+            FunctionalRequester::open(&bus.can_parameters(&[]), Addressing::Standard).unwrap();
+        assert!(matches!(
+            requester
+                .request(&[0x01; 8], Duration::from_millis(10))
+                .await,
+            Err(SocketError::PayloadLength(8))
+        ));
+    }
+}
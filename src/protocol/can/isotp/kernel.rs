@@ -8,82 +8,23 @@ use std::{
 use can::identifier::Id;
 use futures::ready;
 use mio::{event::Source, unix::SourceFd, Interest, Registry, Token};
-use socketcan_isotp::{IsoTpBehaviour, IsoTpOptions, IsoTpSocket};
+use socketcan_isotp::{
+    FlowControlOptions, IsoTpBehaviour, IsoTpOptions, IsoTpSocket, LinkLayerOptions, TxFlags,
+};
 use tokio::{io::unix::AsyncFd, macros::support::poll_fn, time::timeout};
 
-use crate::common::config::CANParameters;
-
-use super::error::{SocketBuildError, SocketError};
-
-#[derive(Default)]
-pub struct ISOTPSocketBuilder {
-    source_id: Option<Id>,
-    destination_id: Option<Id>,
-    can_parameters: Option<CANParameters>,
-}
-
-impl ISOTPSocketBuilder {
-    pub fn source_id(mut self, id: impl Into<Id>) -> Self {
-        self.source_id = Some(id.into());
-        self
-    }
-
-    pub fn destination_id(mut self, id: impl Into<Id>) -> Self {
-        self.destination_id = Some(id.into());
-        self
-    }
-
-    pub fn can_parameters(mut self, params: CANParameters) -> Self {
-        self.can_parameters = Some(params);
-        self
-    }
-
-    pub fn build(self) -> Result<ISOTPSocket, SocketBuildError> {
-        let source_id = self
-            .source_id
-            .ok_or(SocketBuildError::MissingRequiredField {
-                field_name: "source_id",
-            })?;
-        let destination_id = self
-            .destination_id
-            .ok_or(SocketBuildError::MissingRequiredField {
-                field_name: "destination_id",
-            })?;
-        let can_parameters = self
-            .can_parameters
-            .ok_or(SocketBuildError::MissingRequiredField {
-                field_name: "can_parameters",
-            })?;
-
-        let mut isotp_options = IsoTpOptions::default();
-        if !can_parameters.disable_isotp_frame_padding {
-            isotp_options.set_txpad_content(can_parameters.tx_frame_padding);
-
-            let new_flags = isotp_options
-                .get_flags()
-                .map(|flags| flags | IsoTpBehaviour::CAN_ISOTP_TX_PADDING)
-                .unwrap_or(IsoTpBehaviour::CAN_ISOTP_TX_PADDING);
-
-            isotp_options.set_flags(new_flags);
-        }
+use crate::{
+    common::config::CANParameters,
+    protocol::can::{
+        error::{SocketBuildError, SocketError},
+        frame::CANFD_MTU,
+    },
+};
 
-        let socket = IsoTpSocket::open_with_opts(
-            can_parameters.socket_name.as_ref(),
-            source_id,
-            destination_id,
-            Some(isotp_options),
-            None,
-            None,
-        )?;
-        socket.set_nonblocking(true)?;
+use super::{frame::separation_time_to_raw, ISOTPOptions};
 
-        Ok(ISOTPSocket {
-            inner: AsyncFd::new(EventedISOTPSocket { inner: socket })?,
-            default_read_timeout: Some(can_parameters.read_timeout),
-            default_write_timeout: Some(can_parameters.write_timeout),
-        })
-    }
-}
+/// Frame transmit time the kernel reads as zero. (`CAN_ISOTP_FRAME_TXTIME_ZERO`)
+const FRAME_TRANSMIT_TIME_ZERO: Duration = Duration::from_nanos(0xFFFF_FFFF);
 
 pub struct EventedISOTPSocket {
     inner: IsoTpSocket,
@@ -129,15 +70,103 @@ impl Source for EventedISOTPSocket {
     }
 }
 
-pub struct ISOTPSocket {
+/// ISO-TP socket backed by the kernel's `can-isotp` module.
+pub struct KernelISOTPSocket {
     inner: AsyncFd<EventedISOTPSocket>,
     default_read_timeout: Option<Duration>,
     default_write_timeout: Option<Duration>,
 }
 
-impl ISOTPSocket {
-    pub fn builder() -> ISOTPSocketBuilder {
-        ISOTPSocketBuilder::default()
+impl KernelISOTPSocket {
+    pub(super) fn open(
+        can_parameters: &CANParameters,
+        options: &ISOTPOptions,
+        source_id: Id,
+        destination_id: Id,
+    ) -> Result<Self, SocketBuildError> {
+        let mut flags = IsoTpBehaviour::empty();
+        let mut isotp_options = IsoTpOptions::default();
+        if !can_parameters.disable_isotp_frame_padding {
+            isotp_options.set_txpad_content(can_parameters.tx_frame_padding);
+            flags |= IsoTpBehaviour::CAN_ISOTP_TX_PADDING;
+        }
+
+        if let Some(padding) = options.rx_padding {
+            isotp_options.set_rxpad_content(padding);
+            flags |= IsoTpBehaviour::CAN_ISOTP_RX_PADDING
+                | IsoTpBehaviour::CAN_ISOTP_CHK_PAD_LEN
+                | IsoTpBehaviour::CAN_ISOTP_CHK_PAD_DATA;
+        }
+
+        if let Some(frame_transmit_time) = options.frame_transmit_time {
+            // The kernel takes zero to mean its default, so zero itself has a value of its own.
+            let frame_transmit_time = if frame_transmit_time.is_zero() {
+                FRAME_TRANSMIT_TIME_ZERO
+            } else {
+                frame_transmit_time
+            };
+            isotp_options
+                .set_frame_txtime(frame_transmit_time)
+                .map_err(|_| SocketBuildError::InvalidOption {
+                    option_name: "frame_transmit_time",
+                })?;
+        }
+
+        if options.listen_only {
+            flags |= IsoTpBehaviour::CAN_ISOTP_LISTEN_MODE;
+        }
+
+        if let Some(address) = options.tx_address() {
+            isotp_options.set_ext_address(address);
+            flags |= IsoTpBehaviour::CAN_ISOTP_EXTEND_ADDR;
+        }
+
+        if let Some(address) = options.rx_address() {
+            isotp_options.set_rx_ext_address(address);
+            flags |= IsoTpBehaviour::CAN_ISOTP_RX_EXT_ADDR;
+        }
+
+        isotp_options.set_flags(flags);
+
+        let flow_control_options = FlowControlOptions::new(
+            options.block_size,
+            separation_time_to_raw(options.separation_time),
+            options.max_wait_frames,
+        );
+
+        let link_layer_options = if can_parameters.can_fd {
+            let mut tx_flags = TxFlags::empty();
+            if can_parameters.can_fd_bit_rate_switch {
+                tx_flags |= TxFlags::CANFD_BRS;
+            }
+            if can_parameters.can_fd_error_state_indicator {
+                tx_flags |= TxFlags::CANFD_ESI;
+            }
+
+            Some(LinkLayerOptions::new(
+                CANFD_MTU as u8,
+                options.tx_data_length as u8,
+                tx_flags,
+            ))
+        } else {
+            None
+        };
+
+        let socket = IsoTpSocket::open_with_opts(
+            can_parameters.socket_name.as_ref(),
+            source_id,
+            destination_id,
+            Some(isotp_options),
+            Some(flow_control_options),
+            link_layer_options,
+        )?;
+        socket.set_nonblocking(true)?;
+
+        Ok(Self {
+            inner: AsyncFd::new(EventedISOTPSocket { inner: socket })?,
+            default_read_timeout: Some(can_parameters.read_timeout),
+            default_write_timeout: Some(can_parameters.write_timeout),
+        })
     }
 
     pub async fn read(&mut self) -> Result<Vec<u8>, SocketError> {
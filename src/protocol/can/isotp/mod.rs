@@ -0,0 +1,288 @@
+use std::time::Duration;
+
+use can::identifier::Id;
+use clap::ArgEnum;
+
+use crate::common::{backend::Backend, config::CANParameters};
+
+use self::{
+    frame::CAN_FRAME_LENGTH, kernel::KernelISOTPSocket, socketcand::SocketcandISOTPSocket,
+    userspace::UserspaceISOTPSocket,
+};
+
+use super::error::{SocketBuildError, SocketError};
+
+mod frame;
+mod functional;
+mod kernel;
+mod reception;
+mod socketcand;
+mod userspace;
+
+pub use self::{
+    frame::{separation_time_to_raw, Frame, FrameFormat},
+    functional::{FunctionalRequester, FunctionalResponse},
+    reception::Reception,
+};
+
+/// Implementation of ISO-TP used by `ISOTPSocket`.
+#[derive(ArgEnum, Clone, Copy, Debug)]
+pub enum ISOTPBackend {
+    /// The kernel's `can-isotp` module.
+    Kernel,
+    /// Our own implementation, running on top of a raw CAN socket.
+    Userspace,
+}
+
+/// Tunable ISO-TP behavior, shared by both backends.
+#[derive(Clone, Default)]
+struct ISOTPOptions {
+    block_size: u8,
+    separation_time: Duration,
+    max_wait_frames: u8,
+    wait_frame_limit: u8,
+    rx_padding: Option<u8>,
+    frame_transmit_time: Option<Duration>,
+    listen_only: bool,
+    ext_address: Option<u8>,
+    rx_ext_address: Option<u8>,
+    tx_data_length: usize,
+}
+
+impl ISOTPOptions {
+    /// Address byte that transmitted frames are prefixed with, if any.
+    fn tx_address(&self) -> Option<u8> {
+        self.ext_address
+    }
+
+    /// Address byte that received frames must be prefixed with, if any.
+    ///
+    /// This is the same as the transmit address unless a separate receive address was configured.
+    fn rx_address(&self) -> Option<u8> {
+        self.ext_address
+            .map(|address| self.rx_ext_address.unwrap_or(address))
+    }
+}
+
+#[derive(Default)]
+pub struct ISOTPSocketBuilder {
+    source_id: Option<Id>,
+    destination_id: Option<Id>,
+    can_parameters: Option<CANParameters>,
+    options: ISOTPOptions,
+}
+
+impl ISOTPSocketBuilder {
+    pub fn source_id(mut self, id: impl Into<Id>) -> Self {
+        self.source_id = Some(id.into());
+        self
+    }
+
+    pub fn destination_id(mut self, id: impl Into<Id>) -> Self {
+        self.destination_id = Some(id.into());
+        self
+    }
+
+    /// Sets the CAN parameters to use, including any ISO-TP options given on the command line.
+    ///
+    /// ISO-TP options set after this override those given on the command line.
+    pub fn can_parameters(self, params: CANParameters) -> Self {
+        let mut builder = self
+            .block_size(params.isotp_block_size)
+            .separation_time(params.isotp_separation_time)
+            .max_wait_frames(params.isotp_max_wait_frames)
+            .wait_frame_limit(params.isotp_wait_frame_limit)
+            .listen_only(params.isotp_listen_only)
+            .tx_data_length(params.isotp_tx_data_length);
+        if let Some(padding) = params.isotp_rx_padding {
+            builder = builder.rx_padding(padding);
+        }
+        if let Some(frame_transmit_time) = params.isotp_frame_transmit_time {
+            builder = builder.frame_transmit_time(frame_transmit_time);
+        }
+        if let Some(address) = params.isotp_ext_address {
+            builder = builder.ext_address(address);
+        }
+        if let Some(address) = params.isotp_rx_ext_address {
+            builder = builder.rx_ext_address(address);
+        }
+        if let Some(address) = params.payload_address() {
+            builder = builder.ext_address(address.tx).rx_ext_address(address.rx);
+        }
+
+        builder.can_parameters = Some(params);
+        builder
+    }
+
+    /// Sets the number of consecutive frames a sender may send before waiting for another flow
+    /// control frame, where zero means there is no limit.
+    pub fn block_size(mut self, block_size: u8) -> Self {
+        self.options.block_size = block_size;
+        self
+    }
+
+    /// Sets the minimum time a sender must leave between consecutive frames. (STmin)
+    pub fn separation_time(mut self, separation_time: Duration) -> Self {
+        self.options.separation_time = separation_time;
+        self
+    }
+
+    /// Sets the maximum number of wait frames to send in a row while receiving. (N_WFTmax)
+    ///
+    /// Only the kernel's implementation takes this, as ours never sends wait frames.
+    pub fn max_wait_frames(mut self, max_wait_frames: u8) -> Self {
+        self.options.max_wait_frames = max_wait_frames;
+        self
+    }
+
+    /// Sets the number of wait frames to accept in a row while sending, before giving up.
+    ///
+    /// Only our implementation enforces this, as the kernel's waits for as long as it's told to.
+    pub fn wait_frame_limit(mut self, wait_frame_limit: u8) -> Self {
+        self.options.wait_frame_limit = wait_frame_limit;
+        self
+    }
+
+    /// Requires received frames to be padded out to a full frame with the given byte.
+    pub fn rx_padding(mut self, padding: u8) -> Self {
+        self.options.rx_padding = Some(padding);
+        self
+    }
+
+    /// Sets the time each transmitted frame takes on the bus, which is left between consecutive
+    /// frames on top of any separation time. (N_As)
+    pub fn frame_transmit_time(mut self, frame_transmit_time: Duration) -> Self {
+        self.options.frame_transmit_time = Some(frame_transmit_time);
+        self
+    }
+
+    /// Sets whether to only listen, never sending flow control frames.
+    pub fn listen_only(mut self, listen_only: bool) -> Self {
+        self.options.listen_only = listen_only;
+        self
+    }
+
+    /// Sets the extended address byte that frames are prefixed with.
+    pub fn ext_address(mut self, address: u8) -> Self {
+        self.options.ext_address = Some(address);
+        self
+    }
+
+    /// Sets the extended address byte that received frames are prefixed with, when it differs
+    /// from the one transmitted frames are prefixed with.
+    pub fn rx_ext_address(mut self, address: u8) -> Self {
+        self.options.rx_ext_address = Some(address);
+        self
+    }
+
+    /// Sets the length of transmitted frames. (TX_DL)
+    ///
+    /// Anything longer than a classic frame requires CAN FD.
+    pub fn tx_data_length(mut self, tx_data_length: u8) -> Self {
+        self.options.tx_data_length = tx_data_length as usize;
+        self
+    }
+
+    pub fn build(self) -> Result<ISOTPSocket, SocketBuildError> {
+        let source_id = self
+            .source_id
+            .ok_or(SocketBuildError::MissingRequiredField {
+                field_name: "source_id",
+            })?;
+        let destination_id = self
+            .destination_id
+            .ok_or(SocketBuildError::MissingRequiredField {
+                field_name: "destination_id",
+            })?;
+        let can_parameters = self
+            .can_parameters
+            .ok_or(SocketBuildError::MissingRequiredField {
+                field_name: "can_parameters",
+            })?;
+
+        if self.options.tx_data_length > CAN_FRAME_LENGTH && !can_parameters.can_fd {
+            return Err(SocketBuildError::InvalidOption {
+                option_name: "tx_data_length",
+            });
+        }
+
+        // Only SocketCAN interfaces have the kernel's implementation, so adapters get ours, other
+        // than socketcand daemons, which have the kernel's on their end.
+        let inner = match (&can_parameters.backend, can_parameters.isotp_backend) {
+            (Backend::SocketCAN, ISOTPBackend::Kernel) => Inner::Kernel(KernelISOTPSocket::open(
+                &can_parameters,
+                &self.options,
+                source_id,
+                destination_id,
+            )?),
+            (Backend::Socketcand { .. }, ISOTPBackend::Kernel) => {
+                Inner::Socketcand(SocketcandISOTPSocket::open(
+                    &can_parameters,
+                    &self.options,
+                    source_id,
+                    destination_id,
+                )?)
+            }
+            _ => Inner::Userspace(UserspaceISOTPSocket::open(
+                &can_parameters,
+                &self.options,
+                source_id,
+                destination_id,
+            )?),
+        };
+
+        Ok(ISOTPSocket { inner })
+    }
+}
+
+enum Inner {
+    Kernel(KernelISOTPSocket),
+    Userspace(UserspaceISOTPSocket),
+    Socketcand(SocketcandISOTPSocket),
+}
+
+pub struct ISOTPSocket {
+    inner: Inner,
+}
+
+impl ISOTPSocket {
+    pub fn builder() -> ISOTPSocketBuilder {
+        ISOTPSocketBuilder::default()
+    }
+
+    pub async fn read(&mut self) -> Result<Vec<u8>, SocketError> {
+        match &mut self.inner {
+            Inner::Kernel(socket) => socket.read().await,
+            Inner::Userspace(socket) => socket.read().await,
+            Inner::Socketcand(socket) => socket.read().await,
+        }
+    }
+
+    pub async fn write(&mut self, buf: &[u8]) -> Result<(), SocketError> {
+        match &mut self.inner {
+            Inner::Kernel(socket) => socket.write(buf).await,
+            Inner::Userspace(socket) => socket.write(buf).await,
+            Inner::Socketcand(socket) => socket.write(buf).await,
+        }
+    }
+}
+
+/// Encodes a payload as a single frame, for sending over a raw socket.
+///
+/// The payload must fit in a single frame, after the address byte, if any.
+pub fn encode_single_frame(payload: &[u8], address: Option<u8>, padding: Option<u8>) -> Vec<u8> {
+    Frame::Single(payload).encode(address, padding)
+}
+
+/// Strips the address byte from the data of a frame received over a raw socket.
+///
+/// Returns `None` if the frame is addressed to someone else.
+pub fn strip_address(data: &[u8], address: Option<u8>) -> Option<&[u8]> {
+    match address {
+        Some(address) => match data.split_first() {
+            Some((actual, data)) if *actual == address => Some(data),
+            _ => None,
+        },
+        None => Some(data),
+    }
+}
@@ -0,0 +1,65 @@
+use crate::protocol::can::error::SocketError;
+
+/// Most we'll allocate up front for a payload being received.
+const MAX_INITIAL_CAPACITY: usize = 0x1000;
+
+/// A segmented payload being received.
+pub struct Reception {
+    length: usize,
+    payload: Vec<u8>,
+    sequence_number: u8,
+    frames_in_block: u8,
+}
+
+impl Reception {
+    /// Starts receiving a payload of `length` bytes, given the data of its first frame.
+    pub fn new(length: usize, data: &[u8]) -> Self {
+        // Lengths come from the sender, so don't trust them with the allocation up front.
+        let mut payload = Vec::with_capacity(length.min(MAX_INITIAL_CAPACITY));
+        payload.extend_from_slice(&data[..data.len().min(length)]);
+
+        Self {
+            length,
+            payload,
+            sequence_number: 1,
+            frames_in_block: 0,
+        }
+    }
+
+    /// Appends the data of a consecutive frame, ignoring any padding past the end of the payload.
+    ///
+    /// Returns the number of bytes appended.
+    pub fn push(&mut self, sequence_number: u8, data: &[u8]) -> Result<usize, SocketError> {
+        if sequence_number != self.sequence_number {
+            return Err(SocketError::SequenceNumber {
+                expected: self.sequence_number,
+                actual: sequence_number,
+            });
+        }
+
+        let appended = data.len().min(self.length - self.payload.len());
+        self.payload.extend_from_slice(&data[..appended]);
+        self.sequence_number = (self.sequence_number + 1) & 0x0F;
+        self.frames_in_block = self.frames_in_block.wrapping_add(1);
+        Ok(appended)
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.payload.len() >= self.length
+    }
+
+    /// Checks whether the last consecutive frame finished a block of `block_size` frames, in which
+    /// case the sender is waiting for another flow control frame, and the next block begins.
+    pub fn end_of_block(&mut self, block_size: u8) -> bool {
+        if block_size != 0 && self.frames_in_block == block_size {
+            self.frames_in_block = 0;
+            true
+        } else {
+            false
+        }
+    }
+
+    pub fn into_payload(self) -> Vec<u8> {
+        self.payload
+    }
+}
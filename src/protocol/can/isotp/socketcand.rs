@@ -0,0 +1,146 @@
+use std::time::Duration;
+
+use can::identifier::Id;
+use socketcan_isotp::IsoTpBehaviour;
+use tokio::time::timeout;
+
+use crate::{
+    common::{backend::Backend, config::CANParameters},
+    protocol::can::{
+        error::{SocketBuildError, SocketError},
+        socketcand::{decode_hex, encode_hex, Connection, Mode},
+    },
+};
+
+use super::{frame::separation_time_to_raw, ISOTPOptions};
+
+/// ISO-TP socket on an isotpmode channel of a socketcand daemon.
+///
+/// The daemon does the segmentation, reassembly, and flow control on its end, with the kernel's
+/// `can-isotp` module, so only whole payloads cross the connection.
+pub struct SocketcandISOTPSocket {
+    connection: Connection,
+    default_read_timeout: Duration,
+    default_write_timeout: Duration,
+}
+
+impl SocketcandISOTPSocket {
+    pub(super) fn open(
+        can_parameters: &CANParameters,
+        options: &ISOTPOptions,
+        source_id: Id,
+        destination_id: Id,
+    ) -> Result<Self, SocketBuildError> {
+        let (host, port, interface) = match &can_parameters.backend {
+            Backend::Socketcand {
+                host,
+                port,
+                interface,
+            } => (host, *port, interface),
+            _ => {
+                return Err(SocketBuildError::InvalidOption {
+                    option_name: "backend",
+                })
+            }
+        };
+
+        // Channels can't be configured for CAN FD, or to space frames out.
+        if can_parameters.can_fd {
+            return Err(SocketBuildError::InvalidOption {
+                option_name: "can_fd",
+            });
+        }
+        if options.frame_transmit_time.is_some() {
+            return Err(SocketBuildError::InvalidOption {
+                option_name: "frame_transmit_time",
+            });
+        }
+
+        let mut flags = IsoTpBehaviour::empty();
+        if !can_parameters.disable_isotp_frame_padding {
+            flags |= IsoTpBehaviour::CAN_ISOTP_TX_PADDING;
+        }
+        if options.rx_padding.is_some() {
+            flags |= IsoTpBehaviour::CAN_ISOTP_RX_PADDING
+                | IsoTpBehaviour::CAN_ISOTP_CHK_PAD_LEN
+                | IsoTpBehaviour::CAN_ISOTP_CHK_PAD_DATA;
+        }
+        if options.listen_only {
+            flags |= IsoTpBehaviour::CAN_ISOTP_LISTEN_MODE;
+        }
+        if options.tx_address().is_some() {
+            flags |= IsoTpBehaviour::CAN_ISOTP_EXTEND_ADDR;
+        }
+        if options.rx_address().is_some() {
+            flags |= IsoTpBehaviour::CAN_ISOTP_RX_EXT_ADDR;
+        }
+
+        // The channel's identifiers, flags, block size, STmin, N_WFTmax, padding, and addresses.
+        let configuration = format!(
+            "{} {} {:X} {} {:X} {} {:X} {:X} {:X} {:X}",
+            format_id(destination_id),
+            format_id(source_id),
+            flags.bits(),
+            options.block_size,
+            separation_time_to_raw(options.separation_time),
+            options.max_wait_frames,
+            can_parameters.tx_frame_padding,
+            options.rx_padding.unwrap_or_default(),
+            options.tx_address().unwrap_or_default(),
+            options.rx_address().unwrap_or_default(),
+        );
+        let connection = Connection::open(
+            host,
+            port,
+            interface,
+            Mode::ISOTP(configuration),
+            can_parameters.read_timeout,
+        )?;
+
+        Ok(Self {
+            connection,
+            default_read_timeout: can_parameters.read_timeout,
+            default_write_timeout: can_parameters.write_timeout,
+        })
+    }
+
+    pub async fn read(&mut self) -> Result<Vec<u8>, SocketError> {
+        let duration = self.default_read_timeout;
+        timeout(duration, read_payload(&mut self.connection))
+            .await
+            .map_err(|_| SocketError::Timeout(duration))?
+    }
+
+    pub async fn write(&mut self, buf: &[u8]) -> Result<(), SocketError> {
+        let duration = self.default_write_timeout;
+        let element = format!("sendpdu {}", encode_hex(buf));
+        timeout(duration, self.connection.write_element(&element))
+            .await
+            .map_err(|_| SocketError::Timeout(duration))?
+    }
+}
+
+/// Reads the next payload the daemon has reassembled, `pdu <data>`.
+async fn read_payload(connection: &mut Connection) -> Result<Vec<u8>, SocketError> {
+    loop {
+        let element = connection.read_element().await?;
+        if let [kind, .., payload] = element.as_slice() {
+            if kind == "pdu" {
+                if let Some(payload) = decode_hex(payload) {
+                    return Ok(payload);
+                }
+            }
+        }
+    }
+}
+
+/// Formats an identifier as the daemon expects: three digits for 11-bit identifiers, or eight for
+/// 29-bit.
+fn format_id(id: Id) -> String {
+    let raw = id.as_raw();
+    if raw > 0x7FF {
+        format!("{:08X}", raw)
+    } else {
+        format!("{:03X}", raw)
+    }
+}
@@ -0,0 +1,579 @@
+use std::time::Duration;
+
+use can::identifier::Id;
+use tokio::time::{sleep, Instant};
+use tracing::trace;
+
+use crate::{
+    common::{addressing::exact_id_filter, config::CANParameters},
+    protocol::can::{
+        error::{SocketBuildError, SocketError},
+        frame::CANAnyFrame,
+        raw::RawSocket,
+    },
+};
+
+use super::{
+    frame::{
+        consecutive_frame_data_length, first_frame_data_length, header_length, is_padded,
+        max_single_frame_length, FlowStatus, Frame, FrameFormat, MAX_PAYLOAD_LENGTH,
+    },
+    reception::Reception,
+    ISOTPOptions,
+};
+
+/// How long to wait for a flow control frame after sending a first frame, or the last consecutive
+/// frame of a block. (N_Bs)
+const FLOW_CONTROL_TIMEOUT: Duration = Duration::from_millis(1000);
+
+/// How long to wait for the next consecutive frame. (N_Cr)
+const CONSECUTIVE_FRAME_TIMEOUT: Duration = Duration::from_millis(1000);
+
+/// Time each transmitted frame takes on the bus, unless configured otherwise, which is the same as
+/// the kernel's default. (N_As)
+const DEFAULT_FRAME_TRANSMIT_TIME: Duration = Duration::from_micros(50);
+
+/// ISO-TP socket implemented in userspace on top of a raw CAN socket.
+///
+/// Segmentation, reassembly, and flow control are all handled here, rather than by the kernel, so
+/// this works without the `can-isotp` module.  Each frame is subject to the write timeout (N_As),
+/// while waiting for flow control (N_Bs) and consecutive frames (N_Cr) have their own timeouts.
+pub struct UserspaceISOTPSocket {
+    socket: RawSocket,
+    destination_id: Id,
+    tx_padding: Option<u8>,
+    frame_format: FrameFormat,
+    options: ISOTPOptions,
+    default_read_timeout: Duration,
+}
+
+impl UserspaceISOTPSocket {
+    pub(super) fn open(
+        can_parameters: &CANParameters,
+        options: &ISOTPOptions,
+        source_id: Id,
+        destination_id: Id,
+    ) -> Result<Self, SocketBuildError> {
+        let socket = RawSocket::builder()
+            .can_parameters(can_parameters.clone())
+            .source_id_filter(exact_id_filter(source_id))
+            .build()?;
+
+        Ok(Self {
+            socket,
+            destination_id,
+            tx_padding: can_parameters.tx_padding(),
+            frame_format: FrameFormat::new(can_parameters),
+            options: options.clone(),
+            default_read_timeout: can_parameters.read_timeout,
+        })
+    }
+
+    pub async fn read(&mut self) -> Result<Vec<u8>, SocketError> {
+        let deadline = Instant::now() + self.default_read_timeout;
+        let rx_address = self.options.rx_address();
+        let mut reception: Option<Reception> = None;
+
+        loop {
+            let frame = match reception {
+                None => {
+                    self.read_frame_until(deadline, self.default_read_timeout)
+                        .await?
+                }
+                Some(_) => {
+                    self.socket
+                        .read_with_timeout(Some(CONSECUTIVE_FRAME_TIMEOUT))
+                        .await?
+                }
+            };
+
+            let parsed = Frame::parse(frame.data(), rx_address);
+            let header_length = parsed
+                .as_ref()
+                .map(|parsed| header_length(parsed, rx_address, frame.data().len()))
+                .unwrap_or_default();
+            match parsed {
+                // A new single or first frame in the middle of a reception means the sender gave
+                // up on the old one, so we do too.
+                Some(Frame::Single(data)) => {
+                    self.check_padding(frame.data(), header_length + data.len())?;
+                    return Ok(data.to_vec());
+                }
+                Some(Frame::First { length, data }) => {
+                    reception = Some(Reception::new(length, data));
+                    self.send_flow_control().await?;
+                }
+                Some(Frame::Consecutive {
+                    sequence_number,
+                    data,
+                }) => {
+                    if let Some(current) = reception.as_mut() {
+                        let appended = current.push(sequence_number, data)?;
+                        if current.is_complete() {
+                            self.check_padding(frame.data(), header_length + appended)?;
+                            let current = reception.take().expect("reception should be present");
+                            return Ok(current.into_payload());
+                        }
+
+                        if current.end_of_block(self.options.block_size) {
+                            self.send_flow_control().await?;
+                        }
+                    } else {
+                        trace!("ignoring consecutive frame outside of a reception");
+                    }
+                }
+                _ => trace!("ignoring unexpected frame: {:02X?}", frame.data()),
+            }
+        }
+    }
+
+    pub async fn write(&mut self, buf: &[u8]) -> Result<(), SocketError> {
+        if self.options.listen_only {
+            return Err(SocketError::ListenOnly);
+        }
+
+        let tx_address = self.options.tx_address();
+        let tx_data_length = self.options.tx_data_length;
+        if buf.is_empty() || buf.len() > MAX_PAYLOAD_LENGTH {
+            return Err(SocketError::PayloadLength(buf.len()));
+        }
+
+        if buf.len() <= max_single_frame_length(tx_address, tx_data_length) {
+            return self.send_frame(Frame::Single(buf)).await;
+        }
+
+        let first_frame_data_length =
+            first_frame_data_length(tx_address, tx_data_length, buf.len());
+        self.send_frame(Frame::First {
+            length: buf.len(),
+            data: &buf[..first_frame_data_length],
+        })
+        .await?;
+
+        let frame_transmit_time = self
+            .options
+            .frame_transmit_time
+            .unwrap_or(DEFAULT_FRAME_TRANSMIT_TIME);
+        let consecutive_frame_data_length =
+            consecutive_frame_data_length(tx_address, tx_data_length);
+        let mut offset = first_frame_data_length;
+        let mut sequence_number = 1;
+        loop {
+            let (block_size, separation_time) = self.wait_for_flow_control().await?;
+
+            let mut frames_in_block = 0usize;
+            while offset < buf.len() {
+                if frames_in_block > 0 {
+                    sleep(separation_time + frame_transmit_time).await;
+                }
+
+                let end = buf.len().min(offset + consecutive_frame_data_length);
+                self.send_frame(Frame::Consecutive {
+                    sequence_number,
+                    data: &buf[offset..end],
+                })
+                .await?;
+
+                offset = end;
+                sequence_number = (sequence_number + 1) & 0x0F;
+                frames_in_block += 1;
+                if block_size != 0 && frames_in_block == block_size as usize {
+                    break;
+                }
+            }
+
+            if offset == buf.len() {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Waits for the receiver to let us continue sending, returning the block size and separation
+    /// time it asked for.
+    async fn wait_for_flow_control(&mut self) -> Result<(u8, Duration), SocketError> {
+        let rx_address = self.options.rx_address();
+        let wait_frame_limit = self.options.wait_frame_limit;
+
+        let mut waits = 0;
+        let mut deadline = Instant::now() + FLOW_CONTROL_TIMEOUT;
+        loop {
+            let frame = self
+                .read_frame_until(deadline, FLOW_CONTROL_TIMEOUT)
+                .await?;
+            match Frame::parse(frame.data(), rx_address) {
+                Some(
+                    parsed @ Frame::FlowControl {
+                        status,
+                        block_size,
+                        separation_time,
+                    },
+                ) => {
+                    let header_length = header_length(&parsed, rx_address, frame.data().len());
+                    self.check_padding(frame.data(), header_length)?;
+                    match status {
+                        FlowStatus::ContinueToSend => return Ok((block_size, separation_time)),
+                        FlowStatus::Wait => {
+                            waits += 1;
+                            if waits > wait_frame_limit {
+                                return Err(SocketError::WaitLimitExceeded(wait_frame_limit));
+                            }
+
+                            deadline = Instant::now() + FLOW_CONTROL_TIMEOUT;
+                        }
+                        FlowStatus::Overflow => return Err(SocketError::Overflow),
+                        FlowStatus::Reserved(raw) => {
+                            return Err(SocketError::InvalidFlowStatus(raw))
+                        }
+                    }
+                }
+                _ => trace!(
+                    "ignoring frame while waiting for flow control: {:02X?}",
+                    frame.data()
+                ),
+            }
+        }
+    }
+
+    async fn send_flow_control(&mut self) -> Result<(), SocketError> {
+        if self.options.listen_only {
+            return Ok(());
+        }
+
+        self.send_frame(Frame::FlowControl {
+            status: FlowStatus::ContinueToSend,
+            block_size: self.options.block_size,
+            separation_time: self.options.separation_time,
+        })
+        .await
+    }
+
+    async fn send_frame(&mut self, frame: Frame<'_>) -> Result<(), SocketError> {
+        let data = frame.encode(self.options.tx_address(), self.tx_padding);
+        let id = self.destination_id.as_raw();
+        self.socket.write(self.frame_format.frame(id, &data)).await
+    }
+
+    /// Checks the padding of the final frame of a received payload, if receive padding is required.
+    fn check_padding(&self, data: &[u8], used: usize) -> Result<(), SocketError> {
+        match self.options.rx_padding {
+            Some(padding) if !is_padded(data, used, padding) => {
+                Err(SocketError::InvalidPadding(padding))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Reads the next frame, failing with a timeout of `timeout` once `deadline` has passed.
+    async fn read_frame_until(
+        &mut self,
+        deadline: Instant,
+        timeout: Duration,
+    ) -> Result<CANAnyFrame, SocketError> {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            return Err(SocketError::Timeout(timeout));
+        }
+
+        self.socket
+            .read_with_timeout(Some(remaining))
+            .await
+            .map_err(|e| match e {
+                SocketError::Timeout(_) => SocketError::Timeout(timeout),
+                e => e,
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use socketcan::CANFrame;
+    use tokio::time::Instant;
+
+    use crate::{
+        common::addressing::id_from_raw,
+        protocol::can::{
+            error::{SocketBuildError, SocketError},
+            frame::{CANAnyFrame, CANFDFrame},
+            isotp::ISOTPSocket,
+            mock::MockBus,
+            raw::RawSocket,
+        },
+    };
+
+    use super::{CONSECUTIVE_FRAME_TIMEOUT, FLOW_CONTROL_TIMEOUT};
+
+    const TESTER_ID: u32 = 0x7E8;
+    const ECU_ID: u32 = 0x7E0;
+
+    fn socket(bus: &MockBus, source_id: u32, destination_id: u32, args: &[&str]) -> ISOTPSocket {
+        let args = ["--read-timeout", "500ms"]
+            .iter()
+            .chain(args)
+            .copied()
+            .collect::<Vec<_>>();
+        ISOTPSocket::builder()
+            .can_parameters(bus.can_parameters(&args))
+            .source_id(id_from_raw(source_id).unwrap())
+            .destination_id(id_from_raw(destination_id).unwrap())
+            .build()
+            .unwrap()
+    }
+
+    /// Sends a frame to the tester from a raw socket standing in for the ECU.
+    async fn send(ecu: &mut RawSocket, data: &[u8]) {
+        let frame = CANFrame::new(TESTER_ID, data, false, false).unwrap();
+        ecu.write(frame).await.unwrap();
+    }
+
+    /// Receives the next frame the tester sent to the ECU.
+    async fn receive(ecu: &mut RawSocket) -> Vec<u8> {
+        let frame = ecu.read().await.unwrap();
+        assert_eq!(frame.id(), ECU_ID);
+        frame.data().to_vec()
+    }
+
+    #[tokio::test]
+    async fn exchanges_payloads_over_a_raw_socket() {
+        let bus = MockBus::new();
+        let mut tester = socket(&bus, TESTER_ID, ECU_ID, &[]);
+        let mut ecu = socket(&bus, ECU_ID, TESTER_ID, &[]);
+
+        let request = (0..100).collect::<Vec<u8>>();
+        let (sent, received) = tokio::join!(tester.write(&request), ecu.read());
+        sent.unwrap();
+        assert_eq!(received.unwrap(), request);
+
+        ecu.write(&[0x50, 0x03]).await.unwrap();
+        assert_eq!(tester.read().await.unwrap(), vec![0x50, 0x03]);
+    }
+
+    #[tokio::test]
+    async fn segments_payloads_and_wraps_sequence_numbers() {
+        let bus = MockBus::new();
+        let mut ecu = bus.raw_socket();
+        let mut tester = socket(&bus, TESTER_ID, ECU_ID, &[]);
+
+        let payload = (0..200).collect::<Vec<u8>>();
+        let ecu_side = async {
+            let first = receive(&mut ecu).await;
+            assert_eq!(first[..2], [0x10, 200]);
+            send(&mut ecu, &[0x30, 0x00, 0x00]).await;
+
+            let mut received = first[2..].to_vec();
+            let mut sequence_numbers = Vec::new();
+            while received.len() < payload.len() {
+                let consecutive = receive(&mut ecu).await;
+                sequence_numbers.push(consecutive[0]);
+                received.extend_from_slice(&consecutive[1..]);
+            }
+            received.truncate(payload.len());
+            (received, sequence_numbers)
+        };
+
+        let (sent, (received, sequence_numbers)) = tokio::join!(tester.write(&payload), ecu_side);
+        sent.unwrap();
+        assert_eq!(received, payload);
+        assert_eq!(sequence_numbers.len(), 28);
+        assert_eq!(sequence_numbers[..2], [0x21, 0x22]);
+        assert_eq!(sequence_numbers[14..17], [0x2F, 0x20, 0x21]);
+    }
+
+    #[tokio::test]
+    async fn reassembles_payloads_in_blocks() {
+        let bus = MockBus::new();
+        let mut ecu = bus.raw_socket();
+        let mut tester = socket(
+            &bus,
+            TESTER_ID,
+            ECU_ID,
+            &["--isotp-block-size", "8", "--isotp-separation-time", "5ms"],
+        );
+
+        let payload = (0..120).collect::<Vec<u8>>();
+        let ecu_side = async {
+            send(&mut ecu, &[&[0x10, 120][..], &payload[..6]].concat()).await;
+            let mut flow_controls = vec![receive(&mut ecu).await];
+
+            // Sequence numbers wrap from 0xF back to 0x0, rather than to 0x1.
+            let mut sequence_number = 1u8;
+            for (i, chunk) in payload[6..].chunks(7).enumerate() {
+                if i > 0 && i % 8 == 0 {
+                    flow_controls.push(receive(&mut ecu).await);
+                }
+                send(&mut ecu, &[&[0x20 | sequence_number][..], chunk].concat()).await;
+                sequence_number = (sequence_number + 1) & 0x0F;
+            }
+            flow_controls
+        };
+
+        let (received, flow_controls) = tokio::join!(tester.read(), ecu_side);
+        assert_eq!(received.unwrap(), payload);
+
+        // 17 consecutive frames, in blocks of 8, each with its own flow control.
+        assert_eq!(flow_controls.len(), 3);
+        for flow_control in flow_controls {
+            assert_eq!(flow_control[..3], [0x30, 0x08, 0x05]);
+        }
+    }
+
+    #[tokio::test]
+    async fn waits_for_flow_control_after_each_block() {
+        let bus = MockBus::new();
+        let mut ecu = bus.raw_socket();
+        let mut tester = socket(&bus, TESTER_ID, ECU_ID, &[]);
+
+        let payload = (0..34).collect::<Vec<u8>>();
+        let ecu_side = async {
+            receive(&mut ecu).await;
+            // Two frames at a time, at least 20ms apart.
+            let started_at = Instant::now();
+            send(&mut ecu, &[0x30, 0x02, 0x14]).await;
+
+            receive(&mut ecu).await;
+            receive(&mut ecu).await;
+            assert!(started_at.elapsed() >= Duration::from_millis(20));
+
+            // Nothing more until we let the tester continue.
+            assert!(matches!(
+                ecu.read_with_timeout(Some(Duration::from_millis(50))).await,
+                Err(SocketError::Timeout(_))
+            ));
+            send(&mut ecu, &[0x30, 0x02, 0x00]).await;
+            assert_eq!(receive(&mut ecu).await[0], 0x23);
+            assert_eq!(receive(&mut ecu).await[0], 0x24);
+        };
+
+        let (sent, ()) = tokio::join!(tester.write(&payload), ecu_side);
+        sent.unwrap();
+    }
+
+    #[tokio::test]
+    async fn follows_flow_status() {
+        let bus = MockBus::new();
+        let mut ecu = bus.raw_socket();
+        let mut tester = socket(&bus, TESTER_ID, ECU_ID, &["--isotp-wait-frame-limit", "2"]);
+        let payload = (0..20).collect::<Vec<u8>>();
+
+        // Waiting, and then continuing.
+        let ecu_side = async {
+            receive(&mut ecu).await;
+            send(&mut ecu, &[0x31, 0x00, 0x00]).await;
+            send(&mut ecu, &[0x31, 0x00, 0x00]).await;
+            send(&mut ecu, &[0x30, 0x00, 0x00]).await;
+            receive(&mut ecu).await;
+            receive(&mut ecu).await;
+        };
+        let (sent, ()) = tokio::join!(tester.write(&payload), ecu_side);
+        sent.unwrap();
+
+        // Waiting for too long.
+        let ecu_side = async {
+            receive(&mut ecu).await;
+            for _ in 0..3 {
+                send(&mut ecu, &[0x31, 0x00, 0x00]).await;
+            }
+        };
+        let (sent, ()) = tokio::join!(tester.write(&payload), ecu_side);
+        assert!(matches!(sent, Err(SocketError::WaitLimitExceeded(2))));
+
+        // Overflowing.
+        let ecu_side = async {
+            receive(&mut ecu).await;
+            send(&mut ecu, &[0x32, 0x00, 0x00]).await;
+        };
+        let (sent, ()) = tokio::join!(tester.write(&payload), ecu_side);
+        assert!(matches!(sent, Err(SocketError::Overflow)));
+
+        // Reserved flow status.
+        let ecu_side = async {
+            receive(&mut ecu).await;
+            send(&mut ecu, &[0x33, 0x00, 0x00]).await;
+        };
+        let (sent, ()) = tokio::join!(tester.write(&payload), ecu_side);
+        assert!(matches!(sent, Err(SocketError::InvalidFlowStatus(0x03))));
+    }
+
+    #[tokio::test]
+    async fn times_out_waiting_for_flow_control() {
+        let bus = MockBus::new();
+        let mut tester = socket(&bus, TESTER_ID, ECU_ID, &[]);
+
+        assert!(matches!(
+            tester.write(&[0x2E; 20]).await,
+            Err(SocketError::Timeout(timeout)) if timeout == FLOW_CONTROL_TIMEOUT
+        ));
+    }
+
+    #[tokio::test]
+    async fn times_out_waiting_for_consecutive_frames() {
+        let bus = MockBus::new();
+        let mut ecu = bus.raw_socket();
+        let mut tester = socket(&bus, TESTER_ID, ECU_ID, &[]);
+
+        let ecu_side = async {
+            send(&mut ecu, &[0x10, 20, 0x62, 0xF1, 0x90, 0x31, 0x48, 0x47]).await;
+            receive(&mut ecu).await;
+        };
+        let (received, ()) = tokio::join!(tester.read(), ecu_side);
+        assert!(matches!(
+            received,
+            Err(SocketError::Timeout(timeout)) if timeout == CONSECUTIVE_FRAME_TIMEOUT
+        ));
+    }
+
+    #[tokio::test]
+    async fn sends_fd_frames_up_to_the_tx_data_length() {
+        let bus = MockBus::new();
+        let mut ecu = bus.raw_socket();
+        let mut tester = socket(
+            &bus,
+            TESTER_ID,
+            ECU_ID,
+            &["--can-fd", "--isotp-tx-data-length", "64"],
+        );
+
+        let payload = (0..100).collect::<Vec<u8>>();
+        let ecu_side = async {
+            let first = ecu.read().await.unwrap();
+            assert!(matches!(first, CANAnyFrame::FD(_)));
+            assert_eq!(first.data().len(), 64);
+            assert_eq!(first.data()[..2], [0x10, 100]);
+            assert_eq!(first.data()[2..], payload[..62]);
+            send(&mut ecu, &[0x30, 0x00, 0x00]).await;
+
+            // The remaining 38 bytes are padded out to the next valid FD length.
+            let consecutive = receive(&mut ecu).await;
+            assert_eq!(consecutive.len(), 48);
+            assert_eq!(consecutive[0], 0x21);
+            assert_eq!(consecutive[1..39], payload[62..]);
+            assert!(consecutive[39..].iter().all(|b| *b == 0xCC));
+
+            // Single frames too long for a classic frame carry their length after the PCI.
+            let response = [&[0x00, 20][..], &payload[..20]].concat();
+            let frame = CANFDFrame::new(TESTER_ID, &response, false, false).unwrap();
+            ecu.write(frame).await.unwrap();
+        };
+
+        let (sent, ()) = tokio::join!(tester.write(&payload), ecu_side);
+        sent.unwrap();
+        assert_eq!(tester.read().await.unwrap(), payload[..20]);
+    }
+
+    #[tokio::test]
+    async fn requires_can_fd_for_long_frames() {
+        let bus = MockBus::new();
+        let built = ISOTPSocket::builder()
+            .can_parameters(bus.can_parameters(&["--isotp-tx-data-length", "64"]))
+            .source_id(id_from_raw(TESTER_ID).unwrap())
+            .destination_id(id_from_raw(ECU_ID).unwrap())
+            .build();
+        assert!(matches!(
+            built,
+            Err(SocketBuildError::InvalidOption {
+                option_name: "tx_data_length"
+            })
+        ));
+    }
+}
@@ -0,0 +1,321 @@
+//! An in-memory CAN bus, for exercising everything above the sockets without SocketCAN.
+//!
+//! Much like a virtual CAN interface, every socket on the bus sees whatever every other socket
+//! writes.  The mock ISO-TP sockets exchange whole payloads rather than frames, for testing
+//! services without caring about segmentation.  Raw sockets carry frames, and anything built from
+//! `CANParameters` with the bus as its backend runs on them, ISO-TP included.
+
+use std::{
+    fmt,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use can::identifier::Id;
+use tokio::{
+    sync::broadcast::{
+        self,
+        error::{RecvError, TryRecvError},
+    },
+    time::{timeout, Instant},
+};
+
+use async_trait::async_trait;
+use clap::Parser;
+
+use crate::common::{
+    addressing::{Addressing, IdFilter},
+    backend::Backend,
+    config::{AppConfig, CANParameters},
+};
+
+use super::{
+    error::SocketError,
+    frame::CANAnyFrame,
+    isotp::FunctionalResponse,
+    raw::{RawFrames, RawSocket},
+};
+
+/// Number of frames, or payloads, a socket can fall behind by before it starts missing them.
+const BUS_CAPACITY: usize = 1024;
+
+/// How long reads wait by default, which is kept short so that timeouts don't slow tests down.
+const DEFAULT_READ_TIMEOUT: Duration = Duration::from_millis(100);
+
+#[derive(Clone)]
+pub struct MockBus {
+    frames: broadcast::Sender<(usize, CANAnyFrame)>,
+    payloads: broadcast::Sender<(Id, Vec<u8>)>,
+    next_socket: Arc<AtomicUsize>,
+}
+
+impl MockBus {
+    pub fn new() -> Self {
+        Self {
+            frames: broadcast::channel(BUS_CAPACITY).0,
+            payloads: broadcast::channel(BUS_CAPACITY).0,
+            next_socket: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+
+    /// Parses CAN parameters from the given arguments, with this bus as their backend.
+    pub fn can_parameters(&self, args: &[&str]) -> CANParameters {
+        let args = ["hypercan"]
+            .iter()
+            .chain(args)
+            .chain(&["validate-socket"])
+            .copied();
+        let mut can_parameters = AppConfig::try_parse_from(args)
+            .expect("arguments should be valid")
+            .can_parameters();
+        can_parameters.backend = Backend::Mock(self.clone());
+        can_parameters
+    }
+
+    /// Opens a raw socket, which receives every frame written by any other raw socket from now on.
+    pub fn raw_socket(&self) -> RawSocket {
+        RawSocket::from_frames(self.raw_frames(None), Some(DEFAULT_READ_TIMEOUT))
+    }
+
+    /// Opens what carries the frames of a raw socket, receiving every frame that passes `filter`.
+    pub(super) fn raw_frames(&self, filter: Option<IdFilter>) -> MockRawSocket {
+        MockRawSocket {
+            socket: self.next_socket.fetch_add(1, Ordering::Relaxed),
+            tx: self.frames.clone(),
+            rx: self.frames.subscribe(),
+            filter,
+        }
+    }
+
+    /// Opens an ISO-TP socket, which receives payloads sent to `source_id` and sends payloads to
+    /// `destination_id`.
+    pub fn isotp_socket(
+        &self,
+        source_id: impl Into<Id>,
+        destination_id: impl Into<Id>,
+    ) -> MockISOTPSocket {
+        MockISOTPSocket {
+            source_id: source_id.into(),
+            destination_id: destination_id.into(),
+            tx: self.payloads.clone(),
+            rx: self.payloads.subscribe(),
+            read_timeout: DEFAULT_READ_TIMEOUT,
+        }
+    }
+
+    /// Opens a functional requester, which sends requests to `request_id` and collects responses
+    /// addressed to the tester.
+    pub fn functional_requester(
+        &self,
+        request_id: impl Into<Id>,
+        addressing: Addressing,
+        tester_address: u8,
+    ) -> MockFunctionalRequester {
+        MockFunctionalRequester {
+            request_id: request_id.into(),
+            addressing,
+            tester_address,
+            tx: self.payloads.clone(),
+            rx: self.payloads.subscribe(),
+        }
+    }
+}
+
+impl Default for MockBus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Buses are only equal to themselves, or their clones.
+impl PartialEq for MockBus {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.next_socket, &other.next_socket)
+    }
+}
+
+impl Eq for MockBus {}
+
+impl fmt::Debug for MockBus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MockBus").finish_non_exhaustive()
+    }
+}
+
+/// Receives the next message from the bus, skipping over any that were missed by falling behind.
+async fn recv<T: Clone>(rx: &mut broadcast::Receiver<T>) -> T {
+    loop {
+        match rx.recv().await {
+            Ok(message) => return message,
+            Err(RecvError::Lagged(_)) => continue,
+            // The bus keeps its own sender, so it can't close while we're subscribed to it.
+            Err(RecvError::Closed) => unreachable!("mock bus should never close"),
+        }
+    }
+}
+
+/// Carries the frames of a `RawSocket` on the bus.
+pub struct MockRawSocket {
+    socket: usize,
+    tx: broadcast::Sender<(usize, CANAnyFrame)>,
+    rx: broadcast::Receiver<(usize, CANAnyFrame)>,
+    filter: Option<IdFilter>,
+}
+
+#[async_trait]
+impl RawFrames for MockRawSocket {
+    async fn read_frame(&mut self) -> Result<CANAnyFrame, SocketError> {
+        loop {
+            let (writer, frame) = recv(&mut self.rx).await;
+            let accepted = match self.filter {
+                Some(filter) => filter.matches(frame.id()),
+                None => true,
+            };
+            if writer != self.socket && accepted {
+                return Ok(frame);
+            }
+        }
+    }
+
+    async fn write_frame(&mut self, frame: &CANAnyFrame) -> Result<(), SocketError> {
+        // Nobody listening is no different to an empty bus, so there's nothing to report.
+        let _ = self.tx.send((self.socket, *frame));
+        Ok(())
+    }
+}
+
+/// Counterpart of `ISOTPSocket`.
+pub struct MockISOTPSocket {
+    source_id: Id,
+    destination_id: Id,
+    tx: broadcast::Sender<(Id, Vec<u8>)>,
+    rx: broadcast::Receiver<(Id, Vec<u8>)>,
+    read_timeout: Duration,
+}
+
+impl MockISOTPSocket {
+    pub async fn read(&mut self) -> Result<Vec<u8>, SocketError> {
+        let source_id = self.source_id;
+        let rx = &mut self.rx;
+        let read = async move {
+            loop {
+                let (id, payload) = recv(rx).await;
+                if id == source_id {
+                    return payload;
+                }
+            }
+        };
+
+        timeout(self.read_timeout, read)
+            .await
+            .map_err(|_| SocketError::Timeout(self.read_timeout))
+    }
+
+    pub async fn write(&mut self, buf: &[u8]) -> Result<(), SocketError> {
+        if buf.is_empty() {
+            return Err(SocketError::PayloadLength(buf.len()));
+        }
+
+        let _ = self.tx.send((self.destination_id, buf.to_vec()));
+        Ok(())
+    }
+}
+
+/// Counterpart of `FunctionalRequester`.
+pub struct MockFunctionalRequester {
+    request_id: Id,
+    addressing: Addressing,
+    tester_address: u8,
+    tx: broadcast::Sender<(Id, Vec<u8>)>,
+    rx: broadcast::Receiver<(Id, Vec<u8>)>,
+}
+
+impl MockFunctionalRequester {
+    /// Sends a functional request, and collects every response received before `listen_timeout`
+    /// elapses.
+    pub async fn request(
+        &mut self,
+        payload: &[u8],
+        listen_timeout: Duration,
+    ) -> Result<Vec<FunctionalResponse>, SocketError> {
+        if payload.is_empty() {
+            return Err(SocketError::PayloadLength(payload.len()));
+        }
+
+        // Anything already waiting was sent before this request, so it can't be a response to it.
+        while !matches!(
+            self.rx.try_recv(),
+            Err(TryRecvError::Empty) | Err(TryRecvError::Closed)
+        ) {}
+
+        let _ = self.tx.send((self.request_id, payload.to_vec()));
+
+        let deadline = Instant::now() + listen_timeout;
+        let mut responses = Vec::new();
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            let (id, payload) = match timeout(remaining, recv(&mut self.rx)).await {
+                Ok(message) => message,
+                Err(_) => break,
+            };
+
+            if let Some(response_address) =
+                self.addressing.response_address(id, self.tester_address)
+            {
+                responses.push(FunctionalResponse {
+                    response_address,
+                    payload,
+                });
+            }
+        }
+
+        Ok(responses)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use socketcan::CANFrame;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn raw_sockets_see_frames_from_other_sockets_only() {
+        let bus = MockBus::new();
+        let mut writer = bus.raw_socket();
+        let mut reader = bus.raw_socket();
+
+        let frame = CANFrame::new(0x7E0, &[0x02, 0x10, 0x01], false, false).unwrap();
+        writer.write(frame).await.unwrap();
+
+        let received = reader.read().await.unwrap();
+        assert_eq!(received.id(), 0x7E0);
+        assert_eq!(received.data(), &[0x02, 0x10, 0x01]);
+        assert!(matches!(writer.read().await, Err(SocketError::Timeout(_))));
+    }
+
+    #[tokio::test]
+    async fn isotp_sockets_only_receive_their_source_id() {
+        let bus = MockBus::new();
+        let tester_id = id(0x7E8);
+        let ecu_id = id(0x7E0);
+        let mut tester = bus.isotp_socket(tester_id, ecu_id);
+        let mut ecu = bus.isotp_socket(ecu_id, tester_id);
+        let mut other = bus.isotp_socket(id(0x7E1), id(0x7E9));
+
+        tester.write(&[0x3E, 0x00]).await.unwrap();
+        assert_eq!(ecu.read().await.unwrap(), vec![0x3E, 0x00]);
+        assert!(matches!(other.read().await, Err(SocketError::Timeout(_))));
+        assert!(matches!(
+            tester.write(&[]).await,
+            Err(SocketError::PayloadLength(0))
+        ));
+    }
+
+    fn id(raw: u32) -> Id {
+        crate::common::addressing::id_from_raw(raw).unwrap()
+    }
+}
@@ -1,3 +1,8 @@
 pub mod error;
+pub mod frame;
 pub mod isotp;
+#[cfg(test)]
+pub mod mock;
 pub mod raw;
+pub mod slcan;
+pub mod socketcand;
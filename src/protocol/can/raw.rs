@@ -5,23 +5,29 @@ use std::{
     time::Duration,
 };
 
+use async_trait::async_trait;
 use futures::ready;
 use mio::{event::Source, unix::SourceFd, Interest, Registry, Token};
-use socketcan::{CANFilter, CANFrame, CANSocket};
+use socketcan::CANSocket;
 use tokio::{io::unix::AsyncFd, macros::support::poll_fn, time::timeout};
 
-use crate::common::config::CANParameters;
+use crate::common::{addressing::IdFilter, backend::Backend, config::CANParameters};
 
-use super::error::{SocketBuildError, SocketError};
+use super::{
+    error::{SocketBuildError, SocketError},
+    frame::{enable_fd_frames, read_any_frame, write_fd_frame, CANAnyFrame},
+    slcan::SlcanSocket,
+    socketcand::SocketcandSocket,
+};
 
 #[derive(Default)]
 pub struct RawSocketBuilder {
-    source_id_filter: Option<CANFilter>,
+    source_id_filter: Option<IdFilter>,
     can_parameters: Option<CANParameters>,
 }
 
 impl RawSocketBuilder {
-    pub fn source_id_filter(mut self, filter: CANFilter) -> Self {
+    pub fn source_id_filter(mut self, filter: IdFilter) -> Self {
         self.source_id_filter = Some(filter);
         self
     }
@@ -39,34 +45,115 @@ impl RawSocketBuilder {
                 field_name: "can_parameters",
             })?;
 
-        let socket = CANSocket::open(can_parameters.socket_name.as_ref())?;
-        socket.set_nonblocking(true)?;
+        let inner: Box<dyn RawFrames> = match &can_parameters.backend {
+            Backend::SocketCAN => Box::new(open_socketcan(&can_parameters, source_id_filter)?),
+            Backend::Slcan {
+                device,
+                baud_rate,
+                bitrate,
+                timestamps,
+            } => {
+                if can_parameters.can_fd {
+                    return Err(SocketBuildError::InvalidOption {
+                        option_name: "can_fd",
+                    });
+                }
 
-        if let Some(filter) = source_id_filter {
-            socket.set_filter(&[filter])?;
-        } else {
-            socket.filter_accept_all()?;
-        }
+                Box::new(SlcanSocket::open(
+                    device,
+                    *baud_rate,
+                    *bitrate,
+                    *timestamps,
+                    source_id_filter,
+                )?)
+            }
+            Backend::Socketcand {
+                host,
+                port,
+                interface,
+            } => {
+                if can_parameters.can_fd {
+                    return Err(SocketBuildError::InvalidOption {
+                        option_name: "can_fd",
+                    });
+                }
+
+                Box::new(SocketcandSocket::open(
+                    host,
+                    *port,
+                    interface,
+                    can_parameters.read_timeout,
+                    source_id_filter,
+                )?)
+            }
+            Backend::Elm327 { .. } | Backend::DoIP { .. } => {
+                return Err(SocketBuildError::InvalidOption {
+                    option_name: "backend",
+                })
+            }
+            #[cfg(test)]
+            Backend::Mock(bus) => Box::new(bus.raw_frames(source_id_filter)),
+        };
 
         Ok(RawSocket {
-            inner: AsyncFd::new(EventedRawSocket { inner: socket })?,
+            inner,
             default_read_timeout: Some(can_parameters.read_timeout),
             default_write_timeout: Some(can_parameters.write_timeout),
         })
     }
 }
 
+fn open_socketcan(
+    can_parameters: &CANParameters,
+    source_id_filter: Option<IdFilter>,
+) -> Result<AsyncFd<EventedRawSocket>, SocketBuildError> {
+    let socket = CANSocket::open(can_parameters.socket_name.as_ref())?;
+    socket.set_nonblocking(true)?;
+
+    if can_parameters.can_fd {
+        enable_fd_frames(socket.as_raw_fd())?;
+    }
+
+    if let Some(filter) = source_id_filter {
+        socket.set_filter(&[filter.to_can_filter()])?;
+    } else {
+        socket.filter_accept_all()?;
+    }
+
+    Ok(AsyncFd::new(EventedRawSocket {
+        inner: socket,
+        fd_frames: can_parameters.can_fd,
+    })?)
+}
+
 pub struct EventedRawSocket {
     inner: CANSocket,
+    fd_frames: bool,
 }
 
 impl EventedRawSocket {
-    fn get_ref(&self) -> &CANSocket {
-        &self.inner
+    fn read_frame(&self) -> io::Result<CANAnyFrame> {
+        if self.fd_frames {
+            read_any_frame(self.inner.as_raw_fd())
+        } else {
+            self.inner.read_frame().map(Into::into)
+        }
     }
 
-    fn get_mut(&mut self) -> &mut CANSocket {
-        &mut self.inner
+    fn write_frame(&self, frame: &CANAnyFrame) -> io::Result<()> {
+        match frame {
+            CANAnyFrame::Classic(frame) => self.inner.write_frame(frame),
+            CANAnyFrame::FD(frame) => {
+                if !self.fd_frames {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        "FD frames require CAN FD to be enabled",
+                    ));
+                }
+
+                write_fd_frame(self.inner.as_raw_fd(), frame)
+            }
+        }
     }
 }
 
@@ -100,8 +187,66 @@ impl Source for EventedRawSocket {
     }
 }
 
+/// Carries the frames of a `RawSocket`, whatever it's actually on.
+#[async_trait]
+pub(super) trait RawFrames: Send {
+    async fn read_frame(&mut self) -> Result<CANAnyFrame, SocketError>;
+
+    async fn write_frame(&mut self, frame: &CANAnyFrame) -> Result<(), SocketError>;
+}
+
+#[async_trait]
+impl RawFrames for AsyncFd<EventedRawSocket> {
+    async fn read_frame(&mut self) -> Result<CANAnyFrame, SocketError> {
+        let frame = poll_fn(|cx| loop {
+            let mut ready_guard = ready!(self.poll_read_ready_mut(cx))?;
+            match ready_guard.try_io(evented_read_owned) {
+                Ok(result) => return Poll::Ready(result),
+                Err(_would_block) => continue,
+            }
+        })
+        .await?;
+        Ok(frame)
+    }
+
+    async fn write_frame(&mut self, frame: &CANAnyFrame) -> Result<(), SocketError> {
+        poll_fn(|cx| loop {
+            let mut ready_guard = ready!(self.poll_write_ready_mut(cx))?;
+            match ready_guard.try_io(|inner| evented_write(inner, frame)) {
+                Ok(result) => return Poll::Ready(result),
+                Err(_would_block) => continue,
+            }
+        })
+        .await?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl RawFrames for SlcanSocket {
+    async fn read_frame(&mut self) -> Result<CANAnyFrame, SocketError> {
+        self.read().await
+    }
+
+    async fn write_frame(&mut self, frame: &CANAnyFrame) -> Result<(), SocketError> {
+        self.write(frame).await
+    }
+}
+
+#[async_trait]
+impl RawFrames for SocketcandSocket {
+    async fn read_frame(&mut self) -> Result<CANAnyFrame, SocketError> {
+        self.read().await
+    }
+
+    async fn write_frame(&mut self, frame: &CANAnyFrame) -> Result<(), SocketError> {
+        self.write(frame).await
+    }
+}
+
+/// A raw CAN socket, on a SocketCAN interface or on an adapter standing in for one.
 pub struct RawSocket {
-    inner: AsyncFd<EventedRawSocket>,
+    inner: Box<dyn RawFrames>,
     default_read_timeout: Option<Duration>,
     default_write_timeout: Option<Duration>,
 }
@@ -111,49 +256,55 @@ impl RawSocket {
         RawSocketBuilder::default()
     }
 
-    pub async fn read(&mut self) -> Result<CANFrame, SocketError> {
-        let read = poll_fn(|cx| loop {
-            let mut ready_guard = ready!(self.inner.poll_read_ready_mut(cx))?;
-            match ready_guard.try_io(evented_read_owned) {
-                Ok(result) => return Poll::Ready(result),
-                Err(_would_block) => continue,
-            }
-        });
+    /// Wraps frames carried by something other than a configured backend.
+    #[cfg(test)]
+    pub(super) fn from_frames(
+        inner: impl RawFrames + 'static,
+        default_read_timeout: Option<Duration>,
+    ) -> Self {
+        Self {
+            inner: Box::new(inner),
+            default_read_timeout,
+            default_write_timeout: None,
+        }
+    }
+
+    pub async fn read(&mut self) -> Result<CANAnyFrame, SocketError> {
+        self.read_with_timeout(self.default_read_timeout).await
+    }
 
-        let result = if let Some(duration) = self.default_read_timeout {
+    /// Reads a frame, waiting no longer than `read_timeout` instead of the default read timeout.
+    pub async fn read_with_timeout(
+        &mut self,
+        read_timeout: Option<Duration>,
+    ) -> Result<CANAnyFrame, SocketError> {
+        let read = self.inner.read_frame();
+        if let Some(duration) = read_timeout {
             timeout(duration, read)
                 .await
                 .map_err(|_| SocketError::Timeout(duration))?
         } else {
             read.await
-        };
-        result.map_err(Into::into)
+        }
     }
 
-    pub async fn write(&mut self, frame: CANFrame) -> Result<(), SocketError> {
-        let write = poll_fn(|cx| loop {
-            let mut ready_guard = ready!(self.inner.poll_write_ready_mut(cx))?;
-            match ready_guard.try_io(|inner| evented_write(inner, &frame)) {
-                Ok(result) => return Poll::Ready(result),
-                Err(_would_block) => continue,
-            }
-        });
-
-        let result = if let Some(duration) = self.default_write_timeout {
+    pub async fn write(&mut self, frame: impl Into<CANAnyFrame>) -> Result<(), SocketError> {
+        let frame = frame.into();
+        let write = self.inner.write_frame(&frame);
+        if let Some(duration) = self.default_write_timeout {
             timeout(duration, write)
                 .await
                 .map_err(|_| SocketError::Timeout(duration))?
         } else {
             write.await
-        };
-        result.map_err(Into::into)
+        }
     }
 }
 
-fn evented_read_owned(af: &mut AsyncFd<EventedRawSocket>) -> io::Result<CANFrame> {
-    af.get_mut().get_mut().read_frame()
+fn evented_read_owned(af: &mut AsyncFd<EventedRawSocket>) -> io::Result<CANAnyFrame> {
+    af.get_ref().read_frame()
 }
 
-fn evented_write(af: &AsyncFd<EventedRawSocket>, frame: &CANFrame) -> io::Result<()> {
-    af.get_ref().get_ref().write_frame(&frame)
+fn evented_write(af: &AsyncFd<EventedRawSocket>, frame: &CANAnyFrame) -> io::Result<()> {
+    af.get_ref().write_frame(frame)
 }
@@ -0,0 +1,192 @@
+//! An slcan adapter on the other end of a pseudo-terminal, on a bus where frames get canned
+//! responses.
+
+use std::{
+    collections::{HashMap, HashSet},
+    path::PathBuf,
+    sync::mpsc as std_mpsc,
+    thread,
+};
+
+use tokio::{
+    runtime::Builder,
+    select,
+    sync::{mpsc, oneshot},
+    time::Instant,
+};
+
+use crate::protocol::serial::{open_pty, SerialPort};
+
+use super::{parse_hex, BELL, BITRATES};
+
+/// Timestamps wrap around every minute.
+const TIMESTAMP_PERIOD_MS: u128 = 60000;
+
+#[derive(Default)]
+struct State {
+    open: bool,
+    timestamps: bool,
+}
+
+/// An adapter on a bus where each frame written gets its canned responses, if any.
+#[derive(Default)]
+pub struct SlcanEmulator {
+    replies: HashMap<(u32, Vec<u8>), Vec<(u32, Vec<u8>)>>,
+    rejected: HashSet<String>,
+}
+
+impl SlcanEmulator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Answers the frame with the given identifier and data with each of `responses`, in order.
+    pub fn reply(mut self, id: u32, data: &[u8], responses: &[(u32, &[u8])]) -> Self {
+        let responses = responses
+            .iter()
+            .map(|(id, data)| (*id, data.to_vec()))
+            .collect();
+        self.replies.insert((id, data.to_vec()), responses);
+        self
+    }
+
+    /// Rejects the given command, such as a bit rate the adapter doesn't support.
+    pub fn reject(mut self, command: &str) -> Self {
+        self.rejected.insert(command.to_string());
+        self
+    }
+
+    /// Starts answering on a new pseudo-terminal until the test finishes, returning the path of
+    /// the serial device to open, and every line written to the adapter.
+    ///
+    /// Opening a socket blocks until the adapter has answered, so the adapter runs on a thread of
+    /// its own, and stops once the test's runtime shuts down.
+    pub fn spawn(self) -> (PathBuf, mpsc::UnboundedReceiver<String>) {
+        let (lines_tx, lines_rx) = mpsc::unbounded_channel();
+        let (path_tx, path_rx) = std_mpsc::channel();
+        let (stop_tx, mut stop_rx) = oneshot::channel::<()>();
+        tokio::spawn(async move {
+            let _stop = stop_tx;
+            std::future::pending::<()>().await
+        });
+
+        thread::spawn(move || {
+            let runtime = Builder::new_current_thread()
+                .enable_all()
+                .build()
+                .expect("should be able to start the emulator's runtime");
+            runtime.block_on(async move {
+                let (port, path) = open_pty().expect("should be able to open a pseudo-terminal");
+                // Masters hang up whenever nothing has the slave open, so hold it open ourselves.
+                let _slave =
+                    SerialPort::open(&path, 115200).expect("should be able to open the slave");
+                path_tx
+                    .send(path)
+                    .expect("test should be waiting for the device");
+
+                let started_at = Instant::now();
+                let mut state = State::default();
+                let mut line = Vec::new();
+                let mut buf = [0; 256];
+                loop {
+                    let n = select! {
+                        _ = &mut stop_rx => return,
+                        read = port.read(&mut buf) => match read {
+                            Ok(n) if n > 0 => n,
+                            _ => return,
+                        },
+                    };
+
+                    for byte in &buf[..n] {
+                        if *byte != b'\r' {
+                            line.push(*byte);
+                            continue;
+                        }
+
+                        let command = String::from_utf8_lossy(&line).into_owned();
+                        line.clear();
+                        let _ = lines_tx.send(command.clone());
+
+                        let timestamp = started_at.elapsed().as_millis() % TIMESTAMP_PERIOD_MS;
+                        let output = self.answer(&mut state, &command, timestamp as u16);
+                        if port.write_all(output.as_bytes()).await.is_err() {
+                            return;
+                        }
+                    }
+                }
+            })
+        });
+
+        let path = path_rx
+            .recv()
+            .expect("emulator should have opened a device");
+        (path, lines_rx)
+    }
+
+    fn answer(&self, state: &mut State, command: &str, timestamp: u16) -> String {
+        let bell = (BELL as char).to_string();
+        if self.rejected.contains(command) {
+            return bell;
+        }
+
+        let mut chars = command.chars();
+        match (chars.next(), chars.as_str()) {
+            (Some('C'), "") if state.open => {
+                state.open = false;
+                "\r".to_string()
+            }
+            (Some('O'), "") if !state.open => {
+                state.open = true;
+                "\r".to_string()
+            }
+            (Some('S'), bitrate) if !state.open => match bitrate.parse::<usize>() {
+                Ok(bitrate) if bitrate < BITRATES.len() => "\r".to_string(),
+                _ => bell,
+            },
+            (Some('Z'), "0") | (Some('Z'), "1") => {
+                state.timestamps = command == "Z1";
+                "\r".to_string()
+            }
+            (Some(kind @ 't'), frame) | (Some(kind @ 'T'), frame) if state.open => {
+                let id_length = if kind == 't' { 3 } else { 8 };
+                let (id, data) = match parse_frame(frame, id_length) {
+                    Some(frame) => frame,
+                    None => return bell,
+                };
+
+                let mut output = if kind == 't' { "z\r" } else { "Z\r" }.to_string();
+                for (id, data) in self.replies.get(&(id, data)).into_iter().flatten() {
+                    if *id > 0x7FF {
+                        output.push_str(&format!("T{:08X}", id));
+                    } else {
+                        output.push_str(&format!("t{:03X}", id));
+                    }
+                    output.push_str(&format!("{:X}", data.len()));
+                    for byte in data {
+                        output.push_str(&format!("{:02X}", byte));
+                    }
+                    if state.timestamps {
+                        output.push_str(&format!("{:04X}", timestamp));
+                    }
+                    output.push('\r');
+                }
+                output
+            }
+            _ => bell,
+        }
+    }
+}
+
+fn parse_frame(frame: &str, id_length: usize) -> Option<(u32, Vec<u8>)> {
+    let id = parse_hex(frame.get(..id_length)?)?;
+    let length = parse_hex(frame.get(id_length..id_length + 1)?)? as usize;
+    let data = frame.get(id_length + 1..)?;
+    if data.len() != length * 2 {
+        return None;
+    }
+
+    let data = (0..length)
+        .map(|i| parse_hex(&data[i * 2..i * 2 + 2]).map(|b| b as u8))
+        .collect::<Option<Vec<_>>>()?;
+    Some((id, data))
+}
@@ -0,0 +1,519 @@
+//! slcan (Lawicel) adapters, which carry raw frames as lines of ASCII over a serial device.
+//!
+//! Only one reader can have a serial device, so every raw socket opened on an adapter shares it: a
+//! task reads each frame the adapter receives, and hands it to every socket, much like the kernel
+//! does for raw sockets on a SocketCAN interface.
+
+use std::{
+    collections::HashMap,
+    io,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, Weak},
+    time::{Duration, Instant},
+};
+
+use once_cell::sync::Lazy;
+use socketcan::CANFrame;
+use tokio::{
+    sync::broadcast::{self, error::RecvError},
+    task::JoinHandle,
+};
+use tracing::{debug, error, trace, warn};
+
+use crate::{common::addressing::IdFilter, protocol::serial::SerialPort};
+
+use super::{
+    error::{SocketBuildError, SocketError},
+    frame::{classic_frame, CANAnyFrame},
+};
+
+#[cfg(test)]
+pub mod emulator;
+
+/// Bit rates adapters can be set to, in the order of the commands that set them: `S0` to `S8`.
+const BITRATES: [u32; 9] = [
+    10000, 20000, 50000, 100000, 125000, 250000, 500000, 800000, 1000000,
+];
+
+/// Number of frames a socket can fall behind by before it starts missing them.
+const DEVICE_CAPACITY: usize = 1024;
+
+/// Adapters ring the bell, rather than answering with a carriage return, when they reject a
+/// command.
+const BELL: u8 = 0x07;
+
+/// How long adapters have to acknowledge each command that sets them up.
+const COMMAND_TIMEOUT: Duration = Duration::from_millis(1000);
+
+/// Adapters that are open, so that sockets opened on the same one share it.
+static OPEN_DEVICES: Lazy<Mutex<HashMap<PathBuf, Weak<SlcanDevice>>>> = Lazy::new(Default::default);
+
+/// Whether adapters can be set to the given bit rate.
+pub fn is_supported_bitrate(bitrate: u32) -> bool {
+    BITRATES.contains(&bitrate)
+}
+
+/// An open adapter, shared by every socket on it, which closes its channel once they're all gone.
+struct SlcanDevice {
+    port: Arc<SerialPort>,
+    write_lock: tokio::sync::Mutex<()>,
+    frames: broadcast::Sender<CANAnyFrame>,
+    reader: JoinHandle<()>,
+}
+
+impl SlcanDevice {
+    /// Gets the adapter on the given serial device, opening it if it isn't open already.
+    fn shared(
+        device: &Path,
+        baud_rate: u32,
+        bitrate: u32,
+        timestamps: bool,
+    ) -> Result<Arc<Self>, SocketBuildError> {
+        let mut open_devices = OPEN_DEVICES
+            .lock()
+            .expect("open slcan devices lock should not be poisoned");
+        if let Some(open) = open_devices.get(device).and_then(Weak::upgrade) {
+            return Ok(open);
+        }
+
+        let opened = Arc::new(Self::open(device, baud_rate, bitrate, timestamps)?);
+        open_devices.insert(device.to_path_buf(), Arc::downgrade(&opened));
+        Ok(opened)
+    }
+
+    /// Opens the adapter, and opens its channel at the given bit rate.
+    ///
+    /// Like opening a local socket, this blocks until the adapter has acknowledged each command,
+    /// though never for longer than `COMMAND_TIMEOUT` at a time.
+    fn open(
+        device: &Path,
+        baud_rate: u32,
+        bitrate: u32,
+        timestamps: bool,
+    ) -> Result<Self, SocketBuildError> {
+        let bitrate_command = BITRATES
+            .iter()
+            .position(|supported| *supported == bitrate)
+            .ok_or(SocketBuildError::InvalidOption {
+                option_name: "bitrate",
+            })?;
+        let port = Arc::new(SerialPort::open(device, baud_rate)?);
+
+        // Channels can't be configured while they're open, and may have been left open, so the
+        // channel is closed first, which is rejected if it was already closed.  Adapters without
+        // timestamps may not know the command that turns them off either, which is fine.
+        command(&port, "C")?;
+        for (command_line, required) in [
+            (format!("S{}", bitrate_command), true),
+            (format!("Z{}", timestamps as u8), timestamps),
+            ("O".to_string(), true),
+        ] {
+            if !command(&port, &command_line)? && required {
+                return Err(SocketBuildError::AdapterInitialization {
+                    source: io::Error::new(
+                        io::ErrorKind::Other,
+                        format!("slcan adapter rejected '{}'", command_line),
+                    )
+                    .into(),
+                });
+            }
+        }
+
+        let (frames, _) = broadcast::channel(DEVICE_CAPACITY);
+        let reader = tokio::spawn(read_frames(port.clone(), frames.clone(), timestamps));
+
+        Ok(Self {
+            port,
+            write_lock: tokio::sync::Mutex::new(()),
+            frames,
+            reader,
+        })
+    }
+
+    async fn write(&self, frame: &CANAnyFrame) -> Result<(), SocketError> {
+        let line = encode_frame(frame)?;
+        trace!("slcan <- {}", line.trim_end());
+
+        // Lines from different sockets mustn't get mixed up with one another.
+        let _guard = self.write_lock.lock().await;
+        Ok(self.port.write_all(line.as_bytes()).await?)
+    }
+}
+
+impl Drop for SlcanDevice {
+    fn drop(&mut self) {
+        self.reader.abort();
+
+        // Nothing is waiting on the adapter anymore, so closing its channel is only a courtesy.
+        let _ = self.port.try_write(b"C\r");
+    }
+}
+
+/// Sends a command to the adapter, and waits for it to be acknowledged or rejected, returning
+/// whether it was acknowledged.
+///
+/// Frames received before the channel was closed are skipped over.  The adapter is read a byte at a
+/// time so that nothing after the acknowledgement is taken from the reader task.
+fn command(port: &SerialPort, command: &str) -> Result<bool, SocketBuildError> {
+    trace!("slcan <- {}", command);
+    let line = format!("{}\r", command);
+    if port.try_write(line.as_bytes())? != line.len() {
+        return Err(
+            io::Error::new(io::ErrorKind::WriteZero, "failed to write adapter command").into(),
+        );
+    }
+
+    let deadline = Instant::now() + COMMAND_TIMEOUT;
+    let mut skipped = Vec::new();
+    let mut byte = [0];
+    loop {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if port.read_blocking(&mut byte, remaining)? == 0 {
+            return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+        }
+
+        match byte[0] {
+            b'\r' if skipped.is_empty() => return Ok(true),
+            BELL => return Ok(false),
+            b'\r' => {
+                trace!(
+                    "skipping {} from slcan adapter",
+                    String::from_utf8_lossy(&skipped)
+                );
+                skipped.clear();
+            }
+            b'\n' => {}
+            byte => skipped.push(byte),
+        }
+    }
+}
+
+/// Reads lines from the adapter until it goes away, handing every frame to every socket.
+async fn read_frames(
+    port: Arc<SerialPort>,
+    frames: broadcast::Sender<CANAnyFrame>,
+    timestamps: bool,
+) {
+    let mut line = Vec::new();
+    let mut buf = [0; 256];
+    loop {
+        let n = match port.read(&mut buf).await {
+            Ok(0) => return error!("slcan adapter went away."),
+            Ok(n) => n,
+            Err(e) => return error!("Failed to read from slcan adapter: {}", e),
+        };
+
+        for byte in &buf[..n] {
+            match *byte {
+                b'\r' if line.is_empty() => {}
+                BELL => {
+                    warn!("slcan adapter rejected a command.");
+                    line.clear();
+                }
+                b'\r' => {
+                    handle_line(&line, &frames, timestamps);
+                    line.clear();
+                }
+                b'\n' => {}
+                byte => line.push(byte),
+            }
+        }
+    }
+}
+
+fn handle_line(line: &[u8], frames: &broadcast::Sender<CANAnyFrame>, timestamps: bool) {
+    match line[0] {
+        b't' | b'T' => match decode_frame(line, timestamps) {
+            Some(frame) => {
+                // Nobody listening is nothing to worry about.
+                let _ = frames.send(frame.into());
+            }
+            None => debug!(
+                "Ignoring malformed frame from slcan adapter: {}",
+                String::from_utf8_lossy(line)
+            ),
+        },
+        // Acknowledgements of transmitted frames.
+        b'z' | b'Z' => {}
+        _ => trace!(
+            "ignoring unexpected output from slcan adapter: {}",
+            String::from_utf8_lossy(line)
+        ),
+    }
+}
+
+/// Encodes a frame as the line that transmits it: `t` and three digits of identifier for 11-bit
+/// identifiers, or `T` and eight for 29-bit, then the length, and then the data.
+fn encode_frame(frame: &CANAnyFrame) -> Result<String, SocketError> {
+    let frame = match frame {
+        CANAnyFrame::Classic(frame) => frame,
+        CANAnyFrame::FD(_) => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "slcan adapters can't send FD frames",
+            )
+            .into())
+        }
+    };
+
+    let mut line = if frame.is_extended() {
+        format!("T{:08X}", frame.id())
+    } else {
+        format!("t{:03X}", frame.id())
+    };
+    line.push_str(&format!("{:X}", frame.data().len()));
+    for byte in frame.data() {
+        line.push_str(&format!("{:02X}", byte));
+    }
+    line.push('\r');
+
+    Ok(line)
+}
+
+/// Decodes a received frame, ignoring its timestamp other than to trace it.
+fn decode_frame(line: &[u8], timestamps: bool) -> Option<CANFrame> {
+    let line = std::str::from_utf8(line).ok()?;
+    let (id_length, extended) = match line.as_bytes().first()? {
+        b't' => (3, false),
+        b'T' => (8, true),
+        _ => return None,
+    };
+
+    let id = parse_hex(line.get(1..1 + id_length)?)?;
+    let rest = &line[1 + id_length..];
+    let length = parse_hex(rest.get(..1)?)? as usize;
+    let data = (0..length)
+        .map(|i| parse_hex(rest.get(1 + i * 2..3 + i * 2)?).map(|b| b as u8))
+        .collect::<Option<Vec<_>>>()?;
+    let rest = &rest[1 + length * 2..];
+
+    if timestamps {
+        // Milliseconds, wrapping around every minute.
+        let timestamp = parse_hex(rest.get(..4)?)?;
+        trace!("slcan -> {} at {}ms", line, timestamp);
+    } else {
+        trace!("slcan -> {}", line);
+    }
+
+    classic_frame(id, &data, extended)
+}
+
+fn parse_hex(s: &str) -> Option<u32> {
+    if !s.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+
+    u32::from_str_radix(s, 16).ok()
+}
+
+/// A raw socket on an slcan adapter.
+pub struct SlcanSocket {
+    device: Arc<SlcanDevice>,
+    frames: broadcast::Receiver<CANAnyFrame>,
+    filter: Option<IdFilter>,
+}
+
+impl SlcanSocket {
+    /// Opens a socket on the adapter on the given serial device, receiving every frame that passes
+    /// `filter` from now on.
+    pub fn open(
+        device: &Path,
+        baud_rate: u32,
+        bitrate: u32,
+        timestamps: bool,
+        filter: Option<IdFilter>,
+    ) -> Result<Self, SocketBuildError> {
+        let device = SlcanDevice::shared(device, baud_rate, bitrate, timestamps)?;
+        let frames = device.frames.subscribe();
+
+        Ok(Self {
+            device,
+            frames,
+            filter,
+        })
+    }
+
+    pub async fn read(&mut self) -> Result<CANAnyFrame, SocketError> {
+        loop {
+            let frame = match self.frames.recv().await {
+                Ok(frame) => frame,
+                Err(RecvError::Lagged(missed)) => {
+                    debug!("Missed {} frames from slcan adapter.", missed);
+                    continue;
+                }
+                Err(RecvError::Closed) => {
+                    return Err(io::Error::from(io::ErrorKind::BrokenPipe).into())
+                }
+            };
+
+            let accepted = match self.filter {
+                Some(filter) => filter.matches(frame.id()),
+                None => true,
+            };
+            if accepted {
+                return Ok(frame);
+            }
+        }
+    }
+
+    pub async fn write(&mut self, frame: &CANAnyFrame) -> Result<(), SocketError> {
+        self.device.write(frame).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use clap::Parser;
+
+    use crate::{
+        common::{
+            addressing::{exact_id_filter, id_from_raw},
+            config::{AppConfig, CANParameters},
+        },
+        protocol::can::{isotp::ISOTPSocket, raw::RawSocket},
+    };
+
+    use super::{emulator::SlcanEmulator, *};
+
+    fn can_parameters(device: &Path) -> CANParameters {
+        let backend = format!("slcan://{}?timestamps=true", device.display());
+        AppConfig::try_parse_from(["hypercan", "--backend", &backend, "validate-socket"])
+            .expect("arguments should be valid")
+            .can_parameters()
+    }
+
+    fn id(raw: u32) -> can::identifier::Id {
+        id_from_raw(raw).expect("should be a valid identifier")
+    }
+
+    #[test]
+    fn frame_round_trip() {
+        let standard = CANFrame::new(0x7E0, &[0x02, 0x10, 0x03], false, false).unwrap();
+        let line = encode_frame(&standard.into()).unwrap();
+        assert_eq!(line, "t7E03021003\r");
+        let decoded = decode_frame(line.trim_end().as_bytes(), false).unwrap();
+        assert_eq!(
+            (decoded.id(), decoded.data()),
+            (0x7E0, &[0x02, 0x10, 0x03][..])
+        );
+
+        let extended = CANFrame::new(0x18DA10F1, &[], false, false).unwrap();
+        let line = encode_frame(&extended.into()).unwrap();
+        assert_eq!(line, "T18DA10F10\r");
+        let decoded = decode_frame(line.trim_end().as_bytes(), false).unwrap();
+        assert!(decoded.is_extended());
+        assert_eq!(decoded.id(), 0x18DA10F1);
+    }
+
+    #[test]
+    fn decodes_timestamps_and_rejects_malformed_frames() {
+        let decoded = decode_frame(b"t7E8203411A2B", true).unwrap();
+        assert_eq!(decoded.data(), &[0x03, 0x41]);
+
+        assert!(decode_frame(b"t7E8203411A2B", false).is_some());
+        assert!(decode_frame(b"t7E820341", true).is_none());
+        assert!(decode_frame(b"t7E8303", false).is_none());
+        assert!(decode_frame(b"t7E89", false).is_none());
+        assert!(decode_frame(b"r7E80", false).is_none());
+        assert!(decode_frame(b"t8000", false).is_none());
+    }
+
+    #[test]
+    fn extended_frames_with_short_identifiers_stay_extended() {
+        let decoded = decode_frame(b"T000007E80", false).unwrap();
+        assert!(decoded.is_extended());
+        assert_eq!(decoded.id(), 0x7E8);
+        assert_eq!(encode_frame(&decoded.into()).unwrap(), "T000007E80\r");
+    }
+
+    #[tokio::test]
+    async fn sockets_share_the_adapter() {
+        let request = [0x02, 0x01, 0x00];
+        let response = [0x06, 0x41, 0x00, 0xBE, 0x1F, 0xA8, 0x13];
+        let (device, mut lines) = SlcanEmulator::new()
+            .reply(0x7DF, &request, &[(0x7E8, &response)])
+            .spawn();
+        let can_parameters = can_parameters(&device);
+
+        let mut requester = RawSocket::builder()
+            .can_parameters(can_parameters.clone())
+            .source_id_filter(exact_id_filter(id(0x7E8)))
+            .build()
+            .unwrap();
+        let mut bystander = RawSocket::builder()
+            .can_parameters(can_parameters)
+            .source_id_filter(exact_id_filter(id(0x7E9)))
+            .build()
+            .unwrap();
+
+        let frame = CANFrame::new(0x7DF, &request, false, false).unwrap();
+        requester.write(frame).await.unwrap();
+        let received = requester.read().await.unwrap();
+        assert_eq!((received.id(), received.data()), (0x7E8, &response[..]));
+        assert!(matches!(
+            bystander
+                .read_with_timeout(Some(Duration::from_millis(50)))
+                .await,
+            Err(SocketError::Timeout(_))
+        ));
+
+        let mut written = Vec::new();
+        while let Ok(line) = lines.try_recv() {
+            written.push(line);
+        }
+        assert_eq!(written, vec!["C", "S6", "Z1", "O", "t7DF3020100"]);
+    }
+
+    #[tokio::test]
+    async fn fails_to_open_adapters_that_reject_their_configuration() {
+        let (device, mut lines) = SlcanEmulator::new().reject("S6").spawn();
+        let opened = RawSocket::builder()
+            .can_parameters(can_parameters(&device))
+            .build();
+        assert!(matches!(
+            opened,
+            Err(SocketBuildError::AdapterInitialization { .. })
+        ));
+
+        // Nothing is opened after the bit rate is rejected.
+        let mut written = Vec::new();
+        while let Ok(line) = lines.try_recv() {
+            written.push(line);
+        }
+        assert_eq!(written, vec!["C", "S6"]);
+    }
+
+    #[tokio::test]
+    async fn userspace_isotp_runs_over_the_adapter() {
+        let mut response = vec![0x62, 0xF1, 0x90];
+        response.extend_from_slice(b"1HGCM82633A004352");
+        let first = [&[0x10, 0x14][..], &response[..6]].concat();
+        let consecutive = [
+            [&[0x21][..], &response[6..13]].concat(),
+            [&[0x22][..], &response[13..]].concat(),
+        ];
+        let (device, _) = SlcanEmulator::new()
+            .reply(
+                0x7E0,
+                &[0x03, 0x22, 0xF1, 0x90, 0xCC, 0xCC, 0xCC, 0xCC],
+                &[(0x7E8, &first)],
+            )
+            .reply(
+                0x7E0,
+                &[0x30, 0x00, 0x00, 0xCC, 0xCC, 0xCC, 0xCC, 0xCC],
+                &[(0x7E8, &consecutive[0]), (0x7E8, &consecutive[1])],
+            )
+            .spawn();
+
+        let mut socket = ISOTPSocket::builder()
+            .can_parameters(can_parameters(&device))
+            .source_id(id(0x7E8))
+            .destination_id(id(0x7E0))
+            .build()
+            .unwrap();
+        socket.write(&[0x22, 0xF1, 0x90]).await.unwrap();
+        assert_eq!(socket.read().await.unwrap(), response);
+    }
+}
@@ -0,0 +1,481 @@
+//! socketcand daemons, which share the CAN interfaces of the machine they run on over TCP.
+//!
+//! Everything sent either way is an element, `< ... >`, made up of whitespace-separated words.
+//! Each connection opens one interface in one mode: rawmode carries raw frames, standing in for a
+//! raw socket, while isotpmode carries ISO-TP payloads, with the daemon's own `can-isotp` socket
+//! handling segmentation and flow control on its end.
+
+use std::{
+    io::{self, Read, Write},
+    net::{TcpStream as StdTcpStream, ToSocketAddrs},
+    time::Duration,
+};
+
+use socketcan::CANFrame;
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+};
+use tracing::{debug, trace};
+
+use crate::common::addressing::IdFilter;
+
+use super::{
+    error::{SocketBuildError, SocketError},
+    frame::{classic_frame, CANAnyFrame},
+};
+
+#[cfg(test)]
+pub mod server;
+
+/// What a connection carries, once its interface is open.
+pub enum Mode {
+    Raw,
+    /// ISO-TP payloads, on a channel configured with the given `isotpconf` arguments.
+    ISOTP(String),
+}
+
+/// Elements received from the daemon that haven't been taken yet.
+#[derive(Default)]
+struct Elements {
+    buf: Vec<u8>,
+}
+
+impl Elements {
+    fn extend(&mut self, data: &[u8]) {
+        self.buf.extend_from_slice(data);
+    }
+
+    /// Takes the next complete element, as the words it's made up of.
+    fn take(&mut self) -> Option<Vec<String>> {
+        let start = match self.buf.iter().position(|byte| *byte == b'<') {
+            Some(start) => start,
+            None => {
+                // Nothing outside of an element means anything.
+                self.buf.clear();
+                return None;
+            }
+        };
+        let end = start + self.buf[start..].iter().position(|byte| *byte == b'>')?;
+
+        let words = String::from_utf8_lossy(&self.buf[start + 1..end])
+            .split_whitespace()
+            .map(str::to_string)
+            .collect();
+        self.buf.drain(..=end);
+        Some(words)
+    }
+}
+
+/// A connection to a daemon, on one of its interfaces.
+pub struct Connection {
+    stream: TcpStream,
+    elements: Elements,
+}
+
+impl Connection {
+    /// Connects to the daemon, opens the given interface, and switches the connection to `mode`.
+    ///
+    /// Like opening a local socket, this blocks until it's done, though never for longer than
+    /// `timeout` at a time.
+    pub fn open(
+        host: &str,
+        port: u16,
+        interface: &str,
+        mode: Mode,
+        timeout: Duration,
+    ) -> Result<Self, SocketBuildError> {
+        let mut stream = connect(host, port, timeout)?;
+        stream.set_read_timeout(Some(timeout))?;
+        stream.set_write_timeout(Some(timeout))?;
+        stream.set_nodelay(true)?;
+
+        let mut elements = Elements::default();
+        expect(&mut stream, &mut elements, "hi")?;
+        write_element_blocking(&mut stream, &format!("open {}", interface))?;
+        expect(&mut stream, &mut elements, "ok")?;
+        match mode {
+            Mode::Raw => {
+                write_element_blocking(&mut stream, "rawmode")?;
+                expect(&mut stream, &mut elements, "ok")?;
+            }
+            Mode::ISOTP(configuration) => {
+                write_element_blocking(&mut stream, "isotpmode")?;
+                expect(&mut stream, &mut elements, "ok")?;
+                // Daemons only speak up about the configuration if they reject it, which is
+                // reported whenever the connection is next read from.
+                write_element_blocking(&mut stream, &format!("isotpconf {}", configuration))?;
+            }
+        }
+
+        stream.set_nonblocking(true)?;
+        Ok(Self {
+            stream: TcpStream::from_std(stream)?,
+            elements,
+        })
+    }
+
+    /// Reads the next element, failing if the daemon reports an error instead.
+    pub async fn read_element(&mut self) -> Result<Vec<String>, SocketError> {
+        let mut buf = [0; 1024];
+        loop {
+            if let Some(element) = self.elements.take() {
+                trace!("socketcand -> < {} >", element.join(" "));
+                if element.first().map(String::as_str) == Some("error") {
+                    return Err(daemon_error(&element).into());
+                }
+                return Ok(element);
+            }
+
+            match self.stream.read(&mut buf).await? {
+                0 => return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into()),
+                n => self.elements.extend(&buf[..n]),
+            }
+        }
+    }
+
+    pub async fn write_element(&mut self, element: &str) -> Result<(), SocketError> {
+        trace!("socketcand <- < {} >", element);
+        Ok(self
+            .stream
+            .write_all(format!("< {} >", element).as_bytes())
+            .await?)
+    }
+}
+
+fn connect(host: &str, port: u16, timeout: Duration) -> io::Result<StdTcpStream> {
+    let mut last_error = io::Error::new(
+        io::ErrorKind::NotFound,
+        format!("no addresses found for {}", host),
+    );
+    for address in (host, port).to_socket_addrs()? {
+        match StdTcpStream::connect_timeout(&address, timeout) {
+            Ok(stream) => return Ok(stream),
+            Err(e) => last_error = e,
+        }
+    }
+
+    Err(last_error)
+}
+
+fn write_element_blocking(stream: &mut StdTcpStream, element: &str) -> io::Result<()> {
+    trace!("socketcand <- < {} >", element);
+    stream.write_all(format!("< {} >", element).as_bytes())
+}
+
+/// Waits for the daemon to answer with the given single-word element.
+fn expect(
+    stream: &mut StdTcpStream,
+    elements: &mut Elements,
+    expected: &str,
+) -> Result<(), SocketBuildError> {
+    let mut buf = [0; 256];
+    let element = loop {
+        if let Some(element) = elements.take() {
+            break element;
+        }
+
+        match stream.read(&mut buf)? {
+            0 => return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into()),
+            n => elements.extend(&buf[..n]),
+        }
+    };
+    trace!("socketcand -> < {} >", element.join(" "));
+
+    match element.as_slice() {
+        [word] if word == expected => Ok(()),
+        _ => Err(SocketBuildError::AdapterInitialization {
+            source: daemon_error(&element).into(),
+        }),
+    }
+}
+
+fn daemon_error(element: &[String]) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::Other,
+        format!("socketcand answered '< {} >'", element.join(" ")),
+    )
+}
+
+/// Encodes bytes as hexadecimal, without anything between them.
+pub fn encode_hex(data: &[u8]) -> String {
+    data.iter().map(|byte| format!("{:02X}", byte)).collect()
+}
+
+pub fn decode_hex(s: &str) -> Option<Vec<u8>> {
+    if s.len() % 2 != 0 || !s.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+
+    (0..s.len() / 2)
+        .map(|i| u8::from_str_radix(&s[i * 2..i * 2 + 2], 16).ok())
+        .collect()
+}
+
+/// Encodes a frame as the element that transmits it: its identifier, three digits for 11-bit
+/// identifiers or eight for 29-bit, then its length, and then each byte of data.
+fn encode_frame(frame: &CANAnyFrame) -> Result<String, SocketError> {
+    let frame = match frame {
+        CANAnyFrame::Classic(frame) => frame,
+        CANAnyFrame::FD(_) => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "socketcand can't send FD frames",
+            )
+            .into())
+        }
+    };
+
+    let mut element = if frame.is_extended() {
+        format!("send {:08X}", frame.id())
+    } else {
+        format!("send {:03X}", frame.id())
+    };
+    element.push_str(&format!(" {}", frame.data().len()));
+    for byte in frame.data() {
+        element.push_str(&format!(" {:02X}", byte));
+    }
+
+    Ok(element)
+}
+
+/// Decodes a received frame, `frame <id> <seconds>.<microseconds> <data>`, ignoring its timestamp.
+///
+/// Frames without data have nothing after their timestamp.  Identifiers are 29-bit when they're
+/// written with eight digits, whatever their value.
+fn decode_frame(element: &[String]) -> Option<CANFrame> {
+    let (id, data) = match element {
+        [_, id, _] => (id, ""),
+        [_, id, _, data] => (id, data.as_str()),
+        _ => return None,
+    };
+    let extended = match id.len() {
+        3 => false,
+        8 => true,
+        _ => return None,
+    };
+    if !id.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+
+    let id = u32::from_str_radix(id, 16).ok()?;
+    classic_frame(id, &decode_hex(data)?, extended)
+}
+
+/// A raw socket on an interface shared by a socketcand daemon.
+pub struct SocketcandSocket {
+    connection: Connection,
+    filter: Option<IdFilter>,
+}
+
+impl SocketcandSocket {
+    /// Opens a socket on the given interface, receiving every frame that passes `filter` from now
+    /// on.
+    pub fn open(
+        host: &str,
+        port: u16,
+        interface: &str,
+        timeout: Duration,
+        filter: Option<IdFilter>,
+    ) -> Result<Self, SocketBuildError> {
+        Ok(Self {
+            connection: Connection::open(host, port, interface, Mode::Raw, timeout)?,
+            filter,
+        })
+    }
+
+    pub async fn read(&mut self) -> Result<CANAnyFrame, SocketError> {
+        loop {
+            let element = self.connection.read_element().await?;
+            if element.first().map(String::as_str) != Some("frame") {
+                continue;
+            }
+
+            let frame = match decode_frame(&element) {
+                Some(frame) => frame,
+                None => {
+                    debug!(
+                        "Ignoring malformed frame from socketcand: < {} >",
+                        element.join(" ")
+                    );
+                    continue;
+                }
+            };
+
+            let accepted = match self.filter {
+                Some(filter) => filter.matches(frame.id()),
+                None => true,
+            };
+            if accepted {
+                return Ok(frame.into());
+            }
+        }
+    }
+
+    pub async fn write(&mut self, frame: &CANAnyFrame) -> Result<(), SocketError> {
+        let element = encode_frame(frame)?;
+        self.connection.write_element(&element).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::Parser;
+
+    use crate::{
+        common::{
+            addressing::{exact_id_filter, id_from_raw},
+            config::{AppConfig, CANParameters},
+        },
+        protocol::can::{isotp::ISOTPSocket, raw::RawSocket},
+    };
+
+    use super::{server::SocketcandServer, *};
+
+    fn can_parameters(port: u16, interface: &str) -> CANParameters {
+        let backend = format!("socketcand://127.0.0.1:{}/{}", port, interface);
+        AppConfig::try_parse_from(["hypercan", "--backend", &backend, "validate-socket"])
+            .expect("arguments should be valid")
+            .can_parameters()
+    }
+
+    fn id(raw: u32) -> can::identifier::Id {
+        id_from_raw(raw).expect("should be a valid identifier")
+    }
+
+    fn words(element: &[&str]) -> Vec<String> {
+        element.iter().map(|word| word.to_string()).collect()
+    }
+
+    #[test]
+    fn elements_split_across_reads() {
+        let mut elements = Elements::default();
+        elements.extend(b"\r\n< hi >< frame 7E8 ");
+        assert_eq!(elements.take(), Some(words(&["hi"])));
+        assert_eq!(elements.take(), None);
+
+        elements.extend(b"1.000000 0341 >");
+        assert_eq!(
+            elements.take(),
+            Some(words(&["frame", "7E8", "1.000000", "0341"]))
+        );
+        assert_eq!(elements.take(), None);
+    }
+
+    #[test]
+    fn frame_round_trip() {
+        let standard = CANFrame::new(0x7E0, &[0x02, 0x10, 0x03], false, false).unwrap();
+        assert_eq!(
+            encode_frame(&standard.into()).unwrap(),
+            "send 7E0 3 02 10 03"
+        );
+        let extended = CANFrame::new(0x18DA10F1, &[], false, false).unwrap();
+        assert_eq!(encode_frame(&extended.into()).unwrap(), "send 18DA10F1 0");
+
+        let decoded = decode_frame(&words(&["frame", "7E8", "12.000345", "021003"])).unwrap();
+        assert_eq!(
+            (decoded.id(), decoded.data()),
+            (0x7E8, &[0x02, 0x10, 0x03][..])
+        );
+        let decoded = decode_frame(&words(&["frame", "18DAF110", "12.000345"])).unwrap();
+        assert!(decoded.is_extended());
+        assert!(decoded.data().is_empty());
+
+        assert!(decode_frame(&words(&["frame", "7E8", "12.000345", "021"])).is_none());
+        assert!(decode_frame(&words(&["frame", "7G8", "12.000345", "02"])).is_none());
+        assert!(decode_frame(&words(&["frame", "7E8"])).is_none());
+
+        // The number of digits decides the format, rather than the identifier's value.
+        let decoded = decode_frame(&words(&["frame", "000007E8", "12.000345"])).unwrap();
+        assert!(decoded.is_extended());
+        assert_eq!(decoded.id(), 0x7E8);
+        assert!(decode_frame(&words(&["frame", "7E80", "12.000345"])).is_none());
+        assert!(decode_frame(&words(&["frame", "800", "12.000345"])).is_none());
+    }
+
+    #[tokio::test]
+    async fn raw_sockets_share_the_interface() {
+        let request = [0x02, 0x01, 0x00];
+        let response = [0x06, 0x41, 0x00, 0xBE, 0x1F, 0xA8, 0x13];
+        let (port, elements) = SocketcandServer::new()
+            .reply(0x7DF, &request, &[(0x7E8, &response)])
+            .spawn();
+        let can_parameters = can_parameters(port, "can0");
+
+        let mut requester = RawSocket::builder()
+            .can_parameters(can_parameters.clone())
+            .source_id_filter(exact_id_filter(id(0x7E8)))
+            .build()
+            .unwrap();
+        let mut bystander = RawSocket::builder()
+            .can_parameters(can_parameters)
+            .source_id_filter(exact_id_filter(id(0x7E9)))
+            .build()
+            .unwrap();
+
+        let frame = CANFrame::new(0x7DF, &request, false, false).unwrap();
+        requester.write(frame).await.unwrap();
+        let received = requester.read().await.unwrap();
+        assert_eq!((received.id(), received.data()), (0x7E8, &response[..]));
+        assert!(matches!(
+            bystander
+                .read_with_timeout(Some(Duration::from_millis(50)))
+                .await,
+            Err(SocketError::Timeout(_))
+        ));
+
+        let received: Vec<_> = elements.try_iter().collect();
+        assert_eq!(
+            received,
+            vec![
+                "open can0",
+                "rawmode",
+                "open can0",
+                "rawmode",
+                "send 7DF 3 02 01 00"
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn isotp_sockets_use_isotpmode_channels() {
+        let mut response = vec![0x62, 0xF1, 0x90];
+        response.extend_from_slice(b"1HGCM82633A004352");
+        let (port, elements) = SocketcandServer::new()
+            .reply_pdu(0x7E0, &[0x22, 0xF1, 0x90], &response)
+            .spawn();
+
+        let mut socket = ISOTPSocket::builder()
+            .can_parameters(can_parameters(port, "can0"))
+            .source_id(id(0x7E8))
+            .destination_id(id(0x7E0))
+            .build()
+            .unwrap();
+        socket.write(&[0x22, 0xF1, 0x90]).await.unwrap();
+        assert_eq!(socket.read().await.unwrap(), response);
+
+        let received: Vec<_> = elements.try_iter().collect();
+        assert_eq!(
+            received,
+            vec![
+                "open can0",
+                "isotpmode",
+                "isotpconf 7E0 7E8 4 0 0 0 CC 0 0 0",
+                "sendpdu 22F190"
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn unknown_interfaces_are_reported() {
+        let (port, _) = SocketcandServer::new().spawn();
+
+        let result = RawSocket::builder()
+            .can_parameters(can_parameters(port, "can9"))
+            .build();
+        assert!(matches!(
+            result,
+            Err(SocketBuildError::AdapterInitialization { .. })
+        ));
+    }
+}
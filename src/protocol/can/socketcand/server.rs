@@ -0,0 +1,177 @@
+//! A socketcand daemon on the loopback interface, sharing a bus where frames and ISO-TP payloads
+//! get canned responses.
+//!
+//! Opening a connection blocks until the daemon has answered, so it runs on threads of its own,
+//! rather than on the runtime of the test talking to it.
+
+use std::{
+    collections::HashMap,
+    io::{Read, Write},
+    net::{TcpListener, TcpStream},
+    sync::{mpsc, Arc, Mutex},
+    thread,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use super::{decode_hex, encode_hex, Elements};
+
+/// The only interface the daemon has.
+const INTERFACE: &str = "can0";
+
+/// A daemon whose bus answers each frame written in rawmode, and each payload sent in isotpmode,
+/// with its canned responses, if any.
+#[derive(Default)]
+pub struct SocketcandServer {
+    replies: HashMap<(u32, Vec<u8>), Vec<(u32, Vec<u8>)>>,
+    pdu_replies: HashMap<(u32, Vec<u8>), Vec<u8>>,
+}
+
+impl SocketcandServer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Answers the frame with the given identifier and data with each of `responses`, in order,
+    /// on every rawmode connection.
+    pub fn reply(mut self, id: u32, data: &[u8], responses: &[(u32, &[u8])]) -> Self {
+        let responses = responses
+            .iter()
+            .map(|(id, data)| (*id, data.to_vec()))
+            .collect();
+        self.replies.insert((id, data.to_vec()), responses);
+        self
+    }
+
+    /// Answers the payload sent to the given identifier with `response`.
+    pub fn reply_pdu(mut self, tx_id: u32, payload: &[u8], response: &[u8]) -> Self {
+        self.pdu_replies
+            .insert((tx_id, payload.to_vec()), response.to_vec());
+        self
+    }
+
+    /// Starts listening on a port of the loopback interface until the test finishes, returning
+    /// the port, and every element sent to the daemon other than frames it sent itself.
+    pub fn spawn(self) -> (u16, mpsc::Receiver<String>) {
+        let listener =
+            TcpListener::bind(("127.0.0.1", 0)).expect("should be able to listen on loopback");
+        let port = listener
+            .local_addr()
+            .expect("listener should have an address")
+            .port();
+        let (elements_tx, elements_rx) = mpsc::channel();
+
+        let server = Arc::new(self);
+        let bus = Arc::new(Mutex::new(Vec::new()));
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let stream = match stream {
+                    Ok(stream) => stream,
+                    Err(_) => return,
+                };
+
+                let server = server.clone();
+                let bus = bus.clone();
+                let elements_tx = elements_tx.clone();
+                thread::spawn(move || server.serve(stream, &bus, &elements_tx));
+            }
+        });
+
+        (port, elements_rx)
+    }
+
+    fn serve(
+        &self,
+        mut stream: TcpStream,
+        bus: &Mutex<Vec<TcpStream>>,
+        elements_tx: &mpsc::Sender<String>,
+    ) {
+        if stream.write_all(b"< hi >").is_err() {
+            return;
+        }
+
+        let mut elements = Elements::default();
+        let mut tx_id = None;
+        let mut buf = [0; 1024];
+        loop {
+            let n = match stream.read(&mut buf) {
+                Ok(n) if n > 0 => n,
+                _ => return,
+            };
+            elements.extend(&buf[..n]);
+
+            while let Some(element) = elements.take() {
+                let _ = elements_tx.send(element.join(" "));
+
+                let words: Vec<_> = element.iter().map(String::as_str).collect();
+                let answer = match words.as_slice() {
+                    ["open", INTERFACE] | ["isotpmode"] => "< ok >".to_string(),
+                    ["open", interface] => format!("< error could not open bus '{}' >", interface),
+                    ["rawmode"] => match stream.try_clone() {
+                        Ok(clone) => {
+                            bus.lock()
+                                .expect("bus lock should not be poisoned")
+                                .push(clone);
+                            "< ok >".to_string()
+                        }
+                        Err(_) => return,
+                    },
+                    ["isotpconf", id, ..] => {
+                        tx_id = u32::from_str_radix(id, 16).ok();
+                        continue;
+                    }
+                    ["send", id, _, data @ ..] => {
+                        self.transmit(bus, id, data);
+                        continue;
+                    }
+                    ["sendpdu", payload] => {
+                        let request = (
+                            tx_id.unwrap_or_default(),
+                            decode_hex(payload).unwrap_or_default(),
+                        );
+                        match self.pdu_replies.get(&request) {
+                            Some(response) => format!("< pdu {} >", encode_hex(response)),
+                            None => continue,
+                        }
+                    }
+                    _ => "< error unknown command >".to_string(),
+                };
+
+                if stream.write_all(answer.as_bytes()).is_err() {
+                    return;
+                }
+            }
+        }
+    }
+
+    /// Puts the responses to a frame on the bus, for every rawmode connection to see.
+    fn transmit(&self, bus: &Mutex<Vec<TcpStream>>, id: &str, data: &[&str]) {
+        let request = (
+            u32::from_str_radix(id, 16).unwrap_or_default(),
+            data.iter()
+                .filter_map(|byte| u8::from_str_radix(byte, 16).ok())
+                .collect(),
+        );
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+
+        let mut bus = bus.lock().expect("bus lock should not be poisoned");
+        for (id, data) in self.replies.get(&request).into_iter().flatten() {
+            let id = if *id > 0x7FF {
+                format!("{:08X}", id)
+            } else {
+                format!("{:03X}", id)
+            };
+            let element = format!(
+                "< frame {} {}.{:06} {} >",
+                id,
+                timestamp.as_secs(),
+                timestamp.subsec_micros(),
+                encode_hex(data)
+            );
+            for connection in bus.iter_mut() {
+                let _ = connection.write_all(element.as_bytes());
+            }
+        }
+    }
+}
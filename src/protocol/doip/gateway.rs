@@ -0,0 +1,216 @@
+//! A DoIP gateway on the loopback interface, with ECUs behind it that give canned responses.
+
+use std::{collections::HashMap, net::SocketAddr, sync::Arc};
+
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream, UdpSocket},
+    sync::mpsc,
+};
+
+use super::message::{Message, VehicleAnnouncement, ROUTING_ACTIVATION_SUCCESS};
+
+/// Logical address of the gateway itself.
+const GATEWAY_ADDRESS: u16 = 0x1000;
+
+const VIN: &str = "1HGCM82633A004352";
+
+/// Routing activation response code for an activation type we don't support.
+const UNSUPPORTED_ACTIVATION_TYPE: u8 = 0x06;
+
+/// Diagnostic message negative acknowledgement codes.
+const INVALID_SOURCE_ADDRESS: u8 = 0x02;
+const UNKNOWN_TARGET_ADDRESS: u8 = 0x03;
+
+/// A gateway that answers each diagnostic message sent to one of its ECUs, or to a functional
+/// address, with its canned responses, if any.
+#[derive(Default)]
+pub struct DoIPGateway {
+    replies: HashMap<(u16, Vec<u8>), Vec<(u16, Vec<u8>)>>,
+    check_alive: bool,
+}
+
+impl DoIPGateway {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Answers the request sent to the given logical address with each of `responses`, in order,
+    /// each coming from the logical address given alongside it.
+    pub fn reply(
+        mut self,
+        target_address: u16,
+        request: &[u8],
+        responses: &[(u16, &[u8])],
+    ) -> Self {
+        let responses = responses
+            .iter()
+            .map(|(source_address, data)| (*source_address, data.to_vec()))
+            .collect();
+        self.replies
+            .insert((target_address, request.to_vec()), responses);
+        self
+    }
+
+    /// Checks that testers are still there as soon as routing has been activated for them.
+    pub fn check_alive(mut self) -> Self {
+        self.check_alive = true;
+        self
+    }
+
+    /// Starts listening on a port of the loopback interface, for both vehicle identification and
+    /// diagnostic connections, until the test finishes, returning its address and every message
+    /// sent to it over TCP.
+    pub fn spawn(self) -> (SocketAddr, mpsc::UnboundedReceiver<Message>) {
+        let listener = std::net::TcpListener::bind(("127.0.0.1", 0))
+            .expect("should be able to listen on loopback");
+        let address = listener
+            .local_addr()
+            .expect("listener should have an address");
+        let udp = std::net::UdpSocket::bind(address)
+            .expect("should be able to bind the same port for vehicle identification");
+        listener
+            .set_nonblocking(true)
+            .expect("listener should become non-blocking");
+        udp.set_nonblocking(true)
+            .expect("socket should become non-blocking");
+        let listener = TcpListener::from_std(listener).expect("listener should be registered");
+        let udp = UdpSocket::from_std(udp).expect("socket should be registered");
+        let (messages_tx, messages_rx) = mpsc::unbounded_channel();
+
+        tokio::spawn(identify(udp));
+
+        let gateway = Arc::new(self);
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                tokio::spawn(gateway.clone().serve(stream, messages_tx.clone()));
+            }
+        });
+
+        (address, messages_rx)
+    }
+
+    async fn serve(
+        self: Arc<Self>,
+        mut stream: TcpStream,
+        messages_tx: mpsc::UnboundedSender<Message>,
+    ) {
+        let mut tester_address = None;
+        let mut buf = Vec::new();
+        let mut read_buf = [0; 4096];
+        loop {
+            let message = match Message::decode(&buf) {
+                Ok(Some((message, length))) => {
+                    buf.drain(..length);
+                    message
+                }
+                Ok(None) => match stream.read(&mut read_buf).await {
+                    Ok(n) if n > 0 => {
+                        buf.extend_from_slice(&read_buf[..n]);
+                        continue;
+                    }
+                    _ => return,
+                },
+                Err(_) => return,
+            };
+            let _ = messages_tx.send(message.clone());
+
+            let answers = match message {
+                Message::RoutingActivationRequest {
+                    source_address,
+                    activation_type,
+                } => {
+                    let code = if activation_type == 0x00 {
+                        tester_address = Some(source_address);
+                        ROUTING_ACTIVATION_SUCCESS
+                    } else {
+                        UNSUPPORTED_ACTIVATION_TYPE
+                    };
+                    let mut answers = vec![Message::RoutingActivationResponse {
+                        tester_address: source_address,
+                        entity_address: GATEWAY_ADDRESS,
+                        code,
+                    }];
+                    if self.check_alive && code == ROUTING_ACTIVATION_SUCCESS {
+                        answers.push(Message::AliveCheckRequest);
+                    }
+                    answers
+                }
+                Message::DiagnosticMessage {
+                    source_address,
+                    target_address,
+                    data,
+                } => self.answer(tester_address, source_address, target_address, data),
+                _ => vec![],
+            };
+
+            for answer in answers {
+                if stream.write_all(&answer.encode()).await.is_err() {
+                    return;
+                }
+            }
+        }
+    }
+
+    fn answer(
+        &self,
+        tester_address: Option<u16>,
+        source_address: u16,
+        target_address: u16,
+        data: Vec<u8>,
+    ) -> Vec<Message> {
+        let nack = |code| {
+            vec![Message::DiagnosticMessageNack {
+                source_address: target_address,
+                target_address: source_address,
+                code,
+            }]
+        };
+        if tester_address != Some(source_address) {
+            return nack(INVALID_SOURCE_ADDRESS);
+        }
+        if !self
+            .replies
+            .keys()
+            .any(|(address, _)| *address == target_address)
+        {
+            return nack(UNKNOWN_TARGET_ADDRESS);
+        }
+
+        let mut answers = vec![Message::DiagnosticMessageAck {
+            source_address: target_address,
+            target_address: source_address,
+        }];
+        for (ecu_address, response) in self
+            .replies
+            .get(&(target_address, data))
+            .into_iter()
+            .flatten()
+        {
+            answers.push(Message::DiagnosticMessage {
+                source_address: *ecu_address,
+                target_address: source_address,
+                data: response.clone(),
+            });
+        }
+        answers
+    }
+}
+
+/// Answers vehicle identification requests with the gateway's announcement.
+async fn identify(udp: UdpSocket) {
+    let announcement = Message::VehicleAnnouncement(VehicleAnnouncement {
+        vin: VIN.to_string(),
+        logical_address: GATEWAY_ADDRESS,
+        eid: [0x00, 0x1A, 0x37, 0x00, 0x00, 0x01],
+        gid: [0; 6],
+        further_action: 0x00,
+    });
+
+    let mut buf = [0; 512];
+    while let Ok((n, address)) = udp.recv_from(&mut buf).await {
+        if let Ok(Some((Message::VehicleIdentificationRequest, _))) = Message::decode(&buf[..n]) {
+            let _ = udp.send_to(&announcement.encode(), address).await;
+        }
+    }
+}
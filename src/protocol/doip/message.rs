@@ -0,0 +1,393 @@
+//! DoIP messages, as they're carried over both UDP and TCP: a generic header giving the protocol
+//! version, the payload type, and the payload length, followed by the payload itself.
+
+use std::io;
+
+/// Protocol version we send: ISO 13400-2:2012.
+const PROTOCOL_VERSION: u8 = 0x02;
+
+/// Protocol version for vehicle identification requests, which entities of any version answer.
+const DEFAULT_PROTOCOL_VERSION: u8 = 0xFF;
+
+pub const HEADER_LENGTH: usize = 8;
+
+/// Largest payload we'll accept, well beyond any diagnostic message we'd expect.
+pub const MAX_PAYLOAD_LENGTH: usize = 0x10000;
+
+const GENERIC_NACK: u16 = 0x0000;
+const VEHICLE_IDENTIFICATION_REQUEST: u16 = 0x0001;
+const VEHICLE_ANNOUNCEMENT: u16 = 0x0004;
+const ROUTING_ACTIVATION_REQUEST: u16 = 0x0005;
+const ROUTING_ACTIVATION_RESPONSE: u16 = 0x0006;
+const ALIVE_CHECK_REQUEST: u16 = 0x0007;
+const ALIVE_CHECK_RESPONSE: u16 = 0x0008;
+const DIAGNOSTIC_MESSAGE: u16 = 0x8001;
+const DIAGNOSTIC_MESSAGE_ACK: u16 = 0x8002;
+const DIAGNOSTIC_MESSAGE_NACK: u16 = 0x8003;
+
+const VIN_LENGTH: usize = 17;
+const EID_LENGTH: usize = 6;
+
+/// Routing activation response code for routing having been activated.
+pub const ROUTING_ACTIVATION_SUCCESS: u8 = 0x10;
+
+/// What a DoIP entity announces about itself, unprompted or when asked to identify itself.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct VehicleAnnouncement {
+    pub vin: String,
+    pub logical_address: u16,
+    pub eid: [u8; EID_LENGTH],
+    pub gid: [u8; EID_LENGTH],
+    pub further_action: u8,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Message {
+    GenericNack(u8),
+    VehicleIdentificationRequest,
+    VehicleAnnouncement(VehicleAnnouncement),
+    RoutingActivationRequest {
+        source_address: u16,
+        activation_type: u8,
+    },
+    RoutingActivationResponse {
+        tester_address: u16,
+        entity_address: u16,
+        code: u8,
+    },
+    AliveCheckRequest,
+    AliveCheckResponse {
+        source_address: u16,
+    },
+    DiagnosticMessage {
+        source_address: u16,
+        target_address: u16,
+        data: Vec<u8>,
+    },
+    DiagnosticMessageAck {
+        source_address: u16,
+        target_address: u16,
+    },
+    DiagnosticMessageNack {
+        source_address: u16,
+        target_address: u16,
+        code: u8,
+    },
+    /// Anything else, which we have no use for.
+    Other {
+        payload_type: u16,
+    },
+}
+
+impl Message {
+    pub fn encode(&self) -> Vec<u8> {
+        let (payload_type, payload) = match self {
+            Message::GenericNack(code) => (GENERIC_NACK, vec![*code]),
+            Message::VehicleIdentificationRequest => (VEHICLE_IDENTIFICATION_REQUEST, vec![]),
+            Message::VehicleAnnouncement(announcement) => {
+                let mut payload = announcement.vin.as_bytes().to_vec();
+                payload.resize(VIN_LENGTH, 0);
+                payload.extend_from_slice(&announcement.logical_address.to_be_bytes());
+                payload.extend_from_slice(&announcement.eid);
+                payload.extend_from_slice(&announcement.gid);
+                payload.push(announcement.further_action);
+                (VEHICLE_ANNOUNCEMENT, payload)
+            }
+            Message::RoutingActivationRequest {
+                source_address,
+                activation_type,
+            } => {
+                let mut payload = source_address.to_be_bytes().to_vec();
+                payload.push(*activation_type);
+                payload.extend_from_slice(&[0; 4]);
+                (ROUTING_ACTIVATION_REQUEST, payload)
+            }
+            Message::RoutingActivationResponse {
+                tester_address,
+                entity_address,
+                code,
+            } => {
+                let mut payload = addresses(*tester_address, *entity_address);
+                payload.push(*code);
+                payload.extend_from_slice(&[0; 4]);
+                (ROUTING_ACTIVATION_RESPONSE, payload)
+            }
+            Message::AliveCheckRequest => (ALIVE_CHECK_REQUEST, vec![]),
+            Message::AliveCheckResponse { source_address } => {
+                (ALIVE_CHECK_RESPONSE, source_address.to_be_bytes().to_vec())
+            }
+            Message::DiagnosticMessage {
+                source_address,
+                target_address,
+                data,
+            } => {
+                let mut payload = addresses(*source_address, *target_address);
+                payload.extend_from_slice(data);
+                (DIAGNOSTIC_MESSAGE, payload)
+            }
+            Message::DiagnosticMessageAck {
+                source_address,
+                target_address,
+            } => {
+                let mut payload = addresses(*source_address, *target_address);
+                payload.push(0x00);
+                (DIAGNOSTIC_MESSAGE_ACK, payload)
+            }
+            Message::DiagnosticMessageNack {
+                source_address,
+                target_address,
+                code,
+            } => {
+                let mut payload = addresses(*source_address, *target_address);
+                payload.push(*code);
+                (DIAGNOSTIC_MESSAGE_NACK, payload)
+            }
+            Message::Other { payload_type } => (*payload_type, vec![]),
+        };
+
+        let version = match self {
+            Message::VehicleIdentificationRequest => DEFAULT_PROTOCOL_VERSION,
+            _ => PROTOCOL_VERSION,
+        };
+        let mut message = vec![version, !version];
+        message.extend_from_slice(&payload_type.to_be_bytes());
+        message.extend_from_slice(&(payload.len() as u32).to_be_bytes());
+        message.extend_from_slice(&payload);
+        message
+    }
+
+    /// Decodes the next message from the start of `buf`, returning it and its length, or `None`
+    /// if the message isn't all there yet.
+    pub fn decode(buf: &[u8]) -> io::Result<Option<(Message, usize)>> {
+        if buf.len() < HEADER_LENGTH {
+            return Ok(None);
+        }
+        if buf[0] != !buf[1] {
+            return Err(invalid_data(format!(
+                "malformed DoIP header: {:02X?}",
+                &buf[..HEADER_LENGTH]
+            )));
+        }
+
+        let payload_type = u16::from_be_bytes([buf[2], buf[3]]);
+        let length = u32::from_be_bytes([buf[4], buf[5], buf[6], buf[7]]) as usize;
+        if length > MAX_PAYLOAD_LENGTH {
+            return Err(invalid_data(format!(
+                "DoIP payload of {} bytes is too large",
+                length
+            )));
+        }
+        if buf.len() < HEADER_LENGTH + length {
+            return Ok(None);
+        }
+
+        let payload = &buf[HEADER_LENGTH..HEADER_LENGTH + length];
+        let message = decode_payload(payload_type, payload).ok_or_else(|| {
+            invalid_data(format!(
+                "malformed DoIP payload of type 0x{:04X}: {:02X?}",
+                payload_type, payload
+            ))
+        })?;
+        Ok(Some((message, HEADER_LENGTH + length)))
+    }
+}
+
+fn decode_payload(payload_type: u16, payload: &[u8]) -> Option<Message> {
+    let message = match payload_type {
+        GENERIC_NACK => Message::GenericNack(*payload.first()?),
+        VEHICLE_IDENTIFICATION_REQUEST => Message::VehicleIdentificationRequest,
+        VEHICLE_ANNOUNCEMENT => {
+            // Entities may add a VIN/GID synchronization status on the end, which we don't need.
+            let vin = payload.get(..VIN_LENGTH)?;
+            let rest = payload.get(VIN_LENGTH..)?;
+            Message::VehicleAnnouncement(VehicleAnnouncement {
+                vin: String::from_utf8_lossy(vin)
+                    .trim_end_matches(|c| c == '\0' || c == ' ')
+                    .to_string(),
+                logical_address: read_u16(rest, 0)?,
+                eid: rest.get(2..8)?.try_into().ok()?,
+                gid: rest.get(8..14)?.try_into().ok()?,
+                further_action: *rest.get(14)?,
+            })
+        }
+        ROUTING_ACTIVATION_REQUEST => Message::RoutingActivationRequest {
+            source_address: read_u16(payload, 0)?,
+            activation_type: *payload.get(2)?,
+        },
+        ROUTING_ACTIVATION_RESPONSE => {
+            let (tester_address, entity_address) = split_addresses(payload)?;
+            Message::RoutingActivationResponse {
+                tester_address,
+                entity_address,
+                code: *payload.get(4)?,
+            }
+        }
+        ALIVE_CHECK_REQUEST => Message::AliveCheckRequest,
+        ALIVE_CHECK_RESPONSE => Message::AliveCheckResponse {
+            source_address: read_u16(payload, 0)?,
+        },
+        DIAGNOSTIC_MESSAGE => {
+            let (source_address, target_address) = split_addresses(payload)?;
+            Message::DiagnosticMessage {
+                source_address,
+                target_address,
+                data: payload[4..].to_vec(),
+            }
+        }
+        // Acknowledgements may echo the message they acknowledge on the end, which we ignore.
+        DIAGNOSTIC_MESSAGE_ACK => {
+            let (source_address, target_address) = split_addresses(payload)?;
+            if payload.len() < 5 {
+                return None;
+            }
+            Message::DiagnosticMessageAck {
+                source_address,
+                target_address,
+            }
+        }
+        DIAGNOSTIC_MESSAGE_NACK => {
+            let (source_address, target_address) = split_addresses(payload)?;
+            Message::DiagnosticMessageNack {
+                source_address,
+                target_address,
+                code: *payload.get(4)?,
+            }
+        }
+        payload_type => Message::Other { payload_type },
+    };
+
+    Some(message)
+}
+
+fn addresses(first: u16, second: u16) -> Vec<u8> {
+    let mut payload = first.to_be_bytes().to_vec();
+    payload.extend_from_slice(&second.to_be_bytes());
+    payload
+}
+
+fn split_addresses(payload: &[u8]) -> Option<(u16, u16)> {
+    Some((read_u16(payload, 0)?, read_u16(payload, 2)?))
+}
+
+fn read_u16(payload: &[u8], offset: usize) -> Option<u16> {
+    let bytes = payload.get(offset..offset + 2)?;
+    Some(u16::from_be_bytes([bytes[0], bytes[1]]))
+}
+
+fn invalid_data(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// Describes a generic negative acknowledgement, sent in response to a message the entity
+/// couldn't make sense of.
+pub fn generic_nack_reason(code: u8) -> &'static str {
+    match code {
+        0x00 => "incorrect pattern format",
+        0x01 => "unknown payload type",
+        0x02 => "message too large",
+        0x03 => "out of memory",
+        0x04 => "invalid payload length",
+        _ => "reserved",
+    }
+}
+
+/// Describes why routing wasn't activated.
+pub fn routing_activation_reason(code: u8) -> &'static str {
+    match code {
+        0x00 => "unknown source address",
+        0x01 => "all sockets are in use",
+        0x02 => "source address differs from the one already active on this socket",
+        0x03 => "source address is already active on another socket",
+        0x04 => "authentication required",
+        0x05 => "confirmation rejected",
+        0x06 => "unsupported activation type",
+        0x07 => "TLS required",
+        0x11 => "confirmation required",
+        _ => "reserved",
+    }
+}
+
+/// Describes a negative acknowledgement of a diagnostic message.
+pub fn diagnostic_nack_reason(code: u8) -> &'static str {
+    match code {
+        0x02 => "invalid source address",
+        0x03 => "unknown target address",
+        0x04 => "diagnostic message too large",
+        0x05 => "out of memory",
+        0x06 => "target unreachable",
+        0x07 => "unknown network",
+        0x08 => "transport protocol error",
+        _ => "reserved",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn diagnostic_message_round_trip() {
+        let message = Message::DiagnosticMessage {
+            source_address: 0x0E00,
+            target_address: 0x1001,
+            data: vec![0x22, 0xF1, 0x90],
+        };
+        let encoded = message.encode();
+        assert_eq!(
+            encoded,
+            vec![
+                0x02, 0xFD, 0x80, 0x01, 0x00, 0x00, 0x00, 0x07, 0x0E, 0x00, 0x10, 0x01, 0x22, 0xF1,
+                0x90
+            ]
+        );
+
+        // Nothing is decoded until the whole message is there.
+        assert_eq!(Message::decode(&encoded[..10]).unwrap(), None);
+        let mut buf = encoded.clone();
+        buf.extend_from_slice(&Message::AliveCheckRequest.encode());
+        assert_eq!(
+            Message::decode(&buf).unwrap(),
+            Some((message, encoded.len()))
+        );
+    }
+
+    #[test]
+    fn announcement_round_trip() {
+        let announcement = VehicleAnnouncement {
+            vin: "1HGCM82633A004352".to_string(),
+            logical_address: 0x1000,
+            eid: [0x00, 0x1A, 0x37, 0x00, 0x00, 0x01],
+            gid: [0; 6],
+            further_action: 0x00,
+        };
+        let message = Message::VehicleAnnouncement(announcement);
+        let encoded = message.encode();
+        assert_eq!(encoded.len(), HEADER_LENGTH + 32);
+        assert_eq!(
+            Message::decode(&encoded).unwrap(),
+            Some((message, encoded.len()))
+        );
+
+        // Vehicle identification requests go out with the default protocol version.
+        assert_eq!(
+            &Message::VehicleIdentificationRequest.encode()[..4],
+            &[0xFF, 0x00, 0x00, 0x01]
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_messages() {
+        assert!(Message::decode(&[0x02, 0xFC, 0x80, 0x01, 0, 0, 0, 0]).is_err());
+        assert!(Message::decode(&[0x02, 0xFD, 0x80, 0x01, 0, 0, 0, 2, 0x0E, 0x00]).is_err());
+        assert!(Message::decode(&[0x02, 0xFD, 0x80, 0x01, 0x7F, 0, 0, 0]).is_err());
+        assert_eq!(
+            Message::decode(&[0x02, 0xFD, 0x40, 0x01, 0, 0, 0, 0]).unwrap(),
+            Some((
+                Message::Other {
+                    payload_type: 0x4001
+                },
+                8
+            ))
+        );
+    }
+}
@@ -0,0 +1,331 @@
+//! Diagnostics over IP (ISO 13400), for vehicles whose ECUs are reached through an Ethernet gateway
+//! rather than on a CAN bus.
+//!
+//! Gateways are found by broadcasting a vehicle identification request over UDP, and diagnostic
+//! messages are exchanged with them over TCP once routing has been activated.  Gateways
+//! acknowledge each diagnostic message before passing it on, and check every so often that we're
+//! still there, so both are handled here as well.
+
+use std::{
+    collections::VecDeque,
+    io,
+    net::{Ipv4Addr, Ipv6Addr, SocketAddr},
+    time::Duration,
+};
+
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpStream, UdpSocket},
+    time::{timeout, timeout_at, Instant},
+};
+use tracing::trace;
+
+use super::can::error::SocketError;
+
+use self::message::{
+    diagnostic_nack_reason, generic_nack_reason, routing_activation_reason, Message,
+    VehicleAnnouncement, ROUTING_ACTIVATION_SUCCESS,
+};
+
+#[cfg(test)]
+pub mod gateway;
+pub mod message;
+
+/// Port that DoIP entities listen on, for both UDP and TCP.
+pub const DOIP_PORT: u16 = 13400;
+
+/// Finds DoIP entities by sending a vehicle identification request to `destination`, usually the
+/// broadcast address, and collecting their announcements until `timeout` elapses.
+pub async fn identify_vehicles(
+    destination: SocketAddr,
+    timeout: Duration,
+) -> io::Result<Vec<(SocketAddr, VehicleAnnouncement)>> {
+    let local: SocketAddr = if destination.is_ipv4() {
+        (Ipv4Addr::UNSPECIFIED, 0).into()
+    } else {
+        (Ipv6Addr::UNSPECIFIED, 0).into()
+    };
+    let socket = UdpSocket::bind(local).await?;
+    socket.set_broadcast(true)?;
+    socket
+        .send_to(&Message::VehicleIdentificationRequest.encode(), destination)
+        .await?;
+
+    let deadline = Instant::now() + timeout;
+    let mut vehicles = Vec::new();
+    let mut buf = [0; 512];
+    loop {
+        let (n, address) = match timeout_at(deadline, socket.recv_from(&mut buf)).await {
+            Ok(received) => received?,
+            Err(_) => return Ok(vehicles),
+        };
+
+        match Message::decode(&buf[..n]) {
+            // Entities announce themselves a few times over, so each is only kept once.
+            Ok(Some((Message::VehicleAnnouncement(announcement), _))) => {
+                let vehicle = (address, announcement);
+                if !vehicles.contains(&vehicle) {
+                    vehicles.push(vehicle);
+                }
+            }
+            _ => trace!("Ignoring datagram from {}: {:02X?}", address, &buf[..n]),
+        }
+    }
+}
+
+/// A connection to a DoIP entity, with routing activated for us as a tester.
+///
+/// Diagnostic messages received while waiting on something else are queued until they're asked
+/// for, and alive checks are answered whenever they're read.
+pub struct DoIPConnection {
+    stream: TcpStream,
+    buf: Vec<u8>,
+    tester_address: u16,
+    entity_address: u16,
+    acknowledgement_timeout: Duration,
+    acknowledgements: VecDeque<(u16, Option<u8>)>,
+    diagnostic_messages: VecDeque<(u16, Vec<u8>)>,
+}
+
+impl DoIPConnection {
+    /// Connects to the entity at `address`, and activates routing for us at `tester_address`.
+    ///
+    /// Connecting, activating routing, and each acknowledgement from then on, is given no longer
+    /// than `acknowledgement_timeout`.
+    pub async fn connect(
+        address: SocketAddr,
+        tester_address: u16,
+        activation_type: u8,
+        acknowledgement_timeout: Duration,
+    ) -> Result<Self, SocketError> {
+        let stream = timeout(acknowledgement_timeout, TcpStream::connect(address))
+            .await
+            .map_err(|_| SocketError::Timeout(acknowledgement_timeout))??;
+        stream.set_nodelay(true)?;
+
+        let mut connection = Self {
+            stream,
+            buf: Vec::new(),
+            tester_address,
+            entity_address: 0,
+            acknowledgement_timeout,
+            acknowledgements: VecDeque::new(),
+            diagnostic_messages: VecDeque::new(),
+        };
+        connection
+            .write(&Message::RoutingActivationRequest {
+                source_address: tester_address,
+                activation_type,
+            })
+            .await?;
+
+        let activation = async {
+            loop {
+                if let Some(Message::RoutingActivationResponse {
+                    entity_address,
+                    code,
+                    ..
+                }) = connection.next_message().await?
+                {
+                    return Ok::<_, SocketError>((entity_address, code));
+                }
+            }
+        };
+        let (entity_address, code) = timeout(acknowledgement_timeout, activation)
+            .await
+            .map_err(|_| SocketError::Timeout(acknowledgement_timeout))??;
+        if code != ROUTING_ACTIVATION_SUCCESS {
+            return Err(other(format!(
+                "routing activation was denied: {} (0x{:02X})",
+                routing_activation_reason(code),
+                code
+            )));
+        }
+
+        connection.entity_address = entity_address;
+        Ok(connection)
+    }
+
+    /// Logical address of the entity we're connected to.
+    pub fn entity_address(&self) -> u16 {
+        self.entity_address
+    }
+
+    /// Sends a diagnostic message to the given logical address, waiting for the entity to
+    /// acknowledge it.
+    pub async fn send(&mut self, target_address: u16, data: &[u8]) -> Result<(), SocketError> {
+        // Anything left over was for a message that's long since been given up on.
+        self.acknowledgements.clear();
+        self.write(&Message::DiagnosticMessage {
+            source_address: self.tester_address,
+            target_address,
+            data: data.to_vec(),
+        })
+        .await?;
+
+        let duration = self.acknowledgement_timeout;
+        let acknowledgement = async {
+            loop {
+                while let Some((source_address, nack)) = self.acknowledgements.pop_front() {
+                    if source_address == target_address {
+                        return Ok::<_, SocketError>(nack);
+                    }
+                }
+                self.next_message().await?;
+            }
+        };
+        let nack = timeout(duration, acknowledgement)
+            .await
+            .map_err(|_| SocketError::Timeout(duration))??;
+
+        match nack {
+            None => Ok(()),
+            Some(code) => Err(other(format!(
+                "diagnostic message to 0x{:04X} was rejected: {} (0x{:02X})",
+                target_address,
+                diagnostic_nack_reason(code),
+                code
+            ))),
+        }
+    }
+
+    /// Receives the next diagnostic message from the given logical address.
+    pub async fn receive_from(&mut self, source_address: u16) -> Result<Vec<u8>, SocketError> {
+        loop {
+            let position = self
+                .diagnostic_messages
+                .iter()
+                .position(|(source, _)| *source == source_address);
+            if let Some((_, data)) = position.and_then(|i| self.diagnostic_messages.remove(i)) {
+                return Ok(data);
+            }
+            self.next_message().await?;
+        }
+    }
+
+    /// Receives the next diagnostic message from anyone, along with who it's from.
+    pub async fn receive_any(&mut self) -> Result<(u16, Vec<u8>), SocketError> {
+        loop {
+            if let Some(message) = self.diagnostic_messages.pop_front() {
+                return Ok(message);
+            }
+            self.next_message().await?;
+        }
+    }
+
+    async fn write(&mut self, message: &Message) -> Result<(), SocketError> {
+        trace!("DoIP <- {:02X?}", message);
+        Ok(self.stream.write_all(&message.encode()).await?)
+    }
+
+    /// Reads the next message, queuing diagnostic messages and acknowledgements, and answering
+    /// alive checks, returning anything else.
+    ///
+    /// Messages are only taken out of the buffer once they've arrived in full, so this can be
+    /// cancelled without losing any.
+    async fn next_message(&mut self) -> Result<Option<Message>, SocketError> {
+        let message = loop {
+            if let Some((message, length)) = Message::decode(&self.buf)? {
+                self.buf.drain(..length);
+                break message;
+            }
+
+            let mut buf = [0; 4096];
+            match self.stream.read(&mut buf).await? {
+                0 => return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into()),
+                n => self.buf.extend_from_slice(&buf[..n]),
+            }
+        };
+        trace!("DoIP -> {:02X?}", message);
+
+        match message {
+            Message::DiagnosticMessage {
+                source_address,
+                target_address,
+                data,
+            } if target_address == self.tester_address => {
+                self.diagnostic_messages.push_back((source_address, data));
+            }
+            Message::DiagnosticMessageAck { source_address, .. } => {
+                self.acknowledgements.push_back((source_address, None));
+            }
+            Message::DiagnosticMessageNack {
+                source_address,
+                code,
+                ..
+            } => {
+                self.acknowledgements
+                    .push_back((source_address, Some(code)));
+            }
+            Message::AliveCheckRequest => {
+                let response = Message::AliveCheckResponse {
+                    source_address: self.tester_address,
+                };
+                self.write(&response).await?;
+            }
+            Message::GenericNack(code) => {
+                return Err(other(format!(
+                    "DoIP entity rejected a message: {} (0x{:02X})",
+                    generic_nack_reason(code),
+                    code
+                )))
+            }
+            message => return Ok(Some(message)),
+        }
+
+        Ok(None)
+    }
+}
+
+fn other(message: String) -> SocketError {
+    io::Error::new(io::ErrorKind::Other, message).into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{gateway::DoIPGateway, *};
+
+    #[tokio::test]
+    async fn identifies_vehicles() {
+        let (address, _) = DoIPGateway::new().spawn();
+
+        let vehicles = identify_vehicles(address, Duration::from_millis(100))
+            .await
+            .unwrap();
+        assert_eq!(vehicles.len(), 1);
+        assert_eq!(vehicles[0].0.ip(), address.ip());
+        assert_eq!(vehicles[0].1.vin, "1HGCM82633A004352");
+        assert_eq!(vehicles[0].1.logical_address, 0x1000);
+    }
+
+    #[tokio::test]
+    async fn answers_alive_checks_and_queues_messages() {
+        let (address, mut received) = DoIPGateway::new()
+            .reply(0x1001, &[0x3E, 0x00], &[(0x1001, &[0x7E, 0x00])])
+            .check_alive()
+            .spawn();
+
+        let mut connection = DoIPConnection::connect(address, 0x0E00, 0x00, Duration::from_secs(1))
+            .await
+            .unwrap();
+        assert_eq!(connection.entity_address(), 0x1000);
+
+        // The alive check is answered while waiting on the first acknowledgement, so it's been
+        // seen by the time the second is.
+        for _ in 0..2 {
+            connection.send(0x1001, &[0x3E, 0x00]).await.unwrap();
+            assert_eq!(
+                connection.receive_from(0x1001).await.unwrap(),
+                vec![0x7E, 0x00]
+            );
+        }
+
+        let mut messages = Vec::new();
+        while let Ok(message) = received.try_recv() {
+            messages.push(message);
+        }
+        assert!(messages.contains(&Message::AliveCheckResponse {
+            source_address: 0x0E00
+        }));
+    }
+}
@@ -0,0 +1,278 @@
+//! Diagnostic messages (J1939-73), for reading and clearing fault codes.
+//!
+//! Active DTCs are broadcast in DM1 once a second, while previously active ones are only sent in
+//! DM2 when asked for.  Both start with the status of the lamps on the dashboard, followed by four
+//! bytes per DTC.
+
+use core::fmt;
+
+use clap::ArgEnum;
+
+/// Active DTCs.
+pub const DM1_PGN: u32 = 65226;
+
+/// Previously active DTCs.
+pub const DM2_PGN: u32 = 65227;
+
+/// Clears previously active DTCs, when requested.
+pub const DM3_PGN: u32 = 65228;
+
+/// Clears active DTCs, when requested.
+pub const DM11_PGN: u32 = 65235;
+
+/// Occurrence count that means the count isn't available.
+const OCCURRENCE_COUNT_UNAVAILABLE: u8 = 0x7F;
+
+/// How SPNs were laid out before J1939-73 settled on the current (fourth) method.
+///
+/// DTCs only say whether they use the current method or an older one, so which older one has to
+/// be known beforehand.
+#[derive(ArgEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SpnConversion {
+    /// All 19 bits of the SPN, most significant first.
+    Version1,
+    /// The low 16 bits of the SPN, most significant first, then the high 3 bits.
+    Version2,
+    /// The same layout as the current method.
+    Version3,
+}
+
+/// State of a lamp on the dashboard.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Lamp {
+    Off,
+    On,
+    SlowFlash,
+    FastFlash,
+    Unavailable,
+}
+
+impl Lamp {
+    /// Decodes a lamp from its two bits of status and two bits of flash status.
+    fn decode(status: u8, flash: u8) -> Self {
+        match (status & 0x03, flash & 0x03) {
+            (0x00, _) => Self::Off,
+            (0x01, 0x00) => Self::SlowFlash,
+            (0x01, 0x01) => Self::FastFlash,
+            (0x01, _) => Self::On,
+            _ => Self::Unavailable,
+        }
+    }
+}
+
+impl fmt::Display for Lamp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Off => write!(f, "off"),
+            Self::On => write!(f, "on"),
+            Self::SlowFlash => write!(f, "flashing slowly"),
+            Self::FastFlash => write!(f, "flashing fast"),
+            Self::Unavailable => write!(f, "unavailable"),
+        }
+    }
+}
+
+/// Status of the lamps a node controls.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Lamps {
+    pub malfunction_indicator: Lamp,
+    pub red_stop: Lamp,
+    pub amber_warning: Lamp,
+    pub protect: Lamp,
+}
+
+impl Lamps {
+    fn decode(status: u8, flash: u8) -> Self {
+        let lamp = |shift: u8| Lamp::decode(status >> shift, flash >> shift);
+        Self {
+            malfunction_indicator: lamp(6),
+            red_stop: lamp(4),
+            amber_warning: lamp(2),
+            protect: lamp(0),
+        }
+    }
+}
+
+impl fmt::Display for Lamps {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "MIL {}, red stop {}, amber warning {}, protect {}",
+            self.malfunction_indicator, self.red_stop, self.amber_warning, self.protect
+        )
+    }
+}
+
+/// A diagnostic trouble code: the suspect parameter, and how it's failing.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Dtc {
+    pub spn: u32,
+    pub fmi: u8,
+    pub occurrence_count: u8,
+}
+
+impl Dtc {
+    /// Decodes a DTC from its four bytes, using `conversion` if it says it uses an older method.
+    fn decode(bytes: &[u8], conversion: SpnConversion) -> Self {
+        let [first, second, third, fourth] = [bytes[0], bytes[1], bytes[2], bytes[3]];
+        let high = (third >> 5) as u32;
+        let fmi = third & 0x1F;
+        let occurrence_count = fourth & 0x7F;
+
+        // The conversion method bit is set for any of the older methods.
+        let spn = match (fourth >> 7, conversion) {
+            (0, _) | (_, SpnConversion::Version3) => {
+                first as u32 | (second as u32) << 8 | high << 16
+            }
+            (_, SpnConversion::Version1) => (first as u32) << 11 | (second as u32) << 3 | high,
+            (_, SpnConversion::Version2) => (first as u32) << 8 | second as u32 | high << 16,
+        };
+
+        Self {
+            spn,
+            fmi,
+            occurrence_count,
+        }
+    }
+}
+
+impl fmt::Display for Dtc {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "SPN {}, FMI {} ({})",
+            self.spn,
+            self.fmi,
+            fmi_description(self.fmi)
+        )?;
+        if self.occurrence_count != OCCURRENCE_COUNT_UNAVAILABLE {
+            write!(f, ", {} occurrences", self.occurrence_count)?;
+        }
+        Ok(())
+    }
+}
+
+/// Describes a failure mode identifier.
+pub fn fmi_description(fmi: u8) -> &'static str {
+    match fmi {
+        0 => "above normal range, most severe",
+        1 => "below normal range, most severe",
+        2 => "erratic, intermittent or incorrect",
+        3 => "voltage above normal, or shorted high",
+        4 => "voltage below normal, or shorted low",
+        5 => "current below normal, or open circuit",
+        6 => "current above normal, or grounded circuit",
+        7 => "mechanical system not responding or out of adjustment",
+        8 => "abnormal frequency, pulse width or period",
+        9 => "abnormal update rate",
+        10 => "abnormal rate of change",
+        11 => "root cause not known",
+        12 => "bad intelligent device or component",
+        13 => "out of calibration",
+        14 => "special instructions",
+        15 => "above normal range, least severe",
+        16 => "above normal range, moderately severe",
+        17 => "below normal range, least severe",
+        18 => "below normal range, moderately severe",
+        19 => "received network data in error",
+        20 => "data drifted high",
+        21 => "data drifted low",
+        31 => "condition exists",
+        _ => "reserved",
+    }
+}
+
+/// Contents of DM1 or DM2.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DiagnosticMessage {
+    pub lamps: Lamps,
+    pub dtcs: Vec<Dtc>,
+}
+
+impl DiagnosticMessage {
+    /// Decodes DM1 or DM2, if there's enough of it for the lamp status.
+    pub fn decode(data: &[u8], conversion: SpnConversion) -> Option<Self> {
+        let lamps = Lamps::decode(*data.first()?, *data.get(1)?);
+
+        // Nodes without any DTCs send a single one that's all zeroes, and single frames are
+        // padded out to eight bytes.
+        let dtcs = data[2..]
+            .chunks_exact(4)
+            .map(|bytes| Dtc::decode(bytes, conversion))
+            .filter(|dtc| dtc.spn != 0 || dtc.fmi != 0)
+            .collect();
+
+        Some(Self { lamps, dtcs })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decodes_lamps_and_dtcs() {
+        // MIL on, amber warning flashing fast, with coolant temperature above normal and an SPN
+        // that needs all 19 bits.
+        let data = [0x44, 0xF7, 0x6E, 0x00, 0x00, 0x03, 0x9C, 0xF0, 0xE3, 0x7F];
+        let message = DiagnosticMessage::decode(&data, SpnConversion::Version1).unwrap();
+        assert_eq!(
+            message.lamps,
+            Lamps {
+                malfunction_indicator: Lamp::On,
+                red_stop: Lamp::Off,
+                amber_warning: Lamp::FastFlash,
+                protect: Lamp::Off,
+            }
+        );
+        assert_eq!(
+            message.dtcs,
+            vec![
+                Dtc {
+                    spn: 110,
+                    fmi: 0,
+                    occurrence_count: 3
+                },
+                Dtc {
+                    spn: 520348,
+                    fmi: 3,
+                    occurrence_count: 0x7F
+                },
+            ]
+        );
+        assert_eq!(
+            message.dtcs[0].to_string(),
+            "SPN 110, FMI 0 (above normal range, most severe), 3 occurrences"
+        );
+        assert_eq!(
+            message.lamps.to_string(),
+            "MIL on, red stop off, amber warning flashing fast, protect off"
+        );
+    }
+
+    #[test]
+    fn decodes_older_spn_conversion_methods() {
+        // SPN 110, FMI 1, once, with the conversion method bit set.
+        let version1 = [0x00, 0xFF, 0x00, 0x0D, 0xC1, 0x81];
+        let version2 = [0x00, 0xFF, 0x00, 0x6E, 0x01, 0x81];
+        let version3 = [0x00, 0xFF, 0x6E, 0x00, 0x01, 0x81];
+        for (data, conversion) in [
+            (version1, SpnConversion::Version1),
+            (version2, SpnConversion::Version2),
+            (version3, SpnConversion::Version3),
+        ] {
+            let message = DiagnosticMessage::decode(&data, conversion).unwrap();
+            assert_eq!(message.dtcs[0].spn, 110, "{:?}", conversion);
+            assert_eq!(message.dtcs[0].fmi, 1);
+        }
+    }
+
+    #[test]
+    fn ignores_placeholder_dtcs() {
+        let data = [0x00, 0xFF, 0x00, 0x00, 0x00, 0x00, 0xFF, 0xFF];
+        let message = DiagnosticMessage::decode(&data, SpnConversion::Version1).unwrap();
+        assert!(message.dtcs.is_empty());
+        assert_eq!(message.lamps.malfunction_indicator, Lamp::Off);
+        assert!(DiagnosticMessage::decode(&[0x00], SpnConversion::Version1).is_none());
+    }
+}
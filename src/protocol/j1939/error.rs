@@ -0,0 +1,15 @@
+use thiserror::Error;
+
+use crate::protocol::can::error::{SocketBuildError, SocketError};
+
+#[derive(Debug, Error)]
+pub enum J1939Error {
+    #[error("failed to initialize socket: {0}")]
+    Initialization(#[from] SocketBuildError),
+    #[error("socket error: {0}")]
+    Io(#[from] SocketError),
+    #[error("no address could be claimed, as every candidate belongs to a node with a higher-priority NAME")]
+    CannotClaimAddress,
+    #[error("lost address 0x{address:02X} to a node with a higher-priority NAME")]
+    AddressLost { address: u8 },
+}
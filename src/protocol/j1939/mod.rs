@@ -0,0 +1,203 @@
+//! SAE J1939, as spoken by heavy-duty vehicles on 29-bit identifiers.
+//!
+//! Rather than requests and responses between a tester and a single ECU, every node has an
+//! address it claims on the bus, and data is exchanged as parameter groups that are either
+//! broadcast or sent to a particular address.  Parameter groups longer than a single frame go
+//! through the transport protocol, either broadcast (BAM) or to us (RTS/CTS).
+//!
+//! Everything runs in userspace over a raw socket, rather than through the kernel's CAN_J1939
+//! sockets, so that it works on every backend with raw CAN access.
+
+use core::fmt;
+
+pub mod diagnostics;
+pub mod error;
+pub mod name;
+pub mod socket;
+pub mod transport;
+
+/// Address that broadcast parameter groups are sent to.
+pub const GLOBAL_ADDRESS: u8 = 0xFF;
+
+/// Address used by nodes that haven't been able to claim one.
+pub const NULL_ADDRESS: u8 = 0xFE;
+
+/// Parameter group used to ask other nodes for a parameter group.
+pub const REQUEST_PGN: u32 = 59904;
+
+/// Parameter group used to acknowledge, or refuse, a request.
+pub const ACKNOWLEDGEMENT_PGN: u32 = 59392;
+
+/// Parameter group used to claim an address, or to say that none could be claimed.
+pub const ADDRESS_CLAIMED_PGN: u32 = 60928;
+
+/// Parameter groups used by the transport protocol for connection management and data transfer.
+pub const TP_CONNECTION_MANAGEMENT_PGN: u32 = 60416;
+pub const TP_DATA_TRANSFER_PGN: u32 = 60160;
+
+/// Priority that requests and transport protocol frames are sent with.
+pub const DEFAULT_PRIORITY: u8 = 6;
+
+/// Whether the given parameter group is sent to a particular address (PDU1), rather than being
+/// broadcast with a group extension in place of the destination address (PDU2).
+pub fn is_destination_specific(pgn: u32) -> bool {
+    (pgn >> 8) & 0xFF < 0xF0
+}
+
+/// A 29-bit identifier, broken down into its J1939 fields.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct J1939Id {
+    pub priority: u8,
+    pub pgn: u32,
+    pub source_address: u8,
+    /// Destination of destination-specific parameter groups, or the global address for broadcast
+    /// ones.
+    pub destination_address: u8,
+}
+
+impl J1939Id {
+    pub fn new(priority: u8, pgn: u32, source_address: u8, destination_address: u8) -> Self {
+        Self {
+            priority,
+            pgn,
+            source_address,
+            destination_address,
+        }
+    }
+
+    pub fn from_raw(raw: u32) -> Self {
+        let priority = ((raw >> 26) & 0x07) as u8;
+        let pgn = (raw >> 8) & 0x3FFFF;
+        let source_address = raw as u8;
+
+        // Destination-specific parameter groups carry the destination address where broadcast
+        // ones carry their group extension, which isn't part of the parameter group number.
+        if is_destination_specific(pgn) {
+            Self::new(priority, pgn & 0x3FF00, source_address, pgn as u8)
+        } else {
+            Self::new(priority, pgn, source_address, GLOBAL_ADDRESS)
+        }
+    }
+
+    pub fn to_raw(&self) -> u32 {
+        let pgn = if is_destination_specific(self.pgn) {
+            (self.pgn & 0x3FF00) | self.destination_address as u32
+        } else {
+            self.pgn & 0x3FFFF
+        };
+
+        ((self.priority as u32 & 0x07) << 26) | (pgn << 8) | self.source_address as u32
+    }
+}
+
+impl fmt::Display for J1939Id {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "PGN {} from 0x{:02X} to 0x{:02X} (priority {})",
+            self.pgn, self.source_address, self.destination_address, self.priority
+        )
+    }
+}
+
+/// A whole parameter group, whether it arrived in a single frame or through the transport
+/// protocol.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Message {
+    pub pgn: u32,
+    pub source_address: u8,
+    pub data: Vec<u8>,
+}
+
+/// How a node answered a request sent to it in particular.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Acknowledgement {
+    Positive,
+    Negative,
+    AccessDenied,
+    CannotRespond,
+    Other(u8),
+}
+
+impl Acknowledgement {
+    /// Decodes the acknowledgement of a request for `pgn`, if that's what the message is.
+    pub fn of(message: &Message, pgn: u32) -> Option<Self> {
+        if message.pgn != ACKNOWLEDGEMENT_PGN
+            || message.data.len() < 8
+            || decode_pgn(&message.data[5..8]) != pgn
+        {
+            return None;
+        }
+
+        Some(match message.data[0] {
+            0x00 => Self::Positive,
+            0x01 => Self::Negative,
+            0x02 => Self::AccessDenied,
+            0x03 => Self::CannotRespond,
+            b => Self::Other(b),
+        })
+    }
+}
+
+impl fmt::Display for Acknowledgement {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Positive => write!(f, "positive acknowledgement"),
+            Self::Negative => write!(f, "negative acknowledgement"),
+            Self::AccessDenied => write!(f, "access denied"),
+            Self::CannotRespond => write!(f, "cannot respond"),
+            Self::Other(b) => write!(f, "unknown acknowledgement (0x{:02X})", b),
+        }
+    }
+}
+
+/// Encodes a parameter group number the way it's carried in requests and transport protocol
+/// frames: three bytes, least significant first.
+pub fn encode_pgn(pgn: u32) -> [u8; 3] {
+    let [a, b, c, _] = pgn.to_le_bytes();
+    [a, b, c]
+}
+
+pub fn decode_pgn(data: &[u8]) -> u32 {
+    u32::from_le_bytes([data[0], data[1], data[2], 0])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn destination_specific_identifiers() {
+        // Request for the address claimed parameter group, from a tester to everyone.
+        let id = J1939Id::from_raw(0x18EAFFF9);
+        assert_eq!(id, J1939Id::new(6, REQUEST_PGN, 0xF9, GLOBAL_ADDRESS));
+        assert_eq!(id.to_raw(), 0x18EAFFF9);
+
+        let id = J1939Id::from_raw(0x1CEC00F9);
+        assert_eq!(
+            id,
+            J1939Id::new(7, TP_CONNECTION_MANAGEMENT_PGN, 0xF9, 0x00)
+        );
+        assert_eq!(id.to_raw(), 0x1CEC00F9);
+    }
+
+    #[test]
+    fn broadcast_identifiers() {
+        // DM1 from the engine, with the group extension as part of the parameter group number.
+        let id = J1939Id::from_raw(0x18FECA00);
+        assert_eq!(id, J1939Id::new(6, 65226, 0x00, GLOBAL_ADDRESS));
+        assert_eq!(id.to_raw(), 0x18FECA00);
+
+        // Data page set, as used by some proprietary parameter groups.
+        let id = J1939Id::from_raw(0x0DFF0117);
+        assert_eq!(id.pgn, 0x1FF01);
+        assert_eq!(id.priority, 3);
+        assert_eq!(id.to_raw(), 0x0DFF0117);
+    }
+
+    #[test]
+    fn pgn_round_trip() {
+        assert_eq!(encode_pgn(65226), [0xCA, 0xFE, 0x00]);
+        assert_eq!(decode_pgn(&encode_pgn(0x1FF01)), 0x1FF01);
+    }
+}
@@ -0,0 +1,43 @@
+use core::fmt;
+
+/// Function of an off-board diagnostic service tool, per J1939 industry group 0.
+const SERVICE_TOOL_FUNCTION: u64 = 129;
+
+/// A 64-bit NAME, which identifies a node on the bus, and settles which node gets an address when
+/// more than one claims it: the lower NAME wins.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Name(pub u64);
+
+impl Name {
+    /// NAME of a service tool, which can claim an arbitrary address if its preferred one is taken.
+    ///
+    /// Tools with the same NAME would fight over addresses, so each one on a bus needs its own
+    /// identity number.  Only the low 21 bits of it are used.
+    pub fn service_tool(identity_number: u32) -> Self {
+        let identity_number = identity_number as u64 & 0x1F_FFFF;
+        Self((1 << 63) | (SERVICE_TOOL_FUNCTION << 40) | identity_number)
+    }
+
+    /// Whether or not the node can move to another address when it loses its preferred one.
+    pub fn is_arbitrary_address_capable(&self) -> bool {
+        self.0 >> 63 != 0
+    }
+
+    /// NAMEs are sent least significant byte first.
+    pub fn to_bytes(self) -> [u8; 8] {
+        self.0.to_le_bytes()
+    }
+
+    /// Decodes a NAME from address claimed data, if there's enough of it.
+    pub fn from_bytes(data: &[u8]) -> Option<Self> {
+        let mut bytes = [0; 8];
+        bytes.copy_from_slice(data.get(..8)?);
+        Some(Self(u64::from_le_bytes(bytes)))
+    }
+}
+
+impl fmt::Display for Name {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:016X}", self.0)
+    }
+}
@@ -0,0 +1,351 @@
+use std::{collections::HashMap, iter, ops::RangeInclusive, time::Duration};
+
+use socketcan::CANFrame;
+use tokio::time::{timeout_at, Instant};
+use tracing::{debug, info};
+
+use crate::{
+    common::config::CANParameters,
+    protocol::can::{error::SocketError, frame::CANAnyFrame, raw::RawSocket},
+};
+
+use super::{
+    decode_pgn, encode_pgn,
+    error::J1939Error,
+    name::Name,
+    transport::{Reassembler, Received},
+    J1939Id, Message, ADDRESS_CLAIMED_PGN, DEFAULT_PRIORITY, GLOBAL_ADDRESS, NULL_ADDRESS,
+    REQUEST_PGN, TP_CONNECTION_MANAGEMENT_PGN, TP_DATA_TRANSFER_PGN,
+};
+
+/// How long other nodes have to contend our claim to an address before it's ours.
+const ADDRESS_CLAIM_TIMEOUT: Duration = Duration::from_millis(250);
+
+/// Addresses that arbitrary address capable nodes pick from when their preferred one is taken.
+const ARBITRARY_ADDRESSES: RangeInclusive<u8> = 128..=247;
+
+/// Priority that address claims are sent with.
+const ADDRESS_CLAIM_PRIORITY: u8 = 6;
+
+/// A node on a J1939 bus, with an address claimed for it.
+pub struct J1939Socket {
+    socket: RawSocket,
+    name: Name,
+    address: u8,
+    reassembler: Reassembler,
+}
+
+impl J1939Socket {
+    /// Opens a raw socket on the configured backend, and claims an address for the given NAME,
+    /// starting with `preferred_address`.
+    pub async fn open(
+        can_parameters: CANParameters,
+        name: Name,
+        preferred_address: u8,
+    ) -> Result<Self, J1939Error> {
+        let socket = RawSocket::builder()
+            .can_parameters(can_parameters)
+            .build()?;
+
+        let mut j1939 = Self {
+            socket,
+            name,
+            address: NULL_ADDRESS,
+            reassembler: Reassembler::new(NULL_ADDRESS),
+        };
+        j1939.claim_address(preferred_address).await?;
+
+        Ok(j1939)
+    }
+
+    /// Address we've claimed.
+    pub fn address(&self) -> u8 {
+        self.address
+    }
+
+    /// Requests a parameter group from the node at `destination_address`, or from every node if
+    /// it's the global address.
+    pub async fn request(&mut self, pgn: u32, destination_address: u8) -> Result<(), J1939Error> {
+        let id = J1939Id::new(
+            DEFAULT_PRIORITY,
+            REQUEST_PGN,
+            self.address,
+            destination_address,
+        );
+        self.write(id, &encode_pgn(pgn)).await
+    }
+
+    /// Receives the next parameter group that's broadcast or sent to us, reassembling any sent
+    /// through the transport protocol, and defending our address along the way.
+    pub async fn receive(&mut self) -> Result<Message, J1939Error> {
+        loop {
+            let frame = self.socket.read_with_timeout(None).await?;
+            let id = match j1939_id(&frame) {
+                Some(id) => id,
+                None => continue,
+            };
+            let data = frame.data();
+
+            let received = match id.pgn {
+                TP_CONNECTION_MANAGEMENT_PGN => self.reassembler.connection_management(id, data),
+                TP_DATA_TRANSFER_PGN => self.reassembler.data_transfer(id, data),
+                ADDRESS_CLAIMED_PGN => {
+                    self.defend_address(id, data).await?;
+                    continue;
+                }
+                REQUEST_PGN if requests_address_claim(id, data, self.address) => {
+                    self.send_address_claimed(self.address).await?;
+                    continue;
+                }
+                _ if id.destination_address == GLOBAL_ADDRESS
+                    || id.destination_address == self.address =>
+                {
+                    Received {
+                        message: Some(Message {
+                            pgn: id.pgn,
+                            source_address: id.source_address,
+                            data: data.to_vec(),
+                        }),
+                        reply: None,
+                    }
+                }
+                _ => continue,
+            };
+
+            if let Some((id, data)) = received.reply {
+                self.write(id, &data).await?;
+            }
+            if let Some(message) = received.message {
+                return Ok(message);
+            }
+        }
+    }
+
+    /// Claims the preferred address, or failing that, the first arbitrary address nobody with a
+    /// higher-priority NAME has claimed, if our NAME allows for it.
+    async fn claim_address(&mut self, preferred_address: u8) -> Result<(), J1939Error> {
+        let candidates = iter::once(preferred_address)
+            .chain(ARBITRARY_ADDRESSES.filter(|address| *address != preferred_address))
+            .take(if self.name.is_arbitrary_address_capable() {
+                usize::MAX
+            } else {
+                1
+            });
+
+        // Claims seen while contending for one address save us from contending for another that
+        // we'd only lose.
+        let mut claimed = HashMap::new();
+        for address in candidates {
+            if matches!(claimed.get(&address), Some(name) if *name < self.name) {
+                continue;
+            }
+
+            if self.contend_for(address, &mut claimed).await? {
+                info!("Claimed J1939 address 0x{:02X} as {}.", address, self.name);
+                self.address = address;
+                self.reassembler.set_address(address);
+                return Ok(());
+            }
+            debug!("J1939 address 0x{:02X} belongs to someone else.", address);
+        }
+
+        self.send_address_claimed(NULL_ADDRESS).await?;
+        Err(J1939Error::CannotClaimAddress)
+    }
+
+    /// Claims the given address, and waits to see if anyone with a higher-priority NAME contends
+    /// it, returning whether or not it's ours.
+    async fn contend_for(
+        &mut self,
+        address: u8,
+        claimed: &mut HashMap<u8, Name>,
+    ) -> Result<bool, J1939Error> {
+        self.send_address_claimed(address).await?;
+
+        let deadline = Instant::now() + ADDRESS_CLAIM_TIMEOUT;
+        loop {
+            let frame = match timeout_at(deadline, self.socket.read_with_timeout(None)).await {
+                Ok(frame) => frame?,
+                Err(_) => return Ok(true),
+            };
+            let id = match j1939_id(&frame) {
+                Some(id) => id,
+                None => continue,
+            };
+
+            if id.pgn == ADDRESS_CLAIMED_PGN {
+                if let Some(name) = Name::from_bytes(frame.data()) {
+                    claimed.insert(id.source_address, name);
+                    if id.source_address == address {
+                        if name < self.name {
+                            return Ok(false);
+                        }
+                        // We win, so we claim it again for them to move on.
+                        self.send_address_claimed(address).await?;
+                    }
+                }
+            } else if requests_address_claim(id, frame.data(), address) {
+                self.send_address_claimed(address).await?;
+            }
+        }
+    }
+
+    /// Answers someone else claiming our address, giving it up if their NAME has priority.
+    async fn defend_address(&mut self, id: J1939Id, data: &[u8]) -> Result<(), J1939Error> {
+        let name = match Name::from_bytes(data) {
+            Some(name) if id.source_address == self.address && name != self.name => name,
+            _ => return Ok(()),
+        };
+
+        if name < self.name {
+            let address = self.address;
+            self.send_address_claimed(NULL_ADDRESS).await?;
+            self.address = NULL_ADDRESS;
+            self.reassembler.set_address(NULL_ADDRESS);
+            return Err(J1939Error::AddressLost { address });
+        }
+
+        self.send_address_claimed(self.address).await
+    }
+
+    /// Claims the given address, or says that we couldn't claim one if it's the null address.
+    async fn send_address_claimed(&mut self, address: u8) -> Result<(), J1939Error> {
+        let id = J1939Id::new(
+            ADDRESS_CLAIM_PRIORITY,
+            ADDRESS_CLAIMED_PGN,
+            address,
+            GLOBAL_ADDRESS,
+        );
+        self.write(id, &self.name.to_bytes()).await
+    }
+
+    async fn write(&mut self, id: J1939Id, data: &[u8]) -> Result<(), J1939Error> {
+        // Identifiers low enough to pass for 11-bit ones need priority 0 and PGN 0, which
+        // nothing we send uses.
+        let frame = CANFrame::new(id.to_raw(), data, false, false)
+            .map_err(|_| SocketError::PayloadLength(data.len()))?;
+        Ok(self.socket.write(frame).await?)
+    }
+}
+
+/// Gets the J1939 identifier of a frame, if it has a 29-bit identifier.
+fn j1939_id(frame: &CANAnyFrame) -> Option<J1939Id> {
+    if frame.is_extended() {
+        Some(J1939Id::from_raw(frame.id()))
+    } else {
+        None
+    }
+}
+
+/// Whether the frame is a request for the address claimed parameter group, sent to everyone or
+/// to `address`.
+fn requests_address_claim(id: J1939Id, data: &[u8], address: u8) -> bool {
+    id.pgn == REQUEST_PGN
+        && data.len() >= 3
+        && decode_pgn(data) == ADDRESS_CLAIMED_PGN
+        && (id.destination_address == GLOBAL_ADDRESS || id.destination_address == address)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use clap::Parser;
+    use tokio::time::timeout;
+
+    use crate::{common::config::AppConfig, protocol::can::slcan::emulator::SlcanEmulator};
+
+    use super::*;
+
+    fn can_parameters(device: &Path) -> CANParameters {
+        let backend = format!("slcan://{}", device.display());
+        AppConfig::try_parse_from(["hypercan", "--backend", &backend, "validate-socket"])
+            .expect("arguments should be valid")
+            .can_parameters()
+    }
+
+    #[tokio::test]
+    async fn requests_parameter_groups_sent_through_the_transport_protocol() {
+        let name = Name::service_tool(0);
+        let (device, mut lines) = SlcanEmulator::new()
+            .reply(
+                0x18EA00F9,
+                &[0xC5, 0xFD, 0x00],
+                &[(
+                    0x1CECF900,
+                    &[0x10, 0x0E, 0x00, 0x02, 0xFF, 0xC5, 0xFD, 0x00],
+                )],
+            )
+            .reply(
+                0x1CEC00F9,
+                &[0x11, 0x02, 0x01, 0xFF, 0xFF, 0xC5, 0xFD, 0x00],
+                &[
+                    (
+                        0x1CEBF900,
+                        &[0x01, b'D', b'D', b'E', b'C', b' ', b'1', b'5'],
+                    ),
+                    (
+                        0x1CEBF900,
+                        &[0x02, b'*', b'*', b'*', b'*', b'*', b'*', b'*'],
+                    ),
+                ],
+            )
+            .spawn();
+
+        let mut socket = J1939Socket::open(can_parameters(&device), name, 0xF9)
+            .await
+            .unwrap();
+        assert_eq!(socket.address(), 0xF9);
+
+        // Component identification, from the engine.
+        socket.request(64965, 0x00).await.unwrap();
+        let message = socket.receive().await.unwrap();
+        assert_eq!(message.pgn, 64965);
+        assert_eq!(message.source_address, 0x00);
+        assert_eq!(message.data, b"DDEC 15*******".to_vec());
+
+        // Our acknowledgement goes out just before the message is returned, so it may not have
+        // reached the adapter yet.
+        let mut written = Vec::new();
+        while written.last().map(String::as_str) != Some("T1CEC00F98130E0002FFC5FD00") {
+            let line = timeout(Duration::from_secs(1), lines.recv())
+                .await
+                .expect("acknowledgement should be written")
+                .expect("adapter should still be running");
+            written.push(line);
+        }
+        assert!(written.contains(&"T18EEFFF980000000000810080".to_string()));
+    }
+
+    #[tokio::test]
+    async fn moves_on_from_contended_addresses() {
+        let name = Name::service_tool(0);
+        let rival = Name(0x0000_0100_0000_0001);
+        let (device, _) = SlcanEmulator::new()
+            .reply(
+                0x18EEFFF9,
+                &name.to_bytes(),
+                &[(0x18EEFFF9, &rival.to_bytes())],
+            )
+            .spawn();
+
+        let socket = J1939Socket::open(can_parameters(&device), name, 0xF9)
+            .await
+            .unwrap();
+        assert_eq!(socket.address(), 0x80);
+
+        // Without an arbitrary address to move to, there's nothing to be done.
+        let name = Name(name.0 & !(1 << 63));
+        let (device, _) = SlcanEmulator::new()
+            .reply(
+                0x18EEFFF9,
+                &name.to_bytes(),
+                &[(0x18EEFFF9, &rival.to_bytes())],
+            )
+            .spawn();
+        assert!(matches!(
+            J1939Socket::open(can_parameters(&device), name, 0xF9).await,
+            Err(J1939Error::CannotClaimAddress)
+        ));
+    }
+}
@@ -0,0 +1,389 @@
+//! Reassembly of parameter groups sent through the transport protocol (J1939-21).
+//!
+//! Broadcast parameter groups are announced (BAM) and then follow at whatever pace the sender
+//! likes, while those sent to us are offered (RTS), and only follow as we ask for them (CTS), with
+//! us acknowledging the whole message once it's arrived.
+
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+use tracing::debug;
+
+use super::{
+    decode_pgn, encode_pgn, J1939Id, Message, GLOBAL_ADDRESS, TP_CONNECTION_MANAGEMENT_PGN,
+};
+
+const REQUEST_TO_SEND: u8 = 0x10;
+const CLEAR_TO_SEND: u8 = 0x11;
+const END_OF_MESSAGE_ACKNOWLEDGEMENT: u8 = 0x13;
+const BROADCAST_ANNOUNCE: u8 = 0x20;
+const ABORT: u8 = 0xFF;
+
+/// Abort reasons.
+const ABORT_RESOURCES: u8 = 0x02;
+const ABORT_BAD_SEQUENCE_NUMBER: u8 = 0x07;
+
+/// Largest parameter group the transport protocol can carry.
+const MAX_MESSAGE_SIZE: usize = 1785;
+
+/// Priority that connection management frames are sent with.
+const CONNECTION_MANAGEMENT_PRIORITY: u8 = 7;
+
+/// How long a sender has to send the next packet, per T1 for broadcasts and T2 for packets we've
+/// asked for.
+const BROADCAST_PACKET_TIMEOUT: Duration = Duration::from_millis(750);
+const REQUESTED_PACKET_TIMEOUT: Duration = Duration::from_millis(1250);
+
+/// A transfer in progress, from one node to another, or to everyone.
+struct Session {
+    pgn: u32,
+    size: usize,
+    total_packets: u8,
+    data: Vec<u8>,
+    next_sequence: u8,
+    /// Last packet of the current window, for transfers to us.
+    window_end: Option<u8>,
+    /// Most packets the sender is willing to send per window.
+    max_window: u8,
+    deadline: Instant,
+}
+
+/// What came of receiving a frame.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Received {
+    /// A parameter group that has arrived in full.
+    pub message: Option<Message>,
+    /// A connection management frame to send back to the sender.
+    pub reply: Option<(J1939Id, [u8; 8])>,
+}
+
+/// Reassembles parameter groups sent through the transport protocol, either broadcast or to
+/// `address`.
+pub struct Reassembler {
+    address: u8,
+    sessions: HashMap<(u8, u8), Session>,
+}
+
+impl Reassembler {
+    pub fn new(address: u8) -> Self {
+        Self {
+            address,
+            sessions: HashMap::new(),
+        }
+    }
+
+    /// Changes the address that transfers are accepted for, abandoning any in progress.
+    pub fn set_address(&mut self, address: u8) {
+        self.address = address;
+        self.sessions.clear();
+    }
+
+    /// Handles a connection management frame.
+    pub fn connection_management(&mut self, id: J1939Id, data: &[u8]) -> Received {
+        if data.len() < 8 || !self.is_for_us(id) {
+            return Received::default();
+        }
+
+        let key = (id.source_address, id.destination_address);
+        let pgn = decode_pgn(&data[5..8]);
+        let size = u16::from_le_bytes([data[1], data[2]]) as usize;
+        let total_packets = data[3];
+        match data[0] {
+            BROADCAST_ANNOUNCE | REQUEST_TO_SEND => {
+                let broadcast = data[0] == BROADCAST_ANNOUNCE;
+                if broadcast != (id.destination_address == GLOBAL_ADDRESS) {
+                    return Received::default();
+                }
+
+                // A new transfer from the same sender replaces whatever it was sending before.
+                self.sessions.remove(&key);
+                if size > MAX_MESSAGE_SIZE || size > total_packets as usize * 7 || size < 9 {
+                    debug!("Ignoring malformed transfer of PGN {} from {}", pgn, id);
+                    return self.abort(id, pgn, ABORT_RESOURCES, broadcast);
+                }
+
+                let mut session = Session {
+                    pgn,
+                    size,
+                    total_packets,
+                    data: Vec::with_capacity(total_packets as usize * 7),
+                    next_sequence: 1,
+                    window_end: None,
+                    max_window: data[4],
+                    deadline: Instant::now() + BROADCAST_PACKET_TIMEOUT,
+                };
+                if broadcast {
+                    self.sessions.insert(key, session);
+                    return Received::default();
+                }
+
+                let reply = clear_to_send(self.address, id.source_address, &mut session);
+                self.sessions.insert(key, session);
+                Received {
+                    message: None,
+                    reply: Some(reply),
+                }
+            }
+            ABORT => {
+                if self.sessions.remove(&key).is_some() {
+                    debug!(
+                        "Transfer of PGN {} from {} was aborted: 0x{:02X}",
+                        pgn, id, data[1]
+                    );
+                }
+                Received::default()
+            }
+            _ => Received::default(),
+        }
+    }
+
+    /// Handles a data transfer frame.
+    pub fn data_transfer(&mut self, id: J1939Id, data: &[u8]) -> Received {
+        let key = (id.source_address, id.destination_address);
+        let session = match self.sessions.get_mut(&key) {
+            Some(session) if data.len() == 8 => session,
+            _ => return Received::default(),
+        };
+        let broadcast = id.destination_address == GLOBAL_ADDRESS;
+
+        if Instant::now() > session.deadline || data[0] != session.next_sequence {
+            let pgn = session.pgn;
+            self.sessions.remove(&key);
+            debug!("Abandoning transfer of PGN {} from {}", pgn, id);
+            return self.abort(id, pgn, ABORT_BAD_SEQUENCE_NUMBER, broadcast);
+        }
+
+        session.data.extend_from_slice(&data[1..]);
+        session.next_sequence = session.next_sequence.wrapping_add(1);
+        session.deadline = Instant::now()
+            + if broadcast {
+                BROADCAST_PACKET_TIMEOUT
+            } else {
+                REQUESTED_PACKET_TIMEOUT
+            };
+
+        if data[0] == session.total_packets {
+            let mut session = self.sessions.remove(&key).expect("session should exist");
+            session.data.truncate(session.size);
+            let message = Message {
+                pgn: session.pgn,
+                source_address: id.source_address,
+                data: session.data,
+            };
+            let reply = if broadcast {
+                None
+            } else {
+                let [size_lo, size_hi] = (session.size as u16).to_le_bytes();
+                let [a, b, c] = encode_pgn(session.pgn);
+                Some((
+                    connection_management_id(self.address, id.source_address),
+                    [
+                        END_OF_MESSAGE_ACKNOWLEDGEMENT,
+                        size_lo,
+                        size_hi,
+                        session.total_packets,
+                        0xFF,
+                        a,
+                        b,
+                        c,
+                    ],
+                ))
+            };
+
+            return Received {
+                message: Some(message),
+                reply,
+            };
+        }
+
+        // Once the window we asked for has arrived, we ask for the next one.
+        if session.window_end == Some(data[0]) {
+            let reply = clear_to_send(self.address, id.source_address, session);
+            return Received {
+                message: None,
+                reply: Some(reply),
+            };
+        }
+
+        Received::default()
+    }
+
+    fn is_for_us(&self, id: J1939Id) -> bool {
+        id.destination_address == GLOBAL_ADDRESS || id.destination_address == self.address
+    }
+
+    /// Aborts a transfer to us, which broadcasts are never worth doing for.
+    fn abort(&self, id: J1939Id, pgn: u32, reason: u8, broadcast: bool) -> Received {
+        if broadcast {
+            return Received::default();
+        }
+
+        let [a, b, c] = encode_pgn(pgn);
+        Received {
+            message: None,
+            reply: Some((
+                connection_management_id(self.address, id.source_address),
+                [ABORT, reason, 0xFF, 0xFF, 0xFF, a, b, c],
+            )),
+        }
+    }
+}
+
+fn connection_management_id(source_address: u8, destination_address: u8) -> J1939Id {
+    J1939Id::new(
+        CONNECTION_MANAGEMENT_PRIORITY,
+        TP_CONNECTION_MANAGEMENT_PGN,
+        source_address,
+        destination_address,
+    )
+}
+
+/// Asks for as many of the remaining packets as the sender is willing to send at once.
+fn clear_to_send(
+    address: u8,
+    destination_address: u8,
+    session: &mut Session,
+) -> (J1939Id, [u8; 8]) {
+    let remaining = session.total_packets - session.next_sequence + 1;
+    let window = remaining.min(session.max_window.max(1));
+    session.window_end = Some(session.next_sequence + (window - 1));
+    session.deadline = Instant::now() + REQUESTED_PACKET_TIMEOUT;
+
+    let [a, b, c] = encode_pgn(session.pgn);
+    (
+        connection_management_id(address, destination_address),
+        [
+            CLEAR_TO_SEND,
+            window,
+            session.next_sequence,
+            0xFF,
+            0xFF,
+            a,
+            b,
+            c,
+        ],
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::protocol::j1939::TP_DATA_TRANSFER_PGN;
+
+    use super::*;
+
+    fn cm(source_address: u8, destination_address: u8) -> J1939Id {
+        J1939Id::new(
+            7,
+            TP_CONNECTION_MANAGEMENT_PGN,
+            source_address,
+            destination_address,
+        )
+    }
+
+    fn dt(source_address: u8, destination_address: u8) -> J1939Id {
+        J1939Id::new(7, TP_DATA_TRANSFER_PGN, source_address, destination_address)
+    }
+
+    #[test]
+    fn reassembles_broadcasts() {
+        let mut reassembler = Reassembler::new(0xF9);
+        let announce = [0x20, 0x0A, 0x00, 0x02, 0xFF, 0xCA, 0xFE, 0x00];
+        assert_eq!(
+            reassembler.connection_management(cm(0x00, 0xFF), &announce),
+            Received::default()
+        );
+
+        let first = [0x01, 0x04, 0xFF, 0x6E, 0x00, 0x03, 0x01, 0x9D];
+        assert_eq!(
+            reassembler.data_transfer(dt(0x00, 0xFF), &first),
+            Received::default()
+        );
+        // Packets from anyone else are no part of it.
+        assert_eq!(
+            reassembler.data_transfer(dt(0x03, 0xFF), &first),
+            Received::default()
+        );
+
+        let second = [0x02, 0x00, 0x00, 0x00, 0xFF, 0xFF, 0xFF, 0xFF];
+        let received = reassembler.data_transfer(dt(0x00, 0xFF), &second);
+        assert_eq!(received.reply, None);
+        assert_eq!(
+            received.message,
+            Some(Message {
+                pgn: 65226,
+                source_address: 0x00,
+                data: vec![0x04, 0xFF, 0x6E, 0x00, 0x03, 0x01, 0x9D, 0x00, 0x00, 0x00],
+            })
+        );
+    }
+
+    #[test]
+    fn asks_for_and_acknowledges_transfers_to_us() {
+        let mut reassembler = Reassembler::new(0xF9);
+
+        // Three packets, but only two at a time.
+        let rts = [0x10, 0x14, 0x00, 0x03, 0x02, 0xCB, 0xFE, 0x00];
+        let received = reassembler.connection_management(cm(0x00, 0xF9), &rts);
+        assert_eq!(
+            received.reply,
+            Some((
+                cm(0xF9, 0x00),
+                [0x11, 0x02, 0x01, 0xFF, 0xFF, 0xCB, 0xFE, 0x00]
+            ))
+        );
+        // Transfers to anyone else are none of our business.
+        assert_eq!(
+            reassembler.connection_management(cm(0x00, 0x17), &rts),
+            Received::default()
+        );
+
+        assert_eq!(
+            reassembler.data_transfer(dt(0x00, 0xF9), &[0x01, 1, 2, 3, 4, 5, 6, 7]),
+            Received::default()
+        );
+        let received = reassembler.data_transfer(dt(0x00, 0xF9), &[0x02, 8, 9, 10, 11, 12, 13, 14]);
+        assert_eq!(
+            received.reply,
+            Some((
+                cm(0xF9, 0x00),
+                [0x11, 0x01, 0x03, 0xFF, 0xFF, 0xCB, 0xFE, 0x00]
+            ))
+        );
+
+        let received =
+            reassembler.data_transfer(dt(0x00, 0xF9), &[0x03, 15, 16, 17, 18, 19, 20, 0xFF]);
+        assert_eq!(
+            received.reply,
+            Some((
+                cm(0xF9, 0x00),
+                [0x13, 0x14, 0x00, 0x03, 0xFF, 0xCB, 0xFE, 0x00]
+            ))
+        );
+        assert_eq!(
+            received.message.map(|message| message.data),
+            Some((1..=20).collect())
+        );
+    }
+
+    #[test]
+    fn aborts_out_of_sequence_transfers() {
+        let mut reassembler = Reassembler::new(0xF9);
+        let rts = [0x10, 0x0E, 0x00, 0x02, 0xFF, 0xCB, 0xFE, 0x00];
+        reassembler.connection_management(cm(0x00, 0xF9), &rts);
+
+        let received = reassembler.data_transfer(dt(0x00, 0xF9), &[0x02, 0, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(
+            received.reply,
+            Some((
+                cm(0xF9, 0x00),
+                [0xFF, 0x07, 0xFF, 0xFF, 0xFF, 0xCB, 0xFE, 0x00]
+            ))
+        );
+        assert_eq!(
+            reassembler.data_transfer(dt(0x00, 0xF9), &[0x01, 0, 0, 0, 0, 0, 0, 0]),
+            Received::default()
+        );
+    }
+}
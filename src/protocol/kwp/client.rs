@@ -0,0 +1,109 @@
+use std::time::Duration;
+
+use crate::{
+    common::{config::CANParameters, targets::Target},
+    protocol::{
+        transport::{open_transport, DiagnosticTransport},
+        uds::client::{exchange, DiagnosticError, DEFAULT_RESPONSE_PENDING_TIMEOUT},
+    },
+};
+
+use super::error::{KwpError, NegativeResponseCode};
+
+/// A client for issuing KWP2000 requests to a single ECU over a diagnostic transport.
+pub struct KwpClient {
+    transport: Box<dyn DiagnosticTransport>,
+    target: Target,
+    response_pending_timeout: Duration,
+}
+
+impl KwpClient {
+    pub fn new(transport: Box<dyn DiagnosticTransport>, target: Target) -> Self {
+        Self {
+            transport,
+            target,
+            response_pending_timeout: DEFAULT_RESPONSE_PENDING_TIMEOUT,
+        }
+    }
+
+    /// Connects to the given ECU over the configured backend.
+    pub async fn connect(can_parameters: CANParameters, target: Target) -> Result<Self, KwpError> {
+        let response_pending_timeout = can_parameters.response_pending_timeout;
+        let transport = open_transport(can_parameters, Some(target)).await?;
+
+        let mut client = Self::new(transport, target);
+        client.response_pending_timeout = response_pending_timeout;
+        Ok(client)
+    }
+
+    /// Sends a request and waits for the matching positive response.
+    ///
+    /// Negative responses are surfaced as errors, with the exception of "response pending", which
+    /// causes us to keep waiting for the real response, for as long as the response pending
+    /// timeout allows.  The returned payload includes the positive response service ID.
+    pub async fn request(&mut self, payload: &[u8]) -> Result<Vec<u8>, KwpError> {
+        exchange(
+            self.transport.as_mut(),
+            self.target,
+            payload,
+            self.response_pending_timeout,
+        )
+        .await
+    }
+}
+
+impl DiagnosticError for KwpError {
+    type Code = NegativeResponseCode;
+
+    const RESPONSE_PENDING: NegativeResponseCode =
+        NegativeResponseCode::RequestCorrectlyReceivedResponsePending;
+
+    fn negative_response(service_id: u8, code: NegativeResponseCode) -> Self {
+        Self::NegativeResponse { service_id, code }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::protocol::{
+        can::mock::MockBus,
+        transport::mock::{standard_target, FakeEcu, MockTransport},
+    };
+
+    use super::*;
+
+    fn client(bus: &MockBus, ecu: FakeEcu) -> KwpClient {
+        let target = standard_target(0x7E0);
+        ecu.spawn(bus);
+        KwpClient::new(Box::new(MockTransport::new(bus)), target)
+    }
+
+    #[tokio::test]
+    async fn surfaces_kwp_negative_responses() {
+        let bus = MockBus::new();
+        let ecu = FakeEcu::new(standard_target(0x7E0))
+            .respond(&[0x21, 0x01], &[&[0x7F, 0x21, 0x78], &[0x61, 0x01, 0x12]])
+            .respond(&[0x10, 0x85], &[&[0x7F, 0x10, 0x80]])
+            .respond(&[0x27, 0x01], &[&[0x7F, 0x27, 0x22]]);
+        let mut client = client(&bus, ecu);
+
+        assert_eq!(
+            client.request(&[0x21, 0x01]).await.unwrap(),
+            vec![0x61, 0x01, 0x12]
+        );
+        assert!(matches!(
+            client.request(&[0x10, 0x85]).await,
+            Err(KwpError::NegativeResponse {
+                service_id: 0x10,
+                code: NegativeResponseCode::ServiceNotSupportedInActiveDiagnosticSession,
+            })
+        ));
+        assert!(matches!(
+            client.request(&[0x27, 0x01]).await,
+            Err(KwpError::NegativeResponse {
+                service_id: 0x27,
+                code: NegativeResponseCode::ConditionsNotCorrectOrRequestSequenceError,
+            })
+        ));
+    }
+}
@@ -0,0 +1,160 @@
+use core::fmt;
+
+use thiserror::Error;
+
+use crate::{
+    common::error::InvalidResponse,
+    protocol::{
+        can::error::{SocketBuildError, SocketError},
+        transport::TransportError,
+    },
+};
+
+/// Negative response codes, as defined by ISO 14230-3.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NegativeResponseCode {
+    GeneralReject,
+    ServiceNotSupported,
+    SubFunctionNotSupportedInvalidFormat,
+    BusyRepeatRequest,
+    ConditionsNotCorrectOrRequestSequenceError,
+    RoutineNotComplete,
+    RequestOutOfRange,
+    SecurityAccessDenied,
+    InvalidKey,
+    ExceedNumberOfAttempts,
+    RequiredTimeDelayNotExpired,
+    DownloadNotAccepted,
+    ImproperDownloadType,
+    CantDownloadToSpecifiedAddress,
+    CantDownloadNumberOfBytesRequested,
+    UploadNotAccepted,
+    ImproperUploadType,
+    CantUploadFromSpecifiedAddress,
+    CantUploadNumberOfBytesRequested,
+    TransferSuspended,
+    TransferAborted,
+    IllegalAddressInBlockTransfer,
+    IllegalByteCountInBlockTransfer,
+    IllegalBlockTransferType,
+    BlockTransferDataChecksumError,
+    RequestCorrectlyReceivedResponsePending,
+    IncorrectByteCountDuringBlockTransfer,
+    ServiceNotSupportedInActiveDiagnosticSession,
+    Other(u8),
+}
+
+impl NegativeResponseCode {
+    pub fn as_byte(&self) -> u8 {
+        match self {
+            Self::GeneralReject => 0x10,
+            Self::ServiceNotSupported => 0x11,
+            Self::SubFunctionNotSupportedInvalidFormat => 0x12,
+            Self::BusyRepeatRequest => 0x21,
+            Self::ConditionsNotCorrectOrRequestSequenceError => 0x22,
+            Self::RoutineNotComplete => 0x23,
+            Self::RequestOutOfRange => 0x31,
+            Self::SecurityAccessDenied => 0x33,
+            Self::InvalidKey => 0x35,
+            Self::ExceedNumberOfAttempts => 0x36,
+            Self::RequiredTimeDelayNotExpired => 0x37,
+            Self::DownloadNotAccepted => 0x40,
+            Self::ImproperDownloadType => 0x41,
+            Self::CantDownloadToSpecifiedAddress => 0x42,
+            Self::CantDownloadNumberOfBytesRequested => 0x43,
+            Self::UploadNotAccepted => 0x50,
+            Self::ImproperUploadType => 0x51,
+            Self::CantUploadFromSpecifiedAddress => 0x52,
+            Self::CantUploadNumberOfBytesRequested => 0x53,
+            Self::TransferSuspended => 0x71,
+            Self::TransferAborted => 0x72,
+            Self::IllegalAddressInBlockTransfer => 0x74,
+            Self::IllegalByteCountInBlockTransfer => 0x75,
+            Self::IllegalBlockTransferType => 0x76,
+            Self::BlockTransferDataChecksumError => 0x77,
+            Self::RequestCorrectlyReceivedResponsePending => 0x78,
+            Self::IncorrectByteCountDuringBlockTransfer => 0x79,
+            Self::ServiceNotSupportedInActiveDiagnosticSession => 0x80,
+            Self::Other(b) => *b,
+        }
+    }
+}
+
+impl From<u8> for NegativeResponseCode {
+    fn from(b: u8) -> Self {
+        match b {
+            0x10 => Self::GeneralReject,
+            0x11 => Self::ServiceNotSupported,
+            0x12 => Self::SubFunctionNotSupportedInvalidFormat,
+            0x21 => Self::BusyRepeatRequest,
+            0x22 => Self::ConditionsNotCorrectOrRequestSequenceError,
+            0x23 => Self::RoutineNotComplete,
+            0x31 => Self::RequestOutOfRange,
+            0x33 => Self::SecurityAccessDenied,
+            0x35 => Self::InvalidKey,
+            0x36 => Self::ExceedNumberOfAttempts,
+            0x37 => Self::RequiredTimeDelayNotExpired,
+            0x40 => Self::DownloadNotAccepted,
+            0x41 => Self::ImproperDownloadType,
+            0x42 => Self::CantDownloadToSpecifiedAddress,
+            0x43 => Self::CantDownloadNumberOfBytesRequested,
+            0x50 => Self::UploadNotAccepted,
+            0x51 => Self::ImproperUploadType,
+            0x52 => Self::CantUploadFromSpecifiedAddress,
+            0x53 => Self::CantUploadNumberOfBytesRequested,
+            0x71 => Self::TransferSuspended,
+            0x72 => Self::TransferAborted,
+            0x74 => Self::IllegalAddressInBlockTransfer,
+            0x75 => Self::IllegalByteCountInBlockTransfer,
+            0x76 => Self::IllegalBlockTransferType,
+            0x77 => Self::BlockTransferDataChecksumError,
+            0x78 => Self::RequestCorrectlyReceivedResponsePending,
+            0x79 => Self::IncorrectByteCountDuringBlockTransfer,
+            0x80 => Self::ServiceNotSupportedInActiveDiagnosticSession,
+            b => Self::Other(b),
+        }
+    }
+}
+
+impl fmt::Display for NegativeResponseCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Other(b) => write!(f, "unknown (0x{:02X})", b),
+            code => write!(f, "{:?} (0x{:02X})", code, code.as_byte()),
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum KwpError {
+    #[error("failed to initialize socket: {0}")]
+    Initialization(#[from] SocketBuildError),
+    #[error("socket error while querying service: {0}")]
+    Io(#[from] SocketError),
+    #[error(transparent)]
+    InvalidResponse(#[from] InvalidResponse),
+    #[error("service 0x{service_id:02X} responded negatively: {code}")]
+    NegativeResponse {
+        service_id: u8,
+        code: NegativeResponseCode,
+    },
+}
+
+impl From<TransportError> for KwpError {
+    fn from(e: TransportError) -> Self {
+        match e {
+            TransportError::Initialization(e) => Self::Initialization(e),
+            TransportError::Io(e) => Self::Io(e),
+        }
+    }
+}
+
+impl KwpError {
+    /// Gets the negative response code, if this error represents a negative response.
+    pub fn negative_response_code(&self) -> Option<NegativeResponseCode> {
+        match self {
+            Self::NegativeResponse { code, .. } => Some(*code),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,9 @@
+//! Keyword Protocol 2000 (ISO 14230-3), as spoken by plenty of ECUs on CAN from before UDS took
+//! over.
+//!
+//! Requests and responses are framed the same way as UDS, and travel over ISO-TP in the same way,
+//! so only the services themselves and their negative response codes differ.
+
+pub mod client;
+pub mod error;
+pub mod services;
@@ -0,0 +1,9 @@
+mod read_dtcs;
+mod read_local_data;
+mod security_access;
+mod session_control;
+
+pub use read_dtcs::{Dtc, ReadDtcsService};
+pub use read_local_data::ReadLocalDataService;
+pub use security_access::SecurityAccessService;
+pub use session_control::SessionControlService;
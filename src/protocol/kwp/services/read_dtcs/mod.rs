@@ -0,0 +1,41 @@
+use core::fmt;
+
+mod service;
+
+pub use self::service::ReadDtcsService;
+
+const READ_DTCS_BY_STATUS_SERVICE_ID: u8 = 0x18;
+
+/// A diagnostic trouble code, along with its status.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Dtc {
+    pub code: u16,
+    pub status: u8,
+}
+
+impl Dtc {
+    /// Whether or not the fault is present at the time of the request, rather than only having
+    /// been stored, or still maturing.
+    pub fn is_present(&self) -> bool {
+        (self.status >> 5) & 0x03 == 0x03
+    }
+
+    /// Whether or not the fault has turned on the warning lamp.
+    pub fn is_warning_lamp_on(&self) -> bool {
+        self.status & 0x80 != 0
+    }
+}
+
+impl fmt::Display for Dtc {
+    /// Formats the DTC in the same way as SAE J2012, with the system letter followed by four
+    /// digits, such as `P0301`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let system = match self.code >> 14 {
+            0 => 'P',
+            1 => 'C',
+            2 => 'B',
+            _ => 'U',
+        };
+        write!(f, "{}{:04X}", system, self.code & 0x3FFF)
+    }
+}
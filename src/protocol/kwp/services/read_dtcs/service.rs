@@ -0,0 +1,82 @@
+use crate::protocol::{
+    kwp::{client::KwpClient, error::KwpError},
+    uds::client::ensure_length,
+};
+
+use super::{Dtc, READ_DTCS_BY_STATUS_SERVICE_ID};
+
+pub struct ReadDtcsService<'a> {
+    client: &'a mut KwpClient,
+}
+
+impl<'a> ReadDtcsService<'a> {
+    pub fn new(client: &'a mut KwpClient) -> Self {
+        Self { client }
+    }
+
+    /// Reads the DTCs in the given group, along with their statuses.  Group `0xFF00` covers every
+    /// DTC the ECU knows about.
+    ///
+    /// Which DTCs are reported depends on `status`: `0x00` for those currently identified, and
+    /// `0x02` for all identified ones, including any only stored in memory.
+    pub async fn read(&mut self, status: u8, group: u16) -> Result<Vec<Dtc>, KwpError> {
+        let [hi, lo] = group.to_be_bytes();
+        let response = self
+            .client
+            .request(&[READ_DTCS_BY_STATUS_SERVICE_ID, status, hi, lo])
+            .await?;
+        ensure_length(&response, 2)?;
+
+        // Each DTC is given as two bytes of code followed by a byte of status.
+        let count = response[1] as usize;
+        ensure_length(&response, 2 + count * 3)?;
+
+        Ok(response[2..2 + count * 3]
+            .chunks_exact(3)
+            .map(|dtc| Dtc {
+                code: u16::from_be_bytes([dtc[0], dtc[1]]),
+                status: dtc[2],
+            })
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::protocol::{
+        can::mock::MockBus,
+        transport::mock::{standard_target, FakeEcu, MockTransport},
+    };
+
+    use super::*;
+
+    #[tokio::test]
+    async fn decodes_dtcs_and_statuses() {
+        let bus = MockBus::new();
+        FakeEcu::new(standard_target(0x7E0))
+            .respond(
+                &[0x18, 0x02, 0xFF, 0x00],
+                &[&[0x58, 0x02, 0x03, 0x01, 0xE0, 0xC1, 0x00, 0x20]],
+            )
+            .respond(
+                &[0x18, 0x00, 0xFF, 0x00],
+                &[&[0x58, 0x02, 0x03, 0x01, 0xE0]],
+            )
+            .spawn(&bus);
+        let mut client = KwpClient::new(Box::new(MockTransport::new(&bus)), standard_target(0x7E0));
+        let mut service = ReadDtcsService::new(&mut client);
+
+        let dtcs = service.read(0x02, 0xFF00).await.unwrap();
+        assert_eq!(dtcs.len(), 2);
+        assert_eq!(dtcs[0].to_string(), "P0301");
+        assert!(dtcs[0].is_present() && dtcs[0].is_warning_lamp_on());
+        assert_eq!(dtcs[1].to_string(), "U0100");
+        assert!(!dtcs[1].is_present() && !dtcs[1].is_warning_lamp_on());
+
+        // Fewer DTCs than the ECU says there are is a malformed response.
+        assert!(matches!(
+            service.read(0x00, 0xFF00).await,
+            Err(KwpError::InvalidResponse(_))
+        ));
+    }
+}
@@ -0,0 +1,5 @@
+mod service;
+
+pub use self::service::ReadLocalDataService;
+
+const READ_DATA_BY_LOCAL_IDENTIFIER_SERVICE_ID: u8 = 0x21;
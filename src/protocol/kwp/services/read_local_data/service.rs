@@ -0,0 +1,29 @@
+use crate::protocol::{
+    kwp::{client::KwpClient, error::KwpError},
+    uds::client::ensure_field,
+};
+
+use super::READ_DATA_BY_LOCAL_IDENTIFIER_SERVICE_ID;
+
+pub struct ReadLocalDataService<'a> {
+    client: &'a mut KwpClient,
+}
+
+impl<'a> ReadLocalDataService<'a> {
+    pub fn new(client: &'a mut KwpClient) -> Self {
+        Self { client }
+    }
+
+    /// Reads the record behind the given local identifier.
+    pub async fn read(&mut self, identifier: u8) -> Result<Vec<u8>, KwpError> {
+        let response = self
+            .client
+            .request(&[READ_DATA_BY_LOCAL_IDENTIFIER_SERVICE_ID, identifier])
+            .await?;
+
+        // Positive responses echo back the identifier before the record itself.
+        ensure_field(&response, 1, identifier)?;
+
+        Ok(response[2..].to_vec())
+    }
+}
@@ -0,0 +1,45 @@
+use crate::protocol::{
+    kwp::{client::KwpClient, error::KwpError},
+    uds::client::ensure_field,
+};
+
+use super::SECURITY_ACCESS_SERVICE_ID;
+
+pub struct SecurityAccessService<'a> {
+    client: &'a mut KwpClient,
+}
+
+impl<'a> SecurityAccessService<'a> {
+    pub fn new(client: &'a mut KwpClient) -> Self {
+        Self { client }
+    }
+
+    /// Requests the seed for the given security level.
+    ///
+    /// Security levels are given as the odd "request seed" access mode.  A seed of all zeroes
+    /// means that the level is already unlocked.
+    pub async fn request_seed(&mut self, level: u8) -> Result<Vec<u8>, KwpError> {
+        let response = self
+            .client
+            .request(&[SECURITY_ACCESS_SERVICE_ID, level])
+            .await?;
+        ensure_field(&response, 1, level)?;
+
+        Ok(response[2..].to_vec())
+    }
+
+    /// Sends the key for the given security level, unlocking it if the key is accepted.
+    ///
+    /// The level is the same odd "request seed" access mode the seed was requested with, with the
+    /// key itself being sent with the even access mode that follows it.
+    pub async fn send_key(&mut self, level: u8, key: &[u8]) -> Result<(), KwpError> {
+        let access_mode = level.wrapping_add(1);
+        let mut request = vec![SECURITY_ACCESS_SERVICE_ID, access_mode];
+        request.extend_from_slice(key);
+
+        let response = self.client.request(&request).await?;
+        ensure_field(&response, 1, access_mode)?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,5 @@
+mod service;
+
+pub use self::service::SessionControlService;
+
+const START_DIAGNOSTIC_SESSION_SERVICE_ID: u8 = 0x10;
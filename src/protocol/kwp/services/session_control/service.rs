@@ -0,0 +1,35 @@
+use crate::protocol::{
+    kwp::{client::KwpClient, error::KwpError},
+    uds::client::ensure_field,
+};
+
+use super::START_DIAGNOSTIC_SESSION_SERVICE_ID;
+
+pub struct SessionControlService<'a> {
+    client: &'a mut KwpClient,
+}
+
+impl<'a> SessionControlService<'a> {
+    pub fn new(client: &'a mut KwpClient) -> Self {
+        Self { client }
+    }
+
+    /// Starts the given diagnostic session.
+    ///
+    /// Sessions are manufacturer-specific beyond the standard session, `0x81`, though `0x85` is
+    /// almost always programming, and `0x86`, `0x87` and `0x89` are common for development,
+    /// adjustment, and extended diagnostics respectively.
+    pub async fn start(&mut self, session: u8) -> Result<(), KwpError> {
+        let response = self
+            .client
+            .request(&[START_DIAGNOSTIC_SESSION_SERVICE_ID, session])
+            .await?;
+
+        // Not every ECU echoes the session back, but any that do should echo the right one.
+        if response.len() > 1 {
+            ensure_field(&response, 1, session)?;
+        }
+
+        Ok(())
+    }
+}
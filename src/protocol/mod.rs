@@ -1,2 +1,8 @@
 pub mod can;
+pub mod doip;
+pub mod j1939;
+pub mod kwp;
 pub mod obd;
+pub mod serial;
+pub mod transport;
+pub mod uds;
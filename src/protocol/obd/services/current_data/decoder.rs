@@ -50,3 +50,25 @@ impl AvailablePidDecoder {
         self.pids
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decodes_supported_pids() {
+        let mut decoder = AvailablePidDecoder::new();
+        assert_eq!(decoder.next_query_pid(), Some(0x00));
+
+        // PIDs 0x01, 0x0C, 0x0D and 0x1F, with 0x20 saying there's more to come.
+        decoder.integrate_response(0x00, [0x80, 0x18, 0x00, 0x03]);
+        assert_eq!(decoder.next_query_pid(), Some(0x20));
+
+        decoder.integrate_response(0x20, [0x40, 0x00, 0x00, 0x00]);
+        assert_eq!(decoder.next_query_pid(), None);
+        assert_eq!(
+            decoder.into_available_pids(),
+            vec![0x01, 0x0C, 0x0D, 0x1F, 0x22]
+        );
+    }
+}
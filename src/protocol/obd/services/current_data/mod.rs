@@ -5,7 +5,10 @@ use thiserror::Error;
 
 use crate::{
     common::error::InvalidResponse,
-    protocol::can::error::{SocketBuildError, SocketError},
+    protocol::{
+        can::error::{SocketBuildError, SocketError},
+        transport::TransportError,
+    },
 };
 
 pub use self::service::CurrentDataService;
@@ -21,3 +24,12 @@ pub enum QueryError {
     #[error(transparent)]
     InvalidResponse(#[from] InvalidResponse),
 }
+
+impl From<TransportError> for QueryError {
+    fn from(e: TransportError) -> Self {
+        match e {
+            TransportError::Initialization(e) => Self::Initialization(e),
+            TransportError::Io(e) => Self::Io(e),
+        }
+    }
+}
@@ -1,21 +1,16 @@
-use std::{
-    collections::{HashMap, HashSet},
-    time::Duration,
-};
+use std::collections::HashMap;
 
 use can::identifier::Id;
-use socketcan::CANFrame;
-use tokio::{pin, select, time::sleep};
-use tracing::info;
+use tracing::{debug, info, warn};
 
 use crate::{
     common::{
-        config::CANParameters,
+        addressing::ResponseAddress,
         error::{FieldIdentifier, FieldValue, InvalidResponse, InvalidResponseKind},
+        targets::Target,
     },
     protocol::{
-        can::{isotp::ISOTPSocket, raw::RawSocket},
-        obd::services::current_data::decoder::AvailablePidDecoder,
+        obd::services::current_data::decoder::AvailablePidDecoder, transport::DiagnosticTransport,
     },
 };
 
@@ -87,103 +82,214 @@ impl AvailablePidResponse {
 }
 
 pub struct CurrentDataService {
-    can_parameters: CANParameters,
+    transport: Box<dyn DiagnosticTransport>,
 }
 
 impl CurrentDataService {
-    pub fn new(can_parameters: CANParameters) -> Self {
-        Self { can_parameters }
+    pub fn new(transport: Box<dyn DiagnosticTransport>) -> Self {
+        Self { transport }
     }
 
-    pub async fn query_available_pids(&mut self) -> Result<HashMap<Id, Vec<u8>>, QueryError> {
-        let addressing = self.can_parameters.addressing;
-
-        // We first issue a broadcast request to see what ECUs are willing to respond to us, and we
-        // do that for a few hundred milliseconds to give them all a chance to transmit.  Once we
-        // have a list of responding identifiers, we run through the normal serialized query process
-        // for each of them individually.
-        let mut raw_socket = RawSocket::builder()
-            .can_parameters(self.can_parameters.clone())
-            .source_id_filter(addressing.obd_response_address_filter())
-            .build()?;
-
-        // We need to craft our payload manually since we aren't using an ISO-TP socket which adds
-        // the length byte for us automatically.
-        //
-        // TODO: Make this better via `can`, ideally.
-        let broadcast_address = addressing.obd_broadcast_address();
-        let broadcast_request = AvailablePidRequest::from_query_pid(0);
-        let request_payload = broadcast_request.payload();
-        let request_payload = &[0x02, request_payload[0], request_payload[1]];
-        let request_frame =
-            CANFrame::new(broadcast_address.id().as_raw(), request_payload, false, false)
-                .expect("should never fail to construct broadcast request frame");
-
-        info!(
-            "Searching for devices via broadcast address {}...",
-            broadcast_address
-        );
-        raw_socket.write(request_frame).await?;
-
-        let listen_timeout = sleep(Duration::from_secs(1));
-        pin!(listen_timeout);
-
-        let mut response_ids = HashSet::new();
-        loop {
-            select! {
-                // Stop listening for responses at this point.
-                _ = &mut listen_timeout => break,
-
-                // We got a response, so just keep track of the identifier.  We don't care about the
-                // data at this point since we'll grab that after.
-                result = raw_socket.read() => {
-                    let frame = result?;
-                    let id = frame.id();
-                    match addressing.get_diagnostic_response_id(id) {
-                        Some(id) => {
-                            response_ids.insert(id);
-                        },
-                        None => panic!("shouldn't have response with ID that can't be converted"),
-                    }
-                },
+    pub async fn query_available_pids(
+        &mut self,
+        target: Option<Target>,
+    ) -> Result<HashMap<Id, Vec<u8>>, QueryError> {
+        // When we've been pointed at a specific ECU, there's no need to go looking for others.
+        match target {
+            Some(target) => {
+                let available_pids = self.query_target_available_pids(target).await?;
+                Ok(HashMap::from([(
+                    target.request_address.id(),
+                    available_pids,
+                )]))
             }
+            None => self.query_all_available_pids().await,
         }
+    }
 
-        info!(
-            "Discovered {} potential device(s) to query.  Enumerating...",
-            response_ids.len()
-        );
+    /// Checks whether any ECU answers a functional request for the supported PIDs.
+    pub async fn has_responders(&mut self) -> Result<bool, QueryError> {
+        let request = AvailablePidRequest::from_query_pid(0);
+        let responses = self.transport.broadcast(&request.payload()).await?;
+        Ok(!responses.is_empty())
+    }
+
+    async fn query_target_available_pids(&mut self, target: Target) -> Result<Vec<u8>, QueryError> {
+        info!("Querying device at {}...", target.request_address);
+
+        let mut decoder = AvailablePidDecoder::new();
+        while let Some(query_pid) = decoder.next_query_pid() {
+            // Build the request and send it.
+            let request = AvailablePidRequest::from_query_pid(query_pid);
+            let payload = request.payload();
+            self.transport.send_request(target, &payload[..]).await?;
+
+            // Wait for a response and attempt to validate it against the request we just sent.
+            let raw_response = self.transport.receive_response(target).await?;
+            let response = request.parse_response(&raw_response)?;
+
+            // Integrate this response and potentially query the next query PID:
+            decoder.integrate_response(response.offset(), response.data());
+        }
+
+        Ok(decoder.into_available_pids())
+    }
 
+    async fn query_all_available_pids(&mut self) -> Result<HashMap<Id, Vec<u8>>, QueryError> {
+        info!("Querying devices via functional requests...");
+
+        // Every ECU that answers the first query gets a decoder, and then we keep broadcasting the
+        // lowest query PID any of them still needs.  ECUs only ever move forward through the query
+        // PIDs, so each response is only integrated if it's for the query PID that ECU is on.
+        let mut decoders = HashMap::new();
         let mut available_pids = HashMap::new();
-        for response_id in response_ids {
-            let request_id = response_id.into_request_address();
-
-            info!("Querying device at {}...", request_id);
-
-            let mut socket = ISOTPSocket::builder()
-                .can_parameters(self.can_parameters.clone())
-                .source_id(response_id)
-                .destination_id(request_id)
-                .build()?;
-
-            let mut decoder = AvailablePidDecoder::new();
-            while let Some(query_pid) = decoder.next_query_pid() {
-                // Build the request and send it.
-                let request = AvailablePidRequest::from_query_pid(query_pid);
-                let payload = request.payload();
-                socket.write(&payload[..]).await?;
-
-                // Wait for a response and attempt to validate it against the request we just sent.
-                let raw_response = socket.read().await?;
-                let response = request.parse_response(&raw_response)?;
-
-                // Integrate this response and potentially query the next query PID:
-                decoder.integrate_response(response.offset(), response.data());
+        let mut next_query_pid = Some(0);
+        while let Some(query_pid) = next_query_pid {
+            let request = AvailablePidRequest::from_query_pid(query_pid);
+            let responses = self.transport.broadcast(&request.payload()).await?;
+
+            for response in responses {
+                if query_pid == 0 {
+                    decoders
+                        .entry(response.response_address)
+                        .or_insert_with(AvailablePidDecoder::new);
+                }
+
+                let decoder = match decoders.get_mut(&response.response_address) {
+                    Some(decoder) if decoder.next_query_pid() == Some(query_pid) => decoder,
+                    _ => continue,
+                };
+
+                match request.parse_response(&response.payload) {
+                    Ok(parsed) => decoder.integrate_response(parsed.offset(), parsed.data()),
+                    Err(e) => debug!(
+                        "Ignoring response from {}: {}",
+                        response.response_address, e
+                    ),
+                }
+            }
+
+            // ECUs still on this query PID didn't answer it, so they're as done as the ECUs that
+            // have nothing left to report.
+            let finished = decoders
+                .iter()
+                .filter(|(_, decoder)| {
+                    !matches!(decoder.next_query_pid(), Some(next) if next != query_pid)
+                })
+                .map(|(response_address, _)| *response_address)
+                .collect::<Vec<ResponseAddress>>();
+            for response_address in finished {
+                let decoder = decoders
+                    .remove(&response_address)
+                    .expect("decoder should be present");
+                if decoder.next_query_pid().is_some() {
+                    warn!(
+                        "Device at {} stopped responding at query PID 0x{:02X}.",
+                        response_address, query_pid
+                    );
+                }
+
+                match self.transport.request_id(response_address) {
+                    Some(request_id) => {
+                        available_pids.insert(request_id, decoder.into_available_pids());
+                    }
+                    None => warn!(
+                        "Skipping device at {}, as its request identifier can't be worked out.",
+                        response_address
+                    ),
+                }
             }
 
-            available_pids.insert(request_id.id(), decoder.into_available_pids());
+            next_query_pid = decoders
+                .values()
+                .filter_map(AvailablePidDecoder::next_query_pid)
+                .min();
         }
 
         Ok(available_pids)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::protocol::{
+        can::{error::SocketError, mock::MockBus},
+        transport::mock::{standard_target, FakeEcu, MockTransport},
+    };
+
+    use super::*;
+
+    fn by_raw_id(available_pids: HashMap<Id, Vec<u8>>) -> HashMap<u32, Vec<u8>> {
+        available_pids
+            .into_iter()
+            .map(|(id, pids)| (id.as_raw(), pids))
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn queries_single_target() {
+        let bus = MockBus::new();
+        let target = standard_target(0x7E0);
+        FakeEcu::new(target)
+            .supported_pids(&[0x01, 0x0C, 0x0D, 0x2F])
+            .spawn(&bus);
+
+        let mut service = CurrentDataService::new(Box::new(MockTransport::new(&bus)));
+        let available_pids = service.query_available_pids(Some(target)).await.unwrap();
+        assert_eq!(
+            by_raw_id(available_pids),
+            HashMap::from([(0x7E0, vec![0x01, 0x0C, 0x0D, 0x2F])])
+        );
+    }
+
+    #[tokio::test]
+    async fn queries_every_responder_functionally() {
+        let bus = MockBus::new();
+        FakeEcu::new(standard_target(0x7E0))
+            .supported_pids(&[0x01, 0x0C, 0x2F, 0x41])
+            .spawn(&bus);
+        FakeEcu::new(standard_target(0x7E1))
+            .supported_pids(&[0x05])
+            .spawn(&bus);
+        // Doesn't support OBD-II at all, so never answers.
+        FakeEcu::new(standard_target(0x7E2)).spawn(&bus);
+
+        let mut service = CurrentDataService::new(Box::new(MockTransport::new(&bus)));
+        assert!(service.has_responders().await.unwrap());
+
+        let available_pids = service.query_available_pids(None).await.unwrap();
+        assert_eq!(
+            by_raw_id(available_pids),
+            HashMap::from([(0x7E0, vec![0x01, 0x0C, 0x2F, 0x41]), (0x7E1, vec![0x05])])
+        );
+    }
+
+    #[tokio::test]
+    async fn finds_no_responders_on_an_empty_bus() {
+        let bus = MockBus::new();
+        let mut service = CurrentDataService::new(Box::new(MockTransport::new(&bus)));
+        assert!(!service.has_responders().await.unwrap());
+        assert!(service.query_available_pids(None).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn reports_invalid_and_missing_responses() {
+        let bus = MockBus::new();
+        let target = standard_target(0x7E0);
+        FakeEcu::new(target)
+            .respond(&[0x01, 0x00], &[&[0x41, 0x20, 0x00, 0x00, 0x00, 0x00]])
+            .spawn(&bus);
+
+        let mut service = CurrentDataService::new(Box::new(MockTransport::new(&bus)));
+        assert!(matches!(
+            service.query_available_pids(Some(target)).await,
+            Err(QueryError::InvalidResponse(_))
+        ));
+        assert!(matches!(
+            service
+                .query_available_pids(Some(standard_target(0x7E1)))
+                .await,
+            Err(QueryError::Io(SocketError::Timeout(_)))
+        ));
+    }
+}
@@ -0,0 +1,231 @@
+//! Serial devices, for adapters that aren't SocketCAN interfaces.
+
+use std::{
+    ffi::CString,
+    io, mem,
+    os::unix::prelude::{AsRawFd, OsStrExt, RawFd},
+    path::Path,
+    time::Duration,
+};
+
+use tokio::io::unix::AsyncFd;
+
+struct SerialFd(RawFd);
+
+impl AsRawFd for SerialFd {
+    fn as_raw_fd(&self) -> RawFd {
+        self.0
+    }
+}
+
+impl Drop for SerialFd {
+    fn drop(&mut self) {
+        unsafe {
+            libc::close(self.0);
+        }
+    }
+}
+
+/// A serial device in raw mode, read from and written to asynchronously.
+pub struct SerialPort {
+    inner: AsyncFd<SerialFd>,
+}
+
+impl SerialPort {
+    /// Opens the given serial device, at the given baud rate.
+    pub fn open(path: &Path, baud_rate: u32) -> io::Result<Self> {
+        let speed = speed_from_baud_rate(baud_rate).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("unsupported baud rate: {}", baud_rate),
+            )
+        })?;
+
+        let path = CString::new(path.as_os_str().as_bytes())?;
+        let fd = unsafe {
+            libc::open(
+                path.as_ptr(),
+                libc::O_RDWR | libc::O_NOCTTY | libc::O_NONBLOCK | libc::O_CLOEXEC,
+            )
+        };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let fd = SerialFd(fd);
+
+        let mut termios = unsafe { mem::zeroed::<libc::termios>() };
+        if unsafe { libc::tcgetattr(fd.0, &mut termios) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        unsafe {
+            libc::cfmakeraw(&mut termios);
+            libc::cfsetispeed(&mut termios, speed);
+            libc::cfsetospeed(&mut termios, speed);
+        }
+        termios.c_cflag |= libc::CLOCAL | libc::CREAD;
+        if unsafe { libc::tcsetattr(fd.0, libc::TCSANOW, &termios) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(Self {
+            inner: AsyncFd::new(fd)?,
+        })
+    }
+
+    /// Reads whatever is available, waiting until there's at least one byte.
+    pub async fn read(&self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            let mut guard = self.inner.readable().await?;
+            let result = guard.try_io(|inner| {
+                let rv = unsafe {
+                    libc::read(
+                        inner.as_raw_fd(),
+                        buf.as_mut_ptr() as *mut libc::c_void,
+                        buf.len(),
+                    )
+                };
+                if rv < 0 {
+                    Err(io::Error::last_os_error())
+                } else {
+                    Ok(rv as usize)
+                }
+            });
+
+            match result {
+                Ok(result) => return result,
+                Err(_would_block) => continue,
+            }
+        }
+    }
+
+    /// Reads whatever is available, blocking for up to `timeout` until there's at least one byte,
+    /// for adapters that have to be set up before the socket on them is returned.
+    pub fn read_blocking(&self, buf: &mut [u8], timeout: Duration) -> io::Result<usize> {
+        let mut poll_fd = libc::pollfd {
+            fd: self.inner.as_raw_fd(),
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        let timeout_ms = timeout.as_millis().min(libc::c_int::MAX as u128) as libc::c_int;
+        let rv = unsafe { libc::poll(&mut poll_fd, 1, timeout_ms) };
+        if rv < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        if rv == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::TimedOut,
+                "timed out waiting for serial device",
+            ));
+        }
+
+        let rv = unsafe {
+            libc::read(
+                self.inner.as_raw_fd(),
+                buf.as_mut_ptr() as *mut libc::c_void,
+                buf.len(),
+            )
+        };
+        if rv < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(rv as usize)
+    }
+
+    pub async fn write_all(&self, mut data: &[u8]) -> io::Result<()> {
+        while !data.is_empty() {
+            let mut guard = self.inner.writable().await?;
+            let result = guard.try_io(|inner| {
+                let rv = unsafe {
+                    libc::write(
+                        inner.as_raw_fd(),
+                        data.as_ptr() as *const libc::c_void,
+                        data.len(),
+                    )
+                };
+                if rv < 0 {
+                    Err(io::Error::last_os_error())
+                } else {
+                    Ok(rv as usize)
+                }
+            });
+
+            match result {
+                Ok(Ok(written)) => data = &data[written..],
+                Ok(Err(e)) => return Err(e),
+                Err(_would_block) => continue,
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Writes as much as can be written without waiting, for when there's no waiting to be done.
+    pub fn try_write(&self, data: &[u8]) -> io::Result<usize> {
+        let rv = unsafe {
+            libc::write(
+                self.inner.as_raw_fd(),
+                data.as_ptr() as *const libc::c_void,
+                data.len(),
+            )
+        };
+        if rv < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(rv as usize)
+    }
+}
+
+fn speed_from_baud_rate(baud_rate: u32) -> Option<libc::speed_t> {
+    let speed = match baud_rate {
+        9600 => libc::B9600,
+        19200 => libc::B19200,
+        38400 => libc::B38400,
+        57600 => libc::B57600,
+        115200 => libc::B115200,
+        230400 => libc::B230400,
+        460800 => libc::B460800,
+        500000 => libc::B500000,
+        921600 => libc::B921600,
+        1000000 => libc::B1000000,
+        2000000 => libc::B2000000,
+        _ => return None,
+    };
+    Some(speed)
+}
+
+/// Opens a pseudo-terminal, returning its master side and the path of its slave side, so that
+/// tests can stand in for an adapter on the other end of a serial device.
+#[cfg(test)]
+pub fn open_pty() -> io::Result<(SerialPort, std::path::PathBuf)> {
+    let fd = unsafe { libc::posix_openpt(libc::O_RDWR | libc::O_NOCTTY | libc::O_CLOEXEC) };
+    if fd < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let fd = SerialFd(fd);
+
+    let mut name = [0 as libc::c_char; 128];
+    unsafe {
+        if libc::grantpt(fd.0) != 0
+            || libc::unlockpt(fd.0) != 0
+            || libc::ptsname_r(fd.0, name.as_mut_ptr(), name.len()) != 0
+        {
+            return Err(io::Error::last_os_error());
+        }
+
+        let flags = libc::fcntl(fd.0, libc::F_GETFL);
+        if flags < 0 || libc::fcntl(fd.0, libc::F_SETFL, flags | libc::O_NONBLOCK) < 0 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+
+    let name = unsafe { std::ffi::CStr::from_ptr(name.as_ptr()) };
+    let path = std::path::PathBuf::from(std::ffi::OsStr::from_bytes(name.to_bytes()));
+    Ok((
+        SerialPort {
+            inner: AsyncFd::new(fd)?,
+        },
+        path,
+    ))
+}
@@ -0,0 +1,325 @@
+use std::{
+    io,
+    net::{Ipv4Addr, SocketAddr, ToSocketAddrs},
+    time::Duration,
+};
+
+use async_trait::async_trait;
+use can::identifier::Id;
+use tokio::time::{timeout, timeout_at, Instant};
+use tracing::info;
+
+use crate::{
+    common::{
+        addressing::{id_from_raw, ResponseAddress},
+        backend::Backend,
+        config::CANParameters,
+        targets::Target,
+    },
+    protocol::{
+        can::{
+            error::{SocketBuildError, SocketError},
+            isotp::FunctionalResponse,
+        },
+        doip::{identify_vehicles, DoIPConnection},
+    },
+};
+
+use super::{DiagnosticTransport, TransportError};
+
+/// Transport over a DoIP gateway, with targets given by their logical addresses.
+///
+/// Gateways take care of getting each diagnostic message to the ECU it's for, and its responses
+/// back, so there's no segmentation to worry about here.
+pub struct DoIPTransport {
+    connection: DoIPConnection,
+    functional_address: u16,
+    read_timeout: Duration,
+    functional_timeout: Duration,
+}
+
+impl DoIPTransport {
+    /// Connects to the gateway and activates routing, finding the gateway with vehicle
+    /// identification first if no host was given.
+    pub async fn open(can_parameters: &CANParameters) -> Result<Self, SocketBuildError> {
+        let (host, port, source_address, activation_type, functional_address) =
+            match &can_parameters.backend {
+                Backend::DoIP {
+                    host,
+                    port,
+                    source_address,
+                    activation_type,
+                    functional_address,
+                } => (
+                    host.clone(),
+                    *port,
+                    *source_address,
+                    *activation_type,
+                    *functional_address,
+                ),
+                _ => {
+                    return Err(SocketBuildError::InvalidOption {
+                        option_name: "backend",
+                    })
+                }
+            };
+
+        let address = match host {
+            Some(host) => (host.as_str(), port)
+                .to_socket_addrs()?
+                .next()
+                .ok_or_else(|| {
+                    io::Error::new(
+                        io::ErrorKind::NotFound,
+                        format!("no addresses found for {}", host),
+                    )
+                })?,
+            None => find_gateway(port, can_parameters.read_timeout).await?,
+        };
+
+        let connection = DoIPConnection::connect(
+            address,
+            source_address,
+            activation_type,
+            can_parameters.read_timeout,
+        )
+        .await
+        .map_err(|source| SocketBuildError::AdapterInitialization { source })?;
+        info!(
+            "Activated routing through DoIP gateway 0x{:04X} at {}.",
+            connection.entity_address(),
+            address
+        );
+
+        Ok(Self {
+            connection,
+            functional_address,
+            read_timeout: can_parameters.read_timeout,
+            functional_timeout: can_parameters.functional_timeout,
+        })
+    }
+}
+
+/// Broadcasts a vehicle identification request, and picks the first gateway to answer.
+async fn find_gateway(port: u16, timeout: Duration) -> Result<SocketAddr, SocketBuildError> {
+    let vehicles = identify_vehicles((Ipv4Addr::BROADCAST, port).into(), timeout).await?;
+    for (address, announcement) in &vehicles {
+        info!(
+            "Found vehicle {} at {}, with logical address 0x{:04X}.",
+            announcement.vin,
+            address.ip(),
+            announcement.logical_address
+        );
+    }
+
+    match vehicles.first() {
+        Some((address, _)) => Ok((address.ip(), port).into()),
+        None => Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            "no DoIP gateways answered vehicle identification",
+        )
+        .into()),
+    }
+}
+
+fn logical_address(target: Target) -> Result<u16, SocketError> {
+    target.logical_address().ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "{} is not a DoIP logical address",
+                target.request_address.id()
+            ),
+        )
+        .into()
+    })
+}
+
+#[async_trait]
+impl DiagnosticTransport for DoIPTransport {
+    async fn send_request(&mut self, target: Target, payload: &[u8]) -> Result<(), TransportError> {
+        let target_address = logical_address(target)?;
+        Ok(self.connection.send(target_address, payload).await?)
+    }
+
+    async fn receive_response(&mut self, target: Target) -> Result<Vec<u8>, TransportError> {
+        let source_address = logical_address(target)?;
+        let duration = self.read_timeout;
+        Ok(
+            timeout(duration, self.connection.receive_from(source_address))
+                .await
+                .map_err(|_| SocketError::Timeout(duration))??,
+        )
+    }
+
+    async fn broadcast(
+        &mut self,
+        payload: &[u8],
+    ) -> Result<Vec<FunctionalResponse>, TransportError> {
+        let deadline = Instant::now() + self.functional_timeout;
+        self.connection
+            .send(self.functional_address, payload)
+            .await?;
+
+        let mut responses = Vec::new();
+        while let Ok(received) = timeout_at(deadline, self.connection.receive_any()).await {
+            let (source_address, payload) = received?;
+            let id = id_from_raw(source_address.into())
+                .expect("logical addresses should always be valid identifiers");
+            responses.push(FunctionalResponse {
+                response_address: ResponseAddress::new(id),
+                payload,
+            });
+        }
+
+        Ok(responses)
+    }
+
+    fn request_id(&self, response_address: ResponseAddress) -> Option<Id> {
+        // ECUs behind a gateway take requests on the same logical address they answer from.
+        Some(response_address.id())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::Parser;
+
+    use crate::{
+        common::config::AppConfig,
+        protocol::{
+            doip::gateway::DoIPGateway, obd::services::CurrentDataService,
+            transport::open_transport, uds::client::UdsClient,
+        },
+    };
+
+    use super::*;
+
+    fn can_parameters(address: SocketAddr, options: &str) -> CANParameters {
+        let backend = format!("doip://{}{}", address, options);
+        AppConfig::try_parse_from([
+            "hypercan",
+            "--backend",
+            &backend,
+            "--read-timeout",
+            "200ms",
+            "--functional-timeout",
+            "100ms",
+            "validate-socket",
+        ])
+        .expect("arguments should be valid")
+        .can_parameters()
+    }
+
+    fn target(logical_address: u16) -> Target {
+        Target::logical(id_from_raw(logical_address.into()).expect("should be a valid identifier"))
+    }
+
+    #[tokio::test]
+    async fn uds_requests_run_over_doip() {
+        let mut response = vec![0x62, 0xF1, 0x90];
+        response.extend_from_slice(b"1HGCM82633A004352");
+        let (address, _) = DoIPGateway::new()
+            .reply(
+                0x1001,
+                &[0x22, 0xF1, 0x90],
+                &[(0x1001, &[0x7F, 0x22, 0x78]), (0x1001, &response)],
+            )
+            .spawn();
+
+        let mut client = UdsClient::connect(can_parameters(address, ""), target(0x1001))
+            .await
+            .unwrap();
+        assert_eq!(client.request(&[0x22, 0xF1, 0x90]).await.unwrap(), response);
+    }
+
+    #[tokio::test]
+    async fn negative_acknowledgements_are_reported() {
+        let (address, _) = DoIPGateway::new()
+            .reply(0x1001, &[0x3E, 0x00], &[(0x1001, &[0x7E, 0x00])])
+            .spawn();
+
+        let mut transport = open_transport(can_parameters(address, ""), None)
+            .await
+            .unwrap();
+        assert!(matches!(
+            transport.send_request(target(0x2000), &[0x3E, 0x00]).await,
+            Err(TransportError::Io(SocketError::Io(_)))
+        ));
+
+        // Nothing answers a request the ECU has no response to.
+        transport
+            .send_request(target(0x1001), &[0x10, 0x01])
+            .await
+            .unwrap();
+        assert!(matches!(
+            transport.receive_response(target(0x1001)).await,
+            Err(TransportError::Io(SocketError::Timeout(_)))
+        ));
+    }
+
+    #[tokio::test]
+    async fn broadcasts_collect_each_ecus_response() {
+        let (address, _) = DoIPGateway::new()
+            .reply(
+                0xE000,
+                &[0x01, 0x00],
+                &[
+                    (0x1001, &[0x41, 0x00, 0xBE, 0x1F, 0xA8, 0x13]),
+                    (0x1002, &[0x41, 0x00, 0x80, 0x00, 0x00, 0x01]),
+                ],
+            )
+            .spawn();
+
+        let mut transport = open_transport(can_parameters(address, ""), None)
+            .await
+            .unwrap();
+        let responses = transport.broadcast(&[0x01, 0x00]).await.unwrap();
+        let addresses: Vec<_> = responses
+            .iter()
+            .map(|response| response.response_address.id().as_raw())
+            .collect();
+        assert_eq!(addresses, vec![0x1001, 0x1002]);
+        assert_eq!(
+            responses[1].payload,
+            vec![0x41, 0x00, 0x80, 0x00, 0x00, 0x01]
+        );
+    }
+
+    #[tokio::test]
+    async fn available_pids_are_keyed_by_logical_address() {
+        let (address, _) = DoIPGateway::new()
+            .reply(
+                0xE000,
+                &[0x01, 0x00],
+                &[(0x1001, &[0x41, 0x00, 0x80, 0x00, 0x00, 0x00])],
+            )
+            .spawn();
+
+        let transport = open_transport(can_parameters(address, ""), None)
+            .await
+            .unwrap();
+        let available_pids = CurrentDataService::new(transport)
+            .query_available_pids(None)
+            .await
+            .unwrap();
+        let available_pids: Vec<_> = available_pids
+            .into_iter()
+            .map(|(id, pids)| (id.as_raw(), pids))
+            .collect();
+        assert_eq!(available_pids, vec![(0x1001, vec![0x01])]);
+    }
+
+    #[tokio::test]
+    async fn denied_routing_activation_is_reported() {
+        let (address, _) = DoIPGateway::new().spawn();
+
+        let result = open_transport(can_parameters(address, "?activation=E0"), None).await;
+        assert!(matches!(
+            result,
+            Err(TransportError::Initialization(
+                SocketBuildError::AdapterInitialization { .. }
+            ))
+        ));
+    }
+}
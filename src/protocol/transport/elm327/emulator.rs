@@ -0,0 +1,251 @@
+//! An ELM327 on the other end of a pseudo-terminal, answering requests with canned responses.
+
+use std::{collections::HashMap, path::PathBuf};
+
+use crate::protocol::serial::{open_pty, SerialPort};
+
+const IDENTIFICATION: &str = "ELM327 v1.5";
+
+/// Where responses come from when the headers don't say otherwise.
+const DEFAULT_RESPONSE_ID: u32 = 0x7E8;
+
+enum Reply {
+    Responses(Vec<Vec<u8>>),
+    Text(String),
+}
+
+/// Settings that the AT commands change.
+struct State {
+    echo: bool,
+    headers: bool,
+    spaces: bool,
+    response_id: Option<u32>,
+}
+
+impl Default for State {
+    fn default() -> Self {
+        Self {
+            echo: true,
+            headers: false,
+            spaces: true,
+            response_id: None,
+        }
+    }
+}
+
+/// A plain ELM327, without any STN extensions, on a single ECU's bus.
+///
+/// Requests are answered the same however they're addressed, with 11-bit identifiers, segmenting
+/// responses the way the ECU would have.  Requests without a canned response get "NO DATA".
+#[derive(Default)]
+pub struct Elm327Emulator {
+    replies: HashMap<Vec<u8>, Reply>,
+    monitored: Vec<Vec<u8>>,
+}
+
+impl Elm327Emulator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Answers `request` with each of `responses`, in order.
+    pub fn respond(mut self, request: &[u8], responses: &[&[u8]]) -> Self {
+        let responses = responses.iter().map(|response| response.to_vec()).collect();
+        self.replies
+            .insert(request.to_vec(), Reply::Responses(responses));
+        self
+    }
+
+    /// Prints `text` in response to `request`, such as an error.
+    pub fn print(mut self, request: &[u8], text: &str) -> Self {
+        self.replies
+            .insert(request.to_vec(), Reply::Text(text.to_string()));
+        self
+    }
+
+    /// Prints each of `responses` whenever the bus is monitored, as if the ECU sent them after the
+    /// adapter stopped waiting for it.
+    pub fn follow_up(mut self, responses: &[&[u8]]) -> Self {
+        self.monitored = responses.iter().map(|response| response.to_vec()).collect();
+        self
+    }
+
+    /// Starts answering on a new pseudo-terminal until the test finishes, returning the path of
+    /// the serial device to open.
+    pub fn spawn(self) -> PathBuf {
+        let (port, path) = open_pty().expect("should be able to open a pseudo-terminal");
+        // Masters hang up whenever nothing has the slave open, so hold it open ourselves until the
+        // transport opens it.
+        let slave = SerialPort::open(&path, 38400).expect("should be able to open the slave");
+
+        tokio::spawn(async move {
+            let _slave = slave;
+            let mut state = State::default();
+            let mut monitoring = false;
+            let mut line = Vec::new();
+            let mut buf = [0; 256];
+            loop {
+                let n = match port.read(&mut buf).await {
+                    Ok(n) if n > 0 => n,
+                    _ => return,
+                };
+
+                for byte in &buf[..n] {
+                    // Any character stops monitoring, and is otherwise ignored.
+                    if monitoring {
+                        monitoring = false;
+                        if port.write_all(b"\r>").await.is_err() {
+                            return;
+                        }
+                        continue;
+                    }
+                    if *byte != b'\r' {
+                        line.push(*byte);
+                        continue;
+                    }
+
+                    let command = String::from_utf8_lossy(&line).into_owned();
+                    line.clear();
+
+                    let mut output = String::new();
+                    if state.echo {
+                        output.push_str(&command);
+                        output.push('\r');
+                    }
+                    if compact(&command) == "ATMA" {
+                        output.push_str(&self.monitor(&state));
+                        monitoring = true;
+                    } else {
+                        output.push_str(&self.answer(&mut state, &command));
+                        output.push_str("\r\r>");
+                    }
+                    if port.write_all(output.as_bytes()).await.is_err() {
+                        return;
+                    }
+                }
+            }
+        });
+
+        path
+    }
+
+    /// Prints the responses seen on the bus while monitoring it, leaving the adapter monitoring.
+    fn monitor(&self, state: &State) -> String {
+        let response_id = state.response_id.unwrap_or(DEFAULT_RESPONSE_ID);
+        self.monitored
+            .iter()
+            .flat_map(|response| segment(response))
+            .map(|frame| format!("{}\r", format_frame(state, response_id, &frame)))
+            .collect()
+    }
+
+    fn answer(&self, state: &mut State, command: &str) -> String {
+        let command = compact(command);
+
+        if let Some(setting) = command.strip_prefix("AT") {
+            return match setting {
+                "Z" => {
+                    *state = State::default();
+                    format!("\r{}", IDENTIFICATION)
+                }
+                "I" => IDENTIFICATION.to_string(),
+                "E0" | "E1" => {
+                    state.echo = setting == "E1";
+                    "OK".to_string()
+                }
+                "H0" | "H1" => {
+                    state.headers = setting == "H1";
+                    "OK".to_string()
+                }
+                "S0" | "S1" => {
+                    state.spaces = setting == "S1";
+                    "OK".to_string()
+                }
+                "AR" => {
+                    state.response_id = None;
+                    "OK".to_string()
+                }
+                _ => match setting.strip_prefix("CRA") {
+                    Some(id) => match u32::from_str_radix(id, 16) {
+                        Ok(id) => {
+                            state.response_id = Some(id);
+                            "OK".to_string()
+                        }
+                        Err(_) => "?".to_string(),
+                    },
+                    None => "OK".to_string(),
+                },
+            };
+        }
+
+        let request = match parse_hex(&command) {
+            Some(request) => request,
+            None => return "?".to_string(),
+        };
+        let responses = match self.replies.get(&request) {
+            Some(Reply::Responses(responses)) if !responses.is_empty() => responses,
+            Some(Reply::Text(text)) => return text.clone(),
+            _ => return "NO DATA".to_string(),
+        };
+
+        let response_id = state.response_id.unwrap_or(DEFAULT_RESPONSE_ID);
+        responses
+            .iter()
+            .flat_map(|response| segment(response))
+            .map(|frame| format_frame(state, response_id, &frame))
+            .collect::<Vec<_>>()
+            .join("\r")
+    }
+}
+
+fn compact(command: &str) -> String {
+    command
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .collect::<String>()
+        .to_ascii_uppercase()
+}
+
+fn parse_hex(s: &str) -> Option<Vec<u8>> {
+    if s.is_empty() || s.len() % 2 != 0 || !s.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+
+    (0..s.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&s[i..i + 2], 16).ok())
+        .collect()
+}
+
+/// Segments a response into the frames an ECU would send it in.
+fn segment(payload: &[u8]) -> Vec<Vec<u8>> {
+    if payload.len() <= 7 {
+        let mut frame = vec![payload.len() as u8];
+        frame.extend_from_slice(payload);
+        return vec![frame];
+    }
+
+    let mut frames = Vec::new();
+    let mut first = vec![0x10 | (payload.len() >> 8) as u8, payload.len() as u8];
+    first.extend_from_slice(&payload[..6]);
+    frames.push(first);
+
+    for (i, chunk) in payload[6..].chunks(7).enumerate() {
+        let mut consecutive = vec![0x20 | ((i + 1) % 16) as u8];
+        consecutive.extend_from_slice(chunk);
+        frames.push(consecutive);
+    }
+
+    frames
+}
+
+fn format_frame(state: &State, response_id: u32, frame: &[u8]) -> String {
+    let mut tokens = Vec::new();
+    if state.headers {
+        tokens.push(format!("{:03X}", response_id));
+    }
+    tokens.extend(frame.iter().map(|b| format!("{:02X}", b)));
+
+    let separator = if state.spaces { " " } else { "" };
+    tokens.join(separator)
+}
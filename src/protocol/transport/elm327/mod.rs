@@ -0,0 +1,711 @@
+//! Transport over an ELM327-compatible adapter, driven with its AT command set over a serial
+//! device.
+//!
+//! Adapters handle ISO-TP themselves, sending flow control and printing each received frame on a
+//! line of its own, so all that's left for us is to configure the headers before each request, and
+//! reassemble the frames printed in response.  Adapters only listen for so long after sending
+//! something, so every response to a request is collected before the request is considered sent,
+//! and when an ECU asks us to keep waiting after the adapter has given up, we monitor the bus for
+//! the rest of its response.
+
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    io,
+    path::Path,
+    time::Duration,
+};
+
+use async_trait::async_trait;
+use can::identifier::Id;
+use tokio::time::Instant;
+use tracing::{debug, info, trace, warn};
+
+use crate::{
+    common::{
+        addressing::{id_from_raw, Addressing, RequestAddress, ResponseAddress},
+        config::CANParameters,
+        targets::Target,
+    },
+    protocol::{
+        can::{
+            error::{SocketBuildError, SocketError},
+            isotp::{separation_time_to_raw, Frame, FunctionalResponse, Reception},
+        },
+        serial::SerialPort,
+        uds::{client::NEGATIVE_RESPONSE_SERVICE_ID, error::NegativeResponseCode},
+    },
+};
+
+use super::{DiagnosticTransport, TransportError};
+
+#[cfg(test)]
+pub mod emulator;
+
+/// Character the adapter prints once it's ready for the next command.
+const PROMPT: u8 = b'>';
+
+/// Resetting takes a while, as the adapter goes through its whole power-on sequence.
+const RESET_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How long to wait for the adapter to answer commands that don't go out on the bus.
+const COMMAND_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// How much longer than the adapter's own timeout to wait for it, before giving up on it.
+const ADAPTER_TIMEOUT_MARGIN: Duration = Duration::from_millis(500);
+
+/// Adapter timeouts are set in units of 4ms, up to 0xFF.
+const ADAPTER_TIMEOUT_UNIT_MS: u128 = 4;
+const MAX_ADAPTER_TIMEOUT: Duration = Duration::from_millis(0xFF * ADAPTER_TIMEOUT_UNIT_MS as u64);
+
+/// Payloads that fit in a single frame, which is all the ELM327 itself can send.
+const MAX_SINGLE_FRAME_LENGTH: usize = 7;
+
+/// Flow status the adapter sends in its flow control frames: continue to send.
+const FLOW_STATUS_CONTINUE_TO_SEND: u8 = 0x30;
+
+/// Which ECUs the adapter is currently set up to talk to.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Mode {
+    Physical(Target),
+    Functional,
+}
+
+/// Transport over an ELM327-compatible adapter.
+///
+/// Adapters with an STN chip are detected, and used to send payloads which don't fit in a single
+/// frame.  Only normal addressing is supported, since the adapter handles ISO-TP itself.
+pub struct Elm327Transport {
+    port: SerialPort,
+    addressing: Addressing,
+    tester_address: u8,
+    functional_request_address: RequestAddress,
+    read_timeout: Duration,
+    functional_timeout: Duration,
+    block_size: u8,
+    separation_time: Duration,
+    is_stn: bool,
+    mode: Option<Mode>,
+    responses: HashMap<Target, VecDeque<Vec<u8>>>,
+    /// ECUs whose last response asked us to keep waiting for the real one.
+    pending: HashSet<Target>,
+}
+
+impl Elm327Transport {
+    /// Opens the adapter on the given serial device, and initializes it for ISO 15765-4 CAN at 500
+    /// kbit/s, with the configured identifier width.
+    pub async fn open(
+        can_parameters: &CANParameters,
+        device: &Path,
+        baud_rate: u32,
+    ) -> Result<Self, SocketBuildError> {
+        if can_parameters.payload_address().is_some() {
+            return Err(SocketBuildError::InvalidOption {
+                option_name: "addressing_format",
+            });
+        }
+        if can_parameters.can_fd {
+            return Err(SocketBuildError::InvalidOption {
+                option_name: "can_fd",
+            });
+        }
+        for timeout in [
+            can_parameters.read_timeout,
+            can_parameters.functional_timeout,
+        ] {
+            if timeout > MAX_ADAPTER_TIMEOUT {
+                warn!(
+                    "ELM327 adapters wait at most {:?} for responses, rather than {:?}.",
+                    MAX_ADAPTER_TIMEOUT, timeout
+                );
+            }
+        }
+
+        let addressing = can_parameters.addressing();
+        let mut transport = Self {
+            port: SerialPort::open(device, baud_rate)?,
+            addressing,
+            tester_address: can_parameters.tester_address,
+            functional_request_address: addressing.functional_request_address(
+                can_parameters.functional_address,
+                can_parameters.tester_address,
+            ),
+            read_timeout: can_parameters.read_timeout,
+            functional_timeout: can_parameters.functional_timeout,
+            block_size: can_parameters.isotp_block_size,
+            separation_time: can_parameters.isotp_separation_time,
+            is_stn: false,
+            mode: None,
+            responses: HashMap::new(),
+            pending: HashSet::new(),
+        };
+        transport
+            .initialize()
+            .await
+            .map_err(|source| SocketBuildError::AdapterInitialization { source })?;
+
+        Ok(transport)
+    }
+
+    async fn initialize(&mut self) -> Result<(), SocketError> {
+        let identification = self.command("ATZ", RESET_TIMEOUT).await?;
+        info!("Found adapter: {}", identification.join(" "));
+
+        // No echo or linefeeds, with spaces and headers in responses, and the adapter handling
+        // ISO-TP.  Adaptive timing is turned off, so that the adapter waits as long as we tell it
+        // to.  Protocol 6 is ISO 15765-4 CAN with 11-bit identifiers, and 7 with 29-bit.
+        let protocol = match self.addressing {
+            Addressing::Standard => "ATSP6",
+            Addressing::Extended => "ATSP7",
+        };
+        for command in ["ATE0", "ATL0", "ATS1", "ATH1", "ATCAF1", "ATAT0", protocol] {
+            self.expect_ok(command).await?;
+        }
+
+        // STN adapters know the command, while plain ELM327 adapters don't understand it.
+        self.is_stn = match self.command("STI", COMMAND_TIMEOUT).await {
+            Ok(identification) => {
+                info!("Adapter is STN-compatible: {}", identification.join(" "));
+                true
+            }
+            Err(SocketError::Io(e)) if e.kind() == io::ErrorKind::InvalidInput => false,
+            Err(e) => return Err(e),
+        };
+
+        Ok(())
+    }
+
+    /// Sets the adapter up to talk to the given ECUs, if it isn't already.
+    async fn configure(&mut self, mode: Mode) -> Result<(), SocketError> {
+        if self.mode == Some(mode) {
+            return Ok(());
+        }
+        // If configuring fails halfway through, we don't know what state the adapter is in.
+        self.mode = None;
+
+        let mut commands = Vec::new();
+        match mode {
+            Mode::Physical(target) => {
+                let request_id = target.request_address.id();
+                commands.extend(header_commands(request_id));
+                commands.push(format!("ATCRA{}", format_id(target.response_address.id())));
+                // Flow control goes to the ECU's request identifier, which the adapter can only
+                // work out for itself for the OBD identifiers.
+                commands.push(format!("ATFCSH{}", format_id(request_id)));
+                commands.push(format!(
+                    "ATFCSD{:02X}{:02X}{:02X}",
+                    FLOW_STATUS_CONTINUE_TO_SEND,
+                    self.block_size,
+                    separation_time_to_raw(self.separation_time)
+                ));
+                commands.push("ATFCSM1".to_string());
+                commands.push(timeout_command(self.read_timeout));
+            }
+            Mode::Functional => {
+                commands.extend(header_commands(self.functional_request_address.id()));
+                commands.push("ATAR".to_string());
+                commands.push("ATFCSM0".to_string());
+                commands.push(timeout_command(self.functional_timeout));
+            }
+        }
+
+        for command in commands {
+            self.expect_ok(&command).await?;
+        }
+        self.mode = Some(mode);
+
+        Ok(())
+    }
+
+    /// Sends a payload on the bus, returning every response the adapter printed.
+    async fn exchange(
+        &mut self,
+        payload: &[u8],
+        timeout: Duration,
+    ) -> Result<Vec<(Id, Vec<u8>)>, SocketError> {
+        if payload.is_empty() || (payload.len() > MAX_SINGLE_FRAME_LENGTH && !self.is_stn) {
+            return Err(SocketError::PayloadLength(payload.len()));
+        }
+
+        let data = payload
+            .iter()
+            .map(|b| format!("{:02X}", b))
+            .collect::<String>();
+        let command = if payload.len() <= MAX_SINGLE_FRAME_LENGTH {
+            data
+        } else {
+            format!("STPX D:{}", data)
+        };
+
+        let lines = self
+            .command(&command, timeout + ADAPTER_TIMEOUT_MARGIN)
+            .await?;
+        if lines.iter().any(|line| line == "NO DATA") {
+            return Ok(Vec::new());
+        }
+
+        Ok(reassemble(&lines))
+    }
+
+    /// Monitors the bus for responses from the ECU we're configured to talk to, without sending
+    /// anything, until one of them is complete or `timeout` passes.
+    async fn listen(&mut self, timeout: Duration) -> Result<Vec<(Id, Vec<u8>)>, SocketError> {
+        trace!("elm327 <- ATMA");
+        self.port.write_all(b"ATMA\r").await?;
+
+        let deadline = Instant::now() + timeout;
+        let mut output = Vec::new();
+        loop {
+            let mut buf = [0; 256];
+            let n = match tokio::time::timeout_at(deadline, self.port.read(&mut buf)).await {
+                Ok(result) => result?,
+                Err(_) => break,
+            };
+            if n == 0 {
+                return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+            }
+            output.extend_from_slice(&buf[..n]);
+
+            // Only whole lines count, as the rest of a frame may still be on its way.
+            let end = output.iter().rposition(|b| *b == b'\r').unwrap_or(0);
+            if !reassemble(&split_lines(&output[..end])).is_empty() {
+                break;
+            }
+        }
+
+        // Anything we send stops the adapter monitoring, after which it prints its prompt.
+        self.port.write_all(b"\r").await?;
+        let deadline = Instant::now() + COMMAND_TIMEOUT;
+        while !output.ends_with(&[PROMPT]) {
+            let mut buf = [0; 256];
+            let n = match tokio::time::timeout_at(deadline, self.port.read(&mut buf)).await {
+                Ok(result) => result?,
+                Err(_) => return Err(SocketError::Timeout(COMMAND_TIMEOUT)),
+            };
+            if n == 0 {
+                return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+            }
+            output.extend_from_slice(&buf[..n]);
+        }
+
+        let lines = split_lines(&output[..output.len() - 1]);
+        trace!("elm327 -> {:?}", lines);
+        Ok(reassemble(&lines))
+    }
+
+    /// Sends a command that the adapter should acknowledge.
+    async fn expect_ok(&mut self, command: &str) -> Result<(), SocketError> {
+        let lines = self.command(command, COMMAND_TIMEOUT).await?;
+        if lines.iter().any(|line| line == "OK") {
+            Ok(())
+        } else {
+            Err(SocketError::Io(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("unexpected response to {}: {:?}", command, lines),
+            )))
+        }
+    }
+
+    /// Sends a command, returning the lines the adapter printed before its prompt.
+    async fn command(
+        &mut self,
+        command: &str,
+        timeout: Duration,
+    ) -> Result<Vec<String>, SocketError> {
+        trace!("elm327 <- {}", command);
+        self.port
+            .write_all(format!("{}\r", command).as_bytes())
+            .await?;
+
+        let deadline = Instant::now() + timeout;
+        let mut output = Vec::new();
+        while !output.contains(&PROMPT) {
+            let mut buf = [0; 256];
+            let n = match tokio::time::timeout_at(deadline, self.port.read(&mut buf)).await {
+                Ok(result) => result?,
+                Err(_) => return Err(SocketError::Timeout(timeout)),
+            };
+            if n == 0 {
+                return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+            }
+            output.extend_from_slice(&buf[..n]);
+        }
+
+        let end = output
+            .iter()
+            .position(|b| *b == PROMPT)
+            .unwrap_or(output.len());
+        let mut lines = split_lines(&output[..end]);
+        trace!("elm327 -> {:?}", lines);
+
+        // Commands are echoed until echo is turned off.
+        if lines.first().map(|line| compact(line) == compact(command)) == Some(true) {
+            lines.remove(0);
+        }
+
+        match lines.iter().find_map(|line| adapter_error(line)) {
+            Some(e) => Err(e),
+            None => Ok(lines),
+        }
+    }
+}
+
+#[async_trait]
+impl DiagnosticTransport for Elm327Transport {
+    async fn send_request(&mut self, target: Target, payload: &[u8]) -> Result<(), TransportError> {
+        self.configure(Mode::Physical(target)).await?;
+        let responses = self.exchange(payload, self.read_timeout).await?;
+
+        self.pending.remove(&target);
+        let queue = self.responses.entry(target).or_default();
+        queue.clear();
+        for (id, payload) in responses {
+            if ResponseAddress::new(id) == target.response_address {
+                queue.push_back(payload);
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn receive_response(&mut self, target: Target) -> Result<Vec<u8>, TransportError> {
+        let is_empty = self
+            .responses
+            .get(&target)
+            .map_or(true, |queue| queue.is_empty());
+        if is_empty && self.pending.contains(&target) {
+            // The adapter stopped listening while the ECU was still working on its response.
+            self.configure(Mode::Physical(target)).await?;
+            for (id, payload) in self.listen(self.read_timeout).await? {
+                if ResponseAddress::new(id) == target.response_address {
+                    self.responses.entry(target).or_default().push_back(payload);
+                }
+            }
+        }
+
+        let response = self
+            .responses
+            .get_mut(&target)
+            .and_then(|queue| queue.pop_front());
+        match response {
+            Some(response) => {
+                if is_response_pending(&response) {
+                    self.pending.insert(target);
+                } else {
+                    self.pending.remove(&target);
+                }
+                Ok(response)
+            }
+            None => {
+                self.pending.remove(&target);
+                Err(SocketError::Timeout(self.read_timeout).into())
+            }
+        }
+    }
+
+    async fn broadcast(
+        &mut self,
+        payload: &[u8],
+    ) -> Result<Vec<FunctionalResponse>, TransportError> {
+        if payload.len() > MAX_SINGLE_FRAME_LENGTH {
+            return Err(SocketError::PayloadLength(payload.len()).into());
+        }
+
+        self.configure(Mode::Functional).await?;
+        let responses = self.exchange(payload, self.functional_timeout).await?;
+
+        Ok(responses
+            .into_iter()
+            .filter_map(|(id, payload)| {
+                let response_address = self.addressing.response_address(id, self.tester_address)?;
+                Some(FunctionalResponse {
+                    response_address,
+                    payload,
+                })
+            })
+            .collect())
+    }
+}
+
+/// Commands that set the identifier requests are sent on.
+///
+/// The header only covers the low 24 bits of 29-bit identifiers, with the priority bits above them
+/// set separately.
+fn header_commands(id: Id) -> Vec<String> {
+    let raw = id.as_raw();
+    if raw <= 0x7FF {
+        vec![format!("ATSH{:03X}", raw)]
+    } else {
+        vec![
+            format!("ATCP{:02X}", raw >> 24),
+            format!("ATSH{:06X}", raw & 0xFFFFFF),
+        ]
+    }
+}
+
+fn format_id(id: Id) -> String {
+    let raw = id.as_raw();
+    if raw <= 0x7FF {
+        format!("{:03X}", raw)
+    } else {
+        format!("{:08X}", raw)
+    }
+}
+
+fn timeout_command(timeout: Duration) -> String {
+    let units = (timeout.as_millis() / ADAPTER_TIMEOUT_UNIT_MS).clamp(1, 0xFF);
+    format!("ATST{:02X}", units)
+}
+
+/// Splits adapter output into its non-empty lines.
+fn split_lines(output: &[u8]) -> Vec<String> {
+    String::from_utf8_lossy(output)
+        .split(|c| c == '\r' || c == '\n')
+        // Some adapters print NULs now and then.
+        .map(|line| line.trim_matches(|c: char| c.is_whitespace() || c == '\0'))
+        .filter(|line| !line.is_empty())
+        .map(String::from)
+        .collect()
+}
+
+/// Whether a response asks us to keep waiting for the real response.
+fn is_response_pending(response: &[u8]) -> bool {
+    matches!(
+        response,
+        [NEGATIVE_RESPONSE_SERVICE_ID, _, code]
+            if *code == NegativeResponseCode::RequestCorrectlyReceivedResponsePending.as_byte()
+    )
+}
+
+fn compact(s: &str) -> String {
+    s.chars()
+        .filter(|c| !c.is_whitespace())
+        .map(|c| c.to_ascii_uppercase())
+        .collect()
+}
+
+/// Maps the errors adapters print in place of responses onto socket errors.
+fn adapter_error(line: &str) -> Option<SocketError> {
+    let error = match line {
+        "?" => io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "adapter did not understand the command",
+        ),
+        "BUFFER FULL" => return Some(SocketError::Overflow),
+        "STOPPED" => io::Error::new(io::ErrorKind::Interrupted, "adapter stopped waiting"),
+        "CAN ERROR" | "BUS ERROR" | "BUS BUSY" | "FB ERROR" | "UNABLE TO CONNECT" => {
+            io::Error::new(io::ErrorKind::Other, format!("adapter reported: {}", line))
+        }
+        line if line.starts_with("ERR") => {
+            io::Error::new(io::ErrorKind::Other, format!("adapter reported: {}", line))
+        }
+        _ => return None,
+    };
+
+    Some(SocketError::Io(error))
+}
+
+/// Parses a line of a response into the identifier and data of the frame on it.
+///
+/// Identifiers are printed as three digits when they're 11-bit, and as four bytes when they're
+/// 29-bit.
+fn parse_frame(line: &str) -> Option<(Id, Vec<u8>)> {
+    let tokens = line.split_whitespace().collect::<Vec<_>>();
+    let (id, data) = match tokens.first()?.len() {
+        3 => (u32::from_str_radix(tokens[0], 16).ok()?, &tokens[1..]),
+        2 if tokens.len() >= 4 => {
+            let id = u32::from_str_radix(&tokens[..4].concat(), 16).ok()?;
+            (id, &tokens[4..])
+        }
+        _ => return None,
+    };
+
+    let data = data
+        .iter()
+        .map(|token| match token.len() {
+            2 => u8::from_str_radix(token, 16).ok(),
+            _ => None,
+        })
+        .collect::<Option<Vec<_>>>()?;
+
+    Some((id_from_raw(id)?, data))
+}
+
+/// Reassembles the frames printed in response to a request into complete payloads, in the order
+/// they completed in.
+fn reassemble(lines: &[String]) -> Vec<(Id, Vec<u8>)> {
+    let mut receptions: HashMap<u32, Reception> = HashMap::new();
+    let mut responses = Vec::new();
+    for line in lines {
+        let (id, data) = match parse_frame(line) {
+            Some(frame) => frame,
+            None => {
+                debug!("Ignoring unexpected output from adapter: {}", line);
+                continue;
+            }
+        };
+
+        match Frame::parse(&data, None) {
+            Some(Frame::Single(payload)) => {
+                receptions.remove(&id.as_raw());
+                responses.push((id, payload.to_vec()));
+            }
+            Some(Frame::First { length, data }) => {
+                receptions.insert(id.as_raw(), Reception::new(length, data));
+            }
+            Some(Frame::Consecutive {
+                sequence_number,
+                data,
+            }) => {
+                let reception = match receptions.get_mut(&id.as_raw()) {
+                    Some(reception) => reception,
+                    None => continue,
+                };
+
+                if let Err(e) = reception.push(sequence_number, data) {
+                    debug!("Dropping response from {}: {}", id, e);
+                    receptions.remove(&id.as_raw());
+                } else if reception.is_complete() {
+                    let reception = receptions
+                        .remove(&id.as_raw())
+                        .expect("reception should be present");
+                    responses.push((id, reception.into_payload()));
+                }
+            }
+            _ => trace!("ignoring unexpected frame: {:02X?}", data),
+        }
+    }
+
+    for id in receptions.keys() {
+        debug!("Dropping incomplete response from 0x{:X}.", id);
+    }
+
+    responses
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::Parser;
+
+    use crate::{
+        common::config::AppConfig,
+        protocol::{
+            transport::{mock::standard_target, open_transport},
+            uds::client::UdsClient,
+        },
+    };
+
+    use super::{emulator::Elm327Emulator, *};
+
+    fn can_parameters(device: &Path) -> CANParameters {
+        let backend = format!("elm327://{}", device.display());
+        AppConfig::try_parse_from(["hypercan", "--backend", &backend, "validate-socket"])
+            .expect("arguments should be valid")
+            .can_parameters()
+    }
+
+    #[test]
+    fn parses_frames() {
+        assert_eq!(
+            parse_frame("7E8 03 41 0D 32"),
+            Some((id_from_raw(0x7E8).unwrap(), vec![0x03, 0x41, 0x0D, 0x32]))
+        );
+        assert_eq!(
+            parse_frame("18 DA F1 10 03 41 0D 32"),
+            Some((
+                id_from_raw(0x18DAF110).unwrap(),
+                vec![0x03, 0x41, 0x0D, 0x32]
+            ))
+        );
+        assert_eq!(parse_frame("SEARCHING..."), None);
+    }
+
+    #[tokio::test]
+    async fn reassembles_multi_line_responses() {
+        let target = standard_target(0x7E0);
+        let vin = b"1HGCM82633A004352";
+        let mut response = vec![0x62, 0xF1, 0x90];
+        response.extend_from_slice(vin);
+        let device = Elm327Emulator::new()
+            .respond(&[0x22, 0xF1, 0x90], &[&[0x7F, 0x22, 0x78], &response])
+            .spawn();
+
+        let mut transport = open_transport(can_parameters(&device), None).await.unwrap();
+        transport
+            .send_request(target, &[0x22, 0xF1, 0x90])
+            .await
+            .unwrap();
+        assert_eq!(
+            transport.receive_response(target).await.unwrap(),
+            vec![0x7F, 0x22, 0x78]
+        );
+        assert_eq!(transport.receive_response(target).await.unwrap(), response);
+    }
+
+    #[tokio::test]
+    async fn listens_for_responses_the_adapter_gave_up_on() {
+        let target = standard_target(0x7E0);
+        let device = Elm327Emulator::new()
+            .respond(&[0x31, 0x01, 0xFF, 0x00], &[&[0x7F, 0x31, 0x78]])
+            .follow_up(&[
+                &[0x7F, 0x31, 0x78],
+                &[0x71, 0x01, 0xFF, 0x00, 0x12, 0x34, 0x56, 0x78],
+            ])
+            .spawn();
+
+        let transport = open_transport(can_parameters(&device), None).await.unwrap();
+        let mut client = UdsClient::new(transport, target);
+        assert_eq!(
+            client.request(&[0x31, 0x01, 0xFF, 0x00]).await.unwrap(),
+            vec![0x71, 0x01, 0xFF, 0x00, 0x12, 0x34, 0x56, 0x78]
+        );
+    }
+
+    #[tokio::test]
+    async fn broadcasts_map_response_addresses() {
+        let device = Elm327Emulator::new()
+            .respond(&[0x01, 0x00], &[&[0x41, 0x00, 0xBE, 0x1F, 0xA8, 0x13]])
+            .spawn();
+
+        let mut transport = open_transport(can_parameters(&device), None).await.unwrap();
+        let responses = transport.broadcast(&[0x01, 0x00]).await.unwrap();
+        assert_eq!(responses.len(), 1);
+        assert_eq!(responses[0].response_address.id().as_raw(), 0x7E8);
+        assert_eq!(
+            responses[0].payload,
+            vec![0x41, 0x00, 0xBE, 0x1F, 0xA8, 0x13]
+        );
+    }
+
+    #[tokio::test]
+    async fn adapter_errors_map_to_socket_errors() {
+        let target = standard_target(0x7E0);
+        let device = Elm327Emulator::new()
+            .print(&[0x3E, 0x00], "CAN ERROR")
+            .print(&[0x3E, 0x80], "BUFFER FULL")
+            .spawn();
+
+        let mut transport = open_transport(can_parameters(&device), None).await.unwrap();
+
+        // Nothing answered, so the adapter printed "NO DATA".
+        transport.send_request(target, &[0x10, 0x01]).await.unwrap();
+        assert!(matches!(
+            transport.receive_response(target).await,
+            Err(TransportError::Io(SocketError::Timeout(_)))
+        ));
+
+        assert!(matches!(
+            transport.send_request(target, &[0x3E, 0x00]).await,
+            Err(TransportError::Io(SocketError::Io(_)))
+        ));
+        assert!(matches!(
+            transport.send_request(target, &[0x3E, 0x80]).await,
+            Err(TransportError::Io(SocketError::Overflow))
+        ));
+
+        // Plain ELM327 adapters can only send single frames.
+        assert!(matches!(
+            transport.send_request(target, &[0x2E; 8]).await,
+            Err(TransportError::Io(SocketError::PayloadLength(8)))
+        ));
+    }
+}
@@ -0,0 +1,319 @@
+//! A transport over the mock CAN bus, and fake ECUs to put on the other end of it.
+
+use std::{
+    collections::{hash_map::Entry, HashMap},
+    time::Duration,
+};
+
+use async_trait::async_trait;
+use can::identifier::Id;
+use tracing::trace;
+
+use crate::{
+    common::{
+        addressing::{id_from_raw, Addressing, RequestAddress},
+        targets::Target,
+    },
+    protocol::{
+        can::{
+            isotp::FunctionalResponse,
+            mock::{MockBus, MockFunctionalRequester, MockISOTPSocket},
+        },
+        uds::{
+            client::{UdsClient, NEGATIVE_RESPONSE_SERVICE_ID},
+            error::NegativeResponseCode,
+        },
+    },
+};
+
+use super::{DiagnosticTransport, TransportError};
+
+const TESTER_ADDRESS: u8 = 0xF1;
+const FUNCTIONAL_ADDRESS: u8 = 0x33;
+
+/// How long to collect responses to functional requests for.  Fake ECUs answer straight away, so
+/// this only needs to be long enough for their tasks to get scheduled.
+const FUNCTIONAL_TIMEOUT: Duration = Duration::from_millis(50);
+
+/// Lowest service ID that belongs to UDS, rather than to OBD-II.
+const FIRST_UDS_SERVICE_ID: u8 = 0x10;
+
+const OBD_CURRENT_DATA_SERVICE_ID: u8 = 0x01;
+const OBD_POSITIVE_RESPONSE_SERVICE_ID: u8 = 0x41;
+const PID_QUERY_STRIDE: u16 = 0x20;
+
+/// Creates the target for the ECU at the given 11-bit physical request identifier.
+pub fn standard_target(request_id: u32) -> Target {
+    let request_address =
+        RequestAddress::new(id_from_raw(request_id).expect("should be a valid identifier"));
+    let response_address = request_address
+        .response_address()
+        .expect("should be an OBD physical request identifier");
+
+    Target {
+        request_address,
+        response_address,
+    }
+}
+
+fn functional_request_id() -> Id {
+    Addressing::Standard
+        .functional_request_address(FUNCTIONAL_ADDRESS, TESTER_ADDRESS)
+        .id()
+}
+
+/// Transport over a `MockBus`, laid out the same as `ISOTPTransport`: an ISO-TP socket per ECU,
+/// and 11-bit functional requests.
+pub struct MockTransport {
+    bus: MockBus,
+    sockets: HashMap<Target, MockISOTPSocket>,
+    requester: MockFunctionalRequester,
+}
+
+impl MockTransport {
+    pub fn new(bus: &MockBus) -> Self {
+        Self {
+            bus: bus.clone(),
+            sockets: HashMap::new(),
+            requester: bus.functional_requester(
+                functional_request_id(),
+                Addressing::Standard,
+                TESTER_ADDRESS,
+            ),
+        }
+    }
+
+    fn open(&mut self, target: Target) -> &mut MockISOTPSocket {
+        match self.sockets.entry(target) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => entry.insert(
+                self.bus
+                    .isotp_socket(target.response_address.id(), target.request_address.id()),
+            ),
+        }
+    }
+}
+
+#[async_trait]
+impl DiagnosticTransport for MockTransport {
+    async fn send_request(&mut self, target: Target, payload: &[u8]) -> Result<(), TransportError> {
+        Ok(self.open(target).write(payload).await?)
+    }
+
+    async fn receive_response(&mut self, target: Target) -> Result<Vec<u8>, TransportError> {
+        Ok(self.open(target).read().await?)
+    }
+
+    async fn broadcast(
+        &mut self,
+        payload: &[u8],
+    ) -> Result<Vec<FunctionalResponse>, TransportError> {
+        Ok(self.requester.request(payload, FUNCTIONAL_TIMEOUT).await?)
+    }
+}
+
+/// A scriptable ECU, which answers requests with canned responses.
+///
+/// Requests without a canned response are treated the way a real ECU would: unsupported UDS
+/// services are rejected when requested physically, and everything else goes unanswered.
+pub struct FakeEcu {
+    target: Target,
+    responses: HashMap<Vec<u8>, Vec<Vec<u8>>>,
+}
+
+impl FakeEcu {
+    pub fn new(target: Target) -> Self {
+        Self {
+            target,
+            responses: HashMap::new(),
+        }
+    }
+
+    /// Answers `request` with each of `responses`, in order.  No responses at all means the
+    /// request goes unanswered.
+    pub fn respond(mut self, request: &[u8], responses: &[&[u8]]) -> Self {
+        self.responses.insert(
+            request.to_vec(),
+            responses.iter().map(|response| response.to_vec()).collect(),
+        );
+        self
+    }
+
+    /// Answers `request` with a negative response.
+    pub fn reject(self, request: &[u8], code: NegativeResponseCode) -> Self {
+        let response = [NEGATIVE_RESPONSE_SERVICE_ID, request[0], code.as_byte()];
+        self.respond(request, &[&response])
+    }
+
+    /// Answers OBD-II queries for the supported PIDs, as supporting each of `pids`.
+    ///
+    /// Query PIDs themselves are implied by the PIDs after them, so they don't need to be listed.
+    pub fn supported_pids(mut self, pids: &[u8]) -> Self {
+        let mut query_pid = 0;
+        loop {
+            let mut data = [0u8; 4];
+            for pid in pids.iter().map(|pid| *pid as u16) {
+                if pid > query_pid && pid <= query_pid + PID_QUERY_STRIDE {
+                    let bit = (pid - query_pid - 1) as usize;
+                    data[bit / 8] |= 0x80 >> (bit % 8);
+                }
+            }
+
+            let more = pids
+                .iter()
+                .any(|pid| *pid as u16 > query_pid + PID_QUERY_STRIDE);
+            if more {
+                data[3] |= 0x01;
+            }
+
+            let request = [OBD_CURRENT_DATA_SERVICE_ID, query_pid as u8];
+            let response = [
+                OBD_POSITIVE_RESPONSE_SERVICE_ID,
+                query_pid as u8,
+                data[0],
+                data[1],
+                data[2],
+                data[3],
+            ];
+            self = self.respond(&request, &[&response]);
+
+            if !more {
+                return self;
+            }
+            query_pid += PID_QUERY_STRIDE;
+        }
+    }
+
+    /// Starts answering requests on a bus of its own, returning a UDS client connected to it.
+    pub fn connect(self) -> UdsClient {
+        let bus = MockBus::new();
+        let target = self.target;
+        self.spawn(&bus);
+        UdsClient::new(Box::new(MockTransport::new(&bus)), target)
+    }
+
+    /// Starts answering requests on the given bus, both physical and functional, until the test
+    /// finishes.
+    pub fn spawn(self, bus: &MockBus) {
+        let target = self.target;
+        spawn_responder(bus, target, move |request, is_functional| {
+            self.answer(request, is_functional)
+        });
+    }
+
+    fn answer(&self, request: &[u8], is_functional: bool) -> Vec<Vec<u8>> {
+        if let Some(responses) = self.responses.get(request) {
+            return responses.clone();
+        }
+
+        // ECUs don't reject functional requests for services they don't support, since every ECU
+        // that doesn't support it would.
+        match request.first() {
+            Some(service_id) if *service_id >= FIRST_UDS_SERVICE_ID && !is_functional => {
+                vec![vec![
+                    NEGATIVE_RESPONSE_SERVICE_ID,
+                    *service_id,
+                    NegativeResponseCode::ServiceNotSupported.as_byte(),
+                ]]
+            }
+            _ => Vec::new(),
+        }
+    }
+}
+
+/// Answers requests to `target` on the given bus, both physical and functional, until the test
+/// finishes.
+pub fn spawn_responder(
+    bus: &MockBus,
+    target: Target,
+    mut answer: impl FnMut(&[u8], bool) -> Vec<Vec<u8>> + Send + 'static,
+) {
+    let mut physical = bus.isotp_socket(target.request_address.id(), target.response_address.id());
+    let mut functional = bus.isotp_socket(functional_request_id(), target.response_address.id());
+
+    tokio::spawn(async move {
+        loop {
+            let (request, is_functional) = tokio::select! {
+                result = physical.read() => (result, false),
+                result = functional.read() => (result, true),
+            };
+
+            // Reads time out when nobody's talking to us, which is nothing to worry about.
+            let request = match request {
+                Ok(request) => request,
+                Err(_) => continue,
+            };
+
+            for response in answer(&request, is_functional) {
+                trace!("fake ECU at {}: {:02X?}", target.request_address, response);
+                let _ = physical.write(&response).await;
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::protocol::can::error::SocketError;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn broadcast_collects_every_ecu() {
+        let bus = MockBus::new();
+        FakeEcu::new(standard_target(0x7E0))
+            .respond(&[0x09, 0x02], &[&[0x49, 0x02, 0x01]])
+            .spawn(&bus);
+        FakeEcu::new(standard_target(0x7E1))
+            .respond(&[0x09, 0x02], &[&[0x49, 0x02, 0x02]])
+            .spawn(&bus);
+        FakeEcu::new(standard_target(0x7E2)).spawn(&bus);
+
+        let mut transport = MockTransport::new(&bus);
+        let mut responses = transport
+            .broadcast(&[0x09, 0x02])
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|response| (response.response_address.id().as_raw(), response.payload))
+            .collect::<Vec<_>>();
+        responses.sort();
+
+        assert_eq!(
+            responses,
+            vec![
+                (0x7E8, vec![0x49, 0x02, 0x01]),
+                (0x7E9, vec![0x49, 0x02, 0x02])
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn unsupported_services_are_only_rejected_physically() {
+        let bus = MockBus::new();
+        let target = standard_target(0x7E0);
+        FakeEcu::new(target).spawn(&bus);
+
+        let mut transport = MockTransport::new(&bus);
+        transport
+            .send_request(target, &[0x22, 0xF1, 0x90])
+            .await
+            .unwrap();
+        assert_eq!(
+            transport.receive_response(target).await.unwrap(),
+            vec![0x7F, 0x22, 0x11]
+        );
+
+        assert!(transport
+            .broadcast(&[0x22, 0xF1, 0x90])
+            .await
+            .unwrap()
+            .is_empty());
+
+        transport.send_request(target, &[0x01, 0x0C]).await.unwrap();
+        assert!(matches!(
+            transport.receive_response(target).await,
+            Err(TransportError::Io(SocketError::Timeout(_)))
+        ));
+    }
+}
@@ -0,0 +1,94 @@
+//! Transports that diagnostic requests can be carried over.
+//!
+//! Services only ever talk to ECUs through a `DiagnosticTransport`, so they work the same no
+//! matter what's underneath, whether that's SocketCAN or something else entirely.
+
+use async_trait::async_trait;
+use can::identifier::Id;
+use thiserror::Error;
+
+use crate::common::{
+    addressing::ResponseAddress, backend::Backend, config::CANParameters, targets::Target,
+};
+
+use super::can::{
+    error::{SocketBuildError, SocketError},
+    isotp::FunctionalResponse,
+};
+
+pub use self::socket::ISOTPTransport;
+use self::{doip::DoIPTransport, elm327::Elm327Transport};
+
+mod doip;
+mod elm327;
+#[cfg(test)]
+pub mod mock;
+mod socket;
+
+#[derive(Debug, Error)]
+pub enum TransportError {
+    #[error("failed to initialize socket: {0}")]
+    Initialization(#[from] SocketBuildError),
+    #[error("socket error: {0}")]
+    Io(#[from] SocketError),
+}
+
+/// Carries diagnostic requests to ECUs, and their responses back.
+#[async_trait]
+pub trait DiagnosticTransport: Send {
+    /// Sends a request to a single ECU.
+    async fn send_request(&mut self, target: Target, payload: &[u8]) -> Result<(), TransportError>;
+
+    /// Receives the next response from a single ECU.
+    async fn receive_response(&mut self, target: Target) -> Result<Vec<u8>, TransportError>;
+
+    /// Sends a functional request to every ECU at once, collecting each complete response that
+    /// arrives before the transport's deadline.
+    async fn broadcast(
+        &mut self,
+        payload: &[u8],
+    ) -> Result<Vec<FunctionalResponse>, TransportError>;
+
+    /// Gets the identifier that an ECU which answered a broadcast on `response_address` expects
+    /// requests on, if it can be worked out.
+    fn request_id(&self, response_address: ResponseAddress) -> Option<Id> {
+        response_address
+            .request_address()
+            .map(|address| address.id())
+    }
+}
+
+/// Opens a transport over the configured backend.
+///
+/// When a target is given, whatever's needed to talk to it is opened up front, so that problems
+/// with it are reported straight away.
+pub async fn open_transport(
+    can_parameters: CANParameters,
+    target: Option<Target>,
+) -> Result<Box<dyn DiagnosticTransport>, TransportError> {
+    match can_parameters.backend.clone() {
+        Backend::SocketCAN | Backend::Slcan { .. } | Backend::Socketcand { .. } => {
+            let mut transport = ISOTPTransport::new(can_parameters);
+            if let Some(target) = target {
+                transport.open(target)?;
+            }
+            Ok(Box::new(transport))
+        }
+        Backend::Elm327 { device, baud_rate } => {
+            let transport = Elm327Transport::open(&can_parameters, &device, baud_rate).await?;
+            Ok(Box::new(transport))
+        }
+        Backend::DoIP { .. } => {
+            let transport = DoIPTransport::open(&can_parameters).await?;
+            Ok(Box::new(transport))
+        }
+        #[cfg(test)]
+        Backend::Mock(_) => {
+            let mut transport = ISOTPTransport::new(can_parameters);
+            if let Some(target) = target {
+                transport.open(target)?;
+            }
+            Ok(Box::new(transport))
+        }
+    }
+}
@@ -0,0 +1,78 @@
+use std::collections::{hash_map::Entry, HashMap};
+
+use async_trait::async_trait;
+
+use crate::{
+    common::{config::CANParameters, targets::Target},
+    protocol::can::{
+        error::SocketBuildError,
+        isotp::{FunctionalRequester, FunctionalResponse, ISOTPSocket},
+    },
+};
+
+use super::{DiagnosticTransport, TransportError};
+
+/// Transport over raw CAN access, whether that's SocketCAN or an adapter standing in for it, using
+/// an ISO-TP socket per ECU and a raw socket for functional requests.
+///
+/// Sockets are opened as they're first needed, and kept open from then on.
+pub struct ISOTPTransport {
+    can_parameters: CANParameters,
+    sockets: HashMap<Target, ISOTPSocket>,
+    requester: Option<FunctionalRequester>,
+}
+
+impl ISOTPTransport {
+    pub fn new(can_parameters: CANParameters) -> Self {
+        Self {
+            can_parameters,
+            sockets: HashMap::new(),
+            requester: None,
+        }
+    }
+
+    /// Opens the ISO-TP socket for the given ECU, if it isn't open already.
+    pub fn open(&mut self, target: Target) -> Result<&mut ISOTPSocket, SocketBuildError> {
+        match self.sockets.entry(target) {
+            Entry::Occupied(entry) => Ok(entry.into_mut()),
+            Entry::Vacant(entry) => {
+                let socket = ISOTPSocket::builder()
+                    .can_parameters(self.can_parameters.clone())
+                    .source_id(target.response_address.id())
+                    .destination_id(target.request_address.id())
+                    .build()?;
+                Ok(entry.insert(socket))
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl DiagnosticTransport for ISOTPTransport {
+    async fn send_request(&mut self, target: Target, payload: &[u8]) -> Result<(), TransportError> {
+        Ok(self.open(target)?.write(payload).await?)
+    }
+
+    async fn receive_response(&mut self, target: Target) -> Result<Vec<u8>, TransportError> {
+        Ok(self.open(target)?.read().await?)
+    }
+
+    async fn broadcast(
+        &mut self,
+        payload: &[u8],
+    ) -> Result<Vec<FunctionalResponse>, TransportError> {
+        if self.requester.is_none() {
+            let requester =
+                FunctionalRequester::open(&self.can_parameters, self.can_parameters.addressing())?;
+            self.requester = Some(requester);
+        }
+
+        let requester = self
+            .requester
+            .as_mut()
+            .expect("functional requester should be open");
+        Ok(requester
+            .request(payload, self.can_parameters.functional_timeout)
+            .await?)
+    }
+}
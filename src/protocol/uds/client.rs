@@ -0,0 +1,278 @@
+use std::time::Duration;
+
+use tokio::time::{timeout_at, Instant};
+use tracing::trace;
+
+use crate::{
+    common::{
+        config::CANParameters,
+        error::{FieldIdentifier, FieldValue, InvalidResponse, InvalidResponseKind},
+        targets::Target,
+    },
+    protocol::{
+        can::error::SocketError,
+        transport::{open_transport, DiagnosticTransport, TransportError},
+    },
+};
+
+use super::error::{NegativeResponseCode, UdsError};
+
+pub const NEGATIVE_RESPONSE_SERVICE_ID: u8 = 0x7F;
+pub const POSITIVE_RESPONSE_OFFSET: u8 = 0x40;
+
+/// How long to wait for the real response once an ECU says it's still working on it, unless
+/// configured otherwise.  This is the default P2* of ISO 14229-2.
+pub const DEFAULT_RESPONSE_PENDING_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// A client for issuing UDS requests to a single ECU over a diagnostic transport.
+pub struct UdsClient {
+    transport: Box<dyn DiagnosticTransport>,
+    target: Target,
+    response_pending_timeout: Duration,
+}
+
+impl UdsClient {
+    pub fn new(transport: Box<dyn DiagnosticTransport>, target: Target) -> Self {
+        Self {
+            transport,
+            target,
+            response_pending_timeout: DEFAULT_RESPONSE_PENDING_TIMEOUT,
+        }
+    }
+
+    /// Connects to the given ECU over the configured backend.
+    pub async fn connect(can_parameters: CANParameters, target: Target) -> Result<Self, UdsError> {
+        let response_pending_timeout = can_parameters.response_pending_timeout;
+        let transport = open_transport(can_parameters, Some(target)).await?;
+
+        let mut client = Self::new(transport, target);
+        client.response_pending_timeout = response_pending_timeout;
+        Ok(client)
+    }
+
+    /// Sends a request and waits for the matching positive response.
+    ///
+    /// Negative responses are surfaced as errors, with the exception of "response pending", which
+    /// causes us to keep waiting for the real response, for as long as the response pending
+    /// timeout allows.  The returned payload includes the positive response service ID.
+    pub async fn request(&mut self, payload: &[u8]) -> Result<Vec<u8>, UdsError> {
+        exchange(
+            self.transport.as_mut(),
+            self.target,
+            payload,
+            self.response_pending_timeout,
+        )
+        .await
+    }
+}
+
+/// Errors of a diagnostic protocol that frames its responses like UDS does, such as KWP2000.
+pub trait DiagnosticError:
+    From<TransportError> + From<SocketError> + From<InvalidResponse>
+{
+    /// Negative response codes of the protocol.
+    type Code: From<u8> + PartialEq + Copy;
+
+    /// Negative response code asking us to keep waiting for the real response.
+    const RESPONSE_PENDING: Self::Code;
+
+    fn negative_response(service_id: u8, code: Self::Code) -> Self;
+}
+
+impl DiagnosticError for UdsError {
+    type Code = NegativeResponseCode;
+
+    const RESPONSE_PENDING: NegativeResponseCode =
+        NegativeResponseCode::RequestCorrectlyReceivedResponsePending;
+
+    fn negative_response(service_id: u8, code: NegativeResponseCode) -> Self {
+        Self::NegativeResponse { service_id, code }
+    }
+}
+
+/// Sends a request to `target` and waits for the matching positive response.
+///
+/// Negative responses are surfaced as errors, except for "response pending", after which we keep
+/// waiting for up to `response_pending_timeout` for the real response.
+pub async fn exchange<E: DiagnosticError>(
+    transport: &mut dyn DiagnosticTransport,
+    target: Target,
+    payload: &[u8],
+    response_pending_timeout: Duration,
+) -> Result<Vec<u8>, E> {
+    let service_id = match payload.first() {
+        Some(service_id) => *service_id,
+        None => return Err(SocketError::PayloadLength(0).into()),
+    };
+    transport.send_request(target, payload).await?;
+
+    // Only bounded once the ECU asks us to wait, as until then, the transport's own read timeout
+    // applies.
+    let mut deadline = None;
+    loop {
+        let receive = transport.receive_response(target);
+        let response = match deadline {
+            Some(deadline) => timeout_at(deadline, receive)
+                .await
+                .map_err(|_| SocketError::Timeout(response_pending_timeout))??,
+            None => receive.await?,
+        };
+        match response.first().copied() {
+            Some(NEGATIVE_RESPONSE_SERVICE_ID) => {
+                ensure_length(&response, 3)?;
+                ensure_field(&response, 1, service_id)?;
+
+                let code: E::Code = response[2].into();
+                if code == E::RESPONSE_PENDING {
+                    trace!("service 0x{:02X}: response pending, waiting...", service_id);
+                    deadline.get_or_insert_with(|| Instant::now() + response_pending_timeout);
+                    continue;
+                }
+
+                return Err(E::negative_response(service_id, code));
+            }
+            Some(actual) => {
+                let expected = service_id.wrapping_add(POSITIVE_RESPONSE_OFFSET);
+                if actual != expected {
+                    return Err(InvalidResponse::from(InvalidResponseKind::ServiceId {
+                        actual,
+                        expected,
+                    })
+                    .into());
+                }
+
+                return Ok(response);
+            }
+            None => ensure_length(&response, 1)?,
+        }
+    }
+}
+
+/// Ensures that the given response is at least `expected` bytes long.
+pub fn ensure_length(data: &[u8], expected: usize) -> Result<(), InvalidResponse> {
+    if data.len() < expected {
+        return Err(InvalidResponseKind::PayloadSize {
+            actual: data.len(),
+            expected,
+        }
+        .into());
+    }
+
+    Ok(())
+}
+
+/// Ensures that the byte at `position` in the given response matches `expected`.
+pub fn ensure_field(data: &[u8], position: usize, expected: u8) -> Result<(), InvalidResponse> {
+    ensure_length(data, position + 1)?;
+
+    if data[position] != expected {
+        return Err(InvalidResponseKind::FieldValue {
+            field_id: FieldIdentifier::Position(position),
+            actual: FieldValue::Byte(data[position]),
+            expected: FieldValue::Byte(expected),
+        }
+        .into());
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::protocol::{
+        transport::mock::{standard_target, FakeEcu},
+        uds::services::ReadDataService,
+    };
+
+    use super::*;
+
+    #[tokio::test]
+    async fn waits_out_pending_responses() {
+        let ecu = FakeEcu::new(standard_target(0x7E0)).respond(
+            &[0x31, 0x01, 0xFF, 0x00],
+            &[
+                &[0x7F, 0x31, 0x78],
+                &[0x7F, 0x31, 0x78],
+                &[0x71, 0x01, 0xFF, 0x00],
+            ],
+        );
+        let mut client = ecu.connect();
+
+        assert_eq!(
+            client.request(&[0x31, 0x01, 0xFF, 0x00]).await.unwrap(),
+            vec![0x71, 0x01, 0xFF, 0x00]
+        );
+    }
+
+    #[tokio::test]
+    async fn gives_up_on_endlessly_pending_responses() {
+        let ecu = FakeEcu::new(standard_target(0x7E0))
+            .respond(&[0x31, 0x01, 0xFF, 0x00], &[&[0x7F, 0x31, 0x78]; 8]);
+        let mut client = ecu.connect();
+        client.response_pending_timeout = Duration::from_millis(20);
+
+        // Gives up well before the transport's own read timeout would.
+        assert!(matches!(
+            client.request(&[0x31, 0x01, 0xFF, 0x00]).await,
+            Err(UdsError::Io(SocketError::Timeout(timeout))) if timeout == Duration::from_millis(20)
+        ));
+    }
+
+    #[tokio::test]
+    async fn rejects_empty_requests() {
+        let mut client = FakeEcu::new(standard_target(0x7E0)).connect();
+
+        assert!(matches!(
+            client.request(&[]).await,
+            Err(UdsError::Io(SocketError::PayloadLength(0)))
+        ));
+    }
+
+    #[tokio::test]
+    async fn surfaces_negative_responses() {
+        let ecu = FakeEcu::new(standard_target(0x7E0)).reject(
+            &[0x27, 0x01],
+            NegativeResponseCode::RequiredTimeDelayNotExpired,
+        );
+        let mut client = ecu.connect();
+
+        assert!(matches!(
+            client.request(&[0x27, 0x01]).await,
+            Err(UdsError::NegativeResponse {
+                service_id: 0x27,
+                code: NegativeResponseCode::RequiredTimeDelayNotExpired,
+            })
+        ));
+        assert!(matches!(
+            client.request(&[0x85, 0x02]).await,
+            Err(UdsError::NegativeResponse {
+                service_id: 0x85,
+                code: NegativeResponseCode::ServiceNotSupported,
+            })
+        ));
+    }
+
+    #[tokio::test]
+    async fn rejects_mismatched_responses() {
+        let ecu = FakeEcu::new(standard_target(0x7E0))
+            .respond(&[0x10, 0x03], &[&[0x51, 0x03]])
+            .respond(&[0x22, 0xF1, 0x90], &[&[0x62, 0xF1, 0x91, 0x57]])
+            .respond(&[0x3E, 0x00], &[]);
+        let mut client = ecu.connect();
+
+        assert!(matches!(
+            client.request(&[0x10, 0x03]).await,
+            Err(UdsError::InvalidResponse(_))
+        ));
+        assert!(matches!(
+            ReadDataService::new(&mut client).read(0xF190).await,
+            Err(UdsError::InvalidResponse(_))
+        ));
+
+        assert!(client
+            .request(&[0x3E, 0x00])
+            .await
+            .unwrap_err()
+            .is_timeout());
+    }
+}
@@ -0,0 +1,201 @@
+use core::fmt;
+
+use thiserror::Error;
+
+use crate::{
+    common::error::InvalidResponse,
+    protocol::{
+        can::error::{SocketBuildError, SocketError},
+        transport::TransportError,
+    },
+};
+
+/// Negative response codes, as defined by ISO 14229-1.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NegativeResponseCode {
+    GeneralReject,
+    ServiceNotSupported,
+    SubFunctionNotSupported,
+    IncorrectMessageLengthOrInvalidFormat,
+    ResponseTooLong,
+    BusyRepeatRequest,
+    ConditionsNotCorrect,
+    RequestSequenceError,
+    NoResponseFromSubnetComponent,
+    FailurePreventsExecutionOfRequestedAction,
+    RequestOutOfRange,
+    SecurityAccessDenied,
+    AuthenticationRequired,
+    InvalidKey,
+    ExceededNumberOfAttempts,
+    RequiredTimeDelayNotExpired,
+    SecureDataTransmissionRequired,
+    SecureDataTransmissionNotAllowed,
+    SecureDataVerificationFailed,
+    CertificateVerificationFailedInvalidTimePeriod,
+    CertificateVerificationFailedInvalidSignature,
+    CertificateVerificationFailedInvalidChainOfTrust,
+    CertificateVerificationFailedInvalidType,
+    CertificateVerificationFailedInvalidFormat,
+    CertificateVerificationFailedInvalidContent,
+    CertificateVerificationFailedInvalidScope,
+    CertificateVerificationFailedInvalidCertificate,
+    OwnershipVerificationFailed,
+    ChallengeCalculationFailed,
+    SettingAccessRightsFailed,
+    SessionKeyCreationDerivationFailed,
+    ConfigurationDataUsageFailed,
+    DeAuthenticationFailed,
+    UploadDownloadNotAccepted,
+    TransferDataSuspended,
+    GeneralProgrammingFailure,
+    WrongBlockSequenceCounter,
+    RequestCorrectlyReceivedResponsePending,
+    SubFunctionNotSupportedInActiveSession,
+    ServiceNotSupportedInActiveSession,
+    Other(u8),
+}
+
+impl NegativeResponseCode {
+    pub fn as_byte(&self) -> u8 {
+        match self {
+            Self::GeneralReject => 0x10,
+            Self::ServiceNotSupported => 0x11,
+            Self::SubFunctionNotSupported => 0x12,
+            Self::IncorrectMessageLengthOrInvalidFormat => 0x13,
+            Self::ResponseTooLong => 0x14,
+            Self::BusyRepeatRequest => 0x21,
+            Self::ConditionsNotCorrect => 0x22,
+            Self::RequestSequenceError => 0x24,
+            Self::NoResponseFromSubnetComponent => 0x25,
+            Self::FailurePreventsExecutionOfRequestedAction => 0x26,
+            Self::RequestOutOfRange => 0x31,
+            Self::SecurityAccessDenied => 0x33,
+            Self::AuthenticationRequired => 0x34,
+            Self::InvalidKey => 0x35,
+            Self::ExceededNumberOfAttempts => 0x36,
+            Self::RequiredTimeDelayNotExpired => 0x37,
+            Self::SecureDataTransmissionRequired => 0x38,
+            Self::SecureDataTransmissionNotAllowed => 0x39,
+            Self::SecureDataVerificationFailed => 0x3A,
+            Self::CertificateVerificationFailedInvalidTimePeriod => 0x50,
+            Self::CertificateVerificationFailedInvalidSignature => 0x51,
+            Self::CertificateVerificationFailedInvalidChainOfTrust => 0x52,
+            Self::CertificateVerificationFailedInvalidType => 0x53,
+            Self::CertificateVerificationFailedInvalidFormat => 0x54,
+            Self::CertificateVerificationFailedInvalidContent => 0x55,
+            Self::CertificateVerificationFailedInvalidScope => 0x56,
+            Self::CertificateVerificationFailedInvalidCertificate => 0x57,
+            Self::OwnershipVerificationFailed => 0x58,
+            Self::ChallengeCalculationFailed => 0x59,
+            Self::SettingAccessRightsFailed => 0x5A,
+            Self::SessionKeyCreationDerivationFailed => 0x5B,
+            Self::ConfigurationDataUsageFailed => 0x5C,
+            Self::DeAuthenticationFailed => 0x5D,
+            Self::UploadDownloadNotAccepted => 0x70,
+            Self::TransferDataSuspended => 0x71,
+            Self::GeneralProgrammingFailure => 0x72,
+            Self::WrongBlockSequenceCounter => 0x73,
+            Self::RequestCorrectlyReceivedResponsePending => 0x78,
+            Self::SubFunctionNotSupportedInActiveSession => 0x7E,
+            Self::ServiceNotSupportedInActiveSession => 0x7F,
+            Self::Other(b) => *b,
+        }
+    }
+}
+
+impl From<u8> for NegativeResponseCode {
+    fn from(b: u8) -> Self {
+        match b {
+            0x10 => Self::GeneralReject,
+            0x11 => Self::ServiceNotSupported,
+            0x12 => Self::SubFunctionNotSupported,
+            0x13 => Self::IncorrectMessageLengthOrInvalidFormat,
+            0x14 => Self::ResponseTooLong,
+            0x21 => Self::BusyRepeatRequest,
+            0x22 => Self::ConditionsNotCorrect,
+            0x24 => Self::RequestSequenceError,
+            0x25 => Self::NoResponseFromSubnetComponent,
+            0x26 => Self::FailurePreventsExecutionOfRequestedAction,
+            0x31 => Self::RequestOutOfRange,
+            0x33 => Self::SecurityAccessDenied,
+            0x34 => Self::AuthenticationRequired,
+            0x35 => Self::InvalidKey,
+            0x36 => Self::ExceededNumberOfAttempts,
+            0x37 => Self::RequiredTimeDelayNotExpired,
+            0x38 => Self::SecureDataTransmissionRequired,
+            0x39 => Self::SecureDataTransmissionNotAllowed,
+            0x3A => Self::SecureDataVerificationFailed,
+            0x50 => Self::CertificateVerificationFailedInvalidTimePeriod,
+            0x51 => Self::CertificateVerificationFailedInvalidSignature,
+            0x52 => Self::CertificateVerificationFailedInvalidChainOfTrust,
+            0x53 => Self::CertificateVerificationFailedInvalidType,
+            0x54 => Self::CertificateVerificationFailedInvalidFormat,
+            0x55 => Self::CertificateVerificationFailedInvalidContent,
+            0x56 => Self::CertificateVerificationFailedInvalidScope,
+            0x57 => Self::CertificateVerificationFailedInvalidCertificate,
+            0x58 => Self::OwnershipVerificationFailed,
+            0x59 => Self::ChallengeCalculationFailed,
+            0x5A => Self::SettingAccessRightsFailed,
+            0x5B => Self::SessionKeyCreationDerivationFailed,
+            0x5C => Self::ConfigurationDataUsageFailed,
+            0x5D => Self::DeAuthenticationFailed,
+            0x70 => Self::UploadDownloadNotAccepted,
+            0x71 => Self::TransferDataSuspended,
+            0x72 => Self::GeneralProgrammingFailure,
+            0x73 => Self::WrongBlockSequenceCounter,
+            0x78 => Self::RequestCorrectlyReceivedResponsePending,
+            0x7E => Self::SubFunctionNotSupportedInActiveSession,
+            0x7F => Self::ServiceNotSupportedInActiveSession,
+            b => Self::Other(b),
+        }
+    }
+}
+
+impl fmt::Display for NegativeResponseCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Other(b) => write!(f, "unknown (0x{:02X})", b),
+            code => write!(f, "{:?} (0x{:02X})", code, code.as_byte()),
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum UdsError {
+    #[error("failed to initialize socket: {0}")]
+    Initialization(#[from] SocketBuildError),
+    #[error("socket error while querying service: {0}")]
+    Io(#[from] SocketError),
+    #[error(transparent)]
+    InvalidResponse(#[from] InvalidResponse),
+    #[error("service 0x{service_id:02X} responded negatively: {code}")]
+    NegativeResponse {
+        service_id: u8,
+        code: NegativeResponseCode,
+    },
+}
+
+impl From<TransportError> for UdsError {
+    fn from(e: TransportError) -> Self {
+        match e {
+            TransportError::Initialization(e) => Self::Initialization(e),
+            TransportError::Io(e) => Self::Io(e),
+        }
+    }
+}
+
+impl UdsError {
+    /// Gets the negative response code, if this error represents a negative response.
+    pub fn negative_response_code(&self) -> Option<NegativeResponseCode> {
+        match self {
+            Self::NegativeResponse { code, .. } => Some(*code),
+            _ => None,
+        }
+    }
+
+    /// Whether or not this error represents the ECU not responding in time.
+    pub fn is_timeout(&self) -> bool {
+        matches!(self, Self::Io(SocketError::Timeout(_)))
+    }
+}
@@ -0,0 +1,180 @@
+use core::fmt;
+
+use clap::ArgEnum;
+use rand::{seq::SliceRandom, Rng, RngCore, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+
+/// Services which take a sub-function as their first parameter.
+const SUB_FUNCTION_SERVICES: &[u8] = &[0x19, 0x27, 0x29, 0x2C, 0x31, 0x3E, 0x86, 0x87];
+
+/// Services which take no sub-function.
+const PARAMETER_SERVICES: &[u8] = &[
+    0x14, 0x22, 0x23, 0x24, 0x2A, 0x2E, 0x2F, 0x34, 0x35, 0x36, 0x37, 0x38, 0x3D, 0x84,
+];
+
+/// Services that are never generated, since even a valid request can knock the ECU offline in a
+/// way that's indistinguishable from a crash: ECUReset, CommunicationControl, and
+/// ControlDTCSetting.  DiagnosticSessionControl is left out too, as a valid request would switch
+/// sessions, which looks just like the ECU resetting.
+const EXCLUDED_SERVICES: &[u8] = &[0x10, 0x11, 0x28, 0x85];
+
+/// Largest payload a classic ISO-TP transfer can carry.
+const MAX_ISOTP_PAYLOAD_LENGTH: usize = 4095;
+
+/// Largest payload generated by random length mutations.
+const MAX_RANDOM_PAYLOAD_LENGTH: usize = 64;
+
+/// Smallest payload generated by oversized mutations.
+const MIN_OVERSIZED_PAYLOAD_LENGTH: usize = 256;
+
+#[derive(ArgEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Mutation {
+    /// Random service ID followed by a random number of random bytes.
+    RandomLength,
+    /// Valid service ID with a random, likely invalid, sub-function.
+    InvalidSubFunction,
+    /// Valid service ID followed by a payload larger than most ECUs will accept.
+    Oversized,
+}
+
+impl fmt::Display for Mutation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::RandomLength => write!(f, "random-length"),
+            Self::InvalidSubFunction => write!(f, "invalid-sub-function"),
+            Self::Oversized => write!(f, "oversized"),
+        }
+    }
+}
+
+/// A single generated fuzz case.
+pub struct FuzzCase {
+    pub index: u64,
+    pub mutation: Mutation,
+    pub payload: Vec<u8>,
+}
+
+/// Generates reproducible fuzz cases.
+///
+/// Every case is generated from its own stream of the seeded RNG, so any individual case can be
+/// regenerated from the seed and its index alone, without replaying every case before it.
+pub struct FuzzCaseGenerator {
+    seed: u64,
+    mutations: Vec<Mutation>,
+}
+
+impl FuzzCaseGenerator {
+    pub fn new(seed: u64, mutations: Vec<Mutation>) -> Self {
+        Self { seed, mutations }
+    }
+
+    pub fn generate(&self, index: u64) -> FuzzCase {
+        let mut rng = ChaCha8Rng::seed_from_u64(self.seed);
+        rng.set_stream(index);
+
+        let mutation = *self
+            .mutations
+            .choose(&mut rng)
+            .expect("mutations should never be empty");
+        let payload = match mutation {
+            Mutation::RandomLength => {
+                let service_id = loop {
+                    let service_id = rng.gen::<u8>();
+                    if !EXCLUDED_SERVICES.contains(&service_id) {
+                        break service_id;
+                    }
+                };
+
+                let length = rng.gen_range(0..MAX_RANDOM_PAYLOAD_LENGTH);
+                let mut payload = vec![service_id];
+                payload.extend((0..length).map(|_| rng.gen::<u8>()));
+                payload
+            }
+            Mutation::InvalidSubFunction => {
+                let service_id = *SUB_FUNCTION_SERVICES
+                    .choose(&mut rng)
+                    .expect("should never be empty");
+                let length = rng.gen_range(0..8);
+                let mut payload = vec![service_id, rng.gen::<u8>()];
+                payload.extend((0..length).map(|_| rng.gen::<u8>()));
+                payload
+            }
+            Mutation::Oversized => {
+                let candidates = SUB_FUNCTION_SERVICES
+                    .iter()
+                    .chain(PARAMETER_SERVICES)
+                    .copied()
+                    .collect::<Vec<_>>();
+                let service_id = *candidates.choose(&mut rng).expect("should never be empty");
+                let length = rng.gen_range(MIN_OVERSIZED_PAYLOAD_LENGTH..=MAX_ISOTP_PAYLOAD_LENGTH);
+                let mut payload = vec![0; length];
+                payload[0] = service_id;
+                rng.fill_bytes(&mut payload[1..]);
+                payload
+            }
+        };
+
+        FuzzCase {
+            index,
+            mutation,
+            payload,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ALL_MUTATIONS: [Mutation; 3] = [
+        Mutation::RandomLength,
+        Mutation::InvalidSubFunction,
+        Mutation::Oversized,
+    ];
+
+    #[test]
+    fn cases_are_reproducible_from_their_index() {
+        let generator = FuzzCaseGenerator::new(42, ALL_MUTATIONS.to_vec());
+        let forwards = (0..64)
+            .map(|index| generator.generate(index).payload)
+            .collect::<Vec<_>>();
+        let backwards = (0..64)
+            .rev()
+            .map(|index| generator.generate(index).payload)
+            .collect::<Vec<_>>();
+
+        assert!(forwards.iter().eq(backwards.iter().rev()));
+        assert_ne!(
+            generator.generate(0).payload,
+            FuzzCaseGenerator::new(43, ALL_MUTATIONS.to_vec())
+                .generate(0)
+                .payload
+        );
+    }
+
+    #[test]
+    fn cases_follow_their_mutation() {
+        let generator = FuzzCaseGenerator::new(7, ALL_MUTATIONS.to_vec());
+        for index in 0..1024 {
+            let case = generator.generate(index);
+            let service_id = case.payload[0];
+            assert!(!EXCLUDED_SERVICES.contains(&service_id));
+
+            match case.mutation {
+                Mutation::RandomLength => assert!(case.payload.len() <= MAX_RANDOM_PAYLOAD_LENGTH),
+                Mutation::InvalidSubFunction => {
+                    assert!(SUB_FUNCTION_SERVICES.contains(&service_id));
+                    assert!(case.payload.len() >= 2);
+                }
+                Mutation::Oversized => assert!((MIN_OVERSIZED_PAYLOAD_LENGTH
+                    ..=MAX_ISOTP_PAYLOAD_LENGTH)
+                    .contains(&case.payload.len())),
+            }
+        }
+
+        let generator = FuzzCaseGenerator::new(7, vec![Mutation::InvalidSubFunction]);
+        assert!(
+            (0..64).all(|index| generator.generate(index).mutation == Mutation::InvalidSubFunction)
+        );
+    }
+}
@@ -0,0 +1,4 @@
+pub mod client;
+pub mod error;
+pub mod fuzz;
+pub mod services;
@@ -0,0 +1,117 @@
+use std::{fs, io, path::Path};
+
+use p256::{
+    ecdsa::{
+        signature::{Signer, Verifier},
+        Signature, SigningKey, VerifyingKey,
+    },
+    pkcs8::DecodePrivateKey,
+    SecretKey,
+};
+use thiserror::Error;
+use x509_parser::parse_x509_certificate;
+
+const PEM_PREFIX: &[u8] = b"-----BEGIN";
+const SEC1_PRIVATE_KEY_TAG: &str = "EC PRIVATE KEY";
+
+#[derive(Debug, Error)]
+pub enum CredentialError {
+    #[error("I/O error while loading credentials: {0}")]
+    Io(#[from] io::Error),
+    #[error("failed to decode PEM: {0}")]
+    Pem(#[from] pem::PemError),
+    #[error("unsupported private key: {0}")]
+    UnsupportedKey(String),
+    #[error("invalid certificate: {0}")]
+    InvalidCertificate(String),
+    #[error("certificate is not valid at this time")]
+    OutsideValidityPeriod,
+    #[error("invalid signature: {0}")]
+    InvalidSignature(#[from] p256::ecdsa::Error),
+}
+
+/// A certificate, and the matching private key, used to authenticate with an ECU.
+///
+/// Both can be loaded from either PEM or DER files.  Only P-256 ECDSA keys in PKCS#8 form are
+/// supported.
+pub struct Credentials {
+    certificate: Vec<u8>,
+    signing_key: SigningKey,
+}
+
+impl Credentials {
+    pub fn load(certificate_path: &Path, private_key_path: &Path) -> Result<Self, CredentialError> {
+        let certificate = read_der(certificate_path)?;
+
+        // Make sure the certificate is actually a certificate before we send it anywhere.
+        parse_x509_certificate(&certificate)
+            .map_err(|e| CredentialError::InvalidCertificate(e.to_string()))?;
+
+        let private_key = read_der(private_key_path)?;
+        let secret_key = SecretKey::from_pkcs8_der(&private_key)
+            .map_err(|e| CredentialError::UnsupportedKey(e.to_string()))?;
+
+        Ok(Self {
+            certificate,
+            signing_key: SigningKey::from(secret_key),
+        })
+    }
+
+    /// Gets the certificate, in DER form.
+    pub fn certificate(&self) -> &[u8] {
+        &self.certificate
+    }
+
+    /// Proves ownership of our certificate by signing the given challenge.
+    ///
+    /// The signature is returned in DER form.
+    pub fn prove_ownership(&self, challenge: &[u8]) -> Vec<u8> {
+        let signature: Signature = self.signing_key.sign(challenge);
+        signature.to_der().as_bytes().to_vec()
+    }
+}
+
+/// Verifies that the owner of `certificate` signed `challenge`, producing `proof`.
+pub fn verify_ownership(
+    certificate: &[u8],
+    challenge: &[u8],
+    proof: &[u8],
+) -> Result<(), CredentialError> {
+    let (_, certificate) = parse_x509_certificate(certificate)
+        .map_err(|e| CredentialError::InvalidCertificate(e.to_string()))?;
+    let public_key = &*certificate.public_key().subject_public_key.data;
+
+    let verifying_key = VerifyingKey::from_sec1_bytes(public_key)?;
+    let signature = Signature::from_der(proof)?;
+    verifying_key.verify(challenge, &signature)?;
+
+    Ok(())
+}
+
+/// Verifies that `certificate` is a certificate, and that it is valid right now.
+pub fn verify_validity(certificate: &[u8]) -> Result<(), CredentialError> {
+    let (_, certificate) = parse_x509_certificate(certificate)
+        .map_err(|e| CredentialError::InvalidCertificate(e.to_string()))?;
+    if !certificate.validity().is_valid() {
+        return Err(CredentialError::OutsideValidityPeriod);
+    }
+
+    Ok(())
+}
+
+fn read_der(path: &Path) -> Result<Vec<u8>, CredentialError> {
+    let contents = fs::read(path)?;
+    if !contents.starts_with(PEM_PREFIX) {
+        return Ok(contents);
+    }
+
+    let pem = pem::parse(&contents)?;
+    if pem.tag == SEC1_PRIVATE_KEY_TAG {
+        return Err(CredentialError::UnsupportedKey(
+            "SEC1 keys must be converted to PKCS#8 first (`openssl pkcs8 -topk8 -nocrypt`)"
+                .to_string(),
+        ));
+    }
+
+    Ok(pem.contents)
+}
@@ -0,0 +1,84 @@
+mod credentials;
+mod service;
+use core::fmt;
+
+use clap::ArgEnum;
+use thiserror::Error;
+
+use crate::protocol::uds::error::UdsError;
+
+pub use self::{
+    credentials::{verify_ownership, verify_validity, CredentialError, Credentials},
+    service::AuthenticationService,
+};
+
+const AUTHENTICATION_SERVICE_ID: u8 = 0x29;
+
+const DE_AUTHENTICATE: u8 = 0x00;
+const VERIFY_CERTIFICATE_UNIDIRECTIONAL: u8 = 0x01;
+const VERIFY_CERTIFICATE_BIDIRECTIONAL: u8 = 0x02;
+const PROOF_OF_OWNERSHIP: u8 = 0x03;
+const AUTHENTICATION_CONFIGURATION: u8 = 0x08;
+
+/// Size of the challenge we send to the ECU when doing bidirectional authentication.
+const CLIENT_CHALLENGE_LENGTH: usize = 32;
+
+/// Whether only the tester proves its identity, or both the tester and the ECU do.
+#[derive(ArgEnum, Clone, Copy, Debug)]
+pub enum AuthenticationMode {
+    Unidirectional,
+    Bidirectional,
+}
+
+/// Authentication return parameters, as defined by ISO 14229-1:2020.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AuthenticationReturnParameter {
+    RequestAccepted,
+    GeneralReject,
+    ConfigurationCertificateExchange,
+    ConfigurationAsymmetricChallengeResponse,
+    ConfigurationSymmetricChallengeResponse,
+    DeAuthenticationSuccessful,
+    CertificateVerifiedOwnershipVerificationNecessary,
+    OwnershipVerifiedAuthenticationComplete,
+    CertificateVerified,
+    Other(u8),
+}
+
+impl From<u8> for AuthenticationReturnParameter {
+    fn from(b: u8) -> Self {
+        match b {
+            0x00 => Self::RequestAccepted,
+            0x01 => Self::GeneralReject,
+            0x02 => Self::ConfigurationCertificateExchange,
+            0x03 => Self::ConfigurationAsymmetricChallengeResponse,
+            0x04 => Self::ConfigurationSymmetricChallengeResponse,
+            0x10 => Self::DeAuthenticationSuccessful,
+            0x11 => Self::CertificateVerifiedOwnershipVerificationNecessary,
+            0x12 => Self::OwnershipVerifiedAuthenticationComplete,
+            0x13 => Self::CertificateVerified,
+            b => Self::Other(b),
+        }
+    }
+}
+
+impl fmt::Display for AuthenticationReturnParameter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Other(b) => write!(f, "unknown (0x{:02X})", b),
+            parameter => write!(f, "{:?}", parameter),
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum AuthenticationError {
+    #[error(transparent)]
+    Uds(#[from] UdsError),
+    #[error(transparent)]
+    Credentials(#[from] CredentialError),
+    #[error("ECU did not accept authentication: {0}")]
+    Rejected(AuthenticationReturnParameter),
+    #[error("ECU failed to prove ownership of its certificate: {0}")]
+    ServerOwnership(CredentialError),
+}
@@ -0,0 +1,361 @@
+use rand::RngCore;
+use tracing::debug;
+
+use crate::protocol::uds::{
+    client::{ensure_field, ensure_length, UdsClient},
+    error::UdsError,
+};
+
+use super::{
+    verify_ownership, AuthenticationError, AuthenticationMode, AuthenticationReturnParameter,
+    Credentials, AUTHENTICATION_CONFIGURATION, AUTHENTICATION_SERVICE_ID, CLIENT_CHALLENGE_LENGTH,
+    DE_AUTHENTICATE, PROOF_OF_OWNERSHIP, VERIFY_CERTIFICATE_BIDIRECTIONAL,
+    VERIFY_CERTIFICATE_UNIDIRECTIONAL,
+};
+
+/// Communication configuration sent when verifying certificates.  We don't negotiate secure
+/// communication, so this is always zero.
+const COMMUNICATION_CONFIGURATION: u8 = 0x00;
+
+/// Response to a certificate verification request.
+pub struct CertificateVerification {
+    pub return_parameter: AuthenticationReturnParameter,
+    pub server_challenge: Vec<u8>,
+    pub server_certificate: Option<Vec<u8>>,
+    pub server_proof_of_ownership: Option<Vec<u8>>,
+    pub server_ephemeral_public_key: Vec<u8>,
+}
+
+/// Response to a proof of ownership request.
+pub struct OwnershipVerification {
+    pub return_parameter: AuthenticationReturnParameter,
+    pub session_key_info: Vec<u8>,
+}
+
+pub struct AuthenticationService<'a> {
+    client: &'a mut UdsClient,
+}
+
+impl<'a> AuthenticationService<'a> {
+    pub fn new(client: &'a mut UdsClient) -> Self {
+        Self { client }
+    }
+
+    /// Asks the ECU which authentication scheme it supports.
+    pub async fn configuration(&mut self) -> Result<AuthenticationReturnParameter, UdsError> {
+        self.send_simple(AUTHENTICATION_CONFIGURATION).await
+    }
+
+    /// Ends the authenticated state, returning to the unauthenticated default.
+    pub async fn deauthenticate(&mut self) -> Result<AuthenticationReturnParameter, UdsError> {
+        self.send_simple(DE_AUTHENTICATE).await
+    }
+
+    /// Sends our certificate to the ECU for verification.
+    ///
+    /// When verifying bidirectionally, the ECU also sends back its own certificate, along with
+    /// proof of ownership over our challenge.
+    pub async fn verify_certificate(
+        &mut self,
+        mode: AuthenticationMode,
+        certificate: &[u8],
+        challenge: &[u8],
+    ) -> Result<CertificateVerification, UdsError> {
+        let sub_function = match mode {
+            AuthenticationMode::Unidirectional => VERIFY_CERTIFICATE_UNIDIRECTIONAL,
+            AuthenticationMode::Bidirectional => VERIFY_CERTIFICATE_BIDIRECTIONAL,
+        };
+
+        let mut payload = vec![
+            AUTHENTICATION_SERVICE_ID,
+            sub_function,
+            COMMUNICATION_CONFIGURATION,
+        ];
+        push_length_prefixed(&mut payload, certificate);
+        push_length_prefixed(&mut payload, challenge);
+
+        let response = self.client.request(&payload).await?;
+        let return_parameter = parse_return_parameter(&response, sub_function)?;
+
+        let mut idx = 3;
+        let server_challenge = read_length_prefixed(&response, &mut idx)?;
+        let (server_certificate, server_proof_of_ownership) = match mode {
+            AuthenticationMode::Unidirectional => (None, None),
+            AuthenticationMode::Bidirectional => (
+                Some(read_length_prefixed(&response, &mut idx)?),
+                Some(read_length_prefixed(&response, &mut idx)?),
+            ),
+        };
+        let server_ephemeral_public_key = read_length_prefixed(&response, &mut idx)?;
+
+        Ok(CertificateVerification {
+            return_parameter,
+            server_challenge,
+            server_certificate,
+            server_proof_of_ownership,
+            server_ephemeral_public_key,
+        })
+    }
+
+    /// Sends proof of ownership over our certificate to the ECU.
+    pub async fn proof_of_ownership(
+        &mut self,
+        proof: &[u8],
+        ephemeral_public_key: &[u8],
+    ) -> Result<OwnershipVerification, UdsError> {
+        let mut payload = vec![AUTHENTICATION_SERVICE_ID, PROOF_OF_OWNERSHIP];
+        push_length_prefixed(&mut payload, proof);
+        push_length_prefixed(&mut payload, ephemeral_public_key);
+
+        let response = self.client.request(&payload).await?;
+        let return_parameter = parse_return_parameter(&response, PROOF_OF_OWNERSHIP)?;
+
+        let mut idx = 3;
+        let session_key_info = read_length_prefixed(&response, &mut idx)?;
+
+        Ok(OwnershipVerification {
+            return_parameter,
+            session_key_info,
+        })
+    }
+
+    /// Runs the full certificate exchange with the ECU.
+    ///
+    /// When authenticating bidirectionally, the ECU's proof of ownership is verified against its
+    /// certificate before we prove ownership of our own certificate.  Session key establishment is
+    /// not supported, so no ephemeral public key is sent.
+    pub async fn authenticate(
+        &mut self,
+        mode: AuthenticationMode,
+        credentials: &Credentials,
+    ) -> Result<AuthenticationReturnParameter, AuthenticationError> {
+        // The client challenge is only meaningful when the ECU has to prove itself to us.
+        let challenge = match mode {
+            AuthenticationMode::Unidirectional => Vec::new(),
+            AuthenticationMode::Bidirectional => {
+                let mut challenge = vec![0; CLIENT_CHALLENGE_LENGTH];
+                rand::thread_rng().fill_bytes(&mut challenge);
+                challenge
+            }
+        };
+
+        let verification = self
+            .verify_certificate(mode, credentials.certificate(), &challenge)
+            .await?;
+        if verification.return_parameter
+            != AuthenticationReturnParameter::CertificateVerifiedOwnershipVerificationNecessary
+        {
+            return Err(AuthenticationError::Rejected(verification.return_parameter));
+        }
+        debug!(
+            "ECU verified our certificate. (ephemeral public key: {} bytes)",
+            verification.server_ephemeral_public_key.len()
+        );
+
+        if let (Some(certificate), Some(proof)) = (
+            &verification.server_certificate,
+            &verification.server_proof_of_ownership,
+        ) {
+            verify_ownership(certificate, &challenge, proof)
+                .map_err(AuthenticationError::ServerOwnership)?;
+            debug!("Verified ECU proof of ownership.");
+        }
+
+        let proof = credentials.prove_ownership(&verification.server_challenge);
+        let ownership = self.proof_of_ownership(&proof, &[]).await?;
+        debug!(
+            "ECU responded to proof of ownership. (session key info: {} bytes)",
+            ownership.session_key_info.len()
+        );
+        match ownership.return_parameter {
+            AuthenticationReturnParameter::OwnershipVerifiedAuthenticationComplete => {
+                Ok(ownership.return_parameter)
+            }
+            parameter => Err(AuthenticationError::Rejected(parameter)),
+        }
+    }
+
+    async fn send_simple(
+        &mut self,
+        sub_function: u8,
+    ) -> Result<AuthenticationReturnParameter, UdsError> {
+        let response = self
+            .client
+            .request(&[AUTHENTICATION_SERVICE_ID, sub_function])
+            .await?;
+
+        parse_return_parameter(&response, sub_function)
+    }
+}
+
+fn parse_return_parameter(
+    response: &[u8],
+    sub_function: u8,
+) -> Result<AuthenticationReturnParameter, UdsError> {
+    ensure_field(response, 1, sub_function)?;
+    ensure_length(response, 3)?;
+
+    Ok(AuthenticationReturnParameter::from(response[2]))
+}
+
+fn push_length_prefixed(payload: &mut Vec<u8>, data: &[u8]) {
+    payload.extend_from_slice(&(data.len() as u16).to_be_bytes());
+    payload.extend_from_slice(data);
+}
+
+fn read_length_prefixed(response: &[u8], idx: &mut usize) -> Result<Vec<u8>, UdsError> {
+    ensure_length(response, *idx + 2)?;
+    let length = u16::from_be_bytes([response[*idx], response[*idx + 1]]) as usize;
+    *idx += 2;
+
+    ensure_length(response, *idx + length)?;
+    let data = response[*idx..*idx + length].to_vec();
+    *idx += length;
+
+    Ok(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::{Path, PathBuf};
+
+    use crate::{
+        protocol::{
+            can::mock::MockBus,
+            transport::mock::{spawn_responder, standard_target, FakeEcu, MockTransport},
+            uds::error::NegativeResponseCode,
+        },
+        simulator::{EcuProfile, SimulatedEcu},
+    };
+
+    use super::*;
+
+    fn fixture(name: &str) -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("fixtures/authentication")
+            .join(name)
+    }
+
+    fn credentials(certificate: &str, private_key: &str) -> Credentials {
+        Credentials::load(&fixture(certificate), &fixture(private_key)).unwrap()
+    }
+
+    /// Connects to a simulated ECU which authenticates with the given credentials.
+    fn connect(ecu_credentials: Credentials) -> UdsClient {
+        let bus = MockBus::new();
+        let target = standard_target(0x7E0);
+        let mut profile = EcuProfile::empty("engine", target);
+        profile.authentication = Some(ecu_credentials);
+
+        let mut ecu = SimulatedEcu::new(profile);
+        spawn_responder(&bus, target, move |request, is_functional| {
+            ecu.answer(request, is_functional)
+        });
+        UdsClient::new(Box::new(MockTransport::new(&bus)), target)
+    }
+
+    fn negative_response_code(
+        result: Result<AuthenticationReturnParameter, AuthenticationError>,
+    ) -> Option<NegativeResponseCode> {
+        match result {
+            Err(AuthenticationError::Uds(e)) => e.negative_response_code(),
+            _ => None,
+        }
+    }
+
+    #[tokio::test]
+    async fn authenticates_in_both_directions() {
+        let mut client = connect(credentials("ecu.pem", "ecu-key.pem"));
+        let mut service = AuthenticationService::new(&mut client);
+        let tester = credentials("tester.pem", "tester-key.pem");
+
+        assert_eq!(
+            service.configuration().await.unwrap(),
+            AuthenticationReturnParameter::ConfigurationCertificateExchange
+        );
+        for mode in [
+            AuthenticationMode::Unidirectional,
+            AuthenticationMode::Bidirectional,
+        ] {
+            assert_eq!(
+                service.authenticate(mode, &tester).await.unwrap(),
+                AuthenticationReturnParameter::OwnershipVerifiedAuthenticationComplete
+            );
+        }
+        assert_eq!(
+            service.deauthenticate().await.unwrap(),
+            AuthenticationReturnParameter::DeAuthenticationSuccessful
+        );
+    }
+
+    #[tokio::test]
+    async fn rejects_bad_signatures_and_expired_certificates() {
+        let mut client = connect(credentials("ecu.pem", "ecu-key.pem"));
+        let mut service = AuthenticationService::new(&mut client);
+
+        // Signed with a key that doesn't belong to the certificate.
+        let impostor = credentials("tester.pem", "ecu-key.pem");
+        assert_eq!(
+            negative_response_code(
+                service
+                    .authenticate(AuthenticationMode::Unidirectional, &impostor)
+                    .await
+            ),
+            Some(NegativeResponseCode::OwnershipVerificationFailed)
+        );
+
+        let expired = credentials("expired.pem", "tester-key.pem");
+        assert_eq!(
+            negative_response_code(
+                service
+                    .authenticate(AuthenticationMode::Unidirectional, &expired)
+                    .await
+            ),
+            Some(NegativeResponseCode::CertificateVerificationFailedInvalidTimePeriod)
+        );
+    }
+
+    #[tokio::test]
+    async fn verifies_the_ecu_proof_of_ownership() {
+        let mut client = connect(credentials("ecu.pem", "tester-key.pem"));
+        let mut service = AuthenticationService::new(&mut client);
+
+        let tester = credentials("tester.pem", "tester-key.pem");
+        assert!(matches!(
+            service
+                .authenticate(AuthenticationMode::Bidirectional, &tester)
+                .await,
+            Err(AuthenticationError::ServerOwnership(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn surfaces_authentication_negative_responses() {
+        let tester = credentials("tester.pem", "tester-key.pem");
+        let mut request = vec![
+            AUTHENTICATION_SERVICE_ID,
+            VERIFY_CERTIFICATE_UNIDIRECTIONAL,
+            COMMUNICATION_CONFIGURATION,
+        ];
+        push_length_prefixed(&mut request, tester.certificate());
+        push_length_prefixed(&mut request, &[]);
+
+        for code in 0x50..=0x5D {
+            let code = NegativeResponseCode::from(code);
+            assert!(!matches!(code, NegativeResponseCode::Other(_)));
+
+            let mut client = FakeEcu::new(standard_target(0x7E0))
+                .reject(&request, code)
+                .connect();
+            let mut service = AuthenticationService::new(&mut client);
+            assert_eq!(
+                negative_response_code(
+                    service
+                        .authenticate(AuthenticationMode::Unidirectional, &tester)
+                        .await
+                ),
+                Some(code)
+            );
+        }
+    }
+}
@@ -0,0 +1,10 @@
+mod service;
+
+pub use self::service::DataTransferService;
+
+const TRANSFER_DATA_SERVICE_ID: u8 = 0x36;
+const REQUEST_TRANSFER_EXIT_SERVICE_ID: u8 = 0x37;
+
+/// Size of the service ID and block sequence counter, which count against the maximum block length
+/// negotiated by the ECU.
+const BLOCK_HEADER_LENGTH: usize = 2;
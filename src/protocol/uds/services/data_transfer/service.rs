@@ -0,0 +1,101 @@
+use tracing::debug;
+
+use crate::{
+    common::error::{InvalidResponse, InvalidResponseKind},
+    protocol::uds::{
+        client::{ensure_field, UdsClient},
+        error::UdsError,
+    },
+};
+
+use super::{BLOCK_HEADER_LENGTH, REQUEST_TRANSFER_EXIT_SERVICE_ID, TRANSFER_DATA_SERVICE_ID};
+
+/// Drives TransferData and RequestTransferExit once a transfer has been negotiated.
+pub struct DataTransferService<'a> {
+    client: &'a mut UdsClient,
+    max_block_length: usize,
+    block_sequence_counter: u8,
+}
+
+impl<'a> DataTransferService<'a> {
+    /// Creates a new `DataTransferService`, using the maximum block length negotiated when the
+    /// transfer was requested.
+    pub fn new(client: &'a mut UdsClient, max_block_length: usize) -> Self {
+        Self {
+            client,
+            max_block_length,
+            block_sequence_counter: 1,
+        }
+    }
+
+    /// Sends `data` to the ECU, split into as many blocks as necessary.
+    pub async fn send(&mut self, data: &[u8]) -> Result<(), UdsError> {
+        let chunk_size = self.chunk_size()?;
+        for chunk in data.chunks(chunk_size) {
+            let counter = self.next_block_sequence_counter();
+            let mut payload = vec![TRANSFER_DATA_SERVICE_ID, counter];
+            payload.extend_from_slice(chunk);
+
+            let response = self.client.request(&payload).await?;
+            ensure_field(&response, 1, counter)?;
+            debug!("Sent block {} ({} bytes).", counter, chunk.len());
+        }
+
+        Ok(())
+    }
+
+    /// Receives `length` bytes from the ECU, requesting as many blocks as necessary.
+    pub async fn receive(&mut self, length: usize) -> Result<Vec<u8>, UdsError> {
+        let mut data = Vec::with_capacity(length);
+        while data.len() < length {
+            let counter = self.next_block_sequence_counter();
+            let response = self
+                .client
+                .request(&[TRANSFER_DATA_SERVICE_ID, counter])
+                .await?;
+            ensure_field(&response, 1, counter)?;
+
+            // An ECU that keeps sending empty blocks would otherwise keep us here forever.
+            let block = &response[BLOCK_HEADER_LENGTH..];
+            if block.is_empty() {
+                return Err(InvalidResponse::from(InvalidResponseKind::PayloadSize {
+                    actual: data.len(),
+                    expected: length,
+                })
+                .into());
+            }
+
+            debug!("Received block {} ({} bytes).", counter, block.len());
+            data.extend_from_slice(block);
+        }
+
+        Ok(data)
+    }
+
+    /// Finishes the transfer.
+    pub async fn exit(mut self) -> Result<(), UdsError> {
+        let _ = self
+            .client
+            .request(&[REQUEST_TRANSFER_EXIT_SERVICE_ID])
+            .await?;
+        Ok(())
+    }
+
+    fn chunk_size(&self) -> Result<usize, UdsError> {
+        match self.max_block_length.checked_sub(BLOCK_HEADER_LENGTH) {
+            Some(size) if size > 0 => Ok(size),
+            _ => Err(InvalidResponse::from(InvalidResponseKind::PayloadSize {
+                actual: self.max_block_length,
+                expected: BLOCK_HEADER_LENGTH + 1,
+            })
+            .into()),
+        }
+    }
+
+    fn next_block_sequence_counter(&mut self) -> u8 {
+        // The block sequence counter starts at one, but wraps around to zero.
+        let counter = self.block_sequence_counter;
+        self.block_sequence_counter = self.block_sequence_counter.wrapping_add(1);
+        counter
+    }
+}
@@ -0,0 +1,96 @@
+mod service;
+use core::fmt;
+use std::str::FromStr;
+
+use can::identifier::Id;
+
+use crate::{
+    common::parse::parse_hex_u32,
+    protocol::uds::client::{NEGATIVE_RESPONSE_SERVICE_ID, POSITIVE_RESPONSE_OFFSET},
+};
+
+pub use self::service::DiscoveryService;
+
+const TESTER_PRESENT_SERVICE_ID: u8 = 0x3E;
+const DIAGNOSTIC_SESSION_CONTROL_SERVICE_ID: u8 = 0x10;
+
+/// How a responding ECU was discovered.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ProbeStrategy {
+    /// Functional (broadcast) TesterPresent request.
+    FunctionalTesterPresent,
+    /// Functional (broadcast) DiagnosticSessionControl request for the default session.
+    FunctionalSessionControl,
+    /// Physical TesterPresent request to a specific identifier.
+    PhysicalTesterPresent,
+}
+
+impl ProbeStrategy {
+    fn request(&self) -> [u8; 2] {
+        match self {
+            Self::FunctionalTesterPresent | Self::PhysicalTesterPresent => {
+                [TESTER_PRESENT_SERVICE_ID, 0x00]
+            }
+            Self::FunctionalSessionControl => [DIAGNOSTIC_SESSION_CONTROL_SERVICE_ID, 0x01],
+        }
+    }
+
+    /// Whether or not the given single frame payload is a response to this probe.
+    ///
+    /// Negative responses count, since they still tell us that something is listening.
+    fn is_response(&self, data: &[u8]) -> bool {
+        let service_id = self.request()[0];
+        match data {
+            [pci, sid, ..] if pci & 0xF0 == 0 && *sid == service_id + POSITIVE_RESPONSE_OFFSET => {
+                true
+            }
+            [pci, NEGATIVE_RESPONSE_SERVICE_ID, sid, ..] if pci & 0xF0 == 0 => *sid == service_id,
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for ProbeStrategy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::FunctionalTesterPresent => write!(f, "functional TesterPresent"),
+            Self::FunctionalSessionControl => write!(f, "functional DiagnosticSessionControl"),
+            Self::PhysicalTesterPresent => write!(f, "physical TesterPresent"),
+        }
+    }
+}
+
+/// An ECU that responded to one or more of our probes.
+#[derive(Debug)]
+pub struct DiscoveredEcu {
+    /// The identifier the ECU expects requests on, if it could be determined.
+    pub request_id: Option<Id>,
+    pub response_id: Id,
+    pub strategies: Vec<ProbeStrategy>,
+}
+
+/// An inclusive range of CAN identifiers to send physical requests to.
+///
+/// Parsed from `<start>-<end>`, in hexadecimal.
+#[derive(Clone, Copy, Debug)]
+pub struct IdentifierRange {
+    pub start: u32,
+    pub end: u32,
+}
+
+impl FromStr for IdentifierRange {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (start, end) = s
+            .split_once('-')
+            .ok_or_else(|| format!("expected '<start>-<end>', got '{}'", s))?;
+        let start = parse_hex_u32(start).map_err(|e| e.to_string())?;
+        let end = parse_hex_u32(end).map_err(|e| e.to_string())?;
+        if start > end || end > 0x1FFFFFFF {
+            return Err(format!("invalid identifier range '{}'", s));
+        }
+
+        Ok(Self { start, end })
+    }
+}
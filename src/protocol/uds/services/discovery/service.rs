@@ -0,0 +1,377 @@
+use std::{collections::HashMap, time::Duration};
+
+use can::identifier::Id;
+use tokio::{pin, select, time::sleep};
+use tracing::{debug, info};
+
+use crate::{
+    common::{
+        addressing::{frame_id, id_from_raw, ResponseAddress},
+        config::CANParameters,
+    },
+    protocol::{
+        can::{
+            error::SocketError,
+            frame::CANAnyFrame,
+            isotp::{encode_single_frame, strip_address, FrameFormat},
+            raw::RawSocket,
+        },
+        uds::error::UdsError,
+    },
+};
+
+use super::{DiscoveredEcu, IdentifierRange, ProbeStrategy};
+
+pub struct DiscoveryService {
+    can_parameters: CANParameters,
+}
+
+impl DiscoveryService {
+    pub fn new(can_parameters: CANParameters) -> Self {
+        Self { can_parameters }
+    }
+
+    /// Discovers diagnostic-capable ECUs.
+    ///
+    /// Functional TesterPresent and DiagnosticSessionControl requests are always sent, waiting for
+    /// `listen_timeout` after each for ECUs to respond.  If `physical_range` is given, a physical
+    /// TesterPresent request is then sent to every `step`th identifier in the range, waiting for
+    /// `probe_timeout` after each one.
+    pub async fn discover(
+        &mut self,
+        listen_timeout: Duration,
+        physical_range: Option<(IdentifierRange, u32)>,
+        probe_timeout: Duration,
+    ) -> Result<Vec<DiscoveredEcu>, UdsError> {
+        let addressing = self.can_parameters.addressing();
+
+        // We listen to everything, since ECUs outside of the OBD range aren't guaranteed to
+        // respond on any particular identifier.  Anything that doesn't look like a response to our
+        // probe gets ignored.
+        let mut raw_socket = RawSocket::builder()
+            .can_parameters(self.can_parameters.clone())
+            .build()?;
+
+        let mut discovered: HashMap<Id, DiscoveredEcu> = HashMap::new();
+        let rx_address = self
+            .can_parameters
+            .payload_address()
+            .map(|address| address.rx);
+
+        let tester_address = self.can_parameters.tester_address;
+        let broadcast_address = addressing
+            .functional_request_address(self.can_parameters.functional_address, tester_address);
+        for strategy in [
+            ProbeStrategy::FunctionalTesterPresent,
+            ProbeStrategy::FunctionalSessionControl,
+        ] {
+            info!("Probing via {} to {}...", strategy, broadcast_address);
+
+            let frame = self.probe_frame(broadcast_address.id().as_raw(), strategy);
+            raw_socket.write(frame).await?;
+
+            let response_ids =
+                listen(&mut raw_socket, rx_address, strategy, listen_timeout).await?;
+            for response_id in response_ids {
+                // Functional responses from OBD-compliant ECUs let us figure out the physical
+                // request identifier, but anything else is a guess, so we leave it blank.
+                let request_id = addressing
+                    .response_address(response_id, tester_address)
+                    .and_then(|address| address.request_address())
+                    .map(|address| address.id());
+
+                record(&mut discovered, request_id, response_id, strategy);
+            }
+        }
+
+        if let Some((range, step)) = physical_range {
+            info!(
+                "Probing physical identifiers 0x{:X} to 0x{:X} (step 0x{:X})...",
+                range.start, range.end, step
+            );
+
+            let strategy = ProbeStrategy::PhysicalTesterPresent;
+            let mut raw_request_id = range.start;
+            while raw_request_id <= range.end {
+                debug!("Probing 0x{:X}...", raw_request_id);
+
+                let frame = self.probe_frame(raw_request_id, strategy);
+                raw_socket.write(frame).await?;
+
+                let probed_id = id_from_raw(raw_request_id);
+                let response_ids =
+                    listen(&mut raw_socket, rx_address, strategy, probe_timeout).await?;
+                for response_id in response_ids {
+                    let response_address = ResponseAddress::new(response_id);
+                    if response_address.is_for_other_tester(tester_address) {
+                        continue;
+                    }
+
+                    // Responses are matched to the probe they answer by their identifier where
+                    // possible, since slow ECUs can answer after we've moved on to the next probe.
+                    // Anything else answers the probe it followed, unless it already answered an
+                    // earlier one.
+                    let request_id = match response_address.request_address() {
+                        Some(address) => Some(address.id()),
+                        None if answered_physically(&discovered, response_id) => continue,
+                        None => probed_id,
+                    };
+                    record(&mut discovered, request_id, response_id, strategy);
+                }
+
+                raw_request_id = match raw_request_id.checked_add(step) {
+                    Some(id) => id,
+                    None => break,
+                };
+            }
+        }
+
+        Ok(discovered.into_values().collect())
+    }
+
+    /// Builds a probe, as an FD frame when CAN FD is enabled, the same as any other request.
+    fn probe_frame(&self, raw_id: u32, strategy: ProbeStrategy) -> CANAnyFrame {
+        // We need to craft our payload manually since we aren't using an ISO-TP socket which adds
+        // the length byte, and any address byte, for us automatically.
+        let payload = encode_single_frame(
+            &strategy.request(),
+            self.can_parameters
+                .payload_address()
+                .map(|address| address.tx),
+            self.can_parameters.tx_padding(),
+        );
+
+        FrameFormat::new(&self.can_parameters).frame(raw_id, &payload)
+    }
+}
+
+async fn listen(
+    raw_socket: &mut RawSocket,
+    rx_address: Option<u8>,
+    strategy: ProbeStrategy,
+    listen_timeout: Duration,
+) -> Result<Vec<Id>, UdsError> {
+    let listen_timeout = sleep(listen_timeout);
+    pin!(listen_timeout);
+
+    let mut response_ids = Vec::new();
+    loop {
+        select! {
+            _ = &mut listen_timeout => break,
+            result = raw_socket.read() => {
+                // A quiet bus can easily outlast the read timeout when we're listening for a while.
+                let frame = match result {
+                    Ok(frame) => frame,
+                    Err(SocketError::Timeout(_)) => continue,
+                    Err(e) => return Err(e.into()),
+                };
+                match strip_address(frame.data(), rx_address) {
+                    Some(data) if strategy.is_response(data) => {}
+                    _ => continue,
+                }
+
+                if let Some(id) = frame_id(&frame) {
+                    response_ids.push(id);
+                }
+            },
+        }
+    }
+
+    Ok(response_ids)
+}
+
+/// Whether the ECU responding on `response_id` has already answered a physical probe.
+fn answered_physically(discovered: &HashMap<Id, DiscoveredEcu>, response_id: Id) -> bool {
+    discovered.get(&response_id).map_or(false, |ecu| {
+        ecu.strategies
+            .contains(&ProbeStrategy::PhysicalTesterPresent)
+    })
+}
+
+fn record(
+    discovered: &mut HashMap<Id, DiscoveredEcu>,
+    request_id: Option<Id>,
+    response_id: Id,
+    strategy: ProbeStrategy,
+) {
+    let ecu = discovered
+        .entry(response_id)
+        .or_insert_with(|| DiscoveredEcu {
+            request_id: None,
+            response_id,
+            strategies: Vec::new(),
+        });
+
+    // Physical probes are the most reliable indication of the request identifier, so they always
+    // win out over anything we inferred from a functional response.
+    if strategy == ProbeStrategy::PhysicalTesterPresent || ecu.request_id.is_none() {
+        ecu.request_id = request_id.or(ecu.request_id);
+    }
+
+    if !ecu.strategies.contains(&strategy) {
+        ecu.strategies.push(strategy);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use socketcan::CANFrame;
+
+    use crate::protocol::can::mock::MockBus;
+
+    use super::*;
+
+    /// Starts an ECU that answers probes sent to any of `request_ids` from `response_id`, after
+    /// `delay`.
+    fn spawn_ecu(bus: &MockBus, request_ids: &'static [u32], response_id: u32, delay: Duration) {
+        let mut socket = bus.raw_socket();
+        tokio::spawn(async move {
+            loop {
+                let frame = match socket.read().await {
+                    Ok(frame) if request_ids.contains(&frame.id()) => frame,
+                    _ => continue,
+                };
+                let response: &[u8] = match frame.data() {
+                    [0x02, 0x3E, 0x00, ..] => &[0x02, 0x7E, 0x00],
+                    [0x02, 0x10, 0x01, ..] => &[0x06, 0x50, 0x01, 0x00, 0x32, 0x01, 0xF4],
+                    _ => continue,
+                };
+
+                sleep(delay).await;
+                let response = CANFrame::new(response_id, response, false, false).unwrap();
+                let _ = socket.write(response).await;
+            }
+        });
+    }
+
+    #[tokio::test]
+    async fn discovers_ecus_functionally_and_physically() {
+        let bus = MockBus::new();
+        spawn_ecu(&bus, &[0x7DF, 0x7E0], 0x7E8, Duration::ZERO);
+        // Only answers physical requests, outside of the OBD range.
+        spawn_ecu(&bus, &[0x700], 0x708, Duration::ZERO);
+
+        let range = IdentifierRange {
+            start: 0x700,
+            end: 0x701,
+        };
+        let mut discovered = DiscoveryService::new(bus.can_parameters(&[]))
+            .discover(
+                Duration::from_millis(50),
+                Some((range, 1)),
+                Duration::from_millis(50),
+            )
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|ecu| {
+                (
+                    ecu.response_id.as_raw(),
+                    ecu.request_id.map(|id| id.as_raw()),
+                    ecu.strategies,
+                )
+            })
+            .collect::<Vec<_>>();
+        discovered.sort_by_key(|(response_id, _, _)| *response_id);
+
+        assert_eq!(
+            discovered,
+            vec![
+                (
+                    0x708,
+                    Some(0x700),
+                    vec![ProbeStrategy::PhysicalTesterPresent]
+                ),
+                (
+                    0x7E8,
+                    Some(0x7E0),
+                    vec![
+                        ProbeStrategy::FunctionalTesterPresent,
+                        ProbeStrategy::FunctionalSessionControl,
+                    ]
+                ),
+            ]
+        );
+    }
+
+    /// Discovers ECUs with physical probes alone, returning each one's response and request
+    /// identifiers.
+    async fn probe(bus: &MockBus, start: u32, end: u32) -> Vec<(u32, Option<u32>)> {
+        let range = IdentifierRange { start, end };
+        let mut discovered = DiscoveryService::new(bus.can_parameters(&[]))
+            .discover(
+                Duration::from_millis(10),
+                Some((range, 1)),
+                Duration::from_millis(50),
+            )
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|ecu| {
+                (
+                    ecu.response_id.as_raw(),
+                    ecu.request_id.map(|id| id.as_raw()),
+                )
+            })
+            .collect::<Vec<_>>();
+        discovered.sort();
+        discovered
+    }
+
+    #[tokio::test]
+    async fn matches_late_responses_to_the_probe_they_answer() {
+        let bus = MockBus::new();
+        // Answers after we've moved on to probing 0x7E1.
+        spawn_ecu(&bus, &[0x7E0], 0x7E8, Duration::from_millis(70));
+
+        assert_eq!(probe(&bus, 0x7E0, 0x7E2).await, vec![(0x7E8, Some(0x7E0))]);
+    }
+
+    #[tokio::test]
+    async fn keeps_the_first_probe_answered_by_vendor_identifiers() {
+        let bus = MockBus::new();
+        // Answers every probe in its range, from an identifier nothing can be derived from.
+        spawn_ecu(&bus, &[0x700, 0x701], 0x708, Duration::ZERO);
+
+        assert_eq!(probe(&bus, 0x700, 0x701).await, vec![(0x708, Some(0x700))]);
+    }
+
+    #[tokio::test]
+    async fn ignores_responses_to_other_testers() {
+        let bus = MockBus::new();
+        spawn_ecu(&bus, &[0x700], 0x18DAF210, Duration::ZERO);
+        spawn_ecu(&bus, &[0x701], 0x18DAF110, Duration::ZERO);
+
+        assert_eq!(
+            probe(&bus, 0x700, 0x701).await,
+            vec![(0x18DAF110, Some(0x18DA10F1))]
+        );
+    }
+
+    #[tokio::test]
+    async fn probes_with_fd_frames_when_can_fd_is_enabled() {
+        let bus = MockBus::new();
+        let mut listener = bus.raw_socket();
+        let range = IdentifierRange {
+            start: 0x700,
+            end: 0x700,
+        };
+        let discover = DiscoveryService::new(bus.can_parameters(&["--can-fd"])).discover(
+            Duration::from_millis(10),
+            Some((range, 1)),
+            Duration::from_millis(10),
+        );
+        let listen = async {
+            let mut probes = Vec::new();
+            for _ in 0..3 {
+                let frame = listener.read().await.unwrap();
+                probes.push((frame.id(), matches!(frame, CANAnyFrame::FD(_))));
+            }
+            probes
+        };
+
+        let (discovered, probes) = tokio::join!(discover, listen);
+        assert!(discovered.unwrap().is_empty());
+        assert_eq!(probes, vec![(0x7DF, true), (0x7DF, true), (0x700, true)]);
+    }
+}
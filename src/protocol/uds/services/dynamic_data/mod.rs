@@ -0,0 +1,90 @@
+mod service;
+use std::str::FromStr;
+
+use crate::common::parse::{parse_hex_u16, parse_hex_u32};
+
+pub use self::service::DynamicDataService;
+
+const DYNAMICALLY_DEFINE_DATA_IDENTIFIER_SERVICE_ID: u8 = 0x2C;
+
+const DEFINE_BY_IDENTIFIER: u8 = 0x01;
+const DEFINE_BY_MEMORY_ADDRESS: u8 = 0x02;
+const CLEAR_DYNAMICALLY_DEFINED_DATA_IDENTIFIER: u8 = 0x03;
+
+/// Address and length format used when defining by memory address: four address bytes, and two
+/// memory size bytes.
+const MEMORY_ADDRESS_AND_LENGTH_FORMAT: u8 = 0x24;
+
+/// A slice of an existing data identifier to include in a dynamically-defined data identifier.
+///
+/// Parsed from `<did>:<position>:<size>`, where the data identifier is in hexadecimal and the
+/// position (1-based) and size are in decimal.
+#[derive(Clone, Copy, Debug)]
+pub struct IdentifierSource {
+    pub identifier: u16,
+    pub position: u8,
+    pub size: u8,
+}
+
+impl IdentifierSource {
+    fn encode(&self) -> [u8; 4] {
+        let [hi, lo] = self.identifier.to_be_bytes();
+        [hi, lo, self.position, self.size]
+    }
+}
+
+impl FromStr for IdentifierSource {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parts = s.split(':').collect::<Vec<_>>();
+        if parts.len() != 3 {
+            return Err(format!("expected '<did>:<position>:<size>', got '{}'", s));
+        }
+
+        let identifier = parse_hex_u16(parts[0]).map_err(|e| e.to_string())?;
+        let position = parts[1].parse::<u8>().map_err(|e| e.to_string())?;
+        let size = parts[2].parse::<u8>().map_err(|e| e.to_string())?;
+        if position == 0 {
+            return Err("position is 1-based and cannot be zero".to_string());
+        }
+
+        Ok(Self {
+            identifier,
+            position,
+            size,
+        })
+    }
+}
+
+/// A memory region to include in a dynamically-defined data identifier.
+///
+/// Parsed from `<address>:<size>`, where the address is in hexadecimal and the size is in decimal.
+#[derive(Clone, Copy, Debug)]
+pub struct MemorySource {
+    pub address: u32,
+    pub size: u16,
+}
+
+impl MemorySource {
+    fn encode(&self) -> [u8; 6] {
+        let [a0, a1, a2, a3] = self.address.to_be_bytes();
+        let [s0, s1] = self.size.to_be_bytes();
+        [a0, a1, a2, a3, s0, s1]
+    }
+}
+
+impl FromStr for MemorySource {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (address, size) = s
+            .split_once(':')
+            .ok_or_else(|| format!("expected '<address>:<size>', got '{}'", s))?;
+
+        Ok(Self {
+            address: parse_hex_u32(address).map_err(|e| e.to_string())?,
+            size: size.parse::<u16>().map_err(|e| e.to_string())?,
+        })
+    }
+}
@@ -0,0 +1,165 @@
+use crate::protocol::uds::{
+    client::{ensure_field, ensure_length, UdsClient},
+    error::UdsError,
+};
+
+use super::{
+    IdentifierSource, MemorySource, CLEAR_DYNAMICALLY_DEFINED_DATA_IDENTIFIER,
+    DEFINE_BY_IDENTIFIER, DEFINE_BY_MEMORY_ADDRESS, DYNAMICALLY_DEFINE_DATA_IDENTIFIER_SERVICE_ID,
+    MEMORY_ADDRESS_AND_LENGTH_FORMAT,
+};
+
+pub struct DynamicDataService<'a> {
+    client: &'a mut UdsClient,
+}
+
+impl<'a> DynamicDataService<'a> {
+    pub fn new(client: &'a mut UdsClient) -> Self {
+        Self { client }
+    }
+
+    /// Defines `identifier` as the concatenation of slices of other data identifiers.
+    pub async fn define_by_identifier(
+        &mut self,
+        identifier: u16,
+        sources: &[IdentifierSource],
+    ) -> Result<(), UdsError> {
+        let mut payload = build_request(DEFINE_BY_IDENTIFIER, Some(identifier));
+        for source in sources {
+            payload.extend_from_slice(&source.encode());
+        }
+
+        self.send(&payload, DEFINE_BY_IDENTIFIER, Some(identifier))
+            .await
+    }
+
+    /// Defines `identifier` as the concatenation of the given memory regions.
+    pub async fn define_by_memory_address(
+        &mut self,
+        identifier: u16,
+        sources: &[MemorySource],
+    ) -> Result<(), UdsError> {
+        let mut payload = build_request(DEFINE_BY_MEMORY_ADDRESS, Some(identifier));
+        payload.push(MEMORY_ADDRESS_AND_LENGTH_FORMAT);
+        for source in sources {
+            payload.extend_from_slice(&source.encode());
+        }
+
+        self.send(&payload, DEFINE_BY_MEMORY_ADDRESS, Some(identifier))
+            .await
+    }
+
+    /// Clears the definition of `identifier`, or of all dynamically-defined data identifiers if
+    /// no identifier is given.
+    pub async fn clear(&mut self, identifier: Option<u16>) -> Result<(), UdsError> {
+        let payload = build_request(CLEAR_DYNAMICALLY_DEFINED_DATA_IDENTIFIER, identifier);
+
+        self.send(
+            &payload,
+            CLEAR_DYNAMICALLY_DEFINED_DATA_IDENTIFIER,
+            identifier,
+        )
+        .await
+    }
+
+    async fn send(
+        &mut self,
+        payload: &[u8],
+        sub_function: u8,
+        identifier: Option<u16>,
+    ) -> Result<(), UdsError> {
+        let response = self.client.request(payload).await?;
+
+        // Positive responses echo back the sub-function and, if one was given, the identifier.
+        ensure_field(&response, 1, sub_function)?;
+        if let Some(identifier) = identifier {
+            let [hi, lo] = identifier.to_be_bytes();
+            ensure_length(&response, 4)?;
+            ensure_field(&response, 2, hi)?;
+            ensure_field(&response, 3, lo)?;
+        }
+
+        Ok(())
+    }
+}
+
+fn build_request(sub_function: u8, identifier: Option<u16>) -> Vec<u8> {
+    let mut payload = vec![DYNAMICALLY_DEFINE_DATA_IDENTIFIER_SERVICE_ID, sub_function];
+    if let Some(identifier) = identifier {
+        payload.extend_from_slice(&identifier.to_be_bytes());
+    }
+    payload
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::protocol::{
+        transport::mock::{standard_target, FakeEcu},
+        uds::error::NegativeResponseCode,
+    };
+
+    use super::*;
+
+    #[tokio::test]
+    async fn defines_and_clears_identifiers() {
+        let mut client = FakeEcu::new(standard_target(0x7E0))
+            .respond(
+                &[0x2C, 0x01, 0xF2, 0x00, 0xF1, 0x90, 0x01, 0x04],
+                &[&[0x6C, 0x01, 0xF2, 0x00]],
+            )
+            .respond(
+                &[
+                    0x2C, 0x02, 0xF2, 0x01, 0x24, 0x00, 0x01, 0x00, 0x00, 0x00, 0x02,
+                ],
+                &[&[0x6C, 0x02, 0xF2, 0x01]],
+            )
+            .respond(&[0x2C, 0x03], &[&[0x6C, 0x03]])
+            .connect();
+        let mut service = DynamicDataService::new(&mut client);
+
+        let source = IdentifierSource {
+            identifier: 0xF190,
+            position: 1,
+            size: 4,
+        };
+        service
+            .define_by_identifier(0xF200, &[source])
+            .await
+            .unwrap();
+
+        let source = MemorySource {
+            address: 0x00010000,
+            size: 2,
+        };
+        service
+            .define_by_memory_address(0xF201, &[source])
+            .await
+            .unwrap();
+
+        service.clear(None).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn surfaces_rejections_and_mismatched_identifiers() {
+        let mut client = FakeEcu::new(standard_target(0x7E0))
+            .reject(
+                &[0x2C, 0x03, 0xF2, 0x00],
+                NegativeResponseCode::RequestOutOfRange,
+            )
+            .respond(&[0x2C, 0x03, 0xF2, 0x01], &[&[0x6C, 0x03, 0xF2, 0x02]])
+            .connect();
+        let mut service = DynamicDataService::new(&mut client);
+
+        assert!(matches!(
+            service.clear(Some(0xF200)).await,
+            Err(UdsError::NegativeResponse {
+                service_id: 0x2C,
+                code: NegativeResponseCode::RequestOutOfRange,
+            })
+        ));
+        assert!(matches!(
+            service.clear(Some(0xF201)).await,
+            Err(UdsError::InvalidResponse(_))
+        ));
+    }
+}
@@ -0,0 +1,43 @@
+mod service;
+
+pub use self::service::{FileTransferService, ReadFileResponse};
+
+const REQUEST_FILE_TRANSFER_SERVICE_ID: u8 = 0x38;
+
+const ADD_FILE: u8 = 0x01;
+const DELETE_FILE: u8 = 0x02;
+const REPLACE_FILE: u8 = 0x03;
+const READ_FILE: u8 = 0x04;
+const READ_DIR: u8 = 0x05;
+
+/// Minimum number of bytes used to encode file sizes in our requests; larger files use as many
+/// bytes as their size needs.
+const MIN_FILE_SIZE_PARAMETER_LENGTH: u8 = 4;
+
+/// Compression and encryption methods to apply to a file transfer.
+///
+/// Both methods are vendor-specific, with zero meaning that neither compression nor encryption is
+/// used.  Data is sent as-is, so it must already be compressed and/or encrypted as appropriate.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct DataFormat {
+    pub compression_method: u8,
+    pub encryption_method: u8,
+}
+
+impl DataFormat {
+    fn as_byte(&self) -> u8 {
+        ((self.compression_method & 0x0F) << 4) | (self.encryption_method & 0x0F)
+    }
+
+    fn from_byte(b: u8) -> Self {
+        Self {
+            compression_method: b >> 4,
+            encryption_method: b & 0x0F,
+        }
+    }
+}
+
+/// Decodes a variable-length, big-endian unsigned integer.
+fn decode_be_uint(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0, |acc, b| (acc << 8) | *b as u64)
+}
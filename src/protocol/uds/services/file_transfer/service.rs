@@ -0,0 +1,325 @@
+use crate::protocol::uds::{
+    client::{ensure_field, ensure_length, UdsClient},
+    error::UdsError,
+    services::DataTransferService,
+};
+
+use super::{
+    decode_be_uint, DataFormat, ADD_FILE, DELETE_FILE, MIN_FILE_SIZE_PARAMETER_LENGTH, READ_DIR,
+    READ_FILE, REPLACE_FILE, REQUEST_FILE_TRANSFER_SERVICE_ID,
+};
+
+/// A file read from the ECU.
+pub struct ReadFileResponse {
+    pub data_format: DataFormat,
+    pub uncompressed_size: u64,
+    pub data: Vec<u8>,
+}
+
+/// Transfer parameters negotiated by the ECU in response to a file transfer request.
+struct TransferParameters {
+    max_block_length: usize,
+    data_format: DataFormat,
+    // The uncompressed size of the file (or the length of the directory listing) followed by the
+    // compressed size of the file, if present.
+    sizes: Vec<u64>,
+}
+
+pub struct FileTransferService<'a> {
+    client: &'a mut UdsClient,
+}
+
+impl<'a> FileTransferService<'a> {
+    pub fn new(client: &'a mut UdsClient) -> Self {
+        Self { client }
+    }
+
+    /// Adds a new file at `path` on the ECU.
+    ///
+    /// If the data is compressed, `uncompressed_size` must be the size of the data once
+    /// decompressed.  Otherwise, it should be the length of the data itself.
+    pub async fn add_file(
+        &mut self,
+        path: &str,
+        data: &[u8],
+        data_format: DataFormat,
+        uncompressed_size: u64,
+    ) -> Result<(), UdsError> {
+        self.write_file(ADD_FILE, path, data, data_format, uncompressed_size)
+            .await
+    }
+
+    /// Replaces the file at `path` on the ECU, creating it if it does not already exist.
+    ///
+    /// If the data is compressed, `uncompressed_size` must be the size of the data once
+    /// decompressed.  Otherwise, it should be the length of the data itself.
+    pub async fn replace_file(
+        &mut self,
+        path: &str,
+        data: &[u8],
+        data_format: DataFormat,
+        uncompressed_size: u64,
+    ) -> Result<(), UdsError> {
+        self.write_file(REPLACE_FILE, path, data, data_format, uncompressed_size)
+            .await
+    }
+
+    /// Deletes the file at `path` on the ECU.
+    pub async fn delete_file(&mut self, path: &str) -> Result<(), UdsError> {
+        let payload = build_request(DELETE_FILE, path);
+        let response = self.client.request(&payload).await?;
+        ensure_field(&response, 1, DELETE_FILE)?;
+
+        Ok(())
+    }
+
+    /// Reads the file at `path` from the ECU.
+    ///
+    /// Data is returned as-is, so it may still be compressed and/or encrypted depending on the
+    /// data format the ECU chose.
+    pub async fn read_file(
+        &mut self,
+        path: &str,
+        data_format: DataFormat,
+    ) -> Result<ReadFileResponse, UdsError> {
+        let mut payload = build_request(READ_FILE, path);
+        payload.push(data_format.as_byte());
+
+        let parameters = self.request_transfer(&payload, READ_FILE, 2).await?;
+        let uncompressed_size = parameters.sizes[0];
+        let compressed_size = parameters.sizes[1];
+
+        let mut transfer = DataTransferService::new(self.client, parameters.max_block_length);
+        let data = transfer.receive(compressed_size as usize).await?;
+        transfer.exit().await?;
+
+        Ok(ReadFileResponse {
+            data_format: parameters.data_format,
+            uncompressed_size,
+            data,
+        })
+    }
+
+    /// Reads the directory listing for `path` from the ECU.
+    ///
+    /// The format of the listing is vendor-specific.
+    pub async fn read_dir(&mut self, path: &str) -> Result<Vec<u8>, UdsError> {
+        let payload = build_request(READ_DIR, path);
+
+        let parameters = self.request_transfer(&payload, READ_DIR, 1).await?;
+        let listing_length = parameters.sizes[0];
+
+        let mut transfer = DataTransferService::new(self.client, parameters.max_block_length);
+        let listing = transfer.receive(listing_length as usize).await?;
+        transfer.exit().await?;
+
+        Ok(listing)
+    }
+
+    async fn write_file(
+        &mut self,
+        mode: u8,
+        path: &str,
+        data: &[u8],
+        data_format: DataFormat,
+        uncompressed_size: u64,
+    ) -> Result<(), UdsError> {
+        let mut payload = build_request(mode, path);
+        payload.push(data_format.as_byte());
+        payload.extend_from_slice(&encode_file_sizes(uncompressed_size, data.len() as u64));
+
+        let parameters = self.request_transfer(&payload, mode, 0).await?;
+
+        let mut transfer = DataTransferService::new(self.client, parameters.max_block_length);
+        transfer.send(data).await?;
+        transfer.exit().await
+    }
+
+    async fn request_transfer(
+        &mut self,
+        payload: &[u8],
+        mode: u8,
+        size_count: usize,
+    ) -> Result<TransferParameters, UdsError> {
+        let response = self.client.request(payload).await?;
+        ensure_field(&response, 1, mode)?;
+
+        // The length format identifier tells us how many bytes are used for the maximum block
+        // length, which is followed by the data format identifier.
+        ensure_length(&response, 3)?;
+        let block_length_size = response[2] as usize;
+        let data_format_idx = 3 + block_length_size;
+        ensure_length(&response, data_format_idx + 1)?;
+
+        let max_block_length = decode_be_uint(&response[3..data_format_idx]) as usize;
+        let data_format = DataFormat::from_byte(response[data_format_idx]);
+
+        // Reading a file or directory also tells us how big the data we're going to receive is,
+        // with the size parameter length first, followed by the sizes themselves.
+        let mut sizes = Vec::with_capacity(size_count);
+        if size_count > 0 {
+            let size_length_idx = data_format_idx + 1;
+            ensure_length(&response, size_length_idx + 2)?;
+            let size_length =
+                decode_be_uint(&response[size_length_idx..size_length_idx + 2]) as usize;
+
+            let sizes_idx = size_length_idx + 2;
+            ensure_length(&response, sizes_idx + size_length * size_count)?;
+            for i in 0..size_count {
+                let size_idx = sizes_idx + i * size_length;
+                sizes.push(decode_be_uint(&response[size_idx..size_idx + size_length]));
+            }
+        }
+
+        Ok(TransferParameters {
+            max_block_length,
+            data_format,
+            sizes,
+        })
+    }
+}
+
+fn build_request(mode: u8, path: &str) -> Vec<u8> {
+    let path = path.as_bytes();
+    let mut payload = vec![REQUEST_FILE_TRANSFER_SERVICE_ID, mode];
+    payload.extend_from_slice(&(path.len() as u16).to_be_bytes());
+    payload.extend_from_slice(path);
+    payload
+}
+
+/// Encodes the file size parameter length followed by both sizes, using enough bytes for the
+/// larger of the two.
+fn encode_file_sizes(uncompressed_size: u64, compressed_size: u64) -> Vec<u8> {
+    let largest = uncompressed_size.max(compressed_size);
+    let needed = 8 - (largest.leading_zeros() / 8) as u8;
+    let length = needed.max(MIN_FILE_SIZE_PARAMETER_LENGTH);
+    let skip = 8 - length as usize;
+
+    let mut encoded = vec![length];
+    encoded.extend_from_slice(&uncompressed_size.to_be_bytes()[skip..]);
+    encoded.extend_from_slice(&compressed_size.to_be_bytes()[skip..]);
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::protocol::{
+        transport::mock::{standard_target, FakeEcu},
+        uds::error::NegativeResponseCode,
+    };
+
+    use super::*;
+
+    fn request(mode: u8, parameters: &[u8]) -> Vec<u8> {
+        [&build_request(mode, "a.bin")[..], parameters].concat()
+    }
+
+    #[tokio::test]
+    async fn adds_files_block_by_block() {
+        let mut client = FakeEcu::new(standard_target(0x7E0))
+            .respond(
+                &request(ADD_FILE, &[0x00, 0x04, 0, 0, 0, 5, 0, 0, 0, 5]),
+                // Blocks of up to five bytes, including the header, so three bytes of data each.
+                &[&[0x78, ADD_FILE, 0x01, 0x05, 0x00]],
+            )
+            .respond(&[0x36, 0x01, 0x01, 0x02, 0x03], &[&[0x76, 0x01]])
+            .respond(&[0x36, 0x02, 0x04, 0x05], &[&[0x76, 0x02]])
+            .respond(&[0x37], &[&[0x77]])
+            .connect();
+
+        FileTransferService::new(&mut client)
+            .add_file(
+                "a.bin",
+                &[0x01, 0x02, 0x03, 0x04, 0x05],
+                DataFormat::default(),
+                5,
+            )
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn encodes_sizes_beyond_four_bytes() {
+        let mut client = FakeEcu::new(standard_target(0x7E0))
+            .reject(
+                &request(
+                    REPLACE_FILE,
+                    &[0x10, 0x05, 0x01, 0x00, 0x00, 0x00, 0x00, 0, 0, 0, 0, 2],
+                ),
+                NegativeResponseCode::RequestOutOfRange,
+            )
+            .connect();
+
+        let data_format = DataFormat {
+            compression_method: 1,
+            encryption_method: 0,
+        };
+        let error = FileTransferService::new(&mut client)
+            .replace_file("a.bin", &[0x01, 0x02], data_format, 1 << 32)
+            .await
+            .unwrap_err();
+        assert_eq!(
+            error.negative_response_code(),
+            Some(NegativeResponseCode::RequestOutOfRange)
+        );
+    }
+
+    #[tokio::test]
+    async fn reads_files() {
+        let mut client = FakeEcu::new(standard_target(0x7E0))
+            .respond(
+                &request(READ_FILE, &[0x11]),
+                &[&[
+                    0x78, READ_FILE, 0x01, 0x10, 0x11, 0x00, 0x02, 0x00, 0x08, 0x00, 0x03,
+                ]],
+            )
+            .respond(&[0x36, 0x01], &[&[0x76, 0x01, 0xAA, 0xBB, 0xCC]])
+            .respond(&[0x37], &[&[0x77]])
+            .connect();
+
+        let data_format = DataFormat {
+            compression_method: 1,
+            encryption_method: 1,
+        };
+        let file = FileTransferService::new(&mut client)
+            .read_file("a.bin", data_format)
+            .await
+            .unwrap();
+        assert_eq!(file.uncompressed_size, 8);
+        assert_eq!(file.data_format.compression_method, 1);
+        assert_eq!(file.data, vec![0xAA, 0xBB, 0xCC]);
+    }
+
+    #[tokio::test]
+    async fn surfaces_rejections() {
+        let mut client = FakeEcu::new(standard_target(0x7E0))
+            .reject(
+                &request(DELETE_FILE, &[]),
+                NegativeResponseCode::UploadDownloadNotAccepted,
+            )
+            .respond(
+                &request(READ_DIR, &[]),
+                &[&[0x78, READ_DIR, 0x01, 0x10, 0x00, 0x00, 0x01, 0x04]],
+            )
+            .reject(&[0x36, 0x01], NegativeResponseCode::TransferDataSuspended)
+            .connect();
+        let mut service = FileTransferService::new(&mut client);
+
+        assert_eq!(
+            service
+                .delete_file("a.bin")
+                .await
+                .unwrap_err()
+                .negative_response_code(),
+            Some(NegativeResponseCode::UploadDownloadNotAccepted)
+        );
+        assert_eq!(
+            service
+                .read_dir("a.bin")
+                .await
+                .unwrap_err()
+                .negative_response_code(),
+            Some(NegativeResponseCode::TransferDataSuspended)
+        );
+    }
+}
@@ -0,0 +1,28 @@
+mod authentication;
+mod data_transfer;
+mod discovery;
+mod dynamic_data;
+mod file_transfer;
+mod periodic_data;
+mod read_data;
+mod security_access;
+mod service_probe;
+mod session_control;
+mod tester_present;
+
+pub use authentication::{
+    verify_ownership, verify_validity, AuthenticationMode, AuthenticationService, CredentialError,
+    Credentials,
+};
+pub use data_transfer::DataTransferService;
+pub use discovery::{DiscoveryService, IdentifierRange};
+pub use dynamic_data::{DynamicDataService, IdentifierSource, MemorySource};
+pub use file_transfer::{DataFormat, FileTransferService, ReadFileResponse};
+pub use periodic_data::{
+    PeriodicDataService, PeriodicFrameFormat, PeriodicListener, PeriodicRecord, TransmissionRate,
+};
+pub use read_data::{DataIdentifierStatus, ReadDataService};
+pub use security_access::SecurityAccessService;
+pub use service_probe::{ServiceProbe, ServiceStatus};
+pub use session_control::{SessionControlService, DEFAULT_SESSION};
+pub use tester_present::TesterPresentService;
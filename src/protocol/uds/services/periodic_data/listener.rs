@@ -0,0 +1,170 @@
+use std::time::Instant;
+
+use futures::{stream, Stream};
+
+use crate::{
+    common::{
+        addressing::{exact_id_filter, ResponseAddress},
+        config::CANParameters,
+        error::{InvalidResponse, InvalidResponseKind},
+    },
+    protocol::{
+        can::{isotp::strip_address, raw::RawSocket},
+        uds::error::UdsError,
+    },
+};
+
+use super::{PeriodicFrameFormat, PERIODIC_IDENTIFIER_BASE};
+
+/// A single periodic response.
+#[derive(Debug)]
+pub struct PeriodicRecord {
+    pub received_at: Instant,
+    pub identifier: u16,
+    pub data: Vec<u8>,
+}
+
+/// Listens for periodic responses on the periodic response identifier.
+///
+/// Periodic responses are not sent over the ISO-TP session used to request them, so we listen for
+/// them with a raw socket that runs alongside it.
+pub struct PeriodicListener {
+    socket: RawSocket,
+    format: PeriodicFrameFormat,
+    rx_address: Option<u8>,
+}
+
+impl PeriodicListener {
+    pub fn open(
+        can_parameters: CANParameters,
+        response_address: ResponseAddress,
+        format: PeriodicFrameFormat,
+    ) -> Result<Self, UdsError> {
+        let rx_address = can_parameters.payload_address().map(|address| address.rx);
+        let socket = RawSocket::builder()
+            .can_parameters(can_parameters)
+            .source_id_filter(exact_id_filter(response_address.id()))
+            .build()?;
+
+        Ok(Self {
+            socket,
+            format,
+            rx_address,
+        })
+    }
+
+    pub async fn next_record(&mut self) -> Result<PeriodicRecord, UdsError> {
+        loop {
+            let frame = self.socket.read().await?;
+            let received_at = Instant::now();
+
+            // Frames carrying someone else's address byte aren't meant for us.
+            if let Some(data) = strip_address(frame.data(), self.rx_address) {
+                return self.parse_record(data, received_at);
+            }
+        }
+    }
+
+    fn parse_record(&self, data: &[u8], received_at: Instant) -> Result<PeriodicRecord, UdsError> {
+        let data = match self.format {
+            PeriodicFrameFormat::Unsegmented => data,
+            PeriodicFrameFormat::SingleFrame => {
+                // The PCI byte for a single frame has a zero upper nibble and the payload length
+                // in the lower nibble.
+                let length = data.first().map(|pci| (pci & 0x0F) as usize).unwrap_or(0);
+                if data.is_empty() || data[0] & 0xF0 != 0 || data.len() < length + 1 {
+                    return Err(InvalidResponse::from(InvalidResponseKind::PayloadSize {
+                        actual: data.len().saturating_sub(1),
+                        expected: length,
+                    })
+                    .into());
+                }
+                &data[1..length + 1]
+            }
+        };
+
+        if data.is_empty() {
+            return Err(InvalidResponse::from(InvalidResponseKind::PayloadSize {
+                actual: 0,
+                expected: 1,
+            })
+            .into());
+        }
+
+        Ok(PeriodicRecord {
+            received_at,
+            identifier: PERIODIC_IDENTIFIER_BASE | data[0] as u16,
+            data: data[1..].to_vec(),
+        })
+    }
+
+    pub fn into_stream(self) -> impl Stream<Item = Result<PeriodicRecord, UdsError>> {
+        stream::unfold(self, |mut listener| async move {
+            let result = listener.next_record().await;
+            Some((result, listener))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use socketcan::CANFrame;
+
+    use crate::{common::addressing::id_from_raw, protocol::can::mock::MockBus};
+
+    use super::*;
+
+    fn listener(bus: &MockBus, format: PeriodicFrameFormat) -> PeriodicListener {
+        let response_address = ResponseAddress::new(id_from_raw(0x6A8).unwrap());
+        let can_parameters = bus.can_parameters(&["--read-timeout", "100ms"]);
+        PeriodicListener::open(can_parameters, response_address, format).unwrap()
+    }
+
+    fn frame(id: u32, data: &[u8]) -> CANFrame {
+        CANFrame::new(id, data, false, false).unwrap()
+    }
+
+    #[tokio::test]
+    async fn reads_unsegmented_records() {
+        let bus = MockBus::new();
+        let mut listener = listener(&bus, PeriodicFrameFormat::Unsegmented);
+        let mut ecu = bus.raw_socket();
+
+        // Frames on other identifiers aren't periodic responses.
+        ecu.write(frame(0x6A9, &[0x05, 0xFF])).await.unwrap();
+        ecu.write(frame(0x6A8, &[0x01, 0xAA, 0xBB])).await.unwrap();
+
+        let record = listener.next_record().await.unwrap();
+        assert_eq!(record.identifier, 0xF201);
+        assert_eq!(record.data, vec![0xAA, 0xBB]);
+        assert!(listener.next_record().await.unwrap_err().is_timeout());
+    }
+
+    #[tokio::test]
+    async fn reads_single_frame_records() {
+        let bus = MockBus::new();
+        let mut listener = listener(&bus, PeriodicFrameFormat::SingleFrame);
+        let mut ecu = bus.raw_socket();
+
+        ecu.write(frame(
+            0x6A8,
+            &[0x03, 0x02, 0xCC, 0xDD, 0xCC, 0xCC, 0xCC, 0xCC],
+        ))
+        .await
+        .unwrap();
+        ecu.write(frame(
+            0x6A8,
+            &[0x10, 0x08, 0x02, 0x00, 0x00, 0x00, 0x00, 0x00],
+        ))
+        .await
+        .unwrap();
+
+        let record = listener.next_record().await.unwrap();
+        assert_eq!(record.identifier, 0xF202);
+        assert_eq!(record.data, vec![0xCC, 0xDD]);
+        assert!(matches!(
+            listener.next_record().await,
+            Err(UdsError::InvalidResponse(_))
+        ));
+    }
+}
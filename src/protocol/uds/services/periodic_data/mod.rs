@@ -0,0 +1,41 @@
+mod listener;
+mod service;
+use clap::ArgEnum;
+
+pub use self::{
+    listener::{PeriodicListener, PeriodicRecord},
+    service::PeriodicDataService,
+};
+
+const READ_DATA_BY_PERIODIC_IDENTIFIER_SERVICE_ID: u8 = 0x2A;
+
+const STOP_SENDING: u8 = 0x04;
+
+/// High byte shared by all periodic data identifiers.  Only the low byte is sent on the wire.
+const PERIODIC_IDENTIFIER_BASE: u16 = 0xF200;
+
+#[derive(ArgEnum, Clone, Copy, Debug)]
+pub enum TransmissionRate {
+    Slow,
+    Medium,
+    Fast,
+}
+
+impl TransmissionRate {
+    fn transmission_mode(&self) -> u8 {
+        match self {
+            Self::Slow => 0x01,
+            Self::Medium => 0x02,
+            Self::Fast => 0x03,
+        }
+    }
+}
+
+/// How periodic responses are framed on the periodic response identifier.
+#[derive(ArgEnum, Clone, Copy, Debug)]
+pub enum PeriodicFrameFormat {
+    /// Unsegmented frames, where the first byte is the periodic data identifier. (type 1)
+    Unsegmented,
+    /// ISO-TP single frames, where the PCI byte precedes the periodic data identifier. (type 2)
+    SingleFrame,
+}
@@ -0,0 +1,77 @@
+use crate::protocol::uds::{client::UdsClient, error::UdsError};
+
+use super::{TransmissionRate, READ_DATA_BY_PERIODIC_IDENTIFIER_SERVICE_ID, STOP_SENDING};
+
+pub struct PeriodicDataService<'a> {
+    client: &'a mut UdsClient,
+}
+
+impl<'a> PeriodicDataService<'a> {
+    pub fn new(client: &'a mut UdsClient) -> Self {
+        Self { client }
+    }
+
+    /// Starts periodic transmission of the given periodic data identifiers.
+    ///
+    /// Periodic data identifiers are given as the low byte of the full `0xF2XX` data identifier.
+    /// Responses are not sent back on this session, but on the periodic response identifier, which
+    /// can be consumed via [`PeriodicListener`](super::PeriodicListener).
+    pub async fn start(
+        &mut self,
+        rate: TransmissionRate,
+        identifiers: &[u8],
+    ) -> Result<(), UdsError> {
+        self.send(rate.transmission_mode(), identifiers).await
+    }
+
+    /// Stops periodic transmission of the given periodic data identifiers, or of all periodic data
+    /// identifiers if none are given.
+    pub async fn stop(&mut self, identifiers: &[u8]) -> Result<(), UdsError> {
+        self.send(STOP_SENDING, identifiers).await
+    }
+
+    async fn send(&mut self, transmission_mode: u8, identifiers: &[u8]) -> Result<(), UdsError> {
+        let mut payload = vec![
+            READ_DATA_BY_PERIODIC_IDENTIFIER_SERVICE_ID,
+            transmission_mode,
+        ];
+        payload.extend_from_slice(identifiers);
+
+        let _ = self.client.request(&payload).await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::protocol::{
+        transport::mock::{standard_target, FakeEcu},
+        uds::error::NegativeResponseCode,
+    };
+
+    use super::*;
+
+    #[tokio::test]
+    async fn starts_and_stops_transmission() {
+        let mut client = FakeEcu::new(standard_target(0x7E0))
+            .respond(&[0x2A, 0x03, 0x01, 0x02], &[&[0x6A]])
+            .respond(&[0x2A, 0x04], &[&[0x6A]])
+            .reject(&[0x2A, 0x01, 0x03], NegativeResponseCode::RequestOutOfRange)
+            .connect();
+        let mut service = PeriodicDataService::new(&mut client);
+
+        service
+            .start(TransmissionRate::Fast, &[0x01, 0x02])
+            .await
+            .unwrap();
+        service.stop(&[]).await.unwrap();
+        assert_eq!(
+            service
+                .start(TransmissionRate::Slow, &[0x03])
+                .await
+                .unwrap_err()
+                .negative_response_code(),
+            Some(NegativeResponseCode::RequestOutOfRange)
+        );
+    }
+}
@@ -0,0 +1,76 @@
+mod service;
+use core::fmt;
+
+use crate::protocol::uds::error::{NegativeResponseCode, UdsError};
+
+pub use self::service::ReadDataService;
+
+const READ_DATA_BY_IDENTIFIER_SERVICE_ID: u8 = 0x22;
+
+/// Outcome of reading a single data identifier, used when scanning for supported identifiers.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum DataIdentifierStatus {
+    Positive(Vec<u8>),
+    RequestOutOfRange,
+    SecurityAccessDenied,
+    Negative(NegativeResponseCode),
+    NoResponse,
+    InvalidResponse,
+}
+
+impl DataIdentifierStatus {
+    /// Classifies the result of reading a data identifier.
+    ///
+    /// Errors which don't tell us anything about the data identifier itself, such as a socket
+    /// failing, are passed back to the caller.
+    pub fn classify(result: Result<Vec<u8>, UdsError>) -> Result<Self, UdsError> {
+        match result {
+            Ok(data) => Ok(Self::Positive(data)),
+            Err(e) => match e.negative_response_code() {
+                Some(NegativeResponseCode::RequestOutOfRange) => Ok(Self::RequestOutOfRange),
+                Some(NegativeResponseCode::SecurityAccessDenied) => Ok(Self::SecurityAccessDenied),
+                Some(code) => Ok(Self::Negative(code)),
+                None if e.is_timeout() => Ok(Self::NoResponse),
+                None if matches!(e, UdsError::InvalidResponse(_)) => Ok(Self::InvalidResponse),
+                None => Err(e),
+            },
+        }
+    }
+
+    /// Gets the negative response code the ECU answered with, if it answered negatively.
+    pub fn negative_response_code(&self) -> Option<NegativeResponseCode> {
+        match self {
+            Self::RequestOutOfRange => Some(NegativeResponseCode::RequestOutOfRange),
+            Self::SecurityAccessDenied => Some(NegativeResponseCode::SecurityAccessDenied),
+            Self::Negative(code) => Some(*code),
+            _ => None,
+        }
+    }
+
+    /// Whether or not the ECU knows about the data identifier, even if we can't read it.
+    ///
+    /// Only negative responses about the identifier itself count, since rejections of the service
+    /// or the request's format, such as serviceNotSupported, say nothing about the identifier.
+    pub fn is_supported(&self) -> bool {
+        matches!(
+            self,
+            Self::Positive(_)
+                | Self::SecurityAccessDenied
+                | Self::Negative(NegativeResponseCode::ConditionsNotCorrect)
+                | Self::Negative(NegativeResponseCode::ResponseTooLong)
+        )
+    }
+}
+
+impl fmt::Display for DataIdentifierStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Positive(_) => write!(f, "positive"),
+            Self::RequestOutOfRange => write!(f, "requestOutOfRange"),
+            Self::SecurityAccessDenied => write!(f, "securityAccessDenied"),
+            Self::Negative(code) => write!(f, "negative:0x{:02X}", code.as_byte()),
+            Self::NoResponse => write!(f, "noResponse"),
+            Self::InvalidResponse => write!(f, "invalidResponse"),
+        }
+    }
+}
@@ -0,0 +1,110 @@
+use crate::protocol::uds::{
+    client::{ensure_field, UdsClient},
+    error::UdsError,
+};
+
+use super::READ_DATA_BY_IDENTIFIER_SERVICE_ID;
+
+pub struct ReadDataService<'a> {
+    client: &'a mut UdsClient,
+}
+
+impl<'a> ReadDataService<'a> {
+    pub fn new(client: &'a mut UdsClient) -> Self {
+        Self { client }
+    }
+
+    /// Reads the value of the given data identifier.
+    pub async fn read(&mut self, identifier: u16) -> Result<Vec<u8>, UdsError> {
+        let [hi, lo] = identifier.to_be_bytes();
+        let response = self
+            .client
+            .request(&[READ_DATA_BY_IDENTIFIER_SERVICE_ID, hi, lo])
+            .await?;
+
+        // Positive responses echo back the identifier before the data itself.
+        ensure_field(&response, 1, hi)?;
+        ensure_field(&response, 2, lo)?;
+
+        Ok(response[3..].to_vec())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::protocol::{
+        transport::mock::{standard_target, FakeEcu},
+        uds::{error::NegativeResponseCode, services::DataIdentifierStatus},
+    };
+
+    use super::*;
+
+    #[tokio::test]
+    async fn classifies_identifiers() {
+        let mut client = FakeEcu::new(standard_target(0x7E0))
+            .respond(&[0x22, 0xF1, 0x90], &[&[0x62, 0xF1, 0x90, 0x57, 0x30]])
+            .reject(&[0x22, 0xF1, 0x91], NegativeResponseCode::RequestOutOfRange)
+            .reject(
+                &[0x22, 0xF1, 0x92],
+                NegativeResponseCode::SecurityAccessDenied,
+            )
+            .reject(
+                &[0x22, 0xF1, 0x93],
+                NegativeResponseCode::ConditionsNotCorrect,
+            )
+            .respond(&[0x22, 0xF1, 0x94], &[])
+            .respond(&[0x22, 0xF1, 0x95], &[&[0x62, 0xF1, 0x96, 0x00]])
+            .connect();
+        let mut service = ReadDataService::new(&mut client);
+
+        let mut statuses = Vec::new();
+        for identifier in 0xF190..=0xF195 {
+            statuses.push(DataIdentifierStatus::classify(service.read(identifier).await).unwrap());
+        }
+        assert_eq!(
+            statuses,
+            vec![
+                DataIdentifierStatus::Positive(vec![0x57, 0x30]),
+                DataIdentifierStatus::RequestOutOfRange,
+                DataIdentifierStatus::SecurityAccessDenied,
+                DataIdentifierStatus::Negative(NegativeResponseCode::ConditionsNotCorrect),
+                DataIdentifierStatus::NoResponse,
+                DataIdentifierStatus::InvalidResponse,
+            ]
+        );
+        assert_eq!(
+            statuses
+                .iter()
+                .map(DataIdentifierStatus::is_supported)
+                .collect::<Vec<_>>(),
+            vec![true, false, true, true, false, false]
+        );
+    }
+
+    #[tokio::test]
+    async fn service_level_rejections_are_not_supported_identifiers() {
+        let mut client = FakeEcu::new(standard_target(0x7E0))
+            .reject(
+                &[0x22, 0xF1, 0x90],
+                NegativeResponseCode::ServiceNotSupported,
+            )
+            .reject(
+                &[0x22, 0xF1, 0x91],
+                NegativeResponseCode::IncorrectMessageLengthOrInvalidFormat,
+            )
+            .reject(
+                &[0x22, 0xF1, 0x92],
+                NegativeResponseCode::ServiceNotSupportedInActiveSession,
+            )
+            .reject(&[0x22, 0xF1, 0x93], NegativeResponseCode::ResponseTooLong)
+            .connect();
+        let mut service = ReadDataService::new(&mut client);
+
+        let mut supported = Vec::new();
+        for identifier in 0xF190..=0xF193 {
+            let status = DataIdentifierStatus::classify(service.read(identifier).await).unwrap();
+            supported.push(status.is_supported());
+        }
+        assert_eq!(supported, vec![false, false, false, true]);
+    }
+}
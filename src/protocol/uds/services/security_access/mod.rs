@@ -0,0 +1,5 @@
+mod service;
+
+pub use self::service::SecurityAccessService;
+
+const SECURITY_ACCESS_SERVICE_ID: u8 = 0x27;
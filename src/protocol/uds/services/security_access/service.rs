@@ -0,0 +1,44 @@
+use crate::protocol::uds::{
+    client::{ensure_field, UdsClient},
+    error::UdsError,
+};
+
+use super::SECURITY_ACCESS_SERVICE_ID;
+
+pub struct SecurityAccessService<'a> {
+    client: &'a mut UdsClient,
+}
+
+impl<'a> SecurityAccessService<'a> {
+    pub fn new(client: &'a mut UdsClient) -> Self {
+        Self { client }
+    }
+
+    /// Requests the seed for the given security level.
+    ///
+    /// Security levels are given as the odd "request seed" sub-function.  A seed of all zeroes
+    /// means that the level is already unlocked.
+    pub async fn request_seed(&mut self, level: u8) -> Result<Vec<u8>, UdsError> {
+        let response = self
+            .client
+            .request(&[SECURITY_ACCESS_SERVICE_ID, level])
+            .await?;
+        ensure_field(&response, 1, level)?;
+
+        Ok(response[2..].to_vec())
+    }
+
+    /// Sends the key for the given security level, unlocking it if the key is accepted.
+    ///
+    /// The level is the same odd "request seed" sub-function the seed was requested with, with the
+    /// key itself being sent with the even "send key" sub-function that follows it.
+    pub async fn send_key(&mut self, level: u8, key: &[u8]) -> Result<(), UdsError> {
+        let sub_function = level.wrapping_add(1);
+        let mut request = vec![SECURITY_ACCESS_SERVICE_ID, sub_function];
+        request.extend_from_slice(key);
+        let response = self.client.request(&request).await?;
+        ensure_field(&response, 1, sub_function)?;
+
+        Ok(())
+    }
+}
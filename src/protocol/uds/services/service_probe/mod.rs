@@ -0,0 +1,85 @@
+mod service;
+use core::fmt;
+
+use crate::protocol::uds::error::{NegativeResponseCode, UdsError};
+
+pub use self::service::ServiceProbe;
+
+/// Outcome of probing a single service ID, used when mapping which services an ECU supports.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ServiceStatus {
+    /// The ECU responded positively.
+    Positive,
+    /// The ECU rejected the request itself, but not the service, such as with
+    /// incorrectMessageLengthOrInvalidFormat.
+    Supported(NegativeResponseCode),
+    /// The ECU does not support the service at all.
+    NotSupported,
+    /// The ECU supports the service, but not in the active session.
+    NotSupportedInSession,
+    NoResponse,
+}
+
+impl ServiceStatus {
+    /// Classifies the result of probing a service.
+    ///
+    /// Errors which don't tell us anything about the service itself, such as a socket failing,
+    /// are passed back to the caller.
+    pub fn classify(result: Result<Vec<u8>, UdsError>) -> Result<Self, UdsError> {
+        match result {
+            Ok(_) => Ok(Self::Positive),
+            Err(e) => match e.negative_response_code() {
+                Some(NegativeResponseCode::ServiceNotSupported) => Ok(Self::NotSupported),
+                Some(NegativeResponseCode::ServiceNotSupportedInActiveSession) => {
+                    Ok(Self::NotSupportedInSession)
+                }
+                Some(code) => Ok(Self::Supported(code)),
+                None if e.is_timeout() => Ok(Self::NoResponse),
+                None => Err(e),
+            },
+        }
+    }
+
+    /// Classifies the result of probing one of a service's sub-functions.
+    ///
+    /// The sub-function not being supported, in general or in the active session, counts the same
+    /// as the service not being supported.  Anything else means that the sub-function exists, even
+    /// if the ECU won't carry it out right now, such as with securityAccessDenied.
+    pub fn classify_sub_function(result: Result<Vec<u8>, UdsError>) -> Result<Self, UdsError> {
+        Ok(match Self::classify(result)? {
+            Self::Supported(NegativeResponseCode::SubFunctionNotSupported) => Self::NotSupported,
+            Self::Supported(NegativeResponseCode::SubFunctionNotSupportedInActiveSession) => {
+                Self::NotSupportedInSession
+            }
+            status => status,
+        })
+    }
+
+    /// Whether or not the service is available in the session it was probed in.
+    pub fn is_available(&self) -> bool {
+        matches!(self, Self::Positive | Self::Supported(_))
+    }
+
+    /// Short label for use in the session/service matrix.
+    pub fn label(&self) -> String {
+        match self {
+            Self::Positive => "OK".to_string(),
+            Self::Supported(code) => format!("{:02X}", code.as_byte()),
+            Self::NotSupported => "-".to_string(),
+            Self::NotSupportedInSession => "S".to_string(),
+            Self::NoResponse => "?".to_string(),
+        }
+    }
+}
+
+impl fmt::Display for ServiceStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Positive => write!(f, "positive"),
+            Self::Supported(code) => write!(f, "supported ({})", code),
+            Self::NotSupported => write!(f, "serviceNotSupported"),
+            Self::NotSupportedInSession => write!(f, "serviceNotSupportedInActiveSession"),
+            Self::NoResponse => write!(f, "noResponse"),
+        }
+    }
+}
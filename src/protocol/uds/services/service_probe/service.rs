@@ -0,0 +1,120 @@
+use crate::protocol::uds::{client::UdsClient, error::UdsError};
+
+use super::ServiceStatus;
+
+pub struct ServiceProbe<'a> {
+    client: &'a mut UdsClient,
+}
+
+impl<'a> ServiceProbe<'a> {
+    pub fn new(client: &'a mut UdsClient) -> Self {
+        Self { client }
+    }
+
+    /// Probes whether or not the ECU supports the given service in the active session.
+    ///
+    /// Only the service ID itself is sent, which is enough for almost every service to either be
+    /// rejected outright, or rejected for being too short, without actually doing anything.
+    pub async fn probe(&mut self, service_id: u8) -> Result<ServiceStatus, UdsError> {
+        ServiceStatus::classify(self.client.request(&[service_id]).await)
+    }
+
+    /// Probes whether or not the ECU supports the given sub-function of a service in the active
+    /// session, sending nothing but the service ID and sub-function.
+    pub async fn probe_sub_function(
+        &mut self,
+        service_id: u8,
+        sub_function: u8,
+    ) -> Result<ServiceStatus, UdsError> {
+        ServiceStatus::classify_sub_function(self.client.request(&[service_id, sub_function]).await)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::protocol::{
+        transport::mock::{standard_target, FakeEcu},
+        uds::error::NegativeResponseCode,
+    };
+
+    use super::*;
+
+    #[tokio::test]
+    async fn classifies_services() {
+        let mut client = FakeEcu::new(standard_target(0x7E0))
+            .respond(&[0x3E], &[&[0x7E]])
+            .reject(
+                &[0x22],
+                NegativeResponseCode::IncorrectMessageLengthOrInvalidFormat,
+            )
+            .reject(
+                &[0x2E],
+                NegativeResponseCode::ServiceNotSupportedInActiveSession,
+            )
+            .respond(&[0x31], &[])
+            .connect();
+        let mut probe = ServiceProbe::new(&mut client);
+
+        let mut statuses = Vec::new();
+        for service_id in [0x3E, 0x22, 0x2E, 0x85, 0x31] {
+            statuses.push(probe.probe(service_id).await.unwrap());
+        }
+        assert_eq!(
+            statuses,
+            vec![
+                ServiceStatus::Positive,
+                ServiceStatus::Supported(
+                    NegativeResponseCode::IncorrectMessageLengthOrInvalidFormat
+                ),
+                ServiceStatus::NotSupportedInSession,
+                ServiceStatus::NotSupported,
+                ServiceStatus::NoResponse,
+            ]
+        );
+        assert_eq!(
+            statuses
+                .iter()
+                .map(ServiceStatus::is_available)
+                .collect::<Vec<_>>(),
+            vec![true, true, false, false, false]
+        );
+    }
+
+    #[tokio::test]
+    async fn classifies_sub_functions() {
+        let mut client = FakeEcu::new(standard_target(0x7E0))
+            .respond(&[0x19, 0x0A], &[&[0x59, 0x0A, 0xFF]])
+            .reject(
+                &[0x19, 0x02],
+                NegativeResponseCode::IncorrectMessageLengthOrInvalidFormat,
+            )
+            .reject(&[0x19, 0x42], NegativeResponseCode::SubFunctionNotSupported)
+            .reject(
+                &[0x31, 0x01],
+                NegativeResponseCode::SubFunctionNotSupportedInActiveSession,
+            )
+            .connect();
+        let mut probe = ServiceProbe::new(&mut client);
+
+        let mut statuses = Vec::new();
+        for (service_id, sub_function) in [(0x19, 0x0A), (0x19, 0x02), (0x19, 0x42), (0x31, 0x01)] {
+            statuses.push(
+                probe
+                    .probe_sub_function(service_id, sub_function)
+                    .await
+                    .unwrap(),
+            );
+        }
+        assert_eq!(
+            statuses,
+            vec![
+                ServiceStatus::Positive,
+                ServiceStatus::Supported(
+                    NegativeResponseCode::IncorrectMessageLengthOrInvalidFormat
+                ),
+                ServiceStatus::NotSupported,
+                ServiceStatus::NotSupportedInSession,
+            ]
+        );
+    }
+}
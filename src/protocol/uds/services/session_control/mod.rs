@@ -0,0 +1,7 @@
+mod service;
+
+pub use self::service::{SessionControlService, SessionTimings};
+
+const DIAGNOSTIC_SESSION_CONTROL_SERVICE_ID: u8 = 0x10;
+
+pub const DEFAULT_SESSION: u8 = 0x01;
@@ -0,0 +1,46 @@
+use std::time::Duration;
+
+use crate::protocol::uds::{
+    client::{ensure_field, ensure_length, UdsClient},
+    error::UdsError,
+};
+
+use super::DIAGNOSTIC_SESSION_CONTROL_SERVICE_ID;
+
+/// Timing parameters reported by the ECU when changing sessions.
+#[derive(Clone, Copy, Debug)]
+pub struct SessionTimings {
+    /// Maximum time the ECU takes to start responding to a request.
+    pub p2: Duration,
+    /// Maximum time the ECU takes to respond after signalling that a response is pending.
+    pub p2_extended: Duration,
+}
+
+pub struct SessionControlService<'a> {
+    client: &'a mut UdsClient,
+}
+
+impl<'a> SessionControlService<'a> {
+    pub fn new(client: &'a mut UdsClient) -> Self {
+        Self { client }
+    }
+
+    /// Changes to the given diagnostic session.
+    pub async fn change(&mut self, session: u8) -> Result<SessionTimings, UdsError> {
+        let response = self
+            .client
+            .request(&[DIAGNOSTIC_SESSION_CONTROL_SERVICE_ID, session])
+            .await?;
+        ensure_field(&response, 1, session)?;
+        ensure_length(&response, 6)?;
+
+        // P2 is given in milliseconds, while P2* is given in units of ten milliseconds.
+        let p2 = u16::from_be_bytes([response[2], response[3]]);
+        let p2_extended = u16::from_be_bytes([response[4], response[5]]);
+
+        Ok(SessionTimings {
+            p2: Duration::from_millis(p2 as u64),
+            p2_extended: Duration::from_millis(p2_extended as u64 * 10),
+        })
+    }
+}
@@ -0,0 +1,5 @@
+mod service;
+
+pub use self::service::TesterPresentService;
+
+const TESTER_PRESENT_SERVICE_ID: u8 = 0x3E;
@@ -0,0 +1,27 @@
+use crate::protocol::uds::{
+    client::{ensure_field, UdsClient},
+    error::UdsError,
+};
+
+use super::TESTER_PRESENT_SERVICE_ID;
+
+pub struct TesterPresentService<'a> {
+    client: &'a mut UdsClient,
+}
+
+impl<'a> TesterPresentService<'a> {
+    pub fn new(client: &'a mut UdsClient) -> Self {
+        Self { client }
+    }
+
+    /// Checks that the ECU is still responding, keeping the active session alive.
+    pub async fn ping(&mut self) -> Result<(), UdsError> {
+        let response = self
+            .client
+            .request(&[TESTER_PRESENT_SERVICE_ID, 0x00])
+            .await?;
+        ensure_field(&response, 1, 0x00)?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,742 @@
+use std::collections::HashMap;
+
+use rand::RngCore;
+use tracing::debug;
+
+use crate::protocol::uds::{
+    client::{NEGATIVE_RESPONSE_SERVICE_ID, POSITIVE_RESPONSE_OFFSET},
+    error::NegativeResponseCode,
+    services::{verify_ownership, verify_validity, CredentialError, DEFAULT_SESSION},
+};
+
+use super::profile::{EcuProfile, SecurityLevel};
+
+const CURRENT_DATA_SERVICE_ID: u8 = 0x01;
+const STORED_DTCS_SERVICE_ID: u8 = 0x03;
+const CLEAR_DTCS_SERVICE_ID: u8 = 0x04;
+const VEHICLE_INFORMATION_SERVICE_ID: u8 = 0x09;
+const DIAGNOSTIC_SESSION_CONTROL_SERVICE_ID: u8 = 0x10;
+const CLEAR_DIAGNOSTIC_INFORMATION_SERVICE_ID: u8 = 0x14;
+const READ_DTC_INFORMATION_SERVICE_ID: u8 = 0x19;
+const READ_DATA_BY_IDENTIFIER_SERVICE_ID: u8 = 0x22;
+const SECURITY_ACCESS_SERVICE_ID: u8 = 0x27;
+const AUTHENTICATION_SERVICE_ID: u8 = 0x29;
+const TESTER_PRESENT_SERVICE_ID: u8 = 0x3E;
+
+/// Largest number of PIDs a single OBD-II request can ask for.
+const MAX_PIDS_PER_REQUEST: usize = 6;
+
+const PID_QUERY_STRIDE: u8 = 0x20;
+const VIN_INFOTYPE: u8 = 0x02;
+
+const VIN_IDENTIFIER: u16 = 0xF190;
+const ACTIVE_DIAGNOSTIC_SESSION_IDENTIFIER: u16 = 0xF186;
+
+/// Bit of a sub-function that asks for the positive response to be left out.
+const SUPPRESS_POSITIVE_RESPONSE: u8 = 0x80;
+
+/// P2 and P2* as reported when changing sessions, in milliseconds and tens of milliseconds.
+const SESSION_TIMINGS: [u8; 4] = [0x00, 0x32, 0x01, 0xF4];
+
+const REPORT_NUMBER_OF_DTC_BY_STATUS_MASK: u8 = 0x01;
+const REPORT_DTC_BY_STATUS_MASK: u8 = 0x02;
+
+/// Status bits we support, and report every stored DTC with. (confirmed)
+const DTC_STATUS: u8 = 0x08;
+const DTC_STATUS_AVAILABILITY_MASK: u8 = 0x08;
+const DTC_FORMAT_ISO_15031_6: u8 = 0x00;
+
+const DE_AUTHENTICATE: u8 = 0x00;
+const VERIFY_CERTIFICATE_UNIDIRECTIONAL: u8 = 0x01;
+const VERIFY_CERTIFICATE_BIDIRECTIONAL: u8 = 0x02;
+const PROOF_OF_OWNERSHIP: u8 = 0x03;
+const AUTHENTICATION_CONFIGURATION: u8 = 0x08;
+
+const CONFIGURATION_CERTIFICATE_EXCHANGE: u8 = 0x02;
+const DE_AUTHENTICATION_SUCCESSFUL: u8 = 0x10;
+const CERTIFICATE_VERIFIED_OWNERSHIP_VERIFICATION_NECESSARY: u8 = 0x11;
+const OWNERSHIP_VERIFIED_AUTHENTICATION_COMPLETE: u8 = 0x12;
+
+const SERVER_CHALLENGE_LENGTH: usize = 32;
+
+enum Reply {
+    Positive(Vec<u8>),
+    Negative(NegativeResponseCode),
+    Silent,
+}
+
+/// An ECU answering requests according to its profile.
+///
+/// OBD-II requests for things the ECU doesn't support go unanswered, as ISO 15765-4 requires,
+/// while UDS requests get the negative response that ISO 14229-1 calls for.  Functional requests
+/// never get a negative response for an unsupported service, sub-function or identifier, as every
+/// ECU that doesn't support it would otherwise answer.
+pub struct SimulatedEcu {
+    profile: EcuProfile,
+    session: u8,
+    unlocked_level: Option<u8>,
+    seed_level: Option<u8>,
+    pid_positions: HashMap<u8, usize>,
+    injected_remaining: Vec<Option<u32>>,
+    /// Certificate of the tester we're authenticating, and the challenge it has to sign.
+    pending_authentication: Option<(Vec<u8>, Vec<u8>)>,
+}
+
+impl SimulatedEcu {
+    pub fn new(profile: EcuProfile) -> Self {
+        let injected_remaining = profile
+            .injected_responses
+            .iter()
+            .map(|injected| injected.count)
+            .collect();
+
+        Self {
+            profile,
+            session: DEFAULT_SESSION,
+            unlocked_level: None,
+            seed_level: None,
+            pid_positions: HashMap::new(),
+            injected_remaining,
+            pending_authentication: None,
+        }
+    }
+
+    pub fn profile(&self) -> &EcuProfile {
+        &self.profile
+    }
+
+    /// Answers a request, returning the responses to send back, in order.
+    pub fn answer(&mut self, request: &[u8], is_functional: bool) -> Vec<Vec<u8>> {
+        let service_id = match request.first() {
+            Some(service_id) => *service_id,
+            None => return Vec::new(),
+        };
+
+        let mut responses = Vec::new();
+        if let Some(code) = self.injected_response(request) {
+            debug!(
+                "{}: injecting {} for {:02X?}",
+                self.profile.name, code, request
+            );
+            responses.push(negative_response(service_id, code));
+
+            // Pending responses are followed by the real thing, same as on a real ECU.
+            if code != NegativeResponseCode::RequestCorrectlyReceivedResponsePending {
+                return responses;
+            }
+        }
+
+        match self.reply(service_id, request) {
+            Reply::Positive(response) => responses.push(response),
+            Reply::Negative(code) if is_functional && is_suppressed_functionally(code) => {}
+            Reply::Negative(code) => responses.push(negative_response(service_id, code)),
+            Reply::Silent => {}
+        }
+        responses
+    }
+
+    /// Gets the negative response to inject for this request, if any.
+    fn injected_response(&mut self, request: &[u8]) -> Option<NegativeResponseCode> {
+        let injected = self
+            .profile
+            .injected_responses
+            .iter()
+            .zip(self.injected_remaining.iter_mut())
+            .find(|(injected, remaining)| {
+                request.starts_with(&injected.request) && **remaining != Some(0)
+            });
+
+        match injected {
+            Some((injected, remaining)) => {
+                if let Some(remaining) = remaining {
+                    *remaining -= 1;
+                }
+                Some(injected.code)
+            }
+            None => None,
+        }
+    }
+
+    fn reply(&mut self, service_id: u8, request: &[u8]) -> Reply {
+        match service_id {
+            CURRENT_DATA_SERVICE_ID => self.current_data(&request[1..]),
+            STORED_DTCS_SERVICE_ID => self.stored_dtcs(),
+            CLEAR_DTCS_SERVICE_ID => self.clear_dtcs(),
+            VEHICLE_INFORMATION_SERVICE_ID => self.vehicle_information(&request[1..]),
+            DIAGNOSTIC_SESSION_CONTROL_SERVICE_ID => {
+                with_sub_function(request, |sub_function| self.change_session(sub_function))
+            }
+            TESTER_PRESENT_SERVICE_ID => with_sub_function(request, |sub_function| {
+                if sub_function == 0x00 {
+                    Reply::Positive(vec![positive_service_id(TESTER_PRESENT_SERVICE_ID), 0x00])
+                } else {
+                    Reply::Negative(NegativeResponseCode::SubFunctionNotSupported)
+                }
+            }),
+            SECURITY_ACCESS_SERVICE_ID => {
+                let key = request.get(2..).unwrap_or_default();
+                with_sub_function(request, |sub_function| {
+                    self.security_access(sub_function, key)
+                })
+            }
+            AUTHENTICATION_SERVICE_ID => {
+                let parameters = request.get(2..).unwrap_or_default();
+                with_sub_function(request, |sub_function| {
+                    self.authentication(sub_function, parameters)
+                })
+            }
+            READ_DATA_BY_IDENTIFIER_SERVICE_ID => self.read_data(&request[1..]),
+            READ_DTC_INFORMATION_SERVICE_ID => self.read_dtc_information(&request[1..]),
+            CLEAR_DIAGNOSTIC_INFORMATION_SERVICE_ID => {
+                if request.len() != 4 {
+                    return Reply::Negative(
+                        NegativeResponseCode::IncorrectMessageLengthOrInvalidFormat,
+                    );
+                }
+                self.profile.dtcs.clear();
+                Reply::Positive(vec![positive_service_id(
+                    CLEAR_DIAGNOSTIC_INFORMATION_SERVICE_ID,
+                )])
+            }
+            // OBD-II services that we don't support are left unanswered, like any other OBD-II
+            // request we can't answer.
+            service_id if service_id < DIAGNOSTIC_SESSION_CONTROL_SERVICE_ID => Reply::Silent,
+            _ => Reply::Negative(NegativeResponseCode::ServiceNotSupported),
+        }
+    }
+
+    fn is_obd_compliant(&self) -> bool {
+        !self.profile.pids.is_empty() || self.profile.vin.is_some()
+    }
+
+    fn current_data(&mut self, pids: &[u8]) -> Reply {
+        if pids.is_empty() || pids.len() > MAX_PIDS_PER_REQUEST {
+            return Reply::Silent;
+        }
+
+        let mut response = vec![positive_service_id(CURRENT_DATA_SERVICE_ID)];
+        for pid in pids {
+            let data = if pid % PID_QUERY_STRIDE == 0 {
+                self.supported_pids(*pid)
+            } else {
+                self.pid_value(*pid)
+            };
+
+            if let Some(data) = data {
+                response.push(*pid);
+                response.extend(data);
+            }
+        }
+
+        if response.len() == 1 {
+            Reply::Silent
+        } else {
+            Reply::Positive(response)
+        }
+    }
+
+    /// Reports which of the PIDs after `query_pid` are supported, if there are any at all.
+    fn supported_pids(&self, query_pid: u8) -> Option<Vec<u8>> {
+        let query_pid = query_pid as u16;
+        let stride = PID_QUERY_STRIDE as u16;
+        let mut data = vec![0u8; 4];
+        let mut any = false;
+        for pid in self.profile.pids.keys().map(|pid| *pid as u16) {
+            if pid > query_pid && pid <= query_pid + stride {
+                let bit = (pid - query_pid - 1) as usize;
+                data[bit / 8] |= 0x80 >> (bit % 8);
+                any = true;
+            } else if pid > query_pid + stride {
+                // The next query PID is supported, as it has supported PIDs of its own to report.
+                data[3] |= 0x01;
+                any = true;
+            }
+        }
+
+        if any || (query_pid == 0 && self.is_obd_compliant()) {
+            Some(data)
+        } else {
+            None
+        }
+    }
+
+    /// Gets the next value of a PID, stepping through its script.
+    fn pid_value(&mut self, pid: u8) -> Option<Vec<u8>> {
+        let values = self.profile.pids.get(&pid)?;
+        let position = self.pid_positions.entry(pid).or_insert(0);
+        let value = values[*position % values.len()].clone();
+        *position = (*position + 1) % values.len();
+        Some(value)
+    }
+
+    fn stored_dtcs(&self) -> Reply {
+        if !self.is_obd_compliant() {
+            return Reply::Silent;
+        }
+
+        // Over CAN, the number of DTCs comes first, as the response may span several frames.
+        let mut response = vec![
+            positive_service_id(STORED_DTCS_SERVICE_ID),
+            self.profile.dtcs.len().min(u8::MAX as usize) as u8,
+        ];
+        for dtc in self.profile.dtcs.iter().take(u8::MAX as usize) {
+            response.extend_from_slice(&dtc.to_be_bytes());
+        }
+        Reply::Positive(response)
+    }
+
+    fn clear_dtcs(&mut self) -> Reply {
+        if !self.is_obd_compliant() {
+            return Reply::Silent;
+        }
+
+        self.profile.dtcs.clear();
+        Reply::Positive(vec![positive_service_id(CLEAR_DTCS_SERVICE_ID)])
+    }
+
+    fn vehicle_information(&self, request: &[u8]) -> Reply {
+        let vin = match &self.profile.vin {
+            Some(vin) => vin,
+            None => return Reply::Silent,
+        };
+
+        let service_id = positive_service_id(VEHICLE_INFORMATION_SERVICE_ID);
+        match request {
+            // Only the VIN is supported.
+            [0x00] => Reply::Positive(vec![service_id, 0x00, 0x40, 0x00, 0x00, 0x00]),
+            [VIN_INFOTYPE] => {
+                let mut response = vec![service_id, VIN_INFOTYPE, 0x01];
+                response.extend_from_slice(vin.as_bytes());
+                Reply::Positive(response)
+            }
+            _ => Reply::Silent,
+        }
+    }
+
+    fn change_session(&mut self, session: u8) -> Reply {
+        if !self.profile.sessions.contains(&session) {
+            return Reply::Negative(NegativeResponseCode::SubFunctionNotSupported);
+        }
+
+        // Changing sessions always locks the ECU again.
+        self.session = session;
+        self.unlocked_level = None;
+        self.seed_level = None;
+
+        let mut response = vec![
+            positive_service_id(DIAGNOSTIC_SESSION_CONTROL_SERVICE_ID),
+            session,
+        ];
+        response.extend_from_slice(&SESSION_TIMINGS);
+        Reply::Positive(response)
+    }
+
+    fn security_access(&mut self, sub_function: u8, key: &[u8]) -> Reply {
+        let service_id = positive_service_id(SECURITY_ACCESS_SERVICE_ID);
+
+        // Odd sub-functions request a seed, and the even sub-function after each sends its key.
+        let is_request_seed = sub_function % 2 == 1;
+        let level = if is_request_seed {
+            sub_function
+        } else {
+            sub_function.wrapping_sub(1)
+        };
+        let security_level = match self.security_level(level) {
+            Some(security_level) => security_level,
+            None => return Reply::Negative(NegativeResponseCode::SubFunctionNotSupported),
+        };
+
+        if is_request_seed {
+            let mut response = vec![service_id, sub_function];
+            if self.unlocked_level == Some(level) {
+                response.extend(vec![0x00; security_level.seed.len()]);
+            } else {
+                response.extend_from_slice(&security_level.seed);
+                self.seed_level = Some(level);
+            }
+            return Reply::Positive(response);
+        }
+
+        if self.seed_level != Some(level) {
+            return Reply::Negative(NegativeResponseCode::RequestSequenceError);
+        }
+        self.seed_level = None;
+
+        if key != security_level.key.as_slice() {
+            return Reply::Negative(NegativeResponseCode::InvalidKey);
+        }
+
+        self.unlocked_level = Some(level);
+        Reply::Positive(vec![service_id, sub_function])
+    }
+
+    fn security_level(&self, level: u8) -> Option<&SecurityLevel> {
+        self.profile
+            .security_levels
+            .iter()
+            .find(|security_level| security_level.level == level)
+    }
+
+    fn authentication(&mut self, sub_function: u8, parameters: &[u8]) -> Reply {
+        let credentials = match &self.profile.authentication {
+            Some(credentials) => credentials,
+            None => return Reply::Negative(NegativeResponseCode::ServiceNotSupported),
+        };
+
+        let service_id = positive_service_id(AUTHENTICATION_SERVICE_ID);
+        match sub_function {
+            AUTHENTICATION_CONFIGURATION => Reply::Positive(vec![
+                service_id,
+                sub_function,
+                CONFIGURATION_CERTIFICATE_EXCHANGE,
+            ]),
+            DE_AUTHENTICATE => {
+                self.pending_authentication = None;
+                Reply::Positive(vec![service_id, sub_function, DE_AUTHENTICATION_SUCCESSFUL])
+            }
+            VERIFY_CERTIFICATE_UNIDIRECTIONAL | VERIFY_CERTIFICATE_BIDIRECTIONAL => {
+                // The communication configuration comes first, which we ignore.
+                let mut fields = parameters.get(1..).unwrap_or_default();
+                let (certificate, challenge) = match (
+                    split_length_prefixed(&mut fields),
+                    split_length_prefixed(&mut fields),
+                ) {
+                    (Some(certificate), Some(challenge)) if fields.is_empty() => {
+                        (certificate, challenge)
+                    }
+                    _ => {
+                        return Reply::Negative(
+                            NegativeResponseCode::IncorrectMessageLengthOrInvalidFormat,
+                        )
+                    }
+                };
+
+                match verify_validity(certificate) {
+                    Ok(()) => {}
+                    Err(CredentialError::OutsideValidityPeriod) => {
+                        return Reply::Negative(
+                            NegativeResponseCode::CertificateVerificationFailedInvalidTimePeriod,
+                        )
+                    }
+                    Err(_) => {
+                        return Reply::Negative(
+                            NegativeResponseCode::CertificateVerificationFailedInvalidFormat,
+                        )
+                    }
+                }
+
+                let mut server_challenge = vec![0; SERVER_CHALLENGE_LENGTH];
+                rand::thread_rng().fill_bytes(&mut server_challenge);
+
+                let mut response = vec![
+                    service_id,
+                    sub_function,
+                    CERTIFICATE_VERIFIED_OWNERSHIP_VERIFICATION_NECESSARY,
+                ];
+                push_length_prefixed(&mut response, &server_challenge);
+                if sub_function == VERIFY_CERTIFICATE_BIDIRECTIONAL {
+                    push_length_prefixed(&mut response, credentials.certificate());
+                    push_length_prefixed(&mut response, &credentials.prove_ownership(challenge));
+                }
+                // We don't establish session keys, so there is no ephemeral public key.
+                push_length_prefixed(&mut response, &[]);
+
+                self.pending_authentication = Some((certificate.to_vec(), server_challenge));
+                Reply::Positive(response)
+            }
+            PROOF_OF_OWNERSHIP => {
+                let (certificate, server_challenge) = match self.pending_authentication.take() {
+                    Some(pending) => pending,
+                    None => return Reply::Negative(NegativeResponseCode::RequestSequenceError),
+                };
+
+                let mut fields = parameters;
+                let proof = match split_length_prefixed(&mut fields) {
+                    Some(proof) => proof,
+                    None => {
+                        return Reply::Negative(
+                            NegativeResponseCode::IncorrectMessageLengthOrInvalidFormat,
+                        )
+                    }
+                };
+                if verify_ownership(&certificate, &server_challenge, proof).is_err() {
+                    return Reply::Negative(NegativeResponseCode::OwnershipVerificationFailed);
+                }
+
+                // No session key information, as there is no session key.
+                let mut response = vec![
+                    service_id,
+                    sub_function,
+                    OWNERSHIP_VERIFIED_AUTHENTICATION_COMPLETE,
+                ];
+                push_length_prefixed(&mut response, &[]);
+                Reply::Positive(response)
+            }
+            _ => Reply::Negative(NegativeResponseCode::SubFunctionNotSupported),
+        }
+    }
+
+    fn read_data(&self, identifiers: &[u8]) -> Reply {
+        if identifiers.is_empty() || identifiers.len() % 2 != 0 {
+            return Reply::Negative(NegativeResponseCode::IncorrectMessageLengthOrInvalidFormat);
+        }
+
+        // Identifiers we don't support are left out, unless we don't support any of them.
+        let mut response = vec![positive_service_id(READ_DATA_BY_IDENTIFIER_SERVICE_ID)];
+        for identifier in identifiers.chunks(2) {
+            let identifier = u16::from_be_bytes([identifier[0], identifier[1]]);
+            if let Some(value) = self.data_identifier(identifier) {
+                response.extend_from_slice(&identifier.to_be_bytes());
+                response.extend(value);
+            }
+        }
+
+        if response.len() == 1 {
+            Reply::Negative(NegativeResponseCode::RequestOutOfRange)
+        } else {
+            Reply::Positive(response)
+        }
+    }
+
+    fn data_identifier(&self, identifier: u16) -> Option<Vec<u8>> {
+        if let Some(value) = self.profile.dids.get(&identifier) {
+            return Some(value.clone());
+        }
+
+        match identifier {
+            VIN_IDENTIFIER => self.profile.vin.as_ref().map(|vin| vin.as_bytes().to_vec()),
+            ACTIVE_DIAGNOSTIC_SESSION_IDENTIFIER => Some(vec![self.session]),
+            _ => None,
+        }
+    }
+
+    fn read_dtc_information(&self, request: &[u8]) -> Reply {
+        let (sub_function, status_mask) = match request {
+            [sub_function, status_mask] => (*sub_function, *status_mask),
+            [REPORT_NUMBER_OF_DTC_BY_STATUS_MASK, ..] | [REPORT_DTC_BY_STATUS_MASK, ..] => {
+                return Reply::Negative(NegativeResponseCode::IncorrectMessageLengthOrInvalidFormat)
+            }
+            _ => return Reply::Negative(NegativeResponseCode::SubFunctionNotSupported),
+        };
+
+        let service_id = positive_service_id(READ_DTC_INFORMATION_SERVICE_ID);
+        let dtcs: &[u16] = if status_mask & DTC_STATUS != 0 {
+            self.profile.dtcs.as_slice()
+        } else {
+            &[]
+        };
+        match sub_function {
+            REPORT_NUMBER_OF_DTC_BY_STATUS_MASK => {
+                let mut response = vec![
+                    service_id,
+                    sub_function,
+                    DTC_STATUS_AVAILABILITY_MASK,
+                    DTC_FORMAT_ISO_15031_6,
+                ];
+                response.extend_from_slice(&(dtcs.len() as u16).to_be_bytes());
+                Reply::Positive(response)
+            }
+            REPORT_DTC_BY_STATUS_MASK => {
+                let mut response = vec![service_id, sub_function, DTC_STATUS_AVAILABILITY_MASK];
+                for dtc in dtcs {
+                    // UDS DTCs have a failure type byte after the OBD-II DTC, which we leave empty.
+                    response.extend_from_slice(&dtc.to_be_bytes());
+                    response.extend_from_slice(&[0x00, DTC_STATUS]);
+                }
+                Reply::Positive(response)
+            }
+            _ => Reply::Negative(NegativeResponseCode::SubFunctionNotSupported),
+        }
+    }
+}
+
+/// Handles a request with a sub-function, leaving out the positive response if asked to.
+fn with_sub_function(request: &[u8], handle: impl FnOnce(u8) -> Reply) -> Reply {
+    let sub_function = match request.get(1) {
+        Some(sub_function) => *sub_function,
+        None => {
+            return Reply::Negative(NegativeResponseCode::IncorrectMessageLengthOrInvalidFormat)
+        }
+    };
+
+    match handle(sub_function & !SUPPRESS_POSITIVE_RESPONSE) {
+        Reply::Positive(_) if sub_function & SUPPRESS_POSITIVE_RESPONSE != 0 => Reply::Silent,
+        reply => reply,
+    }
+}
+
+/// Splits a field with a two byte length in front of it off the start of `data`.
+fn split_length_prefixed<'a>(data: &mut &'a [u8]) -> Option<&'a [u8]> {
+    let length = match *data {
+        [high, low, ..] => u16::from_be_bytes([*high, *low]) as usize,
+        _ => return None,
+    };
+    let field = data.get(2..2 + length)?;
+    *data = &data[2 + length..];
+    Some(field)
+}
+
+fn push_length_prefixed(response: &mut Vec<u8>, data: &[u8]) {
+    response.extend_from_slice(&(data.len() as u16).to_be_bytes());
+    response.extend_from_slice(data);
+}
+
+fn positive_service_id(service_id: u8) -> u8 {
+    service_id + POSITIVE_RESPONSE_OFFSET
+}
+
+fn negative_response(service_id: u8, code: NegativeResponseCode) -> Vec<u8> {
+    vec![NEGATIVE_RESPONSE_SERVICE_ID, service_id, code.as_byte()]
+}
+
+fn is_suppressed_functionally(code: NegativeResponseCode) -> bool {
+    matches!(
+        code,
+        NegativeResponseCode::ServiceNotSupported
+            | NegativeResponseCode::SubFunctionNotSupported
+            | NegativeResponseCode::RequestOutOfRange
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use crate::{
+        common::addressing::Addressing, protocol::transport::mock::standard_target,
+        simulator::profile::InjectedResponse,
+    };
+
+    use super::*;
+
+    fn ecu() -> SimulatedEcu {
+        SimulatedEcu::new(EcuProfile {
+            name: "engine".to_string(),
+            target: standard_target(0x7E0),
+            functional_request_address: Addressing::Standard.functional_request_address(0x33, 0xF1),
+            vin: Some("1HGCM82633A004352".to_string()),
+            pids: BTreeMap::from([
+                (0x05, vec![vec![0x7B]]),
+                (0x0C, vec![vec![0x0F, 0xA0], vec![0x1A, 0xF8]]),
+                (0x2F, vec![vec![0x80]]),
+            ]),
+            dtcs: vec![0x0301, 0x4035],
+            dids: HashMap::new(),
+            sessions: vec![0x01, 0x03],
+            security_levels: vec![SecurityLevel {
+                level: 0x01,
+                seed: vec![0x11, 0x22],
+                key: vec![0xEE, 0xDD],
+            }],
+            injected_responses: vec![InjectedResponse {
+                request: vec![0x31, 0x01],
+                code: NegativeResponseCode::RequestCorrectlyReceivedResponsePending,
+                count: Some(1),
+            }],
+            authentication: None,
+        })
+    }
+
+    #[test]
+    fn reports_supported_pids_and_steps_through_scripts() {
+        let mut ecu = ecu();
+        assert_eq!(
+            ecu.answer(&[0x01, 0x00], true),
+            vec![vec![0x41, 0x00, 0x08, 0x10, 0x00, 0x01]]
+        );
+        assert_eq!(
+            ecu.answer(&[0x01, 0x20], true),
+            vec![vec![0x41, 0x20, 0x00, 0x02, 0x00, 0x00]]
+        );
+        assert!(ecu.answer(&[0x01, 0x40], true).is_empty());
+
+        assert_eq!(
+            ecu.answer(&[0x01, 0x0C, 0x05], false),
+            vec![vec![0x41, 0x0C, 0x0F, 0xA0, 0x05, 0x7B]]
+        );
+        assert_eq!(
+            ecu.answer(&[0x01, 0x0C], false),
+            vec![vec![0x41, 0x0C, 0x1A, 0xF8]]
+        );
+        assert_eq!(
+            ecu.answer(&[0x01, 0x0C], false),
+            vec![vec![0x41, 0x0C, 0x0F, 0xA0]]
+        );
+    }
+
+    #[test]
+    fn reports_and_clears_trouble_codes() {
+        let mut ecu = ecu();
+        assert_eq!(
+            ecu.answer(&[0x03], true),
+            vec![vec![0x43, 0x02, 0x03, 0x01, 0x40, 0x35]]
+        );
+        assert_eq!(
+            ecu.answer(&[0x19, 0x02, 0xFF], false),
+            vec![vec![
+                0x59, 0x02, 0x08, 0x03, 0x01, 0x00, 0x08, 0x40, 0x35, 0x00, 0x08
+            ]]
+        );
+        assert_eq!(ecu.answer(&[0x04], true), vec![vec![0x44]]);
+        assert_eq!(ecu.answer(&[0x03], true), vec![vec![0x43, 0x00]]);
+    }
+
+    #[test]
+    fn unlocks_security_levels_with_the_right_key() {
+        let mut ecu = ecu();
+        assert_eq!(
+            ecu.answer(&[0x27, 0x02, 0xEE, 0xDD], false),
+            vec![vec![0x7F, 0x27, 0x24]]
+        );
+        assert_eq!(
+            ecu.answer(&[0x27, 0x01], false),
+            vec![vec![0x67, 0x01, 0x11, 0x22]]
+        );
+        assert_eq!(
+            ecu.answer(&[0x27, 0x02, 0x00, 0x00], false),
+            vec![vec![0x7F, 0x27, 0x35]]
+        );
+        assert_eq!(
+            ecu.answer(&[0x27, 0x01], false),
+            vec![vec![0x67, 0x01, 0x11, 0x22]]
+        );
+        assert_eq!(
+            ecu.answer(&[0x27, 0x02, 0xEE, 0xDD], false),
+            vec![vec![0x67, 0x02]]
+        );
+        assert_eq!(
+            ecu.answer(&[0x27, 0x01], false),
+            vec![vec![0x67, 0x01, 0x00, 0x00]]
+        );
+
+        // Changing sessions locks the ECU again.
+        assert_eq!(
+            ecu.answer(&[0x10, 0x03], false),
+            vec![vec![0x50, 0x03, 0x00, 0x32, 0x01, 0xF4]]
+        );
+        assert_eq!(
+            ecu.answer(&[0x22, 0xF1, 0x86], false),
+            vec![vec![0x62, 0xF1, 0x86, 0x03]]
+        );
+        assert_eq!(
+            ecu.answer(&[0x27, 0x01], false),
+            vec![vec![0x67, 0x01, 0x11, 0x22]]
+        );
+    }
+
+    #[test]
+    fn injects_negative_responses() {
+        let mut ecu = ecu();
+        assert_eq!(
+            ecu.answer(&[0x31, 0x01, 0xFF, 0x00], false),
+            vec![vec![0x7F, 0x31, 0x78], vec![0x7F, 0x31, 0x11]]
+        );
+        assert_eq!(
+            ecu.answer(&[0x31, 0x01, 0xFF, 0x00], false),
+            vec![vec![0x7F, 0x31, 0x11]]
+        );
+        assert!(ecu.answer(&[0x31, 0x01, 0xFF, 0x00], true).is_empty());
+        assert!(ecu.answer(&[0x3E, 0x80], false).is_empty());
+        assert!(ecu.answer(&[0x22, 0x12, 0x34], true).is_empty());
+    }
+}
@@ -0,0 +1,9 @@
+//! Simulated ECUs, which answer OBD-II and UDS requests according to a profile.
+
+mod ecu;
+mod profile;
+
+pub use self::{
+    ecu::SimulatedEcu,
+    profile::{load_profile, EcuProfile},
+};
@@ -0,0 +1,437 @@
+//! Simulator profiles, which describe the ECUs to simulate.
+//!
+//! Profiles are TOML files with an entry per ECU:
+//!
+//! ```toml
+//! [[ecus]]
+//! name = "engine"
+//! request_id = "0x7E0"
+//! vin = "1HGCM82633A004352"
+//! dtcs = ["P0301", "P0420"]
+//! sessions = ["0x01", "0x03"]
+//!
+//! [ecus.pids]
+//! "0x05" = "7B"
+//! "0x0C" = ["0F A0", "1A F8", "2E E0"]
+//!
+//! [ecus.dids]
+//! "0xF18C" = "31 32 33 34"
+//!
+//! [[ecus.security]]
+//! level = "0x01"
+//! seed = "11 22 33 44"
+//! key = "EE DD CC BB"
+//!
+//! [[ecus.nrcs]]
+//! request = "31 01 FF 00"
+//! code = "0x78"
+//! count = 1
+//!
+//! [ecus.authentication]
+//! certificate = "engine.pem"
+//! private_key = "engine-key.pem"
+//! ```
+//!
+//! As with targets, the response identifier can be left out if it can be derived from the request
+//! identifier.  PID values are either a single value, or a script of values that successive
+//! requests step through, wrapping around at the end.  Injected negative responses apply to every
+//! request starting with the given bytes, for the first `count` requests or forever.  ECUs with a
+//! certificate and private key, given relative to the profile, support authentication.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    io,
+    path::{Path, PathBuf},
+};
+
+use serde::Deserialize;
+use thiserror::Error;
+
+use crate::{
+    common::{
+        addressing::{Addressing, RequestAddress, ResponseAddress},
+        config::CANParameters,
+        parse::{parse_can_id, parse_hex_bytes, parse_hex_u16, parse_hex_u8},
+        targets::Target,
+    },
+    protocol::uds::{
+        error::NegativeResponseCode,
+        services::{Credentials, DEFAULT_SESSION},
+    },
+};
+
+/// Length of a vehicle identification number.
+const VIN_LENGTH: usize = 17;
+
+/// Every ECU supports the default session, whether or not the profile says so.
+/// PIDs which report the PIDs after them, rather than a value of their own.
+const PID_QUERY_STRIDE: u8 = 0x20;
+
+#[derive(Debug, Error)]
+pub enum ProfileError {
+    #[error("failed to read profile '{}': {source}", .path.display())]
+    Io { path: PathBuf, source: io::Error },
+    #[error("failed to parse profile '{}': {source}", .path.display())]
+    Parse {
+        path: PathBuf,
+        source: toml::de::Error,
+    },
+    #[error("profile does not define any ECUs")]
+    NoEcus,
+    #[error("invalid {field_name} for ECU '{ecu}': {reason}")]
+    InvalidField {
+        ecu: String,
+        field_name: &'static str,
+        reason: String,
+    },
+}
+
+/// A security level, and the seed and key that unlock it.
+pub struct SecurityLevel {
+    /// The odd "request seed" sub-function of the level.
+    pub level: u8,
+    pub seed: Vec<u8>,
+    pub key: Vec<u8>,
+}
+
+/// A negative response to send instead of the usual response.
+pub struct InjectedResponse {
+    /// Requests starting with these bytes get the negative response.
+    pub request: Vec<u8>,
+    pub code: NegativeResponseCode,
+    /// How many requests get the negative response, or every one of them if `None`.
+    pub count: Option<u32>,
+}
+
+/// A single simulated ECU.
+pub struct EcuProfile {
+    pub name: String,
+    pub target: Target,
+    /// Address that functional requests, which every ECU answers, are received on.
+    pub functional_request_address: RequestAddress,
+    pub vin: Option<String>,
+    /// Values of each supported PID, which successive requests step through.
+    pub pids: BTreeMap<u8, Vec<Vec<u8>>>,
+    /// Stored diagnostic trouble codes, in their two byte OBD-II form.
+    pub dtcs: Vec<u16>,
+    pub dids: HashMap<u16, Vec<u8>>,
+    pub sessions: Vec<u8>,
+    pub security_levels: Vec<SecurityLevel>,
+    pub injected_responses: Vec<InjectedResponse>,
+    /// Certificate the ECU authenticates testers with, if it supports authentication at all.
+    pub authentication: Option<Credentials>,
+}
+
+#[cfg(test)]
+impl EcuProfile {
+    /// Creates a profile for an ECU which only supports the default session.
+    pub fn empty(name: &str, target: Target) -> Self {
+        Self {
+            name: name.to_string(),
+            target,
+            functional_request_address: Addressing::Standard.functional_request_address(0x33, 0xF1),
+            vin: None,
+            pids: BTreeMap::new(),
+            dtcs: Vec::new(),
+            dids: HashMap::new(),
+            sessions: vec![DEFAULT_SESSION],
+            security_levels: Vec::new(),
+            injected_responses: Vec::new(),
+            authentication: None,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct ProfileFile {
+    #[serde(default)]
+    ecus: Vec<RawEcuProfile>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawEcuProfile {
+    name: String,
+    request_id: String,
+    response_id: Option<String>,
+    functional_id: Option<String>,
+    vin: Option<String>,
+    #[serde(default)]
+    pids: HashMap<String, RawPidValue>,
+    #[serde(default)]
+    dtcs: Vec<String>,
+    #[serde(default)]
+    dids: HashMap<String, String>,
+    #[serde(default)]
+    sessions: Vec<String>,
+    #[serde(default)]
+    security: Vec<RawSecurityLevel>,
+    #[serde(default)]
+    nrcs: Vec<RawInjectedResponse>,
+    authentication: Option<RawAuthentication>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RawPidValue {
+    Static(String),
+    Scripted(Vec<String>),
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawSecurityLevel {
+    level: String,
+    seed: String,
+    key: String,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawInjectedResponse {
+    request: String,
+    code: String,
+    count: Option<u32>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawAuthentication {
+    certificate: PathBuf,
+    private_key: PathBuf,
+}
+
+/// Loads the ECUs to simulate from the given profile.
+///
+/// Functional requests are received on the OBD-II functional identifier matching the width of
+/// each ECU's request identifier, unless the profile says otherwise.
+pub fn load_profile(
+    path: &Path,
+    can_parameters: &CANParameters,
+) -> Result<Vec<EcuProfile>, ProfileError> {
+    let contents = std::fs::read_to_string(path).map_err(|source| ProfileError::Io {
+        path: path.to_path_buf(),
+        source,
+    })?;
+    let file: ProfileFile = toml::from_str(&contents).map_err(|source| ProfileError::Parse {
+        path: path.to_path_buf(),
+        source,
+    })?;
+    if file.ecus.is_empty() {
+        return Err(ProfileError::NoEcus);
+    }
+
+    // Certificates are relative to the profile, rather than wherever we happen to be run from.
+    let base_path = path.parent().unwrap_or_else(|| Path::new(""));
+    file.ecus
+        .into_iter()
+        .map(|raw| raw.validate(can_parameters, base_path))
+        .collect()
+}
+
+impl RawEcuProfile {
+    fn validate(
+        self,
+        can_parameters: &CANParameters,
+        base_path: &Path,
+    ) -> Result<EcuProfile, ProfileError> {
+        let name = self.name;
+        let invalid = |field_name, reason: String| ProfileError::InvalidField {
+            ecu: name.clone(),
+            field_name,
+            reason,
+        };
+
+        let request_id = parse_can_id(&self.request_id).map_err(|e| invalid("request_id", e))?;
+        let request_address = RequestAddress::new(request_id);
+        let response_address = match self.response_id {
+            Some(response_id) => ResponseAddress::new(
+                parse_can_id(&response_id).map_err(|e| invalid("response_id", e))?,
+            ),
+            None => request_address.response_address().ok_or_else(|| {
+                invalid(
+                    "response_id",
+                    "could not be derived, and none was given".to_string(),
+                )
+            })?,
+        };
+
+        let functional_request_address = match self.functional_id {
+            Some(functional_id) => RequestAddress::new(
+                parse_can_id(&functional_id).map_err(|e| invalid("functional_id", e))?,
+            ),
+            None => {
+                let addressing = if request_id.as_raw() > 0x7FF {
+                    Addressing::Extended
+                } else {
+                    Addressing::Standard
+                };
+                addressing.functional_request_address(
+                    can_parameters.functional_address,
+                    can_parameters.tester_address,
+                )
+            }
+        };
+
+        if let Some(vin) = &self.vin {
+            if vin.len() != VIN_LENGTH || !vin.is_ascii() {
+                return Err(invalid(
+                    "vin",
+                    format!("must be {} ASCII characters", VIN_LENGTH),
+                ));
+            }
+        }
+
+        let mut pids = BTreeMap::new();
+        for (pid, value) in self.pids {
+            let pid = parse_hex_u8(&pid).map_err(|e| invalid("pids", e.to_string()))?;
+            if pid % PID_QUERY_STRIDE == 0 {
+                return Err(invalid(
+                    "pids",
+                    format!("PID 0x{:02X} is reported automatically", pid),
+                ));
+            }
+
+            let values = match value {
+                RawPidValue::Static(value) => vec![value],
+                RawPidValue::Scripted(values) if !values.is_empty() => values,
+                RawPidValue::Scripted(_) => {
+                    return Err(invalid(
+                        "pids",
+                        format!("PID 0x{:02X} has an empty script", pid),
+                    ))
+                }
+            };
+            let values = values
+                .iter()
+                .map(|value| parse_hex_bytes(value))
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|e| invalid("pids", e))?;
+            pids.insert(pid, values);
+        }
+
+        let dtcs = self
+            .dtcs
+            .iter()
+            .map(|dtc| parse_dtc(dtc))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| invalid("dtcs", e))?;
+
+        let mut dids = HashMap::new();
+        for (did, value) in self.dids {
+            let did = parse_hex_u16(&did).map_err(|e| invalid("dids", e.to_string()))?;
+            let value = parse_hex_bytes(&value).map_err(|e| invalid("dids", e))?;
+            dids.insert(did, value);
+        }
+
+        let mut sessions = self
+            .sessions
+            .iter()
+            .map(|session| parse_hex_u8(session))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| invalid("sessions", e.to_string()))?;
+        // Every ECU supports the default session, whether or not the profile says so.
+        if !sessions.contains(&DEFAULT_SESSION) {
+            sessions.push(DEFAULT_SESSION);
+        }
+
+        let mut security_levels = Vec::new();
+        for raw in self.security {
+            let level = parse_hex_u8(&raw.level).map_err(|e| invalid("security", e.to_string()))?;
+            if level % 2 == 0 {
+                return Err(invalid(
+                    "security",
+                    format!(
+                        "level 0x{:02X} must be the odd \"request seed\" sub-function",
+                        level
+                    ),
+                ));
+            }
+
+            security_levels.push(SecurityLevel {
+                level,
+                seed: parse_hex_bytes(&raw.seed).map_err(|e| invalid("security", e))?,
+                key: parse_hex_bytes(&raw.key).map_err(|e| invalid("security", e))?,
+            });
+        }
+
+        let mut injected_responses = Vec::new();
+        for raw in self.nrcs {
+            injected_responses.push(InjectedResponse {
+                request: parse_hex_bytes(&raw.request).map_err(|e| invalid("nrcs", e))?,
+                code: parse_hex_u8(&raw.code)
+                    .map(NegativeResponseCode::from)
+                    .map_err(|e| invalid("nrcs", e.to_string()))?,
+                count: raw.count,
+            });
+        }
+
+        let authentication = match self.authentication {
+            Some(raw) => Some(
+                Credentials::load(
+                    &base_path.join(raw.certificate),
+                    &base_path.join(raw.private_key),
+                )
+                .map_err(|e| invalid("authentication", e.to_string()))?,
+            ),
+            None => None,
+        };
+
+        Ok(EcuProfile {
+            name,
+            target: Target {
+                request_address,
+                response_address,
+            },
+            functional_request_address,
+            vin: self.vin,
+            pids,
+            dtcs,
+            dids,
+            sessions,
+            security_levels,
+            injected_responses,
+            authentication,
+        })
+    }
+}
+
+/// Parses a diagnostic trouble code, such as `P0301`, into its two byte form.
+fn parse_dtc(s: &str) -> Result<u16, String> {
+    let invalid = || format!("'{}' is not a valid trouble code", s);
+
+    let mut chars = s.chars();
+    let system = match chars.next().map(|c| c.to_ascii_uppercase()) {
+        Some('P') => 0,
+        Some('C') => 1,
+        Some('B') => 2,
+        Some('U') => 3,
+        _ => return Err(invalid()),
+    };
+
+    let digits = chars.as_str();
+    if digits.len() != 4 || !digits.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(invalid());
+    }
+    let code = u16::from_str_radix(digits, 16).map_err(|_| invalid())?;
+    if code > 0x3FFF {
+        return Err(invalid());
+    }
+
+    Ok((system << 14) | code)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_trouble_codes() {
+        assert_eq!(parse_dtc("P0301"), Ok(0x0301));
+        assert_eq!(parse_dtc("c0035"), Ok(0x4035));
+        assert_eq!(parse_dtc("U3FFF"), Ok(0xFFFF));
+        assert!(parse_dtc("P4000").is_err());
+        assert!(parse_dtc("X0301").is_err());
+        assert!(parse_dtc("P030").is_err());
+    }
+}